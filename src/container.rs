@@ -2,246 +2,51 @@
 // If a copy of the MPL was not distributed with this file,
 // You can obtain one at <https://mozilla.org/MPL/2.0/>.
 
+mod ancestors;
+mod apptainer;
+mod build;
+mod containerfile;
+mod content_hash;
+mod image;
+mod resolve;
+#[cfg(feature = "serde")]
+mod serialization;
+
+pub use ancestors::Ancestors;
+pub use apptainer::{ApptainerBootstrap, ApptainerBuild, ApptainerDefinition};
+pub use build::BuildStep;
+pub(crate) use build::is_shell_name;
+pub use containerfile::{Containerfile, ContainerfileStage};
+pub use image::{
+    DigestAlgorithm, ImageDigest, ImageSelector, ImageSelectorParseError, MAX_NAME_LENGTH,
+    MAX_TAG_LENGTH, OtherAlgorithm,
+};
+pub use resolve::ResolvedContainer;
+#[cfg(feature = "serde")]
+pub use serialization::ContainerGraph;
+
+use crate::hash::ContentHash;
 use std::convert::TryFrom;
 use std::str::FromStr;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, PoisonError, RwLock};
 use thiserror::Error;
 
-/// Errors that can occur when parsing Docker image references.
-///
-/// These errors are returned when attempting to parse an invalid image reference
-/// string into an [`ImageSelector`].
+/// Errors that can occur when walking or building a chain of nested containers.
 #[derive(Debug, Clone, PartialEq, Eq, Error)]
-pub enum ImageSelectorParseError {
-    /// Returned when the repository name is missing in the image reference.
-    ///
-    /// Examples of inputs that trigger this error:
-    /// - Empty string: `""`
-    /// - Only namespace: `"namespace/"`
-    /// - Only tag: `":tag"`
-    /// - Only digest: `"@sha256=hash"`
-    #[error("Missing image repository")]
-    MissingRepository,
-
-    /// Returned when the digest format is invalid.
-    /// The enclosed string is the invalid digest from the input.
-    ///
-    /// The digest format must be `algorithm=hash`, where both algorithm and hash are non-empty.
-    /// Examples of inputs that trigger this error:
-    /// - Missing equals sign: `"ubuntu@sha256"`
-    /// - Empty algorithm: `"ubuntu@=hash"`
-    /// - Empty hash: `"ubuntu@sha256="`
-    #[error("Invalid digest format: {0}")]
-    InvalidDigestFormat(String),
-}
-
-/// Represents a content-addressable digest for an image.
-///
-/// Docker image digests consist of an algorithm and a hash value, typically
-/// in the format `algorithm=hash`. The most common algorithm is SHA-256.
-///
-/// # Examples
-///
-/// A typical image digest might look like:
-/// ```
-/// use rivulet::container::ImageDigest;
-///
-/// let digest = ImageDigest {
-///     algorithm: "sha256".to_string(),
-///     hash: "01ba4719c80b6fe911b091a7c05124b64eeece964e09c058ef8f9805daca546b".to_string(),
-/// };
-/// ```
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct ImageDigest {
-    /// The hashing algorithm used (e.g., "sha256")
-    pub algorithm: String,
-
-    /// The hash value (e.g., "a1b2c3d4e5f6...")
-    pub hash: String,
-}
-
-/// Represents a parsed Docker image reference.
-///
-/// This struct parses and stores the components of a Docker image reference,
-/// which follows the pattern: `[registry/][user/organization/]repository[:tag][@algorithm=hash]`
-///
-/// # Examples
-///
-/// Basic usage:
-/// ```
-/// use std::str::FromStr;
-/// use rivulet::container::ImageSelector;
-///
-/// // Parse a simple image reference
-/// let selector = ImageSelector::from_str("nginx:latest").unwrap();
-/// assert_eq!(selector.repository, "nginx");
-/// assert_eq!(selector.tag, Some("latest".to_string()));
-///
-/// // Parse a more complex image reference with registry and namespace
-/// let selector = ImageSelector::from_str("docker.io/library/ubuntu:20.04").unwrap();
-/// assert_eq!(selector.namespace, Some("docker.io/library".to_string()));
-/// assert_eq!(selector.repository, "ubuntu");
-/// assert_eq!(selector.tag, Some("20.04".to_string()));
-///
-/// // Parse an image reference with digest
-/// let selector = ImageSelector::from_str("ubuntu@sha256=a1b2c3d4e5f6").unwrap();
-/// assert_eq!(selector.repository, "ubuntu");
-/// let digest = selector.digest.unwrap();
-/// assert_eq!(digest.algorithm, "sha256");
-/// assert_eq!(digest.hash, "a1b2c3d4e5f6");
-/// ```
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct ImageSelector {
-    /// Optional namespace (includes registry if present).
-    ///
-    /// Examples:
-    /// - "docker.io/library"
-    /// - "ghcr.io/user"
-    /// - "codeberg.org/forgejo"
-    pub namespace: Option<String>,
-
-    /// Repository name (required).
-    ///
-    /// This is the only required component of an image reference.
-    pub repository: String,
-
-    /// Optional tag reference.
+pub enum ContainerError {
+    /// Returned when a container is, directly or indirectly, its own base.
     ///
-    /// Examples:
-    /// - "latest"
-    /// - "3.9-slim"
-    /// - "v1.0.0"
-    pub tag: Option<String>,
+    /// Such a chain never reaches an external image, so it has no root image and cannot be
+    /// built.
+    #[error("Container chain contains a cycle")]
+    Cycle,
 
-    /// Optional digest reference.
+    /// Returned when a step sets an environment variable whose name is not a shell name.
     ///
-    /// This provides content-addressable references to specific image versions.
-    pub digest: Option<ImageDigest>,
-}
-
-impl ImageSelector {
-    /// Parse a string reference into an ImageSelector.
-    ///
-    /// This method parses a Docker image reference string into its components:
-    /// namespace, repository, tag, and digest.
-    ///
-    /// # Arguments
-    ///
-    /// * `s` - The image reference string to parse
-    ///
-    /// # Returns
-    ///
-    /// A `Result` containing either the parsed `ImageSelector` or an `ImageSelectorParseError`
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use rivulet::container::ImageSelector;
-    ///
-    /// // Parse a simple image name
-    /// let selector = ImageSelector::parse("ubuntu").unwrap();
-    ///
-    /// // Parse an image with tag
-    /// let selector = ImageSelector::parse("nginx:latest").unwrap();
-    ///
-    /// // Parse an image with namespace and tag
-    /// let selector = ImageSelector::parse("docker.io/library/redis:6.2").unwrap();
-    ///
-    /// // Parse an image with digest
-    /// let selector = ImageSelector::parse("ubuntu@sha256=a1b2c3d4e5f6").unwrap();
-    /// ```
-    pub fn parse(s: &str) -> Result<Self, ImageSelectorParseError> {
-        // Check for digest (@)
-        let (s, digest) = match s.split_once('@') {
-            Some((rest, digest_ref)) => {
-                if let Some((algo, hash)) = digest_ref.split_once('=') {
-                    if algo.is_empty() || hash.is_empty() {
-                        return Err(ImageSelectorParseError::InvalidDigestFormat(
-                            digest_ref.to_string(),
-                        ));
-                    }
-                    (
-                        rest,
-                        Some(ImageDigest {
-                            algorithm: algo.to_string(),
-                            hash: hash.to_string(),
-                        }),
-                    )
-                } else {
-                    return Err(ImageSelectorParseError::InvalidDigestFormat(
-                        digest_ref.to_string(),
-                    ));
-                }
-            }
-            None => (s, None),
-        };
-
-        // Check for tag (:)
-        let (s, tag) = match s.rsplit_once(':') {
-            Some((rest, tag)) => (rest, Some(tag.to_string())),
-            None => (s, None),
-        };
-
-        // Check for namespace (/)
-        let (namespace, s) = match s.rsplit_once('/') {
-            Some((namespace, rest)) => (Some(namespace.to_string()), rest),
-            None => (None, s),
-        };
-
-        // Repository is required
-        if s.is_empty() {
-            return Err(ImageSelectorParseError::MissingRepository);
-        }
-
-        Ok(ImageSelector {
-            namespace,
-            repository: s.to_string(),
-            tag,
-            digest,
-        })
-    }
-}
-
-impl FromStr for ImageSelector {
-    type Err = ImageSelectorParseError;
-
-    /// Parse a string into an ImageSelector using the `FromStr` trait.
-    ///
-    /// This allows using the standard library's `parse()` method.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use std::str::FromStr;
-    /// use rivulet::container::ImageSelector;
-    ///
-    /// let selector: ImageSelector = "nginx:latest".parse().unwrap();
-    /// assert_eq!(selector.repository, "nginx");
-    /// assert_eq!(selector.tag, Some("latest".to_string()));
-    /// ```
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Self::parse(s)
-    }
-}
-
-impl TryFrom<&str> for ImageSelector {
-    type Error = ImageSelectorParseError;
-
-    /// Convert a string reference to an ImageSelector using the `TryFrom` trait.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// use std::convert::TryFrom;
-    /// use rivulet::container::ImageSelector;
-    ///
-    /// let selector = ImageSelector::try_from("nginx:latest").unwrap();
-    /// assert_eq!(selector.repository, "nginx");
-    /// assert_eq!(selector.tag, Some("latest".to_string()));
-    /// ```
-    fn try_from(s: &str) -> Result<Self, Self::Error> {
-        Self::parse(s)
-    }
+    /// Names are written unquoted into build files, so only a letter or underscore followed
+    /// by letters, digits and underscores is accepted.
+    #[error("Invalid environment variable name '{0}'")]
+    InvalidEnvKey(String),
 }
 
 /// Represents the base of a container, which can be either an external image reference
@@ -319,10 +124,33 @@ impl TryFrom<&str> for ContainerBase {
 /// // Create a container that references the base container
 /// let derived_container = Container::from(&base_container);
 /// ```
+///
+/// Describing what a derived container adds to its base:
+/// ```
+/// use rivulet::container::Container;
+///
+/// let base_container = Container::from("alpine:latest");
+/// let python_container = Container::from(&base_container);
+/// python_container
+///     .write()
+///     .unwrap()
+///     .run("apk add --no-cache python3 py3-pip")
+///     .env("PYTHONUNBUFFERED", "1")
+///     .workdir("/opt/analysis")
+///     .copy(["requirements.txt"], "/opt/analysis/")
+///     .run("pip install -r requirements.txt")
+///     .entrypoint(["python3"])
+///     .label("org.opencontainers.image.title", "analysis");
+///
+/// assert_eq!(python_container.read().unwrap().steps.len(), 7);
+/// ```
 #[derive(Debug, Clone)]
 pub struct Container {
     /// The base of this container (either an external image or a reference to another container).
     pub base: ContainerBase,
+
+    /// The build steps this container applies on top of its base, in order.
+    pub steps: Vec<BuildStep>,
 }
 
 impl FromStr for Container {
@@ -340,7 +168,10 @@ impl FromStr for Container {
     /// ```
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let base = ContainerBase::try_from(s)?;
-        Ok(Self { base })
+        Ok(Self {
+            base,
+            steps: Vec::new(),
+        })
     }
 }
 
@@ -386,6 +217,7 @@ impl From<ImageSelector> for Container {
     fn from(selector: ImageSelector) -> Self {
         Self {
             base: ContainerBase::External(selector),
+            steps: Vec::new(),
         }
     }
 }
@@ -410,6 +242,7 @@ impl From<&Arc<RwLock<Container>>> for Container {
     fn from(container: &Arc<RwLock<Container>>) -> Self {
         Self {
             base: ContainerBase::Internal(container.clone()),
+            steps: Vec::new(),
         }
     }
 }
@@ -460,205 +293,220 @@ impl Container {
     pub fn from<T: Into<Self>>(value: T) -> Arc<RwLock<Self>> {
         Arc::new(RwLock::new(value.into()))
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
 
-    // ImageSelector parsing tests
-    mod image_selector_parsing {
-        use super::*;
-
-        #[test]
-        fn test_simple_repository() {
-            let selector = ImageSelector::parse("ubuntu").unwrap();
-            assert!(matches!(selector,
-                ImageSelector {
-                    namespace: None,
-                    repository: r,
-                    tag: None,
-                    digest: None,
-                } if r == "ubuntu"
-            ));
-        }
+    /// Iterate over the containers this container is built on, from its parent to the root.
+    ///
+    /// Each item is the shared handle of an ancestor. The walk detects chains that loop back
+    /// on themselves and reports them as [`ContainerError::Cycle`] instead of running forever.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rivulet::container::{Container, ContainerBase, ContainerError};
+    /// use std::sync::Arc;
+    ///
+    /// let base = Container::from("alpine:latest");
+    /// let derived = Container::from(&base);
+    ///
+    /// let guard = derived.read().unwrap();
+    /// let ancestors: Vec<_> = guard.ancestors().collect::<Result<_, _>>().unwrap();
+    /// assert_eq!(ancestors.len(), 1);
+    /// assert!(Arc::ptr_eq(&ancestors[0], &base));
+    /// drop(guard);
+    ///
+    /// // Make the base its own grandchild
+    /// base.write().unwrap().base = ContainerBase::Internal(derived.clone());
+    /// let guard = derived.read().unwrap();
+    /// assert!(guard
+    ///     .ancestors()
+    ///     .any(|ancestor| matches!(ancestor, Err(ContainerError::Cycle))));
+    /// ```
+    pub fn ancestors(&self) -> Ancestors<'_> {
+        Ancestors::new(self)
+    }
 
-        #[test]
-        fn test_with_tag() {
-            let selector = ImageSelector::parse("python:3.9-slim").unwrap();
-            assert!(matches!(selector,
-                ImageSelector {
-                    namespace: None,
-                    repository: r,
-                    tag: Some(t),
-                    digest: None,
-                } if r == "python" && t == "3.9-slim"
-            ));
+    /// The external image at the root of this container's chain.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ContainerError::Cycle`] if the chain loops back on itself.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rivulet::container::Container;
+    ///
+    /// let base = Container::from("quay.io/biocontainers/salmon:1.5.2");
+    /// let derived = Container::from(&Container::from(&base));
+    ///
+    /// let image = derived.read().unwrap().root_image().unwrap();
+    /// assert_eq!(image.to_string(), "quay.io/biocontainers/salmon:1.5.2");
+    /// ```
+    pub fn root_image(&self) -> Result<ImageSelector, ContainerError> {
+        let mut image = match &self.base {
+            ContainerBase::External(selector) => return Ok(selector.clone()),
+            ContainerBase::Internal(_) => None,
+        };
+        for ancestor in self.ancestors() {
+            let ancestor = ancestor?;
+            let guard = ancestor.read().unwrap_or_else(PoisonError::into_inner);
+            if let ContainerBase::External(selector) = &guard.base {
+                image = Some(selector.clone());
+            }
         }
+        Ok(image.expect("an acyclic chain ends at an external image"))
+    }
 
-        #[test]
-        fn test_with_namespace() {
-            let selector = ImageSelector::parse("docker.io/library/redis").unwrap();
-            assert!(matches!(selector,
-                ImageSelector {
-                    namespace: Some(n),
-                    repository: r,
-                    tag: None,
-                    digest: None,
-                } if n == "docker.io/library" && r == "redis"
-            ));
-        }
+    /// Flatten this container's chain into its effective configuration.
+    ///
+    /// The result is a snapshot that holds no locks, so it can be handed to code that should
+    /// not see later changes to the containers.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ContainerError::Cycle`] if the chain loops back on itself, or
+    /// [`ContainerError::InvalidEnvKey`] if a step sets a variable with an invalid name.
+    pub fn resolve(&self) -> Result<ResolvedContainer, ContainerError> {
+        Ok(ResolvedContainer::new(self.lineage()?))
+    }
 
-        #[test]
-        fn test_with_namespace_and_tag() {
-            let selector = ImageSelector::parse("docker.io/library/redis:6.2").unwrap();
-            assert!(matches!(selector,
-                ImageSelector {
-                    namespace: Some(n),
-                    repository: r,
-                    tag: Some(t),
-                    digest: None,
-                } if n == "docker.io/library" && r == "redis" && t == "6.2"
-            ));
-        }
+    /// Compute the content-addressed identity of this container.
+    ///
+    /// The hash covers the root image in canonical form and every build step of every
+    /// container in the chain, in order. It does not depend on where the containers live in
+    /// memory, so separately built chains with the same structure hash the same, and any
+    /// change to the chain changes the hash.
+    ///
+    /// A root image referenced by tag is hashed by its name, so the hash cannot tell when the
+    /// tag is moved to new content upstream. Pin the root image with a digest to make the hash
+    /// cover exactly the image that is used; the tag of a pinned reference is then ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ContainerError::Cycle`] if the chain loops back on itself, or
+    /// [`ContainerError::InvalidEnvKey`] if a step sets a variable with an invalid name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rivulet::container::Container;
+    ///
+    /// let build = || {
+    ///     let base = Container::from("python:3.12-slim");
+    ///     let app = Container::from(&base);
+    ///     app.write().unwrap().run("pip install numpy");
+    ///     app
+    /// };
+    /// let (first, second) = (build(), build());
+    ///
+    /// let hash = first.read().unwrap().content_hash().unwrap();
+    /// assert_eq!(hash, second.read().unwrap().content_hash().unwrap());
+    ///
+    /// second.write().unwrap().run("pip install scipy");
+    /// assert_ne!(hash, second.read().unwrap().content_hash().unwrap());
+    /// ```
+    pub fn content_hash(&self) -> Result<ContentHash, ContainerError> {
+        Ok(content_hash::hash_lineage(&self.lineage()?))
+    }
 
-        #[test]
-        fn test_with_digest() {
-            let selector = ImageSelector::parse("ubuntu@sha256=a1b2c3d4e5f6").unwrap();
-            assert!(matches!(selector,
-                ImageSelector {
-                    namespace: None,
-                    repository: r,
-                    tag: None,
-                    digest: Some(d),
-                } if r == "ubuntu" && d.algorithm == "sha256" && d.hash == "a1b2c3d4e5f6"
-            ));
+    /// Snapshot this container and its ancestors, ordered from the root to this container.
+    ///
+    /// The first entry is always based on an external image; every other entry is based on
+    /// the entry before it. Every step of every entry is [validated](BuildStep::validate).
+    pub(crate) fn lineage(&self) -> Result<Vec<Container>, ContainerError> {
+        let mut lineage = vec![self.clone()];
+        for ancestor in self.ancestors() {
+            let ancestor = ancestor?;
+            lineage.push(
+                ancestor
+                    .read()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .clone(),
+            );
         }
-
-        #[test]
-        fn test_complex_image_reference() {
-            let selector = ImageSelector::parse("codeberg.org/forgejo/forgejo:10.0.1").unwrap();
-            assert!(matches!(selector,
-                ImageSelector {
-                    namespace: Some(n),
-                    repository: r,
-                    tag: Some(t),
-                    digest: None,
-                } if n == "codeberg.org/forgejo" && r == "forgejo" && t == "10.0.1"
-            ));
+        lineage.reverse();
+        for step in lineage.iter().flat_map(|container| &container.steps) {
+            step.validate()?;
         }
+        Ok(lineage)
+    }
 
-        #[test]
-        fn test_multi_level_namespace() {
-            let selector = ImageSelector::parse("docker.io/library/user/repo:tag").unwrap();
-            assert!(matches!(selector,
-                ImageSelector {
-                    namespace: Some(n),
-                    repository: r,
-                    tag: Some(t),
-                    digest: None,
-                } if n == "docker.io/library/user" && r == "repo" && t == "tag"
-            ));
-        }
+    /// Append a build step to this container.
+    ///
+    /// The other step methods are shorthands for the individual [`BuildStep`] variants.
+    /// All of them return the container so calls can be chained.
+    pub fn step(&mut self, step: BuildStep) -> &mut Self {
+        self.steps.push(step);
+        self
+    }
 
-        #[test]
-        fn test_with_tag_and_digest() {
-            // When both tag and digest are present, only digest should be used
-            let selector = ImageSelector::parse("ubuntu:latest@sha256=a1b2c3d4e5f6").unwrap();
-            assert!(matches!(selector,
-                ImageSelector {
-                    namespace: None,
-                    repository: r,
-                    tag: Some(t),
-                    digest: Some(d),
-                } if r == "ubuntu" && t == "latest" && d.algorithm == "sha256" && d.hash == "a1b2c3d4e5f6"
-            ));
-        }
+    /// Append a step that runs a shell command.
+    pub fn run(&mut self, command: impl Into<String>) -> &mut Self {
+        self.step(BuildStep::Run(command.into()))
     }
 
-    // ImageSelector error tests
-    mod image_selector_errors {
-        use super::*;
+    /// Append a step that copies files from the build context to `destination`.
+    pub fn copy<I, S>(&mut self, sources: I, destination: impl Into<String>) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.step(BuildStep::Copy {
+            sources: sources.into_iter().map(Into::into).collect(),
+            destination: destination.into(),
+        })
+    }
 
-        #[test]
-        fn test_invalid_digest_formats() {
-            // Empty algorithm
-            let result = ImageSelector::parse("ubuntu@=hash");
-            assert!(matches!(result,
-                Err(ImageSelectorParseError::InvalidDigestFormat(s)) if s == "=hash"
-            ));
+    /// Append a step that sets an environment variable.
+    pub fn env(&mut self, key: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.step(BuildStep::Env {
+            key: key.into(),
+            value: value.into(),
+        })
+    }
 
-            // Empty hash
-            let result = ImageSelector::parse("ubuntu@sha256=");
-            assert!(matches!(result,
-                Err(ImageSelectorParseError::InvalidDigestFormat(s)) if s == "sha256="
-            ));
+    /// Append a step that sets the working directory.
+    pub fn workdir(&mut self, path: impl Into<String>) -> &mut Self {
+        self.step(BuildStep::Workdir(path.into()))
+    }
 
-            // No equals sign
-            let result = ImageSelector::parse("ubuntu@sha256");
-            assert!(matches!(result,
-                Err(ImageSelectorParseError::InvalidDigestFormat(s)) if s == "sha256"
-            ));
+    /// Append a step that sets the entrypoint, in exec form.
+    pub fn entrypoint<I, S>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.step(BuildStep::Entrypoint(
+            args.into_iter().map(Into::into).collect(),
+        ))
+    }
 
-            // Empty digest
-            let result = ImageSelector::parse("ubuntu@");
-            assert!(matches!(result,
-                Err(ImageSelectorParseError::InvalidDigestFormat(s)) if s.is_empty()
-            ));
-        }
+    /// Append a step that sets the default arguments, in exec form.
+    pub fn cmd<I, S>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.step(BuildStep::Cmd(args.into_iter().map(Into::into).collect()))
+    }
 
-        #[test]
-        fn test_missing_repository() {
-            let inputs = [
-                // Empty string
-                "",
-                // Only namespace
-                "namespace/",
-                // Multiple trailing slashes
-                "namespace///",
-                // Only tag
-                ":tag",
-                // Only digest
-                "@sha256=hash",
-            ];
-
-            for input in inputs {
-                let result = ImageSelector::parse(input);
-                assert_eq!(result, Err(ImageSelectorParseError::MissingRepository));
-            }
-        }
+    /// Append a step that attaches a metadata label.
+    pub fn label(&mut self, key: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.step(BuildStep::Label {
+            key: key.into(),
+            value: value.into(),
+        })
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
     // Trait implementation tests
     mod trait_implementations {
         use super::*;
 
-        #[test]
-        fn test_image_selector_from_str() {
-            let selector: ImageSelector = "nginx:latest".parse().unwrap();
-            assert!(matches!(selector,
-                ImageSelector {
-                    namespace: None,
-                    repository: r,
-                    tag: Some(t),
-                    digest: None,
-                } if r == "nginx" && t == "latest"
-            ));
-        }
-
-        #[test]
-        fn test_image_selector_try_from() {
-            let selector = <ImageSelector as TryFrom<&str>>::try_from("redis:6.2").unwrap();
-            assert!(matches!(selector,
-                ImageSelector {
-                    namespace: None,
-                    repository: r,
-                    tag: Some(t),
-                    digest: None,
-                } if r == "redis" && t == "6.2"
-            ));
-        }
-
         #[test]
         fn test_container_base_from_image_selector() {
             let selector = ImageSelector::parse("nginx:latest").unwrap();
@@ -756,6 +604,56 @@ mod tests {
             ));
         }
 
+        #[test]
+        fn test_new_containers_have_no_steps() {
+            let base = Container::from("alpine:latest");
+            let derived = Container::from(&base);
+            assert!(base.read().unwrap().steps.is_empty());
+            assert!(derived.read().unwrap().steps.is_empty());
+        }
+
+        #[test]
+        fn test_build_steps_are_ordered() {
+            let base = Container::from("alpine:latest");
+            let derived = Container::from(&base);
+            derived
+                .write()
+                .unwrap()
+                .run("apk add python3")
+                .env("LANG", "C.UTF-8")
+                .workdir("/work")
+                .copy(["a.py", "b.py"], "/work/")
+                .entrypoint(["python3", "a.py"])
+                .label("maintainer", "lab")
+                .step(BuildStep::Run("python3 --version".to_string()));
+
+            let guard = derived.read().unwrap();
+            assert_eq!(
+                guard.steps,
+                [
+                    BuildStep::Run("apk add python3".to_string()),
+                    BuildStep::Env {
+                        key: "LANG".to_string(),
+                        value: "C.UTF-8".to_string()
+                    },
+                    BuildStep::Workdir("/work".to_string()),
+                    BuildStep::Copy {
+                        sources: vec!["a.py".to_string(), "b.py".to_string()],
+                        destination: "/work/".to_string()
+                    },
+                    BuildStep::Entrypoint(vec!["python3".to_string(), "a.py".to_string()]),
+                    BuildStep::Label {
+                        key: "maintainer".to_string(),
+                        value: "lab".to_string()
+                    },
+                    BuildStep::Run("python3 --version".to_string()),
+                ]
+            );
+
+            // The parent is unaffected
+            assert!(base.read().unwrap().steps.is_empty());
+        }
+
         #[test]
         #[should_panic(expected = "Failed to parse image reference")]
         fn test_from_string_panic() {
@@ -0,0 +1,141 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::{Container, ContainerBase, ContainerError};
+use std::collections::HashSet;
+use std::iter::FusedIterator;
+use std::mem;
+use std::ptr;
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// An iterator over the ancestors of a container, from its parent to the root.
+///
+/// Created by [`Container::ancestors`]. Each ancestor is locked for reading only while its
+/// base is inspected, so no lock is held between calls to `next`. If the chain loops back on
+/// itself the iterator yields a single [`ContainerError::Cycle`] and then ends.
+///
+/// A loop back to the container itself is detected before its lock is taken, so walking
+/// the ancestors of a container while holding a read or write guard on it never deadlocks.
+/// Reaching any other ancestor locks it for reading, so the walk waits while another thread
+/// holds that ancestor for writing.
+#[derive(Debug)]
+pub struct Ancestors<'a> {
+    origin: &'a Container,
+    next: Option<Arc<RwLock<Container>>>,
+    visited: HashSet<*const RwLock<Container>>,
+}
+
+impl<'a> Ancestors<'a> {
+    /// Start a walk at the parent of `origin`.
+    pub(super) fn new(origin: &'a Container) -> Self {
+        let next = match &origin.base {
+            ContainerBase::Internal(parent) => Some(parent.clone()),
+            ContainerBase::External(_) => None,
+        };
+        Self {
+            origin,
+            next,
+            visited: HashSet::new(),
+        }
+    }
+}
+
+impl Iterator for Ancestors<'_> {
+    type Item = Result<Arc<RwLock<Container>>, ContainerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.next.take()?;
+        if contains(&current, self.origin) || !self.visited.insert(Arc::as_ptr(&current)) {
+            return Some(Err(ContainerError::Cycle));
+        }
+
+        let guard = current.read().unwrap_or_else(PoisonError::into_inner);
+        if let ContainerBase::Internal(parent) = &guard.base {
+            self.next = Some(parent.clone());
+        }
+        drop(guard);
+        Some(Ok(current))
+    }
+}
+
+impl FusedIterator for Ancestors<'_> {}
+
+/// Whether `container` is the one inside `lock`, judged by address so the lock is not taken.
+fn contains(lock: &RwLock<Container>, container: &Container) -> bool {
+    let start = ptr::from_ref(lock).addr();
+    let range = start..start + mem::size_of_val(lock);
+    range.contains(&ptr::from_ref(container).addr())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_external_container_has_no_ancestors() {
+        let container = Container::from("alpine:latest");
+        assert_eq!(container.read().unwrap().ancestors().count(), 0);
+    }
+
+    #[test]
+    fn test_ancestors_are_nearest_first() {
+        let base = Container::from("alpine:latest");
+        let middle = Container::from(&base);
+        let top = Container::from(&middle);
+
+        let guard = top.read().unwrap();
+        let ancestors: Vec<_> = guard.ancestors().map(Result::unwrap).collect();
+        assert_eq!(ancestors.len(), 2);
+        assert!(Arc::ptr_eq(&ancestors[0], &middle));
+        assert!(Arc::ptr_eq(&ancestors[1], &base));
+    }
+
+    #[test]
+    fn test_cycle_through_origin() {
+        let base = Container::from("alpine:latest");
+        let derived = Container::from(&base);
+        base.write().unwrap().base = ContainerBase::Internal(derived.clone());
+
+        let guard = derived.read().unwrap();
+        let mut ancestors = guard.ancestors();
+        assert!(matches!(ancestors.next(), Some(Ok(ref arc)) if Arc::ptr_eq(arc, &base)));
+        assert!(matches!(ancestors.next(), Some(Err(ContainerError::Cycle))));
+        assert!(ancestors.next().is_none());
+    }
+
+    #[test]
+    fn test_cycle_through_write_locked_origin() {
+        let base = Container::from("alpine:latest");
+        let derived = Container::from(&base);
+        base.write().unwrap().base = ContainerBase::Internal(derived.clone());
+
+        let guard = derived.write().unwrap();
+        let results: Vec<_> = guard.ancestors().collect();
+        assert_eq!(results.len(), 2);
+        assert!(matches!(results[1], Err(ContainerError::Cycle)));
+    }
+
+    #[test]
+    fn test_other_container_is_not_contained() {
+        let base = Container::from("alpine:latest");
+        let other = Container::from("alpine:latest");
+        assert!(contains(&base, &base.read().unwrap()));
+        assert!(!contains(&base, &other.read().unwrap()));
+    }
+
+    #[test]
+    fn test_cycle_above_origin() {
+        // A detached snapshot is never reached again, so the loop is caught by pointer
+        let base = Container::from("alpine:latest");
+        let looping = Container::from(&base);
+        base.write().unwrap().base = ContainerBase::Internal(looping.clone());
+        let snapshot = Container::from(&looping).read().unwrap().clone();
+
+        let results: Vec<_> = snapshot.ancestors().collect();
+        assert_eq!(results.len(), 3);
+        assert!(matches!(results[2], Err(ContainerError::Cycle)));
+    }
+}
+
+// EOF
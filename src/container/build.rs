@@ -0,0 +1,119 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::ContainerError;
+
+/// A single customization a container applies on top of its base.
+///
+/// Build steps are stored in order on each [`Container`](super::Container) and describe what
+/// a derived container adds relative to its parent. They map directly onto the instructions
+/// of container build files.
+///
+/// # Examples
+///
+/// ```
+/// use rivulet::container::{BuildStep, Container};
+///
+/// let base = Container::from("alpine:latest");
+/// let with_python = Container::from(&base);
+/// with_python
+///     .write()
+///     .unwrap()
+///     .run("apk add --no-cache python3")
+///     .env("PYTHONUNBUFFERED", "1");
+///
+/// let guard = with_python.read().unwrap();
+/// assert_eq!(guard.steps[0], BuildStep::Run("apk add --no-cache python3".to_string()));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum BuildStep {
+    /// Run a shell command in the container.
+    Run(String),
+
+    /// Copy files from the build context into the container.
+    Copy {
+        /// Paths in the build context to copy.
+        sources: Vec<String>,
+        /// Destination path in the container.
+        destination: String,
+    },
+
+    /// Set an environment variable.
+    Env {
+        /// Variable name.
+        key: String,
+        /// Variable value.
+        value: String,
+    },
+
+    /// Set the working directory for subsequent steps and for the running container.
+    Workdir(String),
+
+    /// Set the command executed when the container starts, in exec form.
+    Entrypoint(Vec<String>),
+
+    /// Set the default arguments passed to the entrypoint, or the default command if there is
+    /// no entrypoint, in exec form.
+    Cmd(Vec<String>),
+
+    /// Attach a metadata label to the image.
+    Label {
+        /// Label name.
+        key: String,
+        /// Label value.
+        value: String,
+    },
+}
+
+impl BuildStep {
+    /// Check that the step can be written to a build file.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ContainerError::InvalidEnvKey`] if the step sets an environment variable
+    /// whose name is not a letter or underscore followed by letters, digits and underscores.
+    pub fn validate(&self) -> Result<(), ContainerError> {
+        match self {
+            BuildStep::Env { key, .. } if !is_shell_name(key) => {
+                Err(ContainerError::InvalidEnvKey(key.clone()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Whether a string is a valid shell variable name.
+pub(crate) fn is_shell_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_keys_are_validated() {
+        let env = |key: &str| BuildStep::Env {
+            key: key.to_string(),
+            value: "1".to_string(),
+        };
+        for key in ["PATH", "_private", "LD_LIBRARY_PATH2"] {
+            assert_eq!(env(key).validate(), Ok(()));
+        }
+        for key in ["", "2FAST", "A-B", "X=1", "X; rm -rf /", "A B"] {
+            assert!(matches!(
+                env(key).validate(),
+                Err(ContainerError::InvalidEnvKey(bad)) if bad == key
+            ));
+        }
+    }
+}
+
+// EOF
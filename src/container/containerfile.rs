@@ -0,0 +1,325 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::{BuildStep, Container, ContainerBase, ContainerError, ResolvedContainer};
+use std::fmt::{self, Write};
+
+/// A single `FROM` stage of a [`Containerfile`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerfileStage {
+    /// The stage name, used by later stages and as the `--target` of a build.
+    pub name: String,
+
+    /// The image reference or earlier stage name this stage builds on.
+    pub from: String,
+
+    /// The build steps applied in this stage.
+    pub steps: Vec<BuildStep>,
+}
+
+/// A multi-stage Containerfile (Dockerfile) generated from a container chain.
+///
+/// Each container in the chain, from the one based on an external image down to the
+/// container the file was generated for, becomes a named stage built `FROM` its parent's
+/// stage. The root stage uses the fully qualified image reference, so the file builds the
+/// same way under Docker, Podman and Buildah regardless of their short-name configuration.
+/// The last stage is the container itself.
+///
+/// # Examples
+///
+/// ```
+/// use rivulet::container::{Container, Containerfile};
+///
+/// let base = Container::from("alpine:3.19");
+/// let python = Container::from(&base);
+/// python.write().unwrap().run("apk add --no-cache python3").workdir("/work");
+///
+/// let containerfile = Containerfile::new(&python.read().unwrap()).unwrap();
+/// assert_eq!(
+///     containerfile.to_string(),
+///     "FROM docker.io/library/alpine:3.19 AS stage0\n\
+///      \n\
+///      FROM stage0 AS stage1\n\
+///      RUN apk add --no-cache python3\n\
+///      WORKDIR /work\n"
+/// );
+/// assert_eq!(containerfile.target(), "stage1");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Containerfile {
+    stages: Vec<ContainerfileStage>,
+}
+
+impl Containerfile {
+    /// Generate the Containerfile for a container and the chain of containers it is based on.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ContainerError::Cycle`] if the chain loops back on itself, or
+    /// [`ContainerError::InvalidEnvKey`] if a step sets a variable with an invalid name.
+    pub fn new(container: &Container) -> Result<Self, ContainerError> {
+        let mut stages: Vec<ContainerfileStage> = Vec::new();
+        for (index, layer) in container.lineage()?.into_iter().enumerate() {
+            let from = match (&layer.base, stages.last()) {
+                (ContainerBase::External(selector), _) => selector.normalize().to_string(),
+                (ContainerBase::Internal(_), Some(parent)) => parent.name.clone(),
+                (ContainerBase::Internal(_), None) => unreachable!("lineage starts at an image"),
+            };
+            stages.push(ContainerfileStage {
+                name: format!("stage{index}"),
+                from,
+                steps: layer.steps,
+            });
+        }
+        Ok(Self { stages })
+    }
+
+    /// Generate a single-stage Containerfile for a resolved container.
+    ///
+    /// The steps of every layer are applied in one stage, which builds the same image as the
+    /// multi-stage file of the original chain.
+    pub fn from_resolved(container: &ResolvedContainer) -> Self {
+        Self {
+            stages: vec![ContainerfileStage {
+                name: "stage0".to_string(),
+                from: container.image().normalize().to_string(),
+                steps: container.layers().concat(),
+            }],
+        }
+    }
+
+    /// The stages of the file, from the external image to the container itself.
+    pub fn stages(&self) -> &[ContainerfileStage] {
+        &self.stages
+    }
+
+    /// The name of the final stage, which builds the container itself.
+    pub fn target(&self) -> &str {
+        &self.stages[self.stages.len() - 1].name
+    }
+}
+
+impl fmt::Display for Containerfile {
+    /// Render the file in Containerfile syntax.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, stage) in self.stages.iter().enumerate() {
+            if index > 0 {
+                f.write_char('\n')?;
+            }
+            writeln!(f, "FROM {} AS {}", stage.from, stage.name)?;
+            for step in &stage.steps {
+                write_instruction(f, step)?;
+                f.write_char('\n')?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write the Containerfile instruction for a build step, without a trailing newline.
+fn write_instruction(f: &mut fmt::Formatter<'_>, step: &BuildStep) -> fmt::Result {
+    match step {
+        // Multi-line scripts cannot use shell form, so they are passed to the shell explicitly
+        BuildStep::Run(command) if command.contains('\n') => {
+            f.write_str("RUN ")?;
+            write_json_array(f, ["/bin/sh", "-c", command.as_str()])
+        }
+        BuildStep::Run(command) => write!(f, "RUN {command}"),
+        BuildStep::Copy {
+            sources,
+            destination,
+        } => {
+            f.write_str("COPY ")?;
+            write_json_array(f, sources.iter().chain([destination]).map(String::as_str))
+        }
+        BuildStep::Env { key, value } => {
+            write!(f, "ENV {key}=")?;
+            write_json_string(f, value)
+        }
+        BuildStep::Workdir(path) => write!(f, "WORKDIR {path}"),
+        BuildStep::Entrypoint(args) => {
+            f.write_str("ENTRYPOINT ")?;
+            write_json_array(f, args.iter().map(String::as_str))
+        }
+        BuildStep::Cmd(args) => {
+            f.write_str("CMD ")?;
+            write_json_array(f, args.iter().map(String::as_str))
+        }
+        BuildStep::Label { key, value } => {
+            f.write_str("LABEL ")?;
+            write_json_string(f, key)?;
+            f.write_char('=')?;
+            write_json_string(f, value)
+        }
+    }
+}
+
+/// Write strings as a JSON array, the exec form of Containerfile instructions.
+fn write_json_array<'a>(
+    f: &mut fmt::Formatter<'_>,
+    items: impl IntoIterator<Item = &'a str>,
+) -> fmt::Result {
+    f.write_char('[')?;
+    for (index, item) in items.into_iter().enumerate() {
+        if index > 0 {
+            f.write_str(", ")?;
+        }
+        write_json_string(f, item)?;
+    }
+    f.write_char(']')
+}
+
+/// Write a string as a double-quoted JSON string.
+fn write_json_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    /// Render a single build step as a Containerfile instruction.
+    fn instruction(step: BuildStep) -> String {
+        let mut container = Container::from_str("alpine").unwrap();
+        container.step(step);
+        let rendered = Containerfile::new(&container).unwrap().to_string();
+        rendered
+            .strip_prefix("FROM docker.io/library/alpine:latest AS stage0\n")
+            .unwrap()
+            .trim_end()
+            .to_string()
+    }
+
+    #[test]
+    fn test_single_stage() {
+        let container = Container::from_str("quay.io/biocontainers/salmon:1.5.2").unwrap();
+        let containerfile = Containerfile::new(&container).unwrap();
+        assert_eq!(
+            containerfile.to_string(),
+            "FROM quay.io/biocontainers/salmon:1.5.2 AS stage0\n"
+        );
+        assert_eq!(containerfile.target(), "stage0");
+    }
+
+    #[test]
+    fn test_invalid_env_key() {
+        let mut container = Container::from_str("alpine").unwrap();
+        container.env("PATH /bin", "1");
+        assert_eq!(
+            Containerfile::new(&container),
+            Err(ContainerError::InvalidEnvKey("PATH /bin".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_cycle_is_detected() {
+        let base = Container::from("alpine");
+        let top = Container::from(&base);
+        base.write().unwrap().base = ContainerBase::Internal(top.clone());
+
+        let guard = top.read().unwrap();
+        assert_eq!(Containerfile::new(&guard), Err(ContainerError::Cycle));
+    }
+
+    #[test]
+    fn test_stages_follow_lineage() {
+        let base = Container::from("alpine");
+        let middle = Container::from(&base);
+        let top = Container::from(&middle);
+
+        let containerfile = Containerfile::new(&top.read().unwrap()).unwrap();
+        let stages = containerfile.stages();
+        assert_eq!(stages.len(), 3);
+        assert_eq!(stages[0].from, "docker.io/library/alpine:latest");
+        assert_eq!(stages[1].from, "stage0");
+        assert_eq!(stages[2].from, "stage1");
+        assert_eq!(containerfile.target(), "stage2");
+    }
+
+    #[test]
+    fn test_run_instructions() {
+        assert_eq!(
+            instruction(BuildStep::Run("apk add python3".to_string())),
+            "RUN apk add python3"
+        );
+        assert_eq!(
+            instruction(BuildStep::Run("set -e\necho \"done\"".to_string())),
+            r#"RUN ["/bin/sh", "-c", "set -e\necho \"done\""]"#
+        );
+    }
+
+    #[test]
+    fn test_copy_instruction() {
+        assert_eq!(
+            instruction(BuildStep::Copy {
+                sources: vec!["my file.txt".to_string(), "src".to_string()],
+                destination: "/app/".to_string(),
+            }),
+            r#"COPY ["my file.txt", "src", "/app/"]"#
+        );
+    }
+
+    #[test]
+    fn test_env_and_label_instructions() {
+        assert_eq!(
+            instruction(BuildStep::Env {
+                key: "GREETING".to_string(),
+                value: "say \"hi\"".to_string(),
+            }),
+            r#"ENV GREETING="say \"hi\"""#
+        );
+        assert_eq!(
+            instruction(BuildStep::Label {
+                key: "org.opencontainers.image.title".to_string(),
+                value: "salmon\tquant".to_string(),
+            }),
+            r#"LABEL "org.opencontainers.image.title"="salmon\tquant""#
+        );
+    }
+
+    #[test]
+    fn test_workdir_entrypoint_and_cmd_instructions() {
+        assert_eq!(
+            instruction(BuildStep::Workdir("/data".to_string())),
+            "WORKDIR /data"
+        );
+        assert_eq!(
+            instruction(BuildStep::Entrypoint(vec![
+                "salmon".to_string(),
+                "quant".to_string()
+            ])),
+            r#"ENTRYPOINT ["salmon", "quant"]"#
+        );
+        assert_eq!(
+            instruction(BuildStep::Cmd(vec!["--help".to_string()])),
+            r#"CMD ["--help"]"#
+        );
+    }
+
+    #[test]
+    fn test_control_characters_are_escaped() {
+        assert_eq!(
+            instruction(BuildStep::Env {
+                key: "BELL".to_string(),
+                value: "\u{7}".to_string(),
+            }),
+            r#"ENV BELL="\u0007""#
+        );
+    }
+}
+
+// EOF
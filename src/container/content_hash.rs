@@ -0,0 +1,151 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::{BuildStep, Container, ContainerBase, ImageSelector};
+use crate::hash::{ContentHash, ContentHasher};
+
+/// Domain of container hashes; bump the version whenever the encoding below changes.
+const DOMAIN: &str = "rivulet.container.v1";
+
+/// Hash a lineage, ordered from the root to the container itself.
+pub(super) fn hash_lineage(lineage: &[Container]) -> ContentHash {
+    let image = match &lineage[0].base {
+        ContainerBase::External(selector) => selector,
+        ContainerBase::Internal(_) => unreachable!("lineage starts at an image"),
+    };
+    hash_layers(image, lineage.iter().map(|layer| layer.steps.as_slice()))
+}
+
+/// Hash the root image of a chain and the build steps of each of its containers.
+pub(super) fn hash_layers<'a>(
+    image: &ImageSelector,
+    layers: impl ExactSizeIterator<Item = &'a [BuildStep]>,
+) -> ContentHash {
+    let mut hasher = ContentHasher::new(DOMAIN);
+    hash_image(&mut hasher, image);
+    hasher.u64(layers.len() as u64);
+    for steps in layers {
+        hasher.u64(steps.len() as u64);
+        for step in steps {
+            hash_step(&mut hasher, step);
+        }
+    }
+    hasher.finish()
+}
+
+/// Hash the canonical form of an image reference.
+///
+/// References are normalized first, so `alpine` and `docker.io/library/alpine:latest` are the
+/// same image. A digest pins the content, so the tag of a pinned reference is ignored.
+fn hash_image(hasher: &mut ContentHasher, selector: &ImageSelector) {
+    let mut image = selector.normalize();
+    if image.digest.is_some() {
+        image.tag = None;
+    }
+    hasher.str(&image.to_string());
+}
+
+/// Hash a build step as its kind followed by its fields.
+fn hash_step(hasher: &mut ContentHasher, step: &BuildStep) {
+    match step {
+        BuildStep::Run(command) => {
+            hasher.str("run").str(command);
+        }
+        BuildStep::Copy {
+            sources,
+            destination,
+        } => {
+            hasher.str("copy");
+            hash_list(hasher, sources);
+            hasher.str(destination);
+        }
+        BuildStep::Env { key, value } => {
+            hasher.str("env").str(key).str(value);
+        }
+        BuildStep::Workdir(path) => {
+            hasher.str("workdir").str(path);
+        }
+        BuildStep::Entrypoint(args) => {
+            hasher.str("entrypoint");
+            hash_list(hasher, args);
+        }
+        BuildStep::Cmd(args) => {
+            hasher.str("cmd");
+            hash_list(hasher, args);
+        }
+        BuildStep::Label { key, value } => {
+            hasher.str("label").str(key).str(value);
+        }
+    }
+}
+
+/// Hash a list of strings, prefixed with its length.
+fn hash_list(hasher: &mut ContentHasher, items: &[String]) {
+    hasher.u64(items.len() as u64);
+    for item in items {
+        hasher.str(item);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn hash(image: &str, steps: &[BuildStep]) -> ContentHash {
+        let mut container = Container::from_str(image).unwrap();
+        container.steps = steps.to_vec();
+        container.content_hash().unwrap()
+    }
+
+    #[test]
+    fn test_equivalent_references_hash_the_same() {
+        assert_eq!(
+            hash("alpine", &[]),
+            hash("docker.io/library/alpine:latest", &[])
+        );
+        assert_ne!(hash("alpine:3.19", &[]), hash("alpine:3.20", &[]));
+    }
+
+    #[test]
+    fn test_pinned_references_ignore_tag() {
+        let digest = "sha256:01ba4719c80b6fe911b091a7c05124b64eeece964e09c058ef8f9805daca546b";
+        assert_eq!(
+            hash(&format!("alpine:3.19@{digest}"), &[]),
+            hash(&format!("alpine@{digest}"), &[])
+        );
+        assert_ne!(hash(&format!("alpine@{digest}"), &[]), hash("alpine", &[]));
+    }
+
+    #[test]
+    fn test_step_fields_are_distinguished() {
+        let run = |command: &str| BuildStep::Run(command.to_string());
+        assert_ne!(
+            hash("alpine", &[run("a"), run("b")]),
+            hash("alpine", &[run("b"), run("a")])
+        );
+        assert_ne!(
+            hash("alpine", &[BuildStep::Entrypoint(vec!["sh".to_string()])]),
+            hash("alpine", &[BuildStep::Cmd(vec!["sh".to_string()])])
+        );
+        assert_ne!(
+            hash(
+                "alpine",
+                &[BuildStep::Copy {
+                    sources: vec!["a".to_string(), "b".to_string()],
+                    destination: "/c".to_string(),
+                }]
+            ),
+            hash(
+                "alpine",
+                &[BuildStep::Copy {
+                    sources: vec!["a".to_string()],
+                    destination: "b".to_string(),
+                }]
+            )
+        );
+    }
+}
+
+// EOF
@@ -0,0 +1,292 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+mod digest;
+mod display;
+mod parse;
+
+pub use digest::{DigestAlgorithm, ImageDigest, OtherAlgorithm};
+use thiserror::Error;
+
+/// Errors that can occur when parsing Docker image references.
+///
+/// These errors are returned when attempting to parse an invalid image reference
+/// string into an [`ImageSelector`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ImageSelectorParseError {
+    /// Returned when the repository name is missing in the image reference.
+    ///
+    /// Examples of inputs that trigger this error:
+    /// - Empty string: `""`
+    /// - Only namespace: `"namespace/"`
+    /// - Only tag: `":tag"`
+    /// - Only digest: `"@sha256:<hex>"`
+    #[error("Missing image repository")]
+    MissingRepository,
+
+    /// Returned when the digest format is invalid.
+    /// The enclosed string is the invalid digest from the input.
+    ///
+    /// The digest format must be `algorithm:hash`, where the algorithm is lowercase alphanumeric
+    /// components separated by `+`, `.`, `_` or `-`, and the hash is non-empty and made of
+    /// alphanumerics, `=`, `_` and `-`.
+    /// Examples of inputs that trigger this error:
+    /// - Missing colon: `"ubuntu@sha256"`
+    /// - Empty algorithm: `"ubuntu@:hash"`
+    /// - Empty hash: `"ubuntu@sha256:"`
+    /// - Invalid algorithm: `"ubuntu@SHA256:hash"`
+    #[error("Invalid digest format: {0}")]
+    InvalidDigestFormat(String),
+
+    /// Returned when the hash of a known digest algorithm has the wrong length.
+    ///
+    /// Examples of inputs that trigger this error:
+    /// - Truncated SHA-256 hash: `"ubuntu@sha256:a1b2c3"`
+    #[error("Invalid {algorithm} digest length: expected {expected} characters, found {found}")]
+    InvalidDigestLength {
+        /// The algorithm of the digest.
+        algorithm: DigestAlgorithm,
+        /// The number of hex characters the algorithm requires.
+        expected: usize,
+        /// The number of characters in the input.
+        found: usize,
+    },
+
+    /// Returned when the hash of a known digest algorithm is not lowercase hexadecimal.
+    /// The enclosed string is the invalid hash from the input.
+    ///
+    /// Examples of inputs that trigger this error:
+    /// - Uppercase hex: `"ubuntu@sha256:A1B2..."`
+    /// - Non-hex characters: `"ubuntu@sha256:zz..."`
+    #[error("Invalid digest encoding: {0}")]
+    InvalidDigestEncoding(String),
+
+    /// Returned when the registry host is malformed.
+    /// The enclosed string is the registry component from the input.
+    ///
+    /// Examples of inputs that trigger this error:
+    /// - Unbracketed IPv6 address: `"::1/salmon"`
+    /// - Unterminated IPv6 literal: `"[::1/salmon"`
+    /// - Empty host: `":5000/salmon"`
+    #[error("Invalid registry: {0}")]
+    InvalidRegistry(String),
+
+    /// Returned when the registry port is not a valid port number.
+    /// The enclosed string is the registry component from the input.
+    ///
+    /// Examples of inputs that trigger this error:
+    /// - Non-numeric port: `"localhost:http/salmon"`
+    /// - Empty port: `"localhost:/salmon"`
+    /// - Out of range port: `"localhost:70000/salmon"`
+    #[error("Invalid registry port: {0}")]
+    InvalidRegistryPort(String),
+
+    /// Returned when a colon appears somewhere it can be neither a registry port nor a tag.
+    /// The enclosed string is the part of the input following the registry.
+    ///
+    /// Examples of inputs that trigger this error:
+    /// - Colon in a namespace component: `"quay.io/lab:1/salmon"`
+    /// - Multiple tags: `"salmon:1.5:2"`
+    #[error("Ambiguous image reference: {0}")]
+    AmbiguousReference(String),
+
+    /// Returned when a label of the registry hostname is not a valid domain component.
+    ///
+    /// Domain components are ASCII letters, digits and hyphens, and must not start or end
+    /// with a hyphen.
+    /// Examples of inputs that trigger this error:
+    /// - Underscore in hostname: `"my_registry.example.com/salmon"`
+    /// - Empty label: `"registry..example.com/salmon"`
+    #[error("Invalid registry domain component {component:?} at byte {offset}")]
+    InvalidDomainComponent {
+        /// The offending domain component.
+        component: String,
+        /// The byte offset of the component in the input.
+        offset: usize,
+    },
+
+    /// Returned when a namespace or repository path component does not follow the grammar.
+    ///
+    /// Path components are lowercase alphanumerics separated by a single `.` or `_`, a double
+    /// `__`, or one or more `-`, and must start and end with an alphanumeric.
+    /// Examples of inputs that trigger this error:
+    /// - Uppercase letters: `"Ubuntu"`
+    /// - Empty component: `"lab//salmon"`
+    /// - Whitespace: `"lab/my salmon"`
+    /// - Leading separator: `"lab/-salmon"`
+    #[error("Invalid path component {component:?} at byte {offset}")]
+    InvalidPathComponent {
+        /// The offending path component.
+        component: String,
+        /// The byte offset of the component in the input.
+        offset: usize,
+    },
+
+    /// Returned when the tag does not follow the grammar.
+    ///
+    /// Tags are 1 to 128 characters of ASCII letters, digits, `_`, `.` and `-`, and must not
+    /// start with `.` or `-`.
+    /// Examples of inputs that trigger this error:
+    /// - Empty tag: `"ubuntu:"`
+    /// - Leading period: `"ubuntu:.22"`
+    /// - Too long: a tag of 200 characters
+    #[error("Invalid tag {tag:?} at byte {offset}")]
+    InvalidTag {
+        /// The offending tag.
+        tag: String,
+        /// The byte offset of the tag in the input.
+        offset: usize,
+    },
+
+    /// Returned when the image name (registry and path) exceeds the maximum length.
+    /// The enclosed value is the length of the name in bytes.
+    #[error("Image name is {0} bytes long, the maximum is {max}", max = MAX_NAME_LENGTH)]
+    NameTooLong(usize),
+}
+
+/// The maximum length of an image name (registry and path, excluding tag and digest).
+pub const MAX_NAME_LENGTH: usize = 255;
+
+/// The maximum length of an image tag.
+pub const MAX_TAG_LENGTH: usize = 128;
+
+/// The registry assumed for references that do not name one.
+const DEFAULT_REGISTRY: &str = "docker.io";
+
+/// Legacy hostnames of the default registry, normalized to [`DEFAULT_REGISTRY`].
+const LEGACY_DEFAULT_REGISTRIES: [&str; 2] = ["index.docker.io", "registry-1.docker.io"];
+
+/// The namespace of official images on the default registry.
+const DEFAULT_NAMESPACE: &str = "library";
+
+/// The tag assumed for references that have neither a tag nor a digest.
+const DEFAULT_TAG: &str = "latest";
+
+/// Represents a parsed Docker image reference.
+///
+/// This struct parses and stores the components of a Docker image reference,
+/// which follows the pattern:
+/// `[registry[:port]/][user/organization/]repository[:tag][@algorithm:hash]`
+///
+/// The first path component is treated as a registry when it contains a `.` or a `:`
+/// (a port or an IPv6 literal such as `[::1]:5000`), or when it is exactly `localhost`.
+/// Otherwise it is part of the namespace, so `library/ubuntu` has no registry.
+///
+/// # Examples
+///
+/// Basic usage:
+/// ```
+/// use std::str::FromStr;
+/// use rivulet::container::ImageSelector;
+///
+/// // Parse a simple image reference
+/// let selector = ImageSelector::from_str("nginx:latest").unwrap();
+/// assert_eq!(selector.repository, "nginx");
+/// assert_eq!(selector.tag, Some("latest".to_string()));
+///
+/// // Parse a more complex image reference with registry and namespace
+/// let selector = ImageSelector::from_str("docker.io/library/ubuntu:20.04").unwrap();
+/// assert_eq!(selector.registry, Some("docker.io".to_string()));
+/// assert_eq!(selector.namespace, Some("library".to_string()));
+/// assert_eq!(selector.repository, "ubuntu");
+/// assert_eq!(selector.tag, Some("20.04".to_string()));
+///
+/// // Parse an image reference from a registry on a non-standard port
+/// let selector = ImageSelector::from_str("localhost:5000/lab/salmon").unwrap();
+/// assert_eq!(selector.registry, Some("localhost:5000".to_string()));
+/// assert_eq!(selector.namespace, Some("lab".to_string()));
+/// assert_eq!(selector.repository, "salmon");
+/// assert_eq!(selector.tag, None);
+///
+/// // Parse an image reference with digest
+/// let selector = ImageSelector::from_str(
+///     "ubuntu@sha256:01ba4719c80b6fe911b091a7c05124b64eeece964e09c058ef8f9805daca546b",
+/// )
+/// .unwrap();
+/// assert_eq!(selector.repository, "ubuntu");
+/// let digest = selector.digest.unwrap();
+/// assert_eq!(digest.algorithm, "sha256");
+/// assert_eq!(
+///     digest.hash,
+///     "01ba4719c80b6fe911b091a7c05124b64eeece964e09c058ef8f9805daca546b"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageSelector {
+    /// Optional registry host, including the port if present.
+    ///
+    /// Examples:
+    /// - "docker.io"
+    /// - "localhost:5000"
+    /// - "\[::1\]:5000"
+    pub registry: Option<String>,
+
+    /// Optional namespace (path components between the registry and the repository).
+    ///
+    /// Examples:
+    /// - "library"
+    /// - "user"
+    /// - "owner/project"
+    pub namespace: Option<String>,
+
+    /// Repository name (required).
+    ///
+    /// This is the only required component of an image reference.
+    pub repository: String,
+
+    /// Optional tag reference.
+    ///
+    /// Examples:
+    /// - "latest"
+    /// - "3.9-slim"
+    /// - "v1.0.0"
+    pub tag: Option<String>,
+
+    /// Optional digest reference.
+    ///
+    /// This provides content-addressable references to specific image versions.
+    pub digest: Option<ImageDigest>,
+}
+
+impl ImageSelector {
+    /// Return the fully qualified form of this reference, applying Docker's defaults.
+    ///
+    /// A missing registry becomes `docker.io`, single-component repositories on `docker.io`
+    /// are placed in the `library` namespace, and a reference with neither a tag nor a digest
+    /// is tagged `latest`. Normalizing an already normalized reference returns it unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rivulet::container::ImageSelector;
+    ///
+    /// let selector = ImageSelector::parse("ubuntu").unwrap();
+    /// assert_eq!(selector.normalize().to_string(), "docker.io/library/ubuntu:latest");
+    ///
+    /// let selector = ImageSelector::parse("quay.io/biocontainers/salmon:1.5.2").unwrap();
+    /// assert_eq!(selector.normalize(), selector);
+    /// ```
+    pub fn normalize(&self) -> Self {
+        let mut normalized = self.clone();
+
+        let registry = match self.registry.as_deref() {
+            None => DEFAULT_REGISTRY,
+            Some(r) if LEGACY_DEFAULT_REGISTRIES.contains(&r) => DEFAULT_REGISTRY,
+            Some(r) => r,
+        };
+        normalized.registry = Some(registry.to_string());
+
+        if registry == DEFAULT_REGISTRY && normalized.namespace.is_none() {
+            normalized.namespace = Some(DEFAULT_NAMESPACE.to_string());
+        }
+
+        if normalized.tag.is_none() && normalized.digest.is_none() {
+            normalized.tag = Some(DEFAULT_TAG.to_string());
+        }
+
+        normalized
+    }
+}
+
+// EOF
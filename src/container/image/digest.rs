@@ -0,0 +1,222 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::ImageSelectorParseError;
+use std::fmt;
+use std::str::FromStr;
+
+/// The hashing algorithm of an [`ImageDigest`].
+///
+/// The algorithms registered by the OCI image specification are validated when parsed.
+/// Any other algorithm that matches the digest grammar is kept as an opaque
+/// [`DigestAlgorithm::Other`] so references using newer algorithms still parse.
+///
+/// # Examples
+///
+/// ```
+/// use rivulet::container::DigestAlgorithm;
+///
+/// assert_eq!(DigestAlgorithm::from("sha256"), DigestAlgorithm::Sha256);
+/// assert!(matches!(DigestAlgorithm::from("blake3"), DigestAlgorithm::Other(a) if a == "blake3"));
+/// assert_eq!(DigestAlgorithm::Sha512.as_str(), "sha512");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DigestAlgorithm {
+    /// SHA-256, encoded as 64 lowercase hex characters.
+    Sha256,
+
+    /// SHA-512, encoded as 128 lowercase hex characters.
+    Sha512,
+
+    /// Any other algorithm, whose encoding is not validated beyond the digest grammar.
+    Other(OtherAlgorithm),
+}
+
+/// The identifier of a [`DigestAlgorithm::Other`].
+///
+/// It can only be created through [`DigestAlgorithm::from`], which maps known identifiers to
+/// their own variants, so equal algorithms always compare equal.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OtherAlgorithm(String);
+
+impl OtherAlgorithm {
+    /// The algorithm identifier as it appears in a digest.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for OtherAlgorithm {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for OtherAlgorithm {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl DigestAlgorithm {
+    /// The algorithm identifier as it appears in a digest (e.g., "sha256").
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+            Self::Other(algorithm) => algorithm.as_str(),
+        }
+    }
+
+    /// The number of hex characters in a hash produced by this algorithm, if known.
+    pub fn hex_len(&self) -> Option<usize> {
+        match self {
+            Self::Sha256 => Some(64),
+            Self::Sha512 => Some(128),
+            Self::Other(_) => None,
+        }
+    }
+}
+
+impl From<&str> for DigestAlgorithm {
+    /// Map an algorithm identifier to a known algorithm, or keep it as [`DigestAlgorithm::Other`].
+    fn from(algorithm: &str) -> Self {
+        match algorithm {
+            "sha256" => Self::Sha256,
+            "sha512" => Self::Sha512,
+            other => Self::Other(OtherAlgorithm(other.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for DigestAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl PartialEq<str> for DigestAlgorithm {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for DigestAlgorithm {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+/// Represents a content-addressable digest for an image.
+///
+/// Image digests consist of an algorithm and a hash value in the OCI format
+/// `algorithm:hash`. The most common algorithm is SHA-256.
+///
+/// # Examples
+///
+/// A typical image digest might look like:
+/// ```
+/// use rivulet::container::{DigestAlgorithm, ImageDigest};
+///
+/// let digest = ImageDigest::parse(
+///     "sha256:01ba4719c80b6fe911b091a7c05124b64eeece964e09c058ef8f9805daca546b",
+/// )
+/// .unwrap();
+/// assert_eq!(digest.algorithm, DigestAlgorithm::Sha256);
+/// assert_eq!(
+///     digest.hash,
+///     "01ba4719c80b6fe911b091a7c05124b64eeece964e09c058ef8f9805daca546b"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageDigest {
+    /// The hashing algorithm used (e.g., "sha256")
+    pub algorithm: DigestAlgorithm,
+
+    /// The hash value (e.g., "a1b2c3d4e5f6...")
+    pub hash: String,
+}
+
+impl ImageDigest {
+    /// Parse a digest in the OCI `algorithm:hash` format.
+    ///
+    /// Hashes of known algorithms must be lowercase hex of the algorithm's length.
+    /// Unknown algorithms are accepted as long as they follow the digest grammar.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rivulet::container::{DigestAlgorithm, ImageDigest, ImageSelectorParseError};
+    ///
+    /// let digest = ImageDigest::parse("blake3:f3c1e2").unwrap();
+    /// assert_eq!(digest.algorithm, "blake3");
+    ///
+    /// let result = ImageDigest::parse("sha256:f3c1e2");
+    /// assert!(matches!(result, Err(ImageSelectorParseError::InvalidDigestLength { .. })));
+    /// ```
+    pub fn parse(s: &str) -> Result<Self, ImageSelectorParseError> {
+        let invalid = || ImageSelectorParseError::InvalidDigestFormat(s.to_string());
+
+        let (algorithm, hash) = s.split_once(':').ok_or_else(invalid)?;
+        if !is_valid_algorithm(algorithm) || !is_valid_hash(hash) {
+            return Err(invalid());
+        }
+
+        let algorithm = DigestAlgorithm::from(algorithm);
+        if let Some(expected) = algorithm.hex_len() {
+            if hash.len() != expected {
+                return Err(ImageSelectorParseError::InvalidDigestLength {
+                    algorithm,
+                    expected,
+                    found: hash.len(),
+                });
+            }
+            if !hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+                return Err(ImageSelectorParseError::InvalidDigestEncoding(
+                    hash.to_string(),
+                ));
+            }
+        }
+
+        Ok(Self {
+            algorithm,
+            hash: hash.to_string(),
+        })
+    }
+}
+
+impl FromStr for ImageDigest {
+    type Err = ImageSelectorParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for ImageDigest {
+    /// Format the digest in the OCI `algorithm:hash` form.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm, self.hash)
+    }
+}
+
+/// Check a digest algorithm against `[a-z0-9]+([+._-][a-z0-9]+)*`.
+fn is_valid_algorithm(algorithm: &str) -> bool {
+    algorithm.split(['+', '.', '_', '-']).all(|component| {
+        !component.is_empty()
+            && component
+                .bytes()
+                .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+    })
+}
+
+/// Check a digest hash against `[a-zA-Z0-9=_-]+`.
+fn is_valid_hash(hash: &str) -> bool {
+    !hash.is_empty()
+        && hash
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'=' | b'_' | b'-'))
+}
+
+// EOF
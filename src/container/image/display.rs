@@ -0,0 +1,75 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::parse::is_registry;
+use super::{DEFAULT_NAMESPACE, DEFAULT_REGISTRY, ImageSelector};
+use std::fmt;
+
+impl ImageSelector {
+    /// Return the short, familiar form of this reference for display to users.
+    ///
+    /// This is the normalized reference with the default `docker.io` registry and the
+    /// `library` namespace of official images omitted, as printed by the Docker CLI. The
+    /// registry is kept when the namespace would otherwise be read as one, as in
+    /// `docker.io/my.org/tool`, so parsing the familiar form and normalizing it yields the
+    /// normalized reference again.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rivulet::container::ImageSelector;
+    ///
+    /// let selector = ImageSelector::parse("docker.io/library/ubuntu").unwrap();
+    /// assert_eq!(selector.familiar(), "ubuntu:latest");
+    ///
+    /// let selector = ImageSelector::parse("index.docker.io/rocker/tidyverse:4.3").unwrap();
+    /// assert_eq!(selector.familiar(), "rocker/tidyverse:4.3");
+    ///
+    /// let selector = ImageSelector::parse("localhost:5000/lab/salmon").unwrap();
+    /// assert_eq!(selector.familiar(), "localhost:5000/lab/salmon:latest");
+    ///
+    /// let selector = ImageSelector::parse("docker.io/my.org/tool").unwrap();
+    /// assert_eq!(selector.familiar(), "docker.io/my.org/tool:latest");
+    /// ```
+    pub fn familiar(&self) -> String {
+        let mut familiar = self.normalize();
+
+        let namespace = familiar.namespace.as_deref().unwrap_or_default();
+        let first = namespace.split('/').next().unwrap_or_default();
+        if familiar.registry.as_deref() == Some(DEFAULT_REGISTRY) && !is_registry(first) {
+            familiar.registry = None;
+            if familiar.namespace.as_deref() == Some(DEFAULT_NAMESPACE) {
+                familiar.namespace = None;
+            }
+        }
+
+        familiar.to_string()
+    }
+}
+
+impl fmt::Display for ImageSelector {
+    /// Format the reference in its canonical string form.
+    ///
+    /// The output contains exactly the components present in the selector, so parsing the
+    /// formatted string yields an equal selector. Use [`ImageSelector::normalize`] first to
+    /// obtain the fully qualified form.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(registry) = &self.registry {
+            write!(f, "{registry}/")?;
+        }
+        if let Some(namespace) = &self.namespace {
+            write!(f, "{namespace}/")?;
+        }
+        f.write_str(&self.repository)?;
+        if let Some(tag) = &self.tag {
+            write!(f, ":{tag}")?;
+        }
+        if let Some(digest) = &self.digest {
+            write!(f, "@{digest}")?;
+        }
+        Ok(())
+    }
+}
+
+// EOF
@@ -0,0 +1,294 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::{ImageDigest, ImageSelector, ImageSelectorParseError, MAX_NAME_LENGTH, MAX_TAG_LENGTH};
+use std::convert::TryFrom;
+use std::net::Ipv6Addr;
+use std::str::FromStr;
+
+impl ImageSelector {
+    /// Parse a string reference into an ImageSelector.
+    ///
+    /// This method parses a Docker image reference string into its components:
+    /// registry, namespace, repository, tag, and digest.
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - The image reference string to parse
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing either the parsed `ImageSelector` or an `ImageSelectorParseError`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rivulet::container::ImageSelector;
+    ///
+    /// // Parse a simple image name
+    /// let selector = ImageSelector::parse("ubuntu").unwrap();
+    ///
+    /// // Parse an image with tag
+    /// let selector = ImageSelector::parse("nginx:latest").unwrap();
+    ///
+    /// // Parse an image with namespace and tag
+    /// let selector = ImageSelector::parse("docker.io/library/redis:6.2").unwrap();
+    ///
+    /// // Parse an image from a registry with a port
+    /// let selector = ImageSelector::parse("localhost:5000/lab/salmon").unwrap();
+    ///
+    /// // Parse an image with digest
+    /// let selector = ImageSelector::parse(
+    ///     "ubuntu@sha256:01ba4719c80b6fe911b091a7c05124b64eeece964e09c058ef8f9805daca546b",
+    /// )
+    /// .unwrap();
+    /// ```
+    pub fn parse(s: &str) -> Result<Self, ImageSelectorParseError> {
+        // Check for digest (@)
+        let (s, digest) = match s.split_once('@') {
+            Some((rest, digest_ref)) => (rest, Some(ImageDigest::parse(digest_ref)?)),
+            None => (s, None),
+        };
+
+        // Check for registry (first path component that looks like a host)
+        let (registry, path_offset) = match s.split_once('/') {
+            Some((host, _)) if is_registry(host) => {
+                validate_registry(host)?;
+                (Some(host), host.len() + 1)
+            }
+            _ => (None, 0),
+        };
+        let name = &s[path_offset..];
+
+        // Check for tag (:)
+        let (path, tag) = split_tag(name)?;
+
+        // Check for namespace (/)
+        let (namespace, repository) = match path.rsplit_once('/') {
+            Some((namespace, repository)) => (Some(namespace), repository),
+            None => (None, path),
+        };
+
+        // Repository is required
+        if repository.is_empty() {
+            return Err(ImageSelectorParseError::MissingRepository);
+        }
+
+        validate_name(path, tag, path_offset)?;
+
+        Ok(ImageSelector {
+            registry: registry.map(str::to_string),
+            namespace: namespace.map(str::to_string),
+            repository: repository.to_string(),
+            tag: tag.map(str::to_string),
+            digest,
+        })
+    }
+}
+
+impl FromStr for ImageSelector {
+    type Err = ImageSelectorParseError;
+
+    /// Parse a string into an ImageSelector using the `FromStr` trait.
+    ///
+    /// This allows using the standard library's `parse()` method.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::str::FromStr;
+    /// use rivulet::container::ImageSelector;
+    ///
+    /// let selector: ImageSelector = "nginx:latest".parse().unwrap();
+    /// assert_eq!(selector.repository, "nginx");
+    /// assert_eq!(selector.tag, Some("latest".to_string()));
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl TryFrom<&str> for ImageSelector {
+    type Error = ImageSelectorParseError;
+
+    /// Convert a string reference to an ImageSelector using the `TryFrom` trait.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryFrom;
+    /// use rivulet::container::ImageSelector;
+    ///
+    /// let selector = ImageSelector::try_from("nginx:latest").unwrap();
+    /// assert_eq!(selector.repository, "nginx");
+    /// assert_eq!(selector.tag, Some("latest".to_string()));
+    /// ```
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        Self::parse(s)
+    }
+}
+
+/// Split the tag off the name of a reference, which follows the registry.
+fn split_tag(name: &str) -> Result<(&str, Option<&str>), ImageSelectorParseError> {
+    match name.rsplit_once(':') {
+        Some((rest, tag)) => {
+            // Any remaining colon is neither a registry port nor a tag separator
+            if tag.contains('/') || rest.contains(':') {
+                return Err(ImageSelectorParseError::AmbiguousReference(
+                    name.to_string(),
+                ));
+            }
+            Ok((rest, Some(tag)))
+        }
+        None => Ok((name, None)),
+    }
+}
+
+/// Validate the repository path and tag of a reference, the path starting at `offset`.
+fn validate_name(
+    path: &str,
+    tag: Option<&str>,
+    offset: usize,
+) -> Result<(), ImageSelectorParseError> {
+    validate_path(path, offset)?;
+    if let Some(tag) = tag {
+        validate_tag(tag, offset + path.len() + 1)?;
+    }
+    if offset + path.len() > MAX_NAME_LENGTH {
+        return Err(ImageSelectorParseError::NameTooLong(offset + path.len()));
+    }
+    Ok(())
+}
+
+/// Check whether the first path component of a reference names a registry.
+///
+/// This follows the Docker convention: a component is a registry if it contains a `.` (a domain
+/// name), a `:` (a port or an IPv6 literal), or is exactly `localhost`.
+pub(super) fn is_registry(component: &str) -> bool {
+    component.contains(['.', ':']) || component.starts_with('[') || component == "localhost"
+}
+
+/// Validate the host and optional port of a registry component.
+fn validate_registry(registry: &str) -> Result<(), ImageSelectorParseError> {
+    let invalid = || ImageSelectorParseError::InvalidRegistry(registry.to_string());
+
+    let (host, port) = match registry.strip_prefix('[') {
+        // IPv6 literal, e.g. `[::1]` or `[::1]:5000`
+        Some(rest) => {
+            let (address, after) = rest.split_once(']').ok_or_else(invalid)?;
+            address.parse::<Ipv6Addr>().map_err(|_| invalid())?;
+            let port = match after {
+                "" => None,
+                after => Some(after.strip_prefix(':').ok_or_else(invalid)?),
+            };
+            (address, port)
+        }
+        None => match registry.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (registry, None),
+        },
+    };
+
+    if host.is_empty() {
+        return Err(invalid());
+    }
+
+    // IPv6 literals were validated above; hostnames must be valid domain names
+    if !registry.starts_with('[') {
+        let mut offset = 0;
+        for component in host.split('.') {
+            if !is_valid_domain_component(component) {
+                return Err(ImageSelectorParseError::InvalidDomainComponent {
+                    component: component.to_string(),
+                    offset,
+                });
+            }
+            offset += component.len() + 1;
+        }
+    }
+
+    if let Some(port) = port
+        && (!port.bytes().all(|b| b.is_ascii_digit()) || port.parse::<u16>().is_err())
+    {
+        return Err(ImageSelectorParseError::InvalidRegistryPort(
+            registry.to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Check a registry hostname label against `[a-zA-Z0-9]([a-zA-Z0-9-]*[a-zA-Z0-9])?`.
+fn is_valid_domain_component(component: &str) -> bool {
+    !component.is_empty()
+        && !component.starts_with('-')
+        && !component.ends_with('-')
+        && component
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+}
+
+/// Validate every `/`-separated component of a repository path starting at `offset`.
+fn validate_path(path: &str, offset: usize) -> Result<(), ImageSelectorParseError> {
+    let mut offset = offset;
+    for component in path.split('/') {
+        if !is_valid_path_component(component) {
+            return Err(ImageSelectorParseError::InvalidPathComponent {
+                component: component.to_string(),
+                offset,
+            });
+        }
+        offset += component.len() + 1;
+    }
+    Ok(())
+}
+
+/// Check a path component against `[a-z0-9]+(([_.]|__|[-]+)[a-z0-9]+)*`.
+fn is_valid_path_component(component: &str) -> bool {
+    let is_alphanumeric = |b: &u8| b.is_ascii_lowercase() || b.is_ascii_digit();
+    let mut rest = component.as_bytes();
+
+    loop {
+        // Each run of alphanumerics must be non-empty
+        let run = rest.iter().take_while(|b| is_alphanumeric(b)).count();
+        if run == 0 {
+            return false;
+        }
+        rest = &rest[run..];
+
+        // Followed by either the end of the component or a separator
+        let separator = match rest {
+            [] => return true,
+            [b'_', b'_', ..] => 2,
+            [b'_' | b'.', ..] => 1,
+            [b'-', ..] => rest.iter().take_while(|&&b| b == b'-').count(),
+            _ => return false,
+        };
+        rest = &rest[separator..];
+    }
+}
+
+/// Validate a tag against `[A-Za-z0-9_][A-Za-z0-9_.-]{0,127}`.
+fn validate_tag(tag: &str, offset: usize) -> Result<(), ImageSelectorParseError> {
+    let is_word = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+    let valid = match tag.as_bytes() {
+        [first, rest @ ..] => {
+            tag.len() <= MAX_TAG_LENGTH
+                && is_word(*first)
+                && rest.iter().all(|&b| is_word(b) || b == b'.' || b == b'-')
+        }
+        [] => false,
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(ImageSelectorParseError::InvalidTag {
+            tag: tag.to_string(),
+            offset,
+        })
+    }
+}
+
+// EOF
@@ -0,0 +1,235 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::{BuildStep, Container, ContainerBase, ImageSelector, content_hash};
+use crate::hash::ContentHash;
+use std::collections::BTreeMap;
+
+/// The effective configuration of a container chain, flattened into a single snapshot.
+///
+/// Created by [`Container::resolve`]. Settings are applied from the root image down to the
+/// container itself with the same rules as a container build:
+///
+/// - Environment variables and labels accumulate, and later values replace earlier ones.
+///   Values are kept as written, without expanding references to other variables.
+/// - The last working directory, entrypoint and command win. Relative working directories
+///   are resolved against the previous one.
+/// - Setting an entrypoint clears any command inherited from an earlier layer.
+///
+/// Only the build steps recorded on the containers are known; the configuration baked into
+/// the root image itself is not inspected.
+///
+/// # Examples
+///
+/// ```
+/// use rivulet::container::Container;
+///
+/// let base = Container::from("python:3.12-slim");
+/// base.write().unwrap().env("LANG", "C.UTF-8").workdir("/opt");
+/// let app = Container::from(&base);
+/// app.write()
+///     .unwrap()
+///     .env("LANG", "en_US.UTF-8")
+///     .workdir("app")
+///     .entrypoint(["python", "-m", "app"]);
+///
+/// let resolved = app.read().unwrap().resolve().unwrap();
+/// assert_eq!(resolved.image().to_string(), "python:3.12-slim");
+/// assert_eq!(resolved.env()["LANG"], "en_US.UTF-8");
+/// assert_eq!(resolved.workdir(), Some("/opt/app"));
+/// assert_eq!(resolved.entrypoint(), Some(&["python", "-m", "app"].map(String::from)[..]));
+/// assert_eq!(resolved.layers().len(), 2);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ResolvedContainer {
+    image: ImageSelector,
+    env: BTreeMap<String, String>,
+    entrypoint: Option<Vec<String>>,
+    cmd: Option<Vec<String>>,
+    workdir: Option<String>,
+    labels: BTreeMap<String, String>,
+    layers: Vec<Vec<BuildStep>>,
+}
+
+impl ResolvedContainer {
+    /// Flatten a lineage, ordered from the root to the container itself.
+    pub(super) fn new(lineage: Vec<Container>) -> Self {
+        let image = match &lineage[0].base {
+            ContainerBase::External(selector) => selector.clone(),
+            ContainerBase::Internal(_) => unreachable!("lineage starts at an image"),
+        };
+        let mut resolved = Self {
+            image,
+            env: BTreeMap::new(),
+            entrypoint: None,
+            cmd: None,
+            workdir: None,
+            labels: BTreeMap::new(),
+            layers: Vec::with_capacity(lineage.len()),
+        };
+        for layer in lineage {
+            for step in &layer.steps {
+                resolved.apply(step);
+            }
+            resolved.layers.push(layer.steps);
+        }
+        resolved
+    }
+
+    /// Update the configuration for a build step.
+    fn apply(&mut self, step: &BuildStep) {
+        match step {
+            BuildStep::Env { key, value } => {
+                self.env.insert(key.clone(), value.clone());
+            }
+            BuildStep::Label { key, value } => {
+                self.labels.insert(key.clone(), value.clone());
+            }
+            BuildStep::Workdir(path) => {
+                self.workdir = Some(resolve_path(self.workdir.as_deref(), path));
+            }
+            BuildStep::Entrypoint(args) => {
+                self.entrypoint = Some(args.clone());
+                self.cmd = None;
+            }
+            BuildStep::Cmd(args) => self.cmd = Some(args.clone()),
+            BuildStep::Run(_) | BuildStep::Copy { .. } => {}
+        }
+    }
+
+    /// The external image at the root of the chain.
+    pub fn image(&self) -> &ImageSelector {
+        &self.image
+    }
+
+    /// Replace the root image by the same image pinned to a digest.
+    pub(crate) fn pin(&mut self, image: ImageSelector) {
+        self.image = image;
+    }
+
+    /// The environment variables set by the chain.
+    pub fn env(&self) -> &BTreeMap<String, String> {
+        &self.env
+    }
+
+    /// The entrypoint, if any layer sets one.
+    pub fn entrypoint(&self) -> Option<&[String]> {
+        self.entrypoint.as_deref()
+    }
+
+    /// The default arguments, if a layer sets them after the last entrypoint.
+    pub fn cmd(&self) -> Option<&[String]> {
+        self.cmd.as_deref()
+    }
+
+    /// The absolute working directory, if any layer sets one.
+    pub fn workdir(&self) -> Option<&str> {
+        self.workdir.as_deref()
+    }
+
+    /// The labels attached by the chain.
+    pub fn labels(&self) -> &BTreeMap<String, String> {
+        &self.labels
+    }
+
+    /// The build steps of each container in the chain, from the root to the container itself.
+    pub fn layers(&self) -> &[Vec<BuildStep>] {
+        &self.layers
+    }
+
+    /// Whether the root image must be built on before it can run the container.
+    ///
+    /// This is the case when a layer runs a command or copies files in. The other settings
+    /// can be applied when a container is started from the root image.
+    pub fn needs_build(&self) -> bool {
+        self.layers
+            .iter()
+            .flatten()
+            .any(|step| matches!(step, BuildStep::Run(_) | BuildStep::Copy { .. }))
+    }
+
+    /// The content hash of the chain, equal to [`Container::content_hash`] of the container
+    /// this was resolved from.
+    pub fn content_hash(&self) -> ContentHash {
+        content_hash::hash_layers(&self.image, self.layers.iter().map(Vec::as_slice))
+    }
+}
+
+/// Resolve a container path against a working directory, as `WORKDIR` does.
+///
+/// Relative paths without a working directory are relative to the filesystem root.
+pub(super) fn resolve_path(workdir: Option<&str>, path: &str) -> String {
+    match workdir {
+        _ if path.starts_with('/') => path.to_string(),
+        Some(workdir) => format!("{}/{path}", workdir.trim_end_matches('/')),
+        None => format!("/{path}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_resolve_path() {
+        assert_eq!(resolve_path(None, "/data"), "/data");
+        assert_eq!(resolve_path(None, "data"), "/data");
+        assert_eq!(resolve_path(Some("/opt/"), "app"), "/opt/app");
+        assert_eq!(resolve_path(Some("/opt"), "/srv"), "/srv");
+    }
+
+    #[test]
+    fn test_image_without_steps() {
+        let container = Container::from_str("alpine:3.19").unwrap();
+        let resolved = container.resolve().unwrap();
+        assert_eq!(resolved.image().to_string(), "alpine:3.19");
+        assert!(resolved.env().is_empty());
+        assert!(resolved.labels().is_empty());
+        assert_eq!(resolved.entrypoint(), None);
+        assert_eq!(resolved.cmd(), None);
+        assert_eq!(resolved.workdir(), None);
+        assert_eq!(resolved.layers(), [Vec::new()]);
+    }
+
+    #[test]
+    fn test_entrypoint_clears_inherited_cmd() {
+        let base = Container::from("alpine");
+        base.write().unwrap().cmd(["sh"]);
+        let derived = Container::from(&base);
+        derived.write().unwrap().entrypoint(["salmon"]);
+
+        let resolved = derived.read().unwrap().resolve().unwrap();
+        assert_eq!(resolved.entrypoint(), Some(&["salmon".to_string()][..]));
+        assert_eq!(resolved.cmd(), None);
+
+        // A command set after the entrypoint is kept
+        derived.write().unwrap().cmd(["--help"]);
+        let resolved = derived.read().unwrap().resolve().unwrap();
+        assert_eq!(resolved.cmd(), Some(&["--help".to_string()][..]));
+    }
+
+    #[test]
+    fn test_labels_are_overridden() {
+        let base = Container::from("alpine");
+        base.write()
+            .unwrap()
+            .label("stage", "base")
+            .label("maintainer", "lab");
+        let derived = Container::from(&base);
+        derived.write().unwrap().label("stage", "prod");
+
+        let resolved = derived.read().unwrap().resolve().unwrap();
+        assert_eq!(
+            resolved.labels().iter().collect::<Vec<_>>(),
+            [
+                (&"maintainer".to_string(), &"lab".to_string()),
+                (&"stage".to_string(), &"prod".to_string())
+            ]
+        );
+    }
+}
+
+// EOF
@@ -0,0 +1,402 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+//! Serde support for container types, enabled by the `serde` feature.
+//!
+//! Image references serialize as their canonical strings. Containers serialize as a table of
+//! container records in which each record refers to its parent by index, so a base shared by
+//! several containers is written once and shared again when deserialized:
+//!
+//! ```json
+//! {
+//!   "containers": [
+//!     { "base": { "image": "alpine:latest" } },
+//!     { "base": { "parent": 0 }, "steps": [{ "run": "apk add python3" }] }
+//!   ],
+//!   "roots": [1]
+//! }
+//! ```
+
+use super::{BuildStep, Container, ContainerBase, ImageDigest, ImageSelector};
+use serde::de::{self, Deserializer};
+use serde::ser::{self, Serializer};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use thiserror::Error;
+
+/// Errors that can occur when converting containers to or from their serialized form.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+enum GraphError {
+    /// A container is its own ancestor.
+    #[error("Container is its own ancestor")]
+    Cycle,
+
+    /// A container lock was poisoned by a panicking writer.
+    #[error("Container lock is poisoned")]
+    Poisoned,
+
+    /// A record refers to a parent that is not defined before it.
+    #[error("Container {index} refers to undefined parent {parent}")]
+    UndefinedParent { index: usize, parent: usize },
+
+    /// A root refers to a container that does not exist.
+    #[error("Root refers to undefined container {0}")]
+    UndefinedRoot(usize),
+
+    /// A single container was expected but the document has a different number of roots.
+    #[error("Expected exactly one root container, found {0}")]
+    RootCount(usize),
+}
+
+/// A set of containers that serializes with shared bases deduplicated.
+///
+/// Serializing containers one at a time writes every ancestor chain in full. Collecting them
+/// in a `ContainerGraph` writes each distinct container once, and deserializing the graph
+/// restores the sharing: containers that had the same base get the same `Arc` again.
+///
+/// # Examples
+///
+/// ```
+/// use rivulet::container::{Container, ContainerBase, ContainerGraph};
+/// use std::sync::{Arc, RwLock};
+///
+/// let base = Container::from("alpine:latest");
+/// let python = Container::from(&base);
+/// let rust = Container::from(&base);
+///
+/// let json = serde_json::to_string(&ContainerGraph::new([python, rust])).unwrap();
+/// let graph: ContainerGraph = serde_json::from_str(&json).unwrap();
+///
+/// let parent = |container: &Arc<RwLock<Container>>| match &container.read().unwrap().base {
+///     ContainerBase::Internal(parent) => parent.clone(),
+///     ContainerBase::External(_) => unreachable!(),
+/// };
+/// assert!(Arc::ptr_eq(&parent(&graph.roots()[0]), &parent(&graph.roots()[1])));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ContainerGraph {
+    roots: Vec<Arc<RwLock<Container>>>,
+}
+
+impl ContainerGraph {
+    /// Create a graph from the containers that should be serialized.
+    pub fn new(roots: impl IntoIterator<Item = Arc<RwLock<Container>>>) -> Self {
+        Self {
+            roots: roots.into_iter().collect(),
+        }
+    }
+
+    /// The containers in the graph, in the order they were added.
+    pub fn roots(&self) -> &[Arc<RwLock<Container>>] {
+        &self.roots
+    }
+
+    /// Consume the graph, returning its containers.
+    pub fn into_roots(self) -> Vec<Arc<RwLock<Container>>> {
+        self.roots
+    }
+}
+
+/// The serialized base of a container record.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum BaseRecord {
+    /// An external image reference.
+    Image(ImageSelector),
+
+    /// The index of the parent record in the container table.
+    Parent(usize),
+}
+
+/// The serialized form of a single container.
+#[derive(Serialize, Deserialize)]
+struct ContainerRecord {
+    base: BaseRecord,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    steps: Vec<BuildStep>,
+}
+
+/// The serialized form of a set of containers and everything they are based on.
+#[derive(Serialize, Deserialize)]
+struct GraphRecord {
+    /// Container records, parents before children.
+    containers: Vec<ContainerRecord>,
+
+    /// Indices of the serialized containers in the table.
+    roots: Vec<usize>,
+}
+
+/// Builds a [`GraphRecord`], assigning each distinct container an index.
+#[derive(Default)]
+struct GraphBuilder {
+    ids: HashMap<*const RwLock<Container>, usize>,
+    containers: Vec<ContainerRecord>,
+}
+
+impl GraphBuilder {
+    /// Record a container and all of its unrecorded ancestors, returning its index.
+    fn insert(&mut self, container: &Arc<RwLock<Container>>) -> Result<usize, GraphError> {
+        if let Some(&id) = self.ids.get(&Arc::as_ptr(container)) {
+            return Ok(id);
+        }
+        let guard = container.read().map_err(|_| GraphError::Poisoned)?;
+        let id = self.insert_unshared(&guard)?;
+        self.ids.insert(Arc::as_ptr(container), id);
+        Ok(id)
+    }
+
+    /// Record the unrecorded ancestors of a container, root-most first.
+    fn insert_ancestors(&mut self, container: &Container) -> Result<(), GraphError> {
+        // Walk up to the first recorded ancestor or the external image, nearest first
+        let mut pending = Vec::new();
+        for ancestor in container.ancestors() {
+            let ancestor = ancestor.map_err(|_| GraphError::Cycle)?;
+            if self.ids.contains_key(&Arc::as_ptr(&ancestor)) {
+                break;
+            }
+            let snapshot = ancestor.read().map_err(|_| GraphError::Poisoned)?.clone();
+            pending.push((ancestor, snapshot));
+        }
+
+        // Record the pending containers root-most first so parents precede children
+        for (ancestor, snapshot) in pending.into_iter().rev() {
+            let id = self.push(&snapshot);
+            self.ids.insert(Arc::as_ptr(&ancestor), id);
+        }
+        Ok(())
+    }
+
+    /// Append a record for a container whose ancestors are already recorded.
+    fn push(&mut self, container: &Container) -> usize {
+        let base = match &container.base {
+            ContainerBase::External(selector) => BaseRecord::Image(selector.clone()),
+            ContainerBase::Internal(parent) => BaseRecord::Parent(self.ids[&Arc::as_ptr(parent)]),
+        };
+        self.containers.push(ContainerRecord {
+            base,
+            steps: container.steps.clone(),
+        });
+        self.containers.len() - 1
+    }
+
+    /// Record a container that is not held in an `Arc`, returning its index.
+    fn insert_unshared(&mut self, container: &Container) -> Result<usize, GraphError> {
+        self.insert_ancestors(container)?;
+        Ok(self.push(container))
+    }
+
+    fn finish(self, roots: Vec<usize>) -> GraphRecord {
+        GraphRecord {
+            containers: self.containers,
+            roots,
+        }
+    }
+}
+
+impl GraphRecord {
+    /// Rebuild the container table, sharing each record between all of its children.
+    fn build(self) -> Result<Vec<Arc<RwLock<Container>>>, GraphError> {
+        let mut containers: Vec<Arc<RwLock<Container>>> = Vec::with_capacity(self.containers.len());
+        for (index, record) in self.containers.into_iter().enumerate() {
+            let base = match record.base {
+                BaseRecord::Image(selector) => ContainerBase::External(selector),
+                BaseRecord::Parent(parent) => containers
+                    .get(parent)
+                    .map(|parent| ContainerBase::Internal(parent.clone()))
+                    .ok_or(GraphError::UndefinedParent { index, parent })?,
+            };
+            containers.push(Arc::new(RwLock::new(Container {
+                base,
+                steps: record.steps,
+            })));
+        }
+
+        self.roots
+            .iter()
+            .map(|&root| {
+                containers
+                    .get(root)
+                    .cloned()
+                    .ok_or(GraphError::UndefinedRoot(root))
+            })
+            .collect()
+    }
+}
+
+impl Serialize for ContainerGraph {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut builder = GraphBuilder::default();
+        let roots = self
+            .roots
+            .iter()
+            .map(|root| builder.insert(root))
+            .collect::<Result<_, _>>()
+            .map_err(ser::Error::custom)?;
+        builder.finish(roots).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ContainerGraph {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let roots = GraphRecord::deserialize(deserializer)?
+            .build()
+            .map_err(de::Error::custom)?;
+        Ok(Self { roots })
+    }
+}
+
+impl Serialize for Container {
+    /// Serialize the container together with the chain of containers it is based on.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut builder = GraphBuilder::default();
+        let root = builder.insert_unshared(self).map_err(ser::Error::custom)?;
+        builder.finish(vec![root]).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Container {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let roots = GraphRecord::deserialize(deserializer)?
+            .build()
+            .map_err(de::Error::custom)?;
+        match roots.as_slice() {
+            [root] => Ok(root
+                .read()
+                .map_err(|_| de::Error::custom(GraphError::Poisoned))?
+                .clone()),
+            roots => Err(de::Error::custom(GraphError::RootCount(roots.len()))),
+        }
+    }
+}
+
+/// The serialized form of a [`ContainerBase`].
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum BaseRef<'a> {
+    Image(&'a ImageSelector),
+    Container(&'a Container),
+}
+
+/// The deserialized form of a [`ContainerBase`].
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum BaseValue {
+    Image(ImageSelector),
+    Container(Container),
+}
+
+impl Serialize for ContainerBase {
+    /// Serialize as `{"image": ...}` or `{"container": ...}` with the referenced container's chain.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ContainerBase::External(selector) => BaseRef::Image(selector).serialize(serializer),
+            ContainerBase::Internal(container) => {
+                let guard = container
+                    .read()
+                    .map_err(|_| ser::Error::custom(GraphError::Poisoned))?;
+                BaseRef::Container(&guard).serialize(serializer)
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ContainerBase {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match BaseValue::deserialize(deserializer)? {
+            BaseValue::Image(selector) => ContainerBase::External(selector),
+            BaseValue::Container(container) => ContainerBase::Internal(Container::from(container)),
+        })
+    }
+}
+
+impl Serialize for ImageSelector {
+    /// Serialize as the canonical reference string.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ImageSelector {
+    /// Deserialize from a reference string, applying the same validation as parsing.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let reference = String::deserialize(deserializer)?;
+        ImageSelector::from_str(&reference).map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for ImageDigest {
+    /// Serialize in the OCI `algorithm:hash` form.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ImageDigest {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let digest = String::deserialize(deserializer)?;
+        ImageDigest::from_str(&digest).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cycle_is_an_error() {
+        let base = Container::from("alpine:latest");
+        let derived = Container::from(&base);
+        base.write().unwrap().base = ContainerBase::Internal(derived.clone());
+
+        let mut builder = GraphBuilder::default();
+        assert_eq!(builder.insert(&derived), Err(GraphError::Cycle));
+    }
+
+    #[test]
+    fn test_parents_precede_children() {
+        let base = Container::from("alpine:latest");
+        let middle = Container::from(&base);
+        let top = Container::from(&middle);
+
+        let mut builder = GraphBuilder::default();
+        assert_eq!(builder.insert(&top), Ok(2));
+        assert_eq!(builder.insert(&middle), Ok(1));
+        assert_eq!(builder.insert(&base), Ok(0));
+        assert!(matches!(builder.containers[0].base, BaseRecord::Image(_)));
+        assert!(matches!(builder.containers[1].base, BaseRecord::Parent(0)));
+        assert!(matches!(builder.containers[2].base, BaseRecord::Parent(1)));
+    }
+
+    #[test]
+    fn test_forward_parent_reference_is_an_error() {
+        let record = GraphRecord {
+            containers: vec![ContainerRecord {
+                base: BaseRecord::Parent(0),
+                steps: Vec::new(),
+            }],
+            roots: vec![0],
+        };
+        assert!(matches!(
+            record.build(),
+            Err(GraphError::UndefinedParent {
+                index: 0,
+                parent: 0
+            })
+        ));
+    }
+
+    #[test]
+    fn test_undefined_root_is_an_error() {
+        let record = GraphRecord {
+            containers: vec![],
+            roots: vec![3],
+        };
+        assert!(matches!(record.build(), Err(GraphError::UndefinedRoot(3))));
+    }
+}
+
+// EOF
@@ -0,0 +1,399 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+//! Backends that run the commands of workflow steps.
+//!
+//! The [`Runner`](crate::workflow::Runner) prepares a [`Job`] for every step: it creates the
+//! step's directories, stages its inputs and renders its command. An [`Executor`] then runs
+//! the job to completion, wherever and however it likes, and the runner collects the outputs
+//! from the job's working directory afterwards.
+//!
+//! [`LocalExecutor`] runs commands directly on the host and [`ContainerExecutor`] runs them in
+//! containers with Docker, Podman or Apptainer. Batch schedulers implement [`Scheduler`]:
+//! [`SlurmExecutor`], [`PbsExecutor`] and [`LsfExecutor`] submit jobs to a cluster and wait
+//! for them to finish.
+
+mod local;
+mod lsf;
+mod pbs;
+mod runtime;
+mod scheduler;
+mod slurm;
+
+pub use local::LocalExecutor;
+pub use lsf::LsfExecutor;
+pub use pbs::{PbsExecutor, PbsFlavor};
+pub use runtime::{ContainerExecutor, Runtime, UserMapping};
+pub use scheduler::{JobStatus, Scheduler};
+pub use slurm::SlurmExecutor;
+
+use crate::container::{ContainerError, ImageSelector, ResolvedContainer};
+use crate::hash::ContentHash;
+use crate::store::StoreError;
+use crate::workflow::{PortType, Resources, Value, WorkflowError};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+use thiserror::Error;
+
+/// Errors that can occur when running a workflow.
+#[derive(Debug, Error)]
+pub enum ExecutionError {
+    /// Returned when the workflow fails validation.
+    #[error(transparent)]
+    Workflow(#[from] WorkflowError),
+
+    /// Returned when the container of a step cannot be resolved.
+    #[error(transparent)]
+    Container(#[from] ContainerError),
+
+    /// Returned when the results of a step cannot be kept in, or restored from, the store
+    /// of a [`StepCache`](crate::workflow::StepCache).
+    #[error(transparent)]
+    Store(#[from] StoreError),
+
+    /// Returned when no value is given for a workflow input.
+    #[error("Missing value for workflow input '{0}'")]
+    MissingInput(String),
+
+    /// Returned when a value is given for an input the workflow does not declare.
+    #[error("Unknown workflow input: '{0}'")]
+    UnknownInput(String),
+
+    /// Returned when the value given for a workflow input does not conform to its type, or
+    /// names a path that does not exist or is of the wrong kind.
+    #[error("Invalid value for workflow input '{input}' of type {expected}")]
+    InvalidInput {
+        /// The input name.
+        input: String,
+        /// The declared type of the input.
+        expected: PortType,
+    },
+
+    /// Returned when reading or writing a file fails, or a command cannot be started.
+    #[error("I/O error on '{}': {source}", path.display())]
+    Io {
+        /// The path being accessed.
+        path: PathBuf,
+        /// The underlying error.
+        source: io::Error,
+    },
+
+    /// Returned when the arrays given to the scattered inputs of a dot-product step differ in
+    /// length.
+    #[error("Scattered input '{port}' of step '{step}' has {found} items, expected {expected}")]
+    ScatterLength {
+        /// The step name.
+        step: String,
+        /// The input port name.
+        port: String,
+        /// The length of the first scattered input.
+        expected: usize,
+        /// The length of this input.
+        found: usize,
+    },
+
+    /// Returned when a step's command does not finish successfully.
+    #[error("Step '{step}' failed with {outcome}")]
+    StepFailed {
+        /// The step name, followed by the index of the job in brackets for scattered steps.
+        step: String,
+        /// How the command ended.
+        outcome: JobOutcome,
+        /// The file holding the command's standard error.
+        stderr: PathBuf,
+    },
+
+    /// Returned when building the image of a step's container fails.
+    #[error(
+        "Building image '{image}' failed with exit code {}",
+        exit_code.map_or("none".to_string(), |code| code.to_string())
+    )]
+    ImageBuild {
+        /// The image being built.
+        image: String,
+        /// The exit code of the build command.
+        exit_code: Option<i32>,
+        /// The file holding the output of the build.
+        log: PathBuf,
+    },
+
+    /// Returned when a scheduler command, such as `sbatch`, fails or its output cannot be
+    /// understood.
+    #[error("Scheduler command '{}' failed: {message}", program.display())]
+    Scheduler {
+        /// The program that was run.
+        program: PathBuf,
+        /// What went wrong, usually the error output of the program.
+        message: String,
+    },
+
+    /// Returned when nothing in the working directory matches the glob of an output.
+    #[error("Step '{step}' produced nothing matching '{pattern}' for output '{port}'")]
+    MissingOutput {
+        /// The step name.
+        step: String,
+        /// The output port name.
+        port: String,
+        /// The glob pattern.
+        pattern: String,
+    },
+
+    /// Returned when the glob of a single-valued output matches more than one path.
+    #[error("Step '{step}' produced {count} matches of '{pattern}' for output '{port}'")]
+    AmbiguousOutput {
+        /// The step name.
+        step: String,
+        /// The output port name.
+        port: String,
+        /// The glob pattern.
+        pattern: String,
+        /// The number of matching paths.
+        count: usize,
+    },
+
+    /// Returned when a collected output cannot be read as its declared type.
+    #[error("Step '{step}' produced an invalid value for output '{port}': {reason}")]
+    InvalidOutput {
+        /// The step name.
+        step: String,
+        /// The output port name.
+        port: String,
+        /// What is wrong with the value.
+        reason: String,
+    },
+
+    /// Returned when a run id or result name given to a [`StepCache`] does not follow the rules of
+    /// step names.
+    ///
+    /// [`StepCache`]: crate::workflow::StepCache
+    #[error("Invalid run id or result name: '{0}'")]
+    InvalidName(String),
+
+    /// Returned when pinning a run the [`StepCache`](crate::workflow::StepCache) has no
+    /// record of.
+    #[error("Unknown run: '{0}'")]
+    UnknownRun(String),
+
+    /// Returned when naming a result the [`StepCache`](crate::workflow::StepCache) does not
+    /// hold.
+    #[error("Unknown cached result: {0}")]
+    UnknownResult(ContentHash),
+}
+
+impl ExecutionError {
+    /// Create an [`Io`](Self::Io) error for a path.
+    pub(crate) fn io(path: impl Into<PathBuf>) -> impl FnOnce(io::Error) -> Self {
+        let path = path.into();
+        move |source| Self::Io { path, source }
+    }
+}
+
+/// A step's command, ready to run.
+#[derive(Debug, Clone)]
+pub struct Job {
+    /// The name of the job, unique within a run.
+    pub name: String,
+
+    /// The effective configuration of the container the command runs in.
+    pub container: ResolvedContainer,
+
+    /// The shell command, with its placeholders replaced by shell-quoted input values.
+    pub command: String,
+
+    /// The staged input values, by port name, which the command refers to.
+    pub inputs: BTreeMap<String, Value>,
+
+    /// The directory holding everything belonging to the job.
+    pub dir: PathBuf,
+
+    /// The working directory of the command, inside `dir`; outputs are collected from here.
+    pub workdir: PathBuf,
+
+    /// The file the command's standard output is written to.
+    pub stdout: PathBuf,
+
+    /// The file the command's standard error is written to.
+    pub stderr: PathBuf,
+
+    /// The compute resources the step asks for.
+    pub resources: Resources,
+
+    /// Paths outside `dir` the command needs to read, such as the targets of symlinked inputs.
+    ///
+    /// Executors that isolate the command, such as container runtimes, must make these
+    /// available at the same paths.
+    pub mounts: Vec<PathBuf>,
+}
+
+#[cfg(test)]
+impl Job {
+    /// A job for tests, running `command` in a container with its files in `dir`.
+    pub(crate) fn example(
+        name: &str,
+        container: &std::sync::RwLock<crate::container::Container>,
+        command: &str,
+        dir: impl Into<PathBuf>,
+    ) -> Self {
+        let dir = dir.into();
+        Self {
+            name: name.to_string(),
+            container: container.read().unwrap().resolve().unwrap(),
+            command: command.to_string(),
+            inputs: BTreeMap::new(),
+            workdir: dir.join("work"),
+            stdout: dir.join("stdout"),
+            stderr: dir.join("stderr"),
+            dir,
+            resources: Resources::default(),
+            mounts: Vec::new(),
+        }
+    }
+}
+
+/// Jobs running the same command on different inputs, such as the elements of a scattered
+/// step.
+///
+/// Executors may run the jobs in any order, or all at once; schedulers such as
+/// [`SlurmExecutor`] submit them as a single array job.
+#[derive(Debug, Clone)]
+pub struct JobArray {
+    /// The name of the array, unique within a run.
+    pub name: String,
+
+    /// A directory for files describing the whole array, such as its manifest.
+    pub dir: PathBuf,
+
+    /// The jobs, each with its own directory.
+    pub jobs: Vec<Job>,
+}
+
+/// The result of running a job.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobOutcome {
+    /// The exit code of the command, or `None` when it did not exit normally.
+    pub exit_code: Option<i32>,
+
+    /// Why the job ended abnormally, such as the signal that killed the command or a
+    /// scheduler state like `TIMEOUT`.
+    pub reason: Option<String>,
+}
+
+impl JobOutcome {
+    /// The outcome of a command that exited on its own.
+    pub fn exited(exit_code: i32) -> Self {
+        Self {
+            exit_code: Some(exit_code),
+            reason: None,
+        }
+    }
+
+    /// Whether the command exited successfully.
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0) && self.reason.is_none()
+    }
+
+    /// The outcome of a finished local process.
+    pub(crate) fn from_status(status: ExitStatus) -> Self {
+        #[cfg(unix)]
+        if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+            return Self {
+                exit_code: None,
+                reason: Some(format!("signal {signal}")),
+            };
+        }
+        Self {
+            exit_code: status.code(),
+            reason: None,
+        }
+    }
+}
+
+impl fmt::Display for JobOutcome {
+    /// Describe the outcome for logs and messages, such as `exit code 1` or `TIMEOUT`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.reason, self.exit_code) {
+            (Some(reason), _) => f.write_str(reason),
+            (None, Some(code)) => write!(f, "exit code {code}"),
+            (None, None) => f.write_str("no exit code"),
+        }
+    }
+}
+
+/// A backend that runs jobs.
+pub trait Executor {
+    /// Run a job to completion.
+    ///
+    /// An unsuccessful command is not an error: its exit code is reported in the outcome.
+    /// Errors are reserved for failing to run the command at all.
+    fn execute(&self, job: &Job) -> Result<JobOutcome, ExecutionError>;
+
+    /// Run all jobs of an array to completion, returning their outcomes in order.
+    ///
+    /// By default the jobs are run one after another.
+    fn execute_array(&self, array: &JobArray) -> Result<Vec<JobOutcome>, ExecutionError> {
+        array.jobs.iter().map(|job| self.execute(job)).collect()
+    }
+
+    /// The name of the host jobs run on, for the provenance of a run.
+    ///
+    /// By default this is the host running the executor. Executors for batch schedulers
+    /// return `None`, as the scheduler picks the nodes jobs run on.
+    fn host(&self) -> Option<String> {
+        local_host()
+    }
+
+    /// Pin an image to the digest its reference currently resolves to, so cached results
+    /// are not reused once a tag moves.
+    ///
+    /// Returns `None` if the image cannot be pinned; jobs in it then bypass the cache. By
+    /// default the image is returned unchanged, as executors running commands on the host
+    /// do not depend on it.
+    fn pin_image(&self, image: &ImageSelector) -> Option<ImageSelector> {
+        Some(image.clone())
+    }
+
+    /// Hash the sources of a container's `COPY` steps in the build context images are built
+    /// from, by source as written in the step, so cached results are not reused once a
+    /// source is edited.
+    ///
+    /// By default there are none, as executors running commands on the host build no images.
+    fn copy_sources(
+        &self,
+        _container: &ResolvedContainer,
+    ) -> Result<BTreeMap<String, ContentHash>, ExecutionError> {
+        Ok(BTreeMap::new())
+    }
+}
+
+/// The name of this host, if it can be found.
+pub(crate) fn local_host() -> Option<String> {
+    let name = match fs::read_to_string("/proc/sys/kernel/hostname") {
+        Ok(name) => name,
+        Err(_) => {
+            let output = Command::new("hostname").output().ok()?;
+            String::from_utf8(output.stdout).ok()?
+        }
+    };
+    Some(name.trim().to_string()).filter(|name| !name.is_empty())
+}
+
+/// Copy a file, or a directory tree.
+pub(crate) fn copy(from: &Path, to: &Path) -> Result<(), ExecutionError> {
+    if !from.is_dir() {
+        fs::copy(from, to).map_err(ExecutionError::io(from))?;
+        return Ok(());
+    }
+    fs::create_dir_all(to).map_err(ExecutionError::io(to))?;
+    for entry in fs::read_dir(from).map_err(ExecutionError::io(from))? {
+        let entry = entry.map_err(ExecutionError::io(from))?;
+        copy(&entry.path(), &to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+// EOF
@@ -0,0 +1,67 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::{ExecutionError, Executor, Job, JobOutcome};
+use std::fs::File;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// Runs jobs as plain processes on the local machine.
+///
+/// The container of a step is treated as metadata only: its image, environment and
+/// entrypoint are ignored and the command runs with the tools installed on the host. This is
+/// meant for development, tests and continuous integration, not for reproducible runs.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rivulet::executor::LocalExecutor;
+///
+/// let mut executor = LocalExecutor::new();
+/// executor.shell("/bin/bash");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalExecutor {
+    shell: PathBuf,
+}
+
+impl LocalExecutor {
+    /// Create an executor running commands with `/bin/sh`.
+    pub fn new() -> Self {
+        Self {
+            shell: PathBuf::from("/bin/sh"),
+        }
+    }
+
+    /// Set the shell commands are run with, as `<shell> -c <command>`.
+    pub fn shell(&mut self, shell: impl Into<PathBuf>) -> &mut Self {
+        self.shell = shell.into();
+        self
+    }
+}
+
+impl Default for LocalExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Executor for LocalExecutor {
+    fn execute(&self, job: &Job) -> Result<JobOutcome, ExecutionError> {
+        let stdout = File::create(&job.stdout).map_err(ExecutionError::io(&job.stdout))?;
+        let stderr = File::create(&job.stderr).map_err(ExecutionError::io(&job.stderr))?;
+        let status = Command::new(&self.shell)
+            .arg("-c")
+            .arg(&job.command)
+            .current_dir(&job.workdir)
+            .stdin(Stdio::null())
+            .stdout(stdout)
+            .stderr(stderr)
+            .status()
+            .map_err(ExecutionError::io(&self.shell))?;
+        Ok(JobOutcome::from_status(status))
+    }
+}
+
+// EOF
@@ -0,0 +1,267 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::scheduler::{capture, script_command, whole_seconds};
+use super::{ContainerExecutor, ExecutionError, Job, JobOutcome, JobStatus, Scheduler};
+use std::fmt::Write;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Duration;
+
+/// Submits jobs to an IBM Spectrum LSF cluster with `bsub`.
+///
+/// Every job is written to a job script, `job.lsf` in the job directory, with directives for
+/// the job's [resources](crate::workflow::Resources) and the configured queue, project and
+/// extra options. The script is passed to `bsub` on standard input, so its directives are
+/// read. Jobs are polled with `bjobs` and cancelled with `bkill`.
+///
+/// Memory is requested with explicit units, which needs LSF 9.1.1 or later; the `-o` output
+/// format of `bjobs` needs LSF 9.1.
+///
+/// # Examples
+///
+/// ```
+/// use rivulet::executor::LsfExecutor;
+///
+/// let mut lsf = LsfExecutor::new();
+/// lsf.queue("normal").project("genomics").option("-R \"select[avx2]\"");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LsfExecutor {
+    bsub: PathBuf,
+    bjobs: PathBuf,
+    bkill: PathBuf,
+    queue: Option<String>,
+    project: Option<String>,
+    options: Vec<String>,
+    container: Option<ContainerExecutor>,
+    poll_interval: Duration,
+}
+
+impl LsfExecutor {
+    /// Create an executor running the LSF commands from the `PATH` and polling every ten
+    /// seconds.
+    pub fn new() -> Self {
+        Self {
+            bsub: PathBuf::from("bsub"),
+            bjobs: PathBuf::from("bjobs"),
+            bkill: PathBuf::from("bkill"),
+            queue: None,
+            project: None,
+            options: Vec::new(),
+            container: None,
+            poll_interval: Duration::from_secs(10),
+        }
+    }
+
+    /// Set the path of `bsub`.
+    pub fn bsub(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.bsub = path.into();
+        self
+    }
+
+    /// Set the path of `bjobs`.
+    pub fn bjobs(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.bjobs = path.into();
+        self
+    }
+
+    /// Set the path of `bkill`.
+    pub fn bkill(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.bkill = path.into();
+        self
+    }
+
+    /// Set the queue jobs are submitted to.
+    pub fn queue(&mut self, queue: impl Into<String>) -> &mut Self {
+        self.queue = Some(queue.into());
+        self
+    }
+
+    /// Set the project jobs are charged to.
+    pub fn project(&mut self, project: impl Into<String>) -> &mut Self {
+        self.project = Some(project.into());
+        self
+    }
+
+    /// Add a `#BSUB` option to every script, such as `-R "select[avx2]"` or `-sla gold`.
+    pub fn option(&mut self, option: impl Into<String>) -> &mut Self {
+        self.options.push(option.into());
+        self
+    }
+
+    /// Run commands in containers, invoking the runtime on the compute node.
+    ///
+    /// Images that need building are built before the job is submitted, so the image
+    /// directory of the container executor should be on a shared filesystem.
+    pub fn container(&mut self, executor: ContainerExecutor) -> &mut Self {
+        self.container = Some(executor);
+        self
+    }
+
+    /// Set how long to wait between polls of a running job.
+    pub fn poll_interval(&mut self, interval: Duration) -> &mut Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Render the job script for a job.
+    pub fn script(&self, job: &Job) -> Result<String, ExecutionError> {
+        let mut script = String::from("#!/bin/sh\n");
+        let mut directive = |option: String| writeln!(script, "#BSUB {option}").unwrap();
+        directive(format!("-J {}", job.name));
+        directive(format!("-cwd {}", job.workdir.display()));
+        directive(format!("-oo {}", job.stdout.display()));
+        directive(format!("-eo {}", job.stderr.display()));
+        let resources = &job.resources;
+        if let Some(cpus) = resources.cpus {
+            directive(format!("-n {cpus}"));
+            directive("-R \"span[hosts=1]\"".to_string());
+        }
+        if let Some(memory) = resources.memory {
+            let megabytes = memory.div_ceil(1 << 20);
+            directive(format!("-M {megabytes}MB"));
+            directive(format!("-R \"rusage[mem={megabytes}MB]\""));
+        }
+        if let Some(time) = resources.time {
+            let minutes = whole_seconds(time).div_ceil(60);
+            directive(format!("-W {}:{:02}", minutes / 60, minutes % 60));
+        }
+        if let Some(gpus) = resources.gpus {
+            directive(format!("-gpu \"num={gpus}\""));
+        }
+        if let Some(queue) = &self.queue {
+            directive(format!("-q {queue}"));
+        }
+        if let Some(project) = &self.project {
+            directive(format!("-P {project}"));
+        }
+        for option in &self.options {
+            directive(option.clone());
+        }
+
+        script.push('\n');
+        script.push_str(&script_command(self.container.as_ref(), job)?);
+        script.push('\n');
+        Ok(script)
+    }
+}
+
+impl Default for LsfExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scheduler for LsfExecutor {
+    /// Write the job script of a job to its directory and submit it.
+    fn submit(&self, job: &Job) -> Result<String, ExecutionError> {
+        let path = job.dir.join("job.lsf");
+        fs::write(&path, self.script(job)?).map_err(ExecutionError::io(&path))?;
+        let script = File::open(&path).map_err(ExecutionError::io(&path))?;
+        let output = capture(Command::new(&self.bsub).stdin(script))?;
+        // bsub answers `Job <1234> is submitted to queue <normal>.`
+        let id = output
+            .split_once('<')
+            .and_then(|(_, rest)| rest.split_once('>'))
+            .map(|(id, _)| id)
+            .filter(|id| !id.is_empty() && id.bytes().all(|byte| byte.is_ascii_digit()));
+        match id {
+            Some(id) => Ok(id.to_string()),
+            None => Err(ExecutionError::Scheduler {
+                program: self.bsub.clone(),
+                message: format!("unexpected output {:?}", output.trim()),
+            }),
+        }
+    }
+
+    /// Look up the state and exit code of a job with `bjobs`.
+    fn poll(&self, id: &str) -> Result<JobStatus, ExecutionError> {
+        let output = capture(Command::new(&self.bjobs).args([
+            "-noheader",
+            "-o",
+            "stat exit_code delimiter='|'",
+            id,
+        ]))?;
+        let line = output.trim();
+        let (state, exit_code) = line.split_once('|').unwrap_or((line, "-"));
+        Ok(match state {
+            "PEND" | "PSUSP" => JobStatus::Queued,
+            "DONE" => JobStatus::Finished(JobOutcome::exited(0)),
+            "EXIT" => JobStatus::Finished(match exit_code.trim().parse() {
+                Ok(code) if code != 0 => JobOutcome::exited(code),
+                // Killed before the command ran, or by LSF without an exit code
+                _ => JobOutcome {
+                    exit_code: None,
+                    reason: Some("EXIT".to_string()),
+                },
+            }),
+            "ZOMBI" => JobStatus::Finished(JobOutcome {
+                exit_code: None,
+                reason: Some("ZOMBI".to_string()),
+            }),
+            "" => {
+                return Err(ExecutionError::Scheduler {
+                    program: self.bjobs.clone(),
+                    message: format!("no state reported for job {id}"),
+                });
+            }
+            _ => JobStatus::Running,
+        })
+    }
+
+    fn cancel(&self, id: &str) -> Result<(), ExecutionError> {
+        capture(Command::new(&self.bkill).arg(id)).map(drop)
+    }
+
+    fn polling_interval(&self) -> Duration {
+        self.poll_interval
+    }
+
+    fn container_executor(&self) -> Option<&ContainerExecutor> {
+        self.container.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::Container;
+    use crate::workflow::Resources;
+
+    #[test]
+    fn test_script() {
+        let container = Container::from("biocontainers/bwa:0.7.17");
+        let command = "bwa mem -t 8 ref.fa reads.fq > aligned.sam";
+        let mut job = Job::example("align", &container, command, "/work/run/steps/align");
+        job.resources = Resources {
+            cpus: Some(8),
+            memory: Some(1536 << 20),
+            time: Some(Duration::from_secs(5400)),
+            gpus: None,
+        };
+        let mut lsf = LsfExecutor::new();
+        lsf.queue("long").project("lab");
+        assert_eq!(
+            lsf.script(&job).unwrap(),
+            "#!/bin/sh\n\
+             #BSUB -J align\n\
+             #BSUB -cwd /work/run/steps/align/work\n\
+             #BSUB -oo /work/run/steps/align/stdout\n\
+             #BSUB -eo /work/run/steps/align/stderr\n\
+             #BSUB -n 8\n\
+             #BSUB -R \"span[hosts=1]\"\n\
+             #BSUB -M 1536MB\n\
+             #BSUB -R \"rusage[mem=1536MB]\"\n\
+             #BSUB -W 1:30\n\
+             #BSUB -q long\n\
+             #BSUB -P lab\n\
+             \n\
+             bwa mem -t 8 ref.fa reads.fq > aligned.sam\n"
+        );
+    }
+}
+
+// EOF
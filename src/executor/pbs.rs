@@ -0,0 +1,339 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::scheduler::{capture, script_command, whole_seconds};
+use super::{ContainerExecutor, ExecutionError, Job, JobOutcome, JobStatus, Scheduler};
+use crate::shell;
+use crate::workflow::Resources;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Duration;
+
+/// The PBS implementation a cluster runs, which decides how resources are requested.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PbsFlavor {
+    /// PBS Professional or OpenPBS, requesting resources with `-l select=...`.
+    #[default]
+    Pro,
+
+    /// Torque, requesting resources with `-l nodes=1:ppn=...`.
+    Torque,
+}
+
+/// Submits jobs to a PBS Professional, OpenPBS or Torque cluster with `qsub`.
+///
+/// Every job is written to a job script, `job.pbs` in the job directory, with directives for
+/// the job's [resources](crate::workflow::Resources) and the configured queue, account and
+/// extra options. Jobs are polled with `qstat -f`, which PBS Professional is asked to keep
+/// answering for finished jobs with `-x`, and cancelled with `qdel`.
+///
+/// A job's exit status is its command's exit code. PBS reports a command killed by a signal
+/// with 256 plus the signal number, and its own failures, such as exceeding the walltime on
+/// PBS Professional, with negative statuses; both are turned into a
+/// [reason](JobOutcome::reason).
+///
+/// # Examples
+///
+/// ```
+/// use rivulet::executor::{PbsExecutor, PbsFlavor};
+///
+/// let mut pbs = PbsExecutor::new(PbsFlavor::Torque);
+/// pbs.queue("batch").account("genomics").option("-m n");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PbsExecutor {
+    flavor: PbsFlavor,
+    qsub: PathBuf,
+    qstat: PathBuf,
+    qdel: PathBuf,
+    queue: Option<String>,
+    account: Option<String>,
+    options: Vec<String>,
+    container: Option<ContainerExecutor>,
+    poll_interval: Duration,
+}
+
+impl PbsExecutor {
+    /// Create an executor running the PBS commands from the `PATH` and polling every ten
+    /// seconds.
+    pub fn new(flavor: PbsFlavor) -> Self {
+        Self {
+            flavor,
+            qsub: PathBuf::from("qsub"),
+            qstat: PathBuf::from("qstat"),
+            qdel: PathBuf::from("qdel"),
+            queue: None,
+            account: None,
+            options: Vec::new(),
+            container: None,
+            poll_interval: Duration::from_secs(10),
+        }
+    }
+
+    /// Set the path of `qsub`.
+    pub fn qsub(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.qsub = path.into();
+        self
+    }
+
+    /// Set the path of `qstat`.
+    pub fn qstat(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.qstat = path.into();
+        self
+    }
+
+    /// Set the path of `qdel`.
+    pub fn qdel(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.qdel = path.into();
+        self
+    }
+
+    /// Set the queue jobs are submitted to.
+    pub fn queue(&mut self, queue: impl Into<String>) -> &mut Self {
+        self.queue = Some(queue.into());
+        self
+    }
+
+    /// Set the account jobs are charged to.
+    pub fn account(&mut self, account: impl Into<String>) -> &mut Self {
+        self.account = Some(account.into());
+        self
+    }
+
+    /// Add a `#PBS` option to every script, such as `-m n` or `-l place=scatter`.
+    pub fn option(&mut self, option: impl Into<String>) -> &mut Self {
+        self.options.push(option.into());
+        self
+    }
+
+    /// Run commands in containers, invoking the runtime on the compute node.
+    ///
+    /// Images that need building are built before the job is submitted, so the image
+    /// directory of the container executor should be on a shared filesystem.
+    pub fn container(&mut self, executor: ContainerExecutor) -> &mut Self {
+        self.container = Some(executor);
+        self
+    }
+
+    /// Set how long to wait between polls of a running job.
+    pub fn poll_interval(&mut self, interval: Duration) -> &mut Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// The resource requests of a job, one per `-l` directive, in the syntax of the flavor.
+    fn resource_directives(&self, resources: &Resources) -> Vec<String> {
+        let mut requests = Vec::new();
+        let memory = resources
+            .memory
+            .map(|bytes| format!("mem={}mb", bytes.div_ceil(1 << 20)));
+        match self.flavor {
+            PbsFlavor::Pro => {
+                let mut select = String::from("select=1");
+                if let Some(cpus) = resources.cpus {
+                    write!(select, ":ncpus={cpus}").unwrap();
+                }
+                if let Some(memory) = &memory {
+                    write!(select, ":{memory}").unwrap();
+                }
+                if let Some(gpus) = resources.gpus {
+                    write!(select, ":ngpus={gpus}").unwrap();
+                }
+                if select != "select=1" {
+                    requests.push(select);
+                }
+            }
+            PbsFlavor::Torque => {
+                if resources.cpus.is_some() || resources.gpus.is_some() {
+                    let mut nodes = format!("nodes=1:ppn={}", resources.cpus.unwrap_or(1));
+                    if let Some(gpus) = resources.gpus {
+                        write!(nodes, ":gpus={gpus}").unwrap();
+                    }
+                    requests.push(nodes);
+                }
+                requests.extend(memory);
+            }
+        }
+        if let Some(time) = resources.time {
+            let seconds = whole_seconds(time);
+            requests.push(format!(
+                "walltime={:02}:{:02}:{:02}",
+                seconds / 3600,
+                seconds % 3600 / 60,
+                seconds % 60
+            ));
+        }
+        requests
+    }
+
+    /// Render the job script for a job.
+    pub fn script(&self, job: &Job) -> Result<String, ExecutionError> {
+        let mut script = String::from("#!/bin/sh\n");
+        let mut directive = |option: String| writeln!(script, "#PBS {option}").unwrap();
+        directive(format!("-N {}", job.name));
+        directive(format!("-o {}", job.stdout.display()));
+        directive(format!("-e {}", job.stderr.display()));
+
+        for resource in self.resource_directives(&job.resources) {
+            directive(format!("-l {resource}"));
+        }
+        if let Some(queue) = &self.queue {
+            directive(format!("-q {queue}"));
+        }
+        if let Some(account) = &self.account {
+            directive(format!("-A {account}"));
+        }
+        for option in &self.options {
+            directive(option.clone());
+        }
+
+        // PBS starts jobs in the home directory
+        let workdir = shell::quote(&job.workdir.to_string_lossy());
+        writeln!(script, "\ncd {workdir} || exit 1").unwrap();
+        script.push_str(&script_command(self.container.as_ref(), job)?);
+        script.push('\n');
+        Ok(script)
+    }
+}
+
+impl Scheduler for PbsExecutor {
+    /// Write the job script of a job to its directory and submit it.
+    fn submit(&self, job: &Job) -> Result<String, ExecutionError> {
+        let path = job.dir.join("job.pbs");
+        fs::write(&path, self.script(job)?).map_err(ExecutionError::io(&path))?;
+        let output = capture(Command::new(&self.qsub).arg(&path))?;
+        // The id includes the server name, as in `1234.pbs01`
+        let id = output.trim();
+        if id.is_empty() || id.contains(char::is_whitespace) {
+            return Err(ExecutionError::Scheduler {
+                program: self.qsub.clone(),
+                message: format!("unexpected output {id:?}"),
+            });
+        }
+        Ok(id.to_string())
+    }
+
+    /// Look up the state of a job with `qstat -f`.
+    fn poll(&self, id: &str) -> Result<JobStatus, ExecutionError> {
+        let mut qstat = Command::new(&self.qstat);
+        if self.flavor == PbsFlavor::Pro {
+            qstat.arg("-x");
+        }
+        let output = capture(qstat.arg("-f").arg(id))?;
+        let attribute = |name: &str| {
+            output.lines().find_map(|line| {
+                let (key, value) = line.split_once('=')?;
+                key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+            })
+        };
+        let Some(state) = attribute("job_state") else {
+            return Err(ExecutionError::Scheduler {
+                program: self.qstat.clone(),
+                message: format!("no state reported for job {id}"),
+            });
+        };
+        Ok(match state {
+            "Q" | "H" | "W" | "T" => JobStatus::Queued,
+            // Finished on PBS Professional, completed on Torque, expired array subjob
+            "F" | "C" | "X" => JobStatus::Finished(outcome(
+                attribute("exit_status").and_then(|s| s.parse().ok()),
+            )),
+            _ => JobStatus::Running,
+        })
+    }
+
+    fn cancel(&self, id: &str) -> Result<(), ExecutionError> {
+        capture(Command::new(&self.qdel).arg(id)).map(drop)
+    }
+
+    fn polling_interval(&self) -> Duration {
+        self.poll_interval
+    }
+
+    fn container_executor(&self) -> Option<&ContainerExecutor> {
+        self.container.as_ref()
+    }
+}
+
+/// The outcome of a finished job from its PBS exit status.
+fn outcome(exit_status: Option<i32>) -> JobOutcome {
+    let reason = match exit_status {
+        Some(code @ 0..256) => return JobOutcome::exited(code),
+        Some(code @ 256..) => format!("signal {}", code - 256),
+        Some(-27) => "memory limit exceeded".to_string(),
+        Some(-29) => "walltime exceeded".to_string(),
+        Some(code) => format!("PBS exit status {code}"),
+        None => "no exit status".to_string(),
+    };
+    JobOutcome {
+        exit_code: None,
+        reason: Some(reason),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::Container;
+    use crate::workflow::Resources;
+
+    fn job(resources: Resources) -> Job {
+        let container = Container::from("rocker/r-ver:4.4.1");
+        let command = "Rscript deseq2.R counts.tsv";
+        let mut job = Job::example("de", &container, command, "/home/lab/run/steps/de");
+        job.resources = resources;
+        job
+    }
+
+    const RESOURCES: Resources = Resources {
+        cpus: Some(4),
+        memory: Some(8 << 30),
+        time: Some(Duration::from_secs(26 * 3600)),
+        gpus: Some(2),
+    };
+
+    #[test]
+    fn test_pro_script() {
+        let mut pbs = PbsExecutor::new(PbsFlavor::Pro);
+        pbs.queue("workq").account("lab");
+        assert_eq!(
+            pbs.script(&job(RESOURCES)).unwrap(),
+            "#!/bin/sh\n\
+             #PBS -N de\n\
+             #PBS -o /home/lab/run/steps/de/stdout\n\
+             #PBS -e /home/lab/run/steps/de/stderr\n\
+             #PBS -l select=1:ncpus=4:mem=8192mb:ngpus=2\n\
+             #PBS -l walltime=26:00:00\n\
+             #PBS -q workq\n\
+             #PBS -A lab\n\
+             \n\
+             cd /home/lab/run/steps/de/work || exit 1\n\
+             Rscript deseq2.R counts.tsv\n"
+        );
+    }
+
+    #[test]
+    fn test_torque_script() {
+        let pbs = PbsExecutor::new(PbsFlavor::Torque);
+        let script = pbs.script(&job(RESOURCES)).unwrap();
+        assert!(script.contains("#PBS -l nodes=1:ppn=4:gpus=2\n#PBS -l mem=8192mb\n"));
+
+        let script = pbs.script(&job(Resources::default())).unwrap();
+        assert!(!script.contains("-l "), "{script}");
+    }
+
+    #[test]
+    fn test_outcome() {
+        assert_eq!(outcome(Some(0)), JobOutcome::exited(0));
+        assert_eq!(outcome(Some(1)), JobOutcome::exited(1));
+        assert_eq!(outcome(Some(271)).to_string(), "signal 15");
+        assert_eq!(outcome(Some(-29)).to_string(), "walltime exceeded");
+        assert_eq!(outcome(Some(-3)).to_string(), "PBS exit status -3");
+        assert_eq!(outcome(None).to_string(), "no exit status");
+    }
+}
+
+// EOF
@@ -0,0 +1,640 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::{ExecutionError, Executor, Job, JobOutcome, copy};
+use crate::container::{
+    ApptainerBuild, BuildStep, Containerfile, ImageDigest, ImageSelector, ResolvedContainer,
+};
+use crate::hash::{ContentHash, ContentHasher};
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+
+/// Domain of image build keys; bump the version whenever the encoding changes.
+const BUILD_DOMAIN: &str = "rivulet.image-build.v1";
+
+/// A container runtime with a command-line interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Runtime {
+    /// Docker, using `docker run`.
+    Docker,
+
+    /// Podman, using `podman run`.
+    Podman,
+
+    /// Apptainer (formerly Singularity), using `apptainer exec`. Common on HPC clusters, where
+    /// it runs without a daemon and as the calling user.
+    Apptainer,
+}
+
+impl Runtime {
+    /// The name of the runtime's command-line tool.
+    pub fn program(self) -> &'static str {
+        match self {
+            Runtime::Docker => "docker",
+            Runtime::Podman => "podman",
+            Runtime::Apptainer => "apptainer",
+        }
+    }
+
+    /// Render an image reference in the syntax the runtime expects.
+    ///
+    /// References are normalized, so Podman does not depend on its short-name configuration.
+    /// Apptainer pulls registry images through the `docker://` transport.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rivulet::container::ImageSelector;
+    /// use rivulet::executor::Runtime;
+    ///
+    /// let image: ImageSelector = "ubuntu:24.04".parse().unwrap();
+    /// assert_eq!(Runtime::Podman.image_reference(&image), "docker.io/library/ubuntu:24.04");
+    /// assert_eq!(
+    ///     Runtime::Apptainer.image_reference(&image),
+    ///     "docker://docker.io/library/ubuntu:24.04"
+    /// );
+    /// ```
+    pub fn image_reference(self, image: &ImageSelector) -> String {
+        match self {
+            Runtime::Docker | Runtime::Podman => image.normalize().to_string(),
+            Runtime::Apptainer => format!("docker://{}", image.normalize()),
+        }
+    }
+}
+
+/// The user a container's command runs as.
+///
+/// Apptainer always runs commands as the calling user, so this only applies to Docker and
+/// Podman.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UserMapping {
+    /// Run as the user running the workflow, so the files a step writes are owned by them.
+    #[default]
+    Host,
+
+    /// Run as the user the image is configured with, often `root`.
+    Image,
+
+    /// Run as a numeric user and group.
+    Id {
+        /// The user id.
+        uid: u32,
+        /// The group id.
+        gid: u32,
+    },
+}
+
+/// Runs jobs in containers with Docker, Podman or Apptainer.
+///
+/// The job directory is bind mounted at the same path inside the container, and so are the
+/// [mounts](Job::mounts) of the job, read-only, so the paths in the command work unchanged.
+/// The command runs with `/bin/sh -c` in the job's working directory, with the environment
+/// variables of the container chain set. The image's own entrypoint is bypassed.
+///
+/// Containers that only set configuration run directly from their root image. Containers
+/// with `RUN` or `COPY` steps are built first, once per [content
+/// hash](crate::container::ResolvedContainer::content_hash) and contents of the `COPY`
+/// sources: Docker and Podman tag the image as `localhost/rivulet:<hash>`, and Apptainer
+/// builds `<hash>/image.sif` in the image directory. The sources of `COPY` steps are copied
+/// from the [build context](ContainerExecutor::context) to `<hash>/context`, which the image
+/// is built from, so editing a source builds a new image.
+/// The build output is written to `build.log` next to the build files.
+///
+/// # Examples
+///
+/// ```
+/// use rivulet::executor::{ContainerExecutor, Runtime, UserMapping};
+///
+/// let mut executor = ContainerExecutor::new(Runtime::Apptainer);
+/// executor
+///     .program("/opt/apptainer/bin/apptainer")
+///     .image_dir("/scratch/images")
+///     .context("/home/alice/analysis")
+///     .arg("--nv");
+///
+/// let mut executor = ContainerExecutor::new(Runtime::Podman);
+/// executor.user(UserMapping::Image);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerExecutor {
+    runtime: Runtime,
+    program: PathBuf,
+    user: UserMapping,
+    image_dir: Option<PathBuf>,
+    context: Option<PathBuf>,
+    args: Vec<String>,
+}
+
+impl ContainerExecutor {
+    /// Create an executor for a runtime, running its tool from the `PATH`.
+    pub fn new(runtime: Runtime) -> Self {
+        Self {
+            runtime,
+            program: PathBuf::from(runtime.program()),
+            user: UserMapping::default(),
+            image_dir: None,
+            context: None,
+            args: Vec::new(),
+        }
+    }
+
+    /// Set the path of the runtime's command-line tool.
+    pub fn program(&mut self, program: impl Into<PathBuf>) -> &mut Self {
+        self.program = program.into();
+        self
+    }
+
+    /// Set the user commands run as.
+    pub fn user(&mut self, user: UserMapping) -> &mut Self {
+        self.user = user;
+        self
+    }
+
+    /// Set the directory built images are kept in.
+    ///
+    /// Without one, images are built in the `image` directory of each job. Docker and Podman
+    /// keep built images themselves, but Apptainer images would be rebuilt for every job.
+    pub fn image_dir(&mut self, dir: impl Into<PathBuf>) -> &mut Self {
+        self.image_dir = Some(dir.into());
+        self
+    }
+
+    /// Set the build context, the directory the sources of `COPY` steps are relative to.
+    ///
+    /// Without one, sources are relative to the current directory.
+    pub fn context(&mut self, dir: impl Into<PathBuf>) -> &mut Self {
+        self.context = Some(dir.into());
+        self
+    }
+
+    /// Add an argument passed to `run` or `exec` before the image, such as `--gpus=all` for
+    /// Docker or `--nv` for Apptainer.
+    pub fn arg(&mut self, arg: impl Into<String>) -> &mut Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// The runtime this executor uses.
+    pub fn runtime(&self) -> Runtime {
+        self.runtime
+    }
+
+    /// The command line running a job, starting with the program, building the image first
+    /// if needed.
+    pub(crate) fn command_line(&self, job: &Job) -> Result<Vec<OsString>, ExecutionError> {
+        let image = self.image(job)?;
+        let mut line = vec![self.program.clone().into_os_string()];
+        line.extend(self.run_args(job, &image)?);
+        Ok(line)
+    }
+
+    /// The arguments running a job in an image, after the program name.
+    fn run_args(&self, job: &Job, image: &str) -> Result<Vec<OsString>, ExecutionError> {
+        let mut args: Vec<OsString> = Vec::new();
+        let (bind, workdir) = match self.runtime {
+            Runtime::Docker | Runtime::Podman => {
+                args.extend(["run", "--rm"].map(OsString::from));
+                ("--volume", "--workdir")
+            }
+            Runtime::Apptainer => {
+                args.extend(["exec", "--cleanenv"].map(OsString::from));
+                ("--bind", "--pwd")
+            }
+        };
+        args.push(bind.into());
+        args.push(bind_spec(&job.dir, false));
+        for mount in &job.mounts {
+            args.push(bind.into());
+            args.push(bind_spec(mount, true));
+        }
+        args.push(workdir.into());
+        args.push(job.workdir.clone().into());
+        for (key, value) in job.container.env() {
+            args.push("--env".into());
+            args.push(format!("{key}={value}").into());
+        }
+        if self.runtime != Runtime::Apptainer
+            && let Some(user) = self.user_spec(job)?
+        {
+            args.push("--user".into());
+            args.push(user.into());
+        }
+        args.extend(self.args.iter().map(OsString::from));
+        match self.runtime {
+            Runtime::Docker | Runtime::Podman => {
+                args.extend(["--entrypoint", "/bin/sh", image, "-c"].map(OsString::from))
+            }
+            Runtime::Apptainer => args.extend([image, "/bin/sh", "-c"].map(OsString::from)),
+        }
+        args.push(job.command.clone().into());
+        Ok(args)
+    }
+
+    /// The `--user` argument for a job, if any.
+    fn user_spec(&self, job: &Job) -> Result<Option<String>, ExecutionError> {
+        match self.user {
+            UserMapping::Image => Ok(None),
+            UserMapping::Id { uid, gid } => Ok(Some(format!("{uid}:{gid}"))),
+            #[cfg(unix)]
+            UserMapping::Host => {
+                // The runner creates the job directory, so it is owned by the current user
+                use std::os::unix::fs::MetadataExt;
+                let metadata = fs::metadata(&job.dir).map_err(ExecutionError::io(&job.dir))?;
+                Ok(Some(format!("{}:{}", metadata.uid(), metadata.gid())))
+            }
+            #[cfg(not(unix))]
+            UserMapping::Host => Ok(None),
+        }
+    }
+
+    /// The image to run a job in, building it first if needed.
+    fn image(&self, job: &Job) -> Result<String, ExecutionError> {
+        let container = &job.container;
+        if !container.needs_build() {
+            return Ok(self.runtime.image_reference(container.image()));
+        }
+        let hash = self.build_key(container)?;
+        let dir = self
+            .image_dir
+            .clone()
+            .unwrap_or_else(|| job.dir.join("image"))
+            .join(hash.to_string());
+        fs::create_dir_all(&dir).map_err(ExecutionError::io(&dir))?;
+        match self.runtime {
+            Runtime::Docker | Runtime::Podman => self.build_tagged(container, hash, &dir),
+            Runtime::Apptainer => self.build_sif(container, &dir),
+        }
+    }
+
+    /// The key an image is built and cached under.
+    ///
+    /// A container's content hash names its `COPY` sources but not what they contain, so the
+    /// key of a container with `COPY` steps also covers the hashes of its sources.
+    fn build_key(&self, container: &ResolvedContainer) -> Result<ContentHash, ExecutionError> {
+        let sources = self.hash_sources(container)?;
+        if sources.is_empty() {
+            return Ok(container.content_hash());
+        }
+        let mut hasher = ContentHasher::new(BUILD_DOMAIN);
+        hasher
+            .bytes(container.content_hash().as_bytes())
+            .u64(sources.len() as u64);
+        for (source, hash) in &sources {
+            hasher.str(source).bytes(hash.as_bytes());
+        }
+        Ok(hasher.finish())
+    }
+
+    /// Hash the sources of the container's `COPY` steps in the build context, by the source
+    /// as written in the step.
+    fn hash_sources(
+        &self,
+        container: &ResolvedContainer,
+    ) -> Result<BTreeMap<String, ContentHash>, ExecutionError> {
+        let context = self.context.as_deref().unwrap_or(Path::new("."));
+        let mut hashes = BTreeMap::new();
+        for source in sources(container) {
+            let path = context.join(context_path(source)?);
+            let hash = if path.is_dir() {
+                ContentHash::of_directory(&path)
+            } else {
+                ContentHash::of_file(&path)
+            };
+            hashes.insert(source.clone(), hash.map_err(ExecutionError::io(&path))?);
+        }
+        Ok(hashes)
+    }
+
+    /// Build a Docker or Podman image tagged with its build key, unless the runtime already
+    /// has it, returning the tag.
+    fn build_tagged(
+        &self,
+        container: &ResolvedContainer,
+        hash: ContentHash,
+        dir: &Path,
+    ) -> Result<String, ExecutionError> {
+        let tag = format!("localhost/rivulet:{hash}");
+        let inspect = ["image", "inspect", tag.as_str()].map(OsString::from);
+        if self.status(&inspect, None, dir)?.success() {
+            return Ok(tag);
+        }
+        let context = self.stage_sources(container, dir)?;
+        let file = dir.join("Containerfile");
+        let contents = Containerfile::from_resolved(container).to_string();
+        fs::write(&file, contents).map_err(ExecutionError::io(&file))?;
+        let build = [
+            OsString::from("build"),
+            "--tag".into(),
+            tag.clone().into(),
+            "--file".into(),
+            file.into(),
+            context.into(),
+        ];
+        self.build(&build, dir, &tag)?;
+        Ok(tag)
+    }
+
+    /// Build an Apptainer image in the build directory, unless it is already there,
+    /// returning its path.
+    fn build_sif(
+        &self,
+        container: &ResolvedContainer,
+        dir: &Path,
+    ) -> Result<String, ExecutionError> {
+        let image = dir.join("image.sif");
+        if image.is_file() {
+            return Ok(image.display().to_string());
+        }
+        self.stage_sources(container, dir)?;
+        let definition = dir.join("image.def");
+        let contents = ApptainerBuild::from_resolved(container)
+            .target()
+            .to_string();
+        fs::write(&definition, contents).map_err(ExecutionError::io(&definition))?;
+        // Build under a temporary name so an interrupted build is not mistaken for a
+        // finished image
+        let partial = dir.join("image.partial.sif");
+        let build = [
+            OsString::from("build"),
+            "--force".into(),
+            partial.clone().into(),
+            definition.into(),
+        ];
+        self.build(&build, dir, &image.display().to_string())?;
+        fs::rename(&partial, &image).map_err(ExecutionError::io(&image))?;
+        Ok(image.display().to_string())
+    }
+
+    /// Copy the sources of the container's `COPY` steps from the build context to the
+    /// `context` directory in the build directory, returning its path.
+    fn stage_sources(
+        &self,
+        container: &ResolvedContainer,
+        dir: &Path,
+    ) -> Result<PathBuf, ExecutionError> {
+        let staged = dir.join("context");
+        fs::create_dir_all(&staged).map_err(ExecutionError::io(&staged))?;
+        let context = self.context.as_deref().unwrap_or(Path::new("."));
+        for source in sources(container) {
+            let path = context_path(source)?;
+            let to = staged.join(&path);
+            if let Some(parent) = to.parent() {
+                fs::create_dir_all(parent).map_err(ExecutionError::io(parent))?;
+            }
+            copy(&context.join(&path), &to)?;
+        }
+        Ok(staged)
+    }
+
+    /// Run a build command in the staged build context, logging its output to `build.log`.
+    fn build(&self, args: &[OsString], dir: &Path, image: &str) -> Result<(), ExecutionError> {
+        let log = dir.join("build.log");
+        let status = self.status(args, Some(&log), &dir.join("context"))?;
+        if !status.success() {
+            return Err(ExecutionError::ImageBuild {
+                image: image.to_string(),
+                exit_code: status.code(),
+                log,
+            });
+        }
+        Ok(())
+    }
+
+    /// Run the runtime's tool, sending its output to a log file or discarding it.
+    fn status(
+        &self,
+        args: &[OsString],
+        log: Option<&Path>,
+        dir: &Path,
+    ) -> Result<ExitStatus, ExecutionError> {
+        let (stdout, stderr) = match log {
+            Some(log) => {
+                let file = File::create(log).map_err(ExecutionError::io(log))?;
+                let copy = file.try_clone().map_err(ExecutionError::io(log))?;
+                (Stdio::from(file), Stdio::from(copy))
+            }
+            None => (Stdio::null(), Stdio::null()),
+        };
+        Command::new(&self.program)
+            .args(args)
+            .current_dir(dir)
+            .stdin(Stdio::null())
+            .stdout(stdout)
+            .stderr(stderr)
+            .status()
+            .map_err(ExecutionError::io(&self.program))
+    }
+
+    /// The digest the runtime has for an image reference, if it has pulled the image.
+    fn repo_digest(&self, reference: &str) -> Option<ImageDigest> {
+        let output = Command::new(&self.program)
+            .args([
+                "image",
+                "inspect",
+                "--format",
+                "{{index .RepoDigests 0}}",
+                reference,
+            ])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let output = String::from_utf8(output.stdout).ok()?;
+        let (_, digest) = output.trim().rsplit_once('@')?;
+        ImageDigest::parse(digest).ok()
+    }
+}
+
+impl Executor for ContainerExecutor {
+    fn execute(&self, job: &Job) -> Result<JobOutcome, ExecutionError> {
+        let line = self.command_line(job)?;
+        let stdout = File::create(&job.stdout).map_err(ExecutionError::io(&job.stdout))?;
+        let stderr = File::create(&job.stderr).map_err(ExecutionError::io(&job.stderr))?;
+        let status = Command::new(&line[0])
+            .args(&line[1..])
+            .current_dir(&job.workdir)
+            .stdin(Stdio::null())
+            .stdout(stdout)
+            .stderr(stderr)
+            .status()
+            .map_err(ExecutionError::io(&self.program))?;
+        Ok(JobOutcome::from_status(status))
+    }
+
+    fn copy_sources(
+        &self,
+        container: &ResolvedContainer,
+    ) -> Result<BTreeMap<String, ContentHash>, ExecutionError> {
+        self.hash_sources(container)
+    }
+
+    /// Docker and Podman look the digest up, pulling the image if they do not have it yet.
+    /// Apptainer keeps no registry digests, so only images with a digest can be pinned.
+    fn pin_image(&self, image: &ImageSelector) -> Option<ImageSelector> {
+        if image.digest.is_some() {
+            return Some(image.clone());
+        }
+        if self.runtime == Runtime::Apptainer {
+            return None;
+        }
+        let reference = self.runtime.image_reference(image);
+        let digest = self.repo_digest(&reference).or_else(|| {
+            let pulled = Command::new(&self.program)
+                .args(["pull", reference.as_str()])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+            pulled
+                .ok()?
+                .success()
+                .then(|| self.repo_digest(&reference))?
+        })?;
+        Some(ImageSelector {
+            digest: Some(digest),
+            ..image.clone()
+        })
+    }
+}
+
+/// The sources of the `COPY` steps of every layer of a container, in build order.
+fn sources(container: &ResolvedContainer) -> impl Iterator<Item = &String> {
+    container
+        .layers()
+        .iter()
+        .flatten()
+        .flat_map(|step| match step {
+            BuildStep::Copy { sources, .. } => sources.as_slice(),
+            _ => &[],
+        })
+}
+
+/// The path of a `COPY` source within the build context, which it may not leave.
+fn context_path(source: &str) -> Result<PathBuf, ExecutionError> {
+    let mut path = PathBuf::new();
+    for component in Path::new(source).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir | Component::Prefix(_) => {
+                return Err(ExecutionError::Io {
+                    path: source.into(),
+                    source: io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "COPY source outside the build context",
+                    ),
+                });
+            }
+        }
+    }
+    Ok(path)
+}
+
+/// A bind mount of a path to the same path inside the container.
+fn bind_spec(path: &Path, read_only: bool) -> OsString {
+    let mut spec = OsString::from(path);
+    spec.push(":");
+    spec.push(path);
+    if read_only {
+        spec.push(OsStr::new(":ro"));
+    }
+    spec
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::Container;
+
+    fn job(container: &str) -> Job {
+        let container = Container::from(container);
+        container.write().unwrap().env("LANG", "C.UTF-8");
+        let command = "wc -l < 'my reads.txt' > lines";
+        let mut job = Job::example("count", &container, command, "/runs/1/steps/count");
+        job.mounts.push(PathBuf::from("/data"));
+        job
+    }
+
+    fn args(executor: &ContainerExecutor, job: &Job) -> Vec<String> {
+        let image = executor.runtime.image_reference(job.container.image());
+        let args = executor.run_args(job, &image).unwrap();
+        args.iter()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_docker_args() {
+        let mut executor = ContainerExecutor::new(Runtime::Docker);
+        executor
+            .user(UserMapping::Id {
+                uid: 1000,
+                gid: 100,
+            })
+            .arg("--gpus=all");
+        assert_eq!(
+            args(&executor, &job("busybox:1.36")),
+            [
+                "run",
+                "--rm",
+                "--volume",
+                "/runs/1/steps/count:/runs/1/steps/count",
+                "--volume",
+                "/data:/data:ro",
+                "--workdir",
+                "/runs/1/steps/count/work",
+                "--env",
+                "LANG=C.UTF-8",
+                "--user",
+                "1000:100",
+                "--gpus=all",
+                "--entrypoint",
+                "/bin/sh",
+                "docker.io/library/busybox:1.36",
+                "-c",
+                "wc -l < 'my reads.txt' > lines",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_apptainer_args() {
+        let executor = ContainerExecutor::new(Runtime::Apptainer);
+        assert_eq!(
+            args(&executor, &job("busybox:1.36")),
+            [
+                "exec",
+                "--cleanenv",
+                "--bind",
+                "/runs/1/steps/count:/runs/1/steps/count",
+                "--bind",
+                "/data:/data:ro",
+                "--pwd",
+                "/runs/1/steps/count/work",
+                "--env",
+                "LANG=C.UTF-8",
+                "docker://docker.io/library/busybox:1.36",
+                "/bin/sh",
+                "-c",
+                "wc -l < 'my reads.txt' > lines",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_image_user_mapping() {
+        let mut executor = ContainerExecutor::new(Runtime::Podman);
+        executor.user(UserMapping::Image);
+        assert!(!args(&executor, &job("busybox")).contains(&"--user".to_string()));
+    }
+}
+
+// EOF
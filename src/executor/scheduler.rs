@@ -0,0 +1,199 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::{ContainerExecutor, ExecutionError, Executor, Job, JobArray, JobOutcome};
+use crate::container::{ImageSelector, ResolvedContainer};
+use crate::hash::ContentHash;
+use crate::shell;
+use std::collections::BTreeMap;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+/// The state of a job submitted to a scheduler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobStatus {
+    /// Waiting for resources.
+    Queued,
+
+    /// Running, or finishing up.
+    Running,
+
+    /// Finished, successfully or not.
+    Finished(JobOutcome),
+}
+
+/// A batch scheduler that runs jobs asynchronously, such as Slurm, PBS or LSF.
+///
+/// Every scheduler is an [`Executor`]: a job is submitted, polled at the
+/// [polling interval](Self::polling_interval) until it finishes, and cancelled if polling
+/// fails, so no job is left running unobserved. The jobs of a [`JobArray`] are submitted
+/// together and polled together until all of them have finished.
+pub trait Scheduler {
+    /// Submit a job, returning the id the scheduler assigned to it.
+    fn submit(&self, job: &Job) -> Result<String, ExecutionError>;
+
+    /// Look up the state of a submitted job.
+    fn poll(&self, id: &str) -> Result<JobStatus, ExecutionError>;
+
+    /// Cancel a submitted job.
+    fn cancel(&self, id: &str) -> Result<(), ExecutionError>;
+
+    /// Submit all jobs of an array, returning the id of every job in order.
+    ///
+    /// By default the jobs are submitted one by one, and those already submitted are
+    /// cancelled if a submission fails.
+    fn submit_array(&self, array: &JobArray) -> Result<Vec<String>, ExecutionError> {
+        let mut ids = Vec::with_capacity(array.jobs.len());
+        for job in &array.jobs {
+            match self.submit(job) {
+                Ok(id) => ids.push(id),
+                Err(error) => {
+                    cancel_all(self, &ids);
+                    return Err(error);
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Look up the states of several submitted jobs, returned in the order of `ids`.
+    ///
+    /// By default every job is polled on its own.
+    fn poll_all(&self, ids: &[String]) -> Result<Vec<JobStatus>, ExecutionError> {
+        ids.iter().map(|id| self.poll(id)).collect()
+    }
+
+    /// How long to wait between polls of a job.
+    fn polling_interval(&self) -> Duration {
+        Duration::from_secs(10)
+    }
+
+    /// The executor running commands in containers on the compute nodes, if any.
+    fn container_executor(&self) -> Option<&ContainerExecutor> {
+        None
+    }
+}
+
+impl<S: Scheduler> Executor for S {
+    fn execute(&self, job: &Job) -> Result<JobOutcome, ExecutionError> {
+        let id = self.submit(job)?;
+        loop {
+            match self.poll(&id) {
+                Ok(JobStatus::Finished(outcome)) => return Ok(outcome),
+                Ok(JobStatus::Queued | JobStatus::Running) => {
+                    thread::sleep(self.polling_interval())
+                }
+                Err(error) => {
+                    // The polling error is the one worth reporting
+                    let _ = self.cancel(&id);
+                    return Err(error);
+                }
+            }
+        }
+    }
+
+    fn execute_array(&self, array: &JobArray) -> Result<Vec<JobOutcome>, ExecutionError> {
+        let ids = self.submit_array(array)?;
+        let mut outcomes: Vec<Option<JobOutcome>> = vec![None; ids.len()];
+        loop {
+            let pending: Vec<_> = (0..ids.len()).filter(|&i| outcomes[i].is_none()).collect();
+            if pending.is_empty() {
+                return Ok(outcomes.into_iter().flatten().collect());
+            }
+            let pending_ids: Vec<_> = pending.iter().map(|&i| ids[i].clone()).collect();
+            match self.poll_all(&pending_ids) {
+                Ok(statuses) => {
+                    for (index, status) in pending.into_iter().zip(statuses) {
+                        if let JobStatus::Finished(outcome) = status {
+                            outcomes[index] = Some(outcome);
+                        }
+                    }
+                }
+                Err(error) => {
+                    cancel_all(self, &pending_ids);
+                    return Err(error);
+                }
+            }
+            if outcomes.iter().any(Option::is_none) {
+                thread::sleep(self.polling_interval());
+            }
+        }
+    }
+
+    /// Unknown, as the scheduler picks the nodes jobs run on.
+    fn host(&self) -> Option<String> {
+        None
+    }
+
+    /// Pinned by the container executor, whose runtime is also installed on the host
+    /// submitting the jobs.
+    fn pin_image(&self, image: &ImageSelector) -> Option<ImageSelector> {
+        match self.container_executor() {
+            Some(executor) => executor.pin_image(image),
+            None => Some(image.clone()),
+        }
+    }
+
+    /// Hashed by the container executor, which builds the images.
+    fn copy_sources(
+        &self,
+        container: &ResolvedContainer,
+    ) -> Result<BTreeMap<String, ContentHash>, ExecutionError> {
+        match self.container_executor() {
+            Some(executor) => executor.copy_sources(container),
+            None => Ok(BTreeMap::new()),
+        }
+    }
+}
+
+/// Cancel jobs on a best-effort basis, after an error worth reporting instead.
+fn cancel_all<S: Scheduler + ?Sized>(scheduler: &S, ids: &[String]) {
+    for id in ids {
+        let _ = scheduler.cancel(id);
+    }
+}
+
+/// Run a scheduler command and return its standard output.
+///
+/// Standard input is empty unless the command sets it. Fails with
+/// [`ExecutionError::Scheduler`] if the command exits unsuccessfully.
+pub(super) fn capture(command: &mut Command) -> Result<String, ExecutionError> {
+    let program = command.get_program().to_owned();
+    let output = command.output().map_err(ExecutionError::io(&program))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(ExecutionError::Scheduler {
+            program: program.into(),
+            message: match stderr.trim() {
+                "" => format!("exited with {}", JobOutcome::from_status(output.status)),
+                message => message.to_string(),
+            },
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The line of a job script that runs the job's command, in a container if one is given.
+pub(super) fn script_command(
+    container: Option<&ContainerExecutor>,
+    job: &Job,
+) -> Result<String, ExecutionError> {
+    let Some(container) = container else {
+        return Ok(job.command.clone());
+    };
+    let quoted: Vec<_> = container
+        .command_line(job)?
+        .iter()
+        .map(|arg| shell::quote(&arg.to_string_lossy()))
+        .collect();
+    Ok(format!("exec {}", quoted.join(" ")))
+}
+
+/// The number of seconds in a duration, rounded up.
+pub(super) fn whole_seconds(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+// EOF
@@ -0,0 +1,619 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::scheduler::{capture, script_command, whole_seconds};
+use super::{ContainerExecutor, ExecutionError, Job, JobArray, JobOutcome, JobStatus, Scheduler};
+use crate::shell;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+/// Submits jobs to a Slurm cluster with `sbatch` and waits for them to finish.
+///
+/// Every job is written to an sbatch script, `job.sbatch` in the job directory, with
+/// directives for the job's [resources](crate::workflow::Resources) and the configured
+/// partition, account and extra options. The script is submitted with `sbatch --parsable`.
+/// The job is then polled with `squeue` while it is queued or running, and its final state
+/// and exit code are read with `sacct`. Jobs are cancelled with `scancel`. Jobs that end in
+/// a state other than `COMPLETED` or `FAILED`, such as `TIMEOUT` or `OUT_OF_MEMORY`, are
+/// reported with that state as the [reason](JobOutcome::reason).
+///
+/// The jobs of a [`JobArray`] are submitted as a single array job, keeping scattered steps
+/// from flooding the queue; see [`array_script`](Self::array_script). The states of its
+/// elements are read with a single `sacct` call per poll.
+///
+/// The run directory must be on a filesystem shared with the compute nodes.
+///
+/// # Examples
+///
+/// ```
+/// use rivulet::executor::{ContainerExecutor, Runtime, SlurmExecutor};
+/// use std::time::Duration;
+///
+/// let mut apptainer = ContainerExecutor::new(Runtime::Apptainer);
+/// apptainer.image_dir("/scratch/images");
+///
+/// let mut slurm = SlurmExecutor::new();
+/// slurm
+///     .partition("batch")
+///     .account("genomics")
+///     .option("--qos=normal")
+///     .container(apptainer)
+///     .poll_interval(Duration::from_secs(30));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlurmExecutor {
+    sbatch: PathBuf,
+    squeue: PathBuf,
+    sacct: PathBuf,
+    scancel: PathBuf,
+    partition: Option<String>,
+    account: Option<String>,
+    options: Vec<String>,
+    container: Option<ContainerExecutor>,
+    array_limit: Option<u32>,
+    poll_interval: Duration,
+}
+
+impl SlurmExecutor {
+    /// Create an executor running the Slurm commands from the `PATH` and polling every ten
+    /// seconds.
+    pub fn new() -> Self {
+        Self {
+            sbatch: PathBuf::from("sbatch"),
+            squeue: PathBuf::from("squeue"),
+            sacct: PathBuf::from("sacct"),
+            scancel: PathBuf::from("scancel"),
+            partition: None,
+            account: None,
+            options: Vec::new(),
+            container: None,
+            array_limit: None,
+            poll_interval: Duration::from_secs(10),
+        }
+    }
+
+    /// Set the path of `sbatch`.
+    pub fn sbatch(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.sbatch = path.into();
+        self
+    }
+
+    /// Set the path of `squeue`.
+    pub fn squeue(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.squeue = path.into();
+        self
+    }
+
+    /// Set the path of `sacct`.
+    pub fn sacct(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.sacct = path.into();
+        self
+    }
+
+    /// Set the path of `scancel`.
+    pub fn scancel(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.scancel = path.into();
+        self
+    }
+
+    /// Set the partition jobs are submitted to.
+    pub fn partition(&mut self, partition: impl Into<String>) -> &mut Self {
+        self.partition = Some(partition.into());
+        self
+    }
+
+    /// Set the account jobs are charged to.
+    pub fn account(&mut self, account: impl Into<String>) -> &mut Self {
+        self.account = Some(account.into());
+        self
+    }
+
+    /// Add an `#SBATCH` option to every script, such as `--qos=long` or `--constraint=avx2`.
+    pub fn option(&mut self, option: impl Into<String>) -> &mut Self {
+        self.options.push(option.into());
+        self
+    }
+
+    /// Run commands in containers, invoking the runtime on the compute node.
+    ///
+    /// Images that need building are built before the job is submitted, so the image
+    /// directory of the container executor should be on a shared filesystem.
+    pub fn container(&mut self, executor: ContainerExecutor) -> &mut Self {
+        self.container = Some(executor);
+        self
+    }
+
+    /// Limit how many elements of an array job may run at the same time.
+    pub fn array_limit(&mut self, limit: u32) -> &mut Self {
+        self.array_limit = Some(limit);
+        self
+    }
+
+    /// Set how long to wait between polls of a running job.
+    pub fn poll_interval(&mut self, interval: Duration) -> &mut Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Render the sbatch script for a job.
+    pub fn script(&self, job: &Job) -> Result<String, ExecutionError> {
+        let mut script = String::from("#!/bin/sh\n");
+        let mut directive = |option: String| writeln!(script, "#SBATCH {option}").unwrap();
+        directive(format!("--job-name={}", job.name));
+        directive(format!("--chdir={}", job.workdir.display()));
+        directive(format!("--output={}", job.stdout.display()));
+        directive(format!("--error={}", job.stderr.display()));
+        self.common_directives(job, &mut directive);
+
+        script.push('\n');
+        script.push_str(&script_command(self.container.as_ref(), job)?);
+        script.push('\n');
+        Ok(script)
+    }
+
+    /// Render the sbatch script for an array job.
+    ///
+    /// The array's manifest, `array.manifest` in the array directory, maps every array index
+    /// to the name and directory of its job, followed by its staged inputs as `port=value`,
+    /// one tab-separated line per job. Tabs, newlines and backslashes in values are escaped
+    /// as `\t`, `\n` and `\\`. Each element
+    /// looks up its job there and runs the `job.sh` script in the job's directory, which
+    /// changes to the working directory and redirects the output of the command. Resources
+    /// are requested per element, as those of the first job; the output of the elements
+    /// outside their commands is written to `slurm-<index>.out` in the array directory.
+    pub fn array_script(&self, array: &JobArray) -> Result<String, ExecutionError> {
+        let mut script = String::from("#!/bin/sh\n");
+        let mut directive = |option: String| writeln!(script, "#SBATCH {option}").unwrap();
+        directive(format!("--job-name={}", array.name));
+        let last = array.jobs.len().saturating_sub(1);
+        directive(match self.array_limit {
+            Some(limit) => format!("--array=0-{last}%{limit}"),
+            None => format!("--array=0-{last}"),
+        });
+        directive(format!(
+            "--output={}",
+            array.dir.join("slurm-%a.out").display()
+        ));
+        if let Some(job) = array.jobs.first() {
+            self.common_directives(job, &mut directive);
+        }
+
+        let manifest = shell::quote(&array.dir.join("array.manifest").to_string_lossy());
+        writeln!(
+            script,
+            "\ndir=$(awk -F '\\t' -v task=\"$SLURM_ARRAY_TASK_ID\" \
+             '$1 == task {{ print $3 }}' {manifest})\n\
+             exec /bin/sh \"$dir/job.sh\""
+        )
+        .unwrap();
+        Ok(script)
+    }
+
+    /// Add the directives shared by plain and array jobs.
+    fn common_directives(&self, job: &Job, directive: &mut impl FnMut(String)) {
+        let resources = &job.resources;
+        if let Some(cpus) = resources.cpus {
+            directive(format!("--cpus-per-task={cpus}"));
+        }
+        if let Some(memory) = resources.memory {
+            directive(format!("--mem={}M", memory.div_ceil(1 << 20)));
+        }
+        if let Some(time) = resources.time {
+            directive(format!("--time={}", time_limit(time)));
+        }
+        if let Some(gpus) = resources.gpus {
+            directive(format!("--gres=gpu:{gpus}"));
+        }
+        if let Some(partition) = &self.partition {
+            directive(format!("--partition={partition}"));
+        }
+        if let Some(account) = &self.account {
+            directive(format!("--account={account}"));
+        }
+        for option in &self.options {
+            directive(option.clone());
+        }
+    }
+
+    /// Render the script an array element runs for its job.
+    fn element_script(&self, job: &Job) -> Result<String, ExecutionError> {
+        let quote = |path: &Path| shell::quote(&path.to_string_lossy());
+        Ok(format!(
+            "cd {} || exit 1\nexec > {} 2> {}\n{}\n",
+            quote(&job.workdir),
+            quote(&job.stdout),
+            quote(&job.stderr),
+            script_command(self.container.as_ref(), job)?
+        ))
+    }
+
+    /// Submit an sbatch script, returning the job id.
+    fn submit_script(&self, path: &Path) -> Result<String, ExecutionError> {
+        let output = capture(Command::new(&self.sbatch).arg("--parsable").arg(path))?;
+        // Federated clusters answer `<id>;<cluster>`
+        let id = output.trim().split(';').next().unwrap_or_default();
+        if id.is_empty() || !id.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Err(ExecutionError::Scheduler {
+                program: self.sbatch.clone(),
+                message: format!("unexpected output {:?}", output.trim()),
+            });
+        }
+        Ok(id.to_string())
+    }
+
+    /// Look up the states of the elements of an array job with `sacct`.
+    ///
+    /// Elements that never started, because they are still pending or the array was
+    /// cancelled before they did, are listed together as a range such as `4343_[0-99%4]`,
+    /// which is expanded to every element in it.
+    fn poll_array(&self, array_id: &str) -> Result<HashMap<String, JobStatus>, ExecutionError> {
+        let output = capture(Command::new(&self.sacct).args([
+            "--noheader",
+            "--parsable2",
+            "--allocations",
+            "--format=JobID,State,ExitCode",
+            &format!("--jobs={array_id}"),
+        ]))?;
+        let mut statuses = HashMap::new();
+        for line in output.lines() {
+            let mut fields = line.trim().split('|');
+            let (Some(id), Some(state)) = (fields.next(), fields.next()) else {
+                continue;
+            };
+            let state = state.split_whitespace().next().unwrap_or_default();
+            let status = if is_terminal(state) {
+                JobStatus::Finished(outcome(state, fields.next().unwrap_or_default()))
+            } else {
+                active_status(state)
+            };
+            match elements(id) {
+                Some(elements) => {
+                    for element in elements {
+                        statuses.insert(element, status.clone());
+                    }
+                }
+                None => {
+                    statuses.insert(id.to_string(), status);
+                }
+            }
+        }
+        Ok(statuses)
+    }
+}
+
+impl Default for SlurmExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scheduler for SlurmExecutor {
+    /// Write the sbatch script of a job to its directory and submit it.
+    fn submit(&self, job: &Job) -> Result<String, ExecutionError> {
+        let path = job.dir.join("job.sbatch");
+        fs::write(&path, self.script(job)?).map_err(ExecutionError::io(&path))?;
+        self.submit_script(&path)
+    }
+
+    /// Look up the state of a job with `squeue`, or `sacct` once it has left the queue.
+    ///
+    /// A job that has left the queue but is not yet in the accounting database is reported
+    /// as running.
+    fn poll(&self, id: &str) -> Result<JobStatus, ExecutionError> {
+        let jobs = format!("--jobs={id}");
+        // squeue fails for jobs it has already forgotten about, which sacct still knows
+        match capture(Command::new(&self.squeue).args(["--noheader", "--format=%T", &jobs])) {
+            Ok(output) => {
+                if let Some(state) = output.split_whitespace().next()
+                    && !is_terminal(state)
+                {
+                    return Ok(active_status(state));
+                }
+            }
+            Err(ExecutionError::Scheduler { .. }) => {}
+            Err(error) => return Err(error),
+        }
+
+        let output = capture(Command::new(&self.sacct).args([
+            "--noheader",
+            "--parsable2",
+            "--allocations",
+            "--format=State,ExitCode",
+            &jobs,
+        ]))?;
+        let Some(line) = output.lines().map(str::trim).find(|line| !line.is_empty()) else {
+            return Ok(JobStatus::Running);
+        };
+        let (state, exit_code) = line.split_once('|').unwrap_or((line, ""));
+        // States can carry details, as in `CANCELLED by 1000`
+        let state = state.split_whitespace().next().unwrap_or_default();
+        if !is_terminal(state) {
+            return Ok(active_status(state));
+        }
+        Ok(JobStatus::Finished(outcome(state, exit_code)))
+    }
+
+    /// Write the manifest, the sbatch script and the element scripts of an array and submit
+    /// it as one array job, returning `<array id>_<index>` for every element.
+    fn submit_array(&self, array: &JobArray) -> Result<Vec<String>, ExecutionError> {
+        if array.jobs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut manifest = String::new();
+        for (index, job) in array.jobs.iter().enumerate() {
+            write!(manifest, "{index}\t{}\t{}", job.name, job.dir.display()).unwrap();
+            for (port, value) in &job.inputs {
+                write!(manifest, "\t{port}={}", escape_field(&value.to_string())).unwrap();
+            }
+            manifest.push('\n');
+            let path = job.dir.join("job.sh");
+            fs::write(&path, self.element_script(job)?).map_err(ExecutionError::io(&path))?;
+        }
+        let path = array.dir.join("array.manifest");
+        fs::write(&path, manifest).map_err(ExecutionError::io(&path))?;
+        let path = array.dir.join("array.sbatch");
+        fs::write(&path, self.array_script(array)?).map_err(ExecutionError::io(&path))?;
+
+        let id = self.submit_script(&path)?;
+        Ok((0..array.jobs.len())
+            .map(|index| format!("{id}_{index}"))
+            .collect())
+    }
+
+    /// Poll array elements, written `<array id>_<index>`, with one `sacct` call per array,
+    /// and other jobs one by one.
+    ///
+    /// Elements missing from the accounting database are reported as queued.
+    fn poll_all(&self, ids: &[String]) -> Result<Vec<JobStatus>, ExecutionError> {
+        let mut arrays = HashMap::new();
+        ids.iter()
+            .map(|id| {
+                let Some((array_id, _)) = id.split_once('_') else {
+                    return self.poll(id);
+                };
+                if !arrays.contains_key(array_id) {
+                    arrays.insert(array_id, self.poll_array(array_id)?);
+                }
+                Ok(arrays[array_id]
+                    .get(id)
+                    .cloned()
+                    .unwrap_or(JobStatus::Queued))
+            })
+            .collect()
+    }
+
+    fn cancel(&self, id: &str) -> Result<(), ExecutionError> {
+        capture(Command::new(&self.scancel).arg(id)).map(drop)
+    }
+
+    fn polling_interval(&self) -> Duration {
+        self.poll_interval
+    }
+
+    fn container_executor(&self) -> Option<&ContainerExecutor> {
+        self.container.as_ref()
+    }
+}
+
+/// The elements of an array listed as a range, written `<array id>_[<indices>]` where the
+/// indices are a comma-separated list of indices and `first-last` ranges, optionally
+/// followed by `%<limit>`.
+///
+/// Returns `None` for ids that are not ranges, including single elements.
+fn elements(id: &str) -> Option<Vec<String>> {
+    let (array_id, indices) = id.split_once("_[")?;
+    let indices = indices.strip_suffix(']')?;
+    let indices = indices
+        .split_once('%')
+        .map_or(indices, |(indices, _)| indices);
+    let mut elements = Vec::new();
+    for part in indices.split(',') {
+        let (first, last) = part.split_once('-').unwrap_or((part, part));
+        let (first, last): (usize, usize) = (first.parse().ok()?, last.parse().ok()?);
+        elements.extend((first..=last).map(|index| format!("{array_id}_{index}")));
+    }
+    Some(elements)
+}
+
+/// Escape the tabs, newlines and backslashes in a field of the array manifest.
+fn escape_field(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+/// Whether a Slurm job state is final.
+fn is_terminal(state: &str) -> bool {
+    matches!(
+        state,
+        "BOOT_FAIL"
+            | "CANCELLED"
+            | "COMPLETED"
+            | "DEADLINE"
+            | "FAILED"
+            | "NODE_FAIL"
+            | "OUT_OF_MEMORY"
+            | "PREEMPTED"
+            | "TIMEOUT"
+    )
+}
+
+/// The status of a job in a state that is not final.
+fn active_status(state: &str) -> JobStatus {
+    match state {
+        "PENDING" | "CONFIGURING" | "REQUEUED" | "REQUEUE_HOLD" => JobStatus::Queued,
+        _ => JobStatus::Running,
+    }
+}
+
+/// The outcome of a job from its final state and `sacct` exit code, written
+/// `<exit code>:<signal>`.
+fn outcome(state: &str, exit_code: &str) -> JobOutcome {
+    let (code, signal) = exit_code.split_once(':').unwrap_or((exit_code, "0"));
+    let exit_code = match signal.parse::<i32>() {
+        Ok(0) => code.parse().ok(),
+        _ => None,
+    };
+    let exited = state == "COMPLETED" || (state == "FAILED" && exit_code.is_some_and(|c| c != 0));
+    JobOutcome {
+        exit_code,
+        reason: (!exited).then(|| state.to_string()),
+    }
+}
+
+/// Format a time limit as `days-hours:minutes:seconds`, rounding up to whole seconds.
+fn time_limit(limit: Duration) -> String {
+    let seconds = whole_seconds(limit);
+    format!(
+        "{}-{:02}:{:02}:{:02}",
+        seconds / 86_400,
+        seconds % 86_400 / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::Container;
+    use crate::executor::Runtime;
+    use crate::workflow::Resources;
+
+    fn job(resources: Resources) -> Job {
+        let container = Container::from("quay.io/biocontainers/salmon:1.10.3--h6dccd9a_2");
+        let command = "salmon quant -i index -l A -r 'my reads.fq' -o quant";
+        let mut job = Job::example("quant", &container, command, "/scratch/run/steps/quant");
+        job.resources = resources;
+        job
+    }
+
+    #[test]
+    fn test_script() {
+        let mut slurm = SlurmExecutor::new();
+        slurm.partition("short").account("lab").option("--qos=high");
+        let resources = Resources {
+            cpus: Some(8),
+            memory: Some(16 << 30),
+            time: Some(Duration::from_secs(90 * 60)),
+            gpus: Some(1),
+        };
+        assert_eq!(
+            slurm.script(&job(resources)).unwrap(),
+            "#!/bin/sh\n\
+             #SBATCH --job-name=quant\n\
+             #SBATCH --chdir=/scratch/run/steps/quant/work\n\
+             #SBATCH --output=/scratch/run/steps/quant/stdout\n\
+             #SBATCH --error=/scratch/run/steps/quant/stderr\n\
+             #SBATCH --cpus-per-task=8\n\
+             #SBATCH --mem=16384M\n\
+             #SBATCH --time=0-01:30:00\n\
+             #SBATCH --gres=gpu:1\n\
+             #SBATCH --partition=short\n\
+             #SBATCH --account=lab\n\
+             #SBATCH --qos=high\n\
+             \n\
+             salmon quant -i index -l A -r 'my reads.fq' -o quant\n"
+        );
+    }
+
+    #[test]
+    fn test_script_in_container() {
+        let mut slurm = SlurmExecutor::new();
+        let mut apptainer = ContainerExecutor::new(Runtime::Apptainer);
+        apptainer.program("/opt/apptainer/bin/apptainer");
+        slurm.container(apptainer);
+        let script = slurm.script(&job(Resources::default())).unwrap();
+        let command = script.lines().last().unwrap();
+        assert!(
+            command.starts_with("exec /opt/apptainer/bin/apptainer exec --cleanenv "),
+            "{command}"
+        );
+        assert!(command.contains(
+            " docker://quay.io/biocontainers/salmon:1.10.3--h6dccd9a_2 /bin/sh -c \
+             'salmon quant -i index -l A -r '\\''my reads.fq'\\'' -o quant'"
+        ));
+    }
+
+    #[test]
+    fn test_array_script() {
+        let mut slurm = SlurmExecutor::new();
+        slurm.partition("short").array_limit(4);
+        let resources = Resources {
+            cpus: Some(2),
+            ..Resources::default()
+        };
+        let array = JobArray {
+            name: "quant".to_string(),
+            dir: PathBuf::from("/scratch/run/steps/quant"),
+            jobs: vec![job(resources); 3],
+        };
+        assert_eq!(
+            slurm.array_script(&array).unwrap(),
+            "#!/bin/sh\n\
+             #SBATCH --job-name=quant\n\
+             #SBATCH --array=0-2%4\n\
+             #SBATCH --output=/scratch/run/steps/quant/slurm-%a.out\n\
+             #SBATCH --cpus-per-task=2\n\
+             #SBATCH --partition=short\n\
+             \n\
+             dir=$(awk -F '\\t' -v task=\"$SLURM_ARRAY_TASK_ID\" \
+             '$1 == task { print $3 }' /scratch/run/steps/quant/array.manifest)\n\
+             exec /bin/sh \"$dir/job.sh\"\n"
+        );
+    }
+
+    #[test]
+    fn test_elements() {
+        assert_eq!(
+            elements("4343_[0-2%4]").unwrap(),
+            ["4343_0", "4343_1", "4343_2"]
+        );
+        assert_eq!(
+            elements("4343_[1,5-6]").unwrap(),
+            ["4343_1", "4343_5", "4343_6"]
+        );
+        assert_eq!(elements("4343_7"), None);
+        assert_eq!(elements("4343"), None);
+        assert_eq!(elements("4343_[x-2]"), None);
+    }
+
+    #[test]
+    fn test_escape_field() {
+        assert_eq!(escape_field("a\tb\nc\\d"), "a\\tb\\nc\\\\d");
+    }
+
+    #[test]
+    fn test_time_limit() {
+        assert_eq!(time_limit(Duration::from_secs(59)), "0-00:00:59");
+        assert_eq!(time_limit(Duration::from_millis(1500)), "0-00:00:02");
+        assert_eq!(
+            time_limit(Duration::from_secs(2 * 86_400 + 3661)),
+            "2-01:01:01"
+        );
+    }
+
+    #[test]
+    fn test_outcome() {
+        assert_eq!(outcome("COMPLETED", "0:0"), JobOutcome::exited(0));
+        assert_eq!(outcome("FAILED", "2:0"), JobOutcome::exited(2));
+        let timeout = outcome("TIMEOUT", "0:15");
+        assert_eq!(timeout.exit_code, None);
+        assert_eq!(timeout.reason.as_deref(), Some("TIMEOUT"));
+        let failed = outcome("FAILED", "0:0");
+        assert!(!failed.success());
+        assert_eq!(failed.to_string(), "FAILED");
+        assert_eq!(
+            outcome("OUT_OF_MEMORY", "0:125").to_string(),
+            "OUT_OF_MEMORY"
+        );
+    }
+}
+
+// EOF
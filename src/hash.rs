@@ -0,0 +1,322 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+//! Content-addressed identities.
+//!
+//! A [`ContentHash`] identifies a definition by what it contains rather than by where it lives
+//! in memory, so two definitions built independently from the same parts share an identity.
+//! Rivulet uses these hashes to recognize work that has already been done, and to identify
+//! the files and directories steps read and write.
+
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Errors that can occur when parsing a [`ContentHash`] from its hex form.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ContentHashParseError {
+    /// Returned when the input is not 64 lowercase hexadecimal characters.
+    /// The enclosed string is the invalid input.
+    #[error("Invalid content hash: {0}")]
+    InvalidFormat(String),
+}
+
+/// A SHA-256 identity computed over a canonical encoding of a definition.
+///
+/// Content hashes display as 64 lowercase hex characters and parse back from the same form.
+///
+/// # Examples
+///
+/// ```
+/// use rivulet::container::Container;
+/// use rivulet::hash::ContentHash;
+///
+/// let base = Container::from("python:3.12-slim");
+/// let hash = base.read().unwrap().content_hash().unwrap();
+///
+/// let hex = hash.to_string();
+/// assert_eq!(hex.len(), 64);
+/// assert_eq!(hex.parse::<ContentHash>().unwrap(), hash);
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ContentHash([u8; 32]);
+
+/// Domain of file hashes; bump the version whenever the encoding changes.
+const FILE_DOMAIN: &str = "rivulet.file.v1";
+
+/// Domain of directory hashes; bump the version whenever the encoding changes.
+const DIRECTORY_DOMAIN: &str = "rivulet.directory.v1";
+
+impl ContentHash {
+    /// The raw bytes of the hash.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Hash the contents of a file, following symlinks.
+    ///
+    /// The hash depends only on the bytes in the file, not on its name or metadata.
+    pub fn of_file(path: &Path) -> io::Result<Self> {
+        copy_file(path, &mut io::sink())
+    }
+
+    /// Hash a directory tree, following a symlink at `path` itself.
+    ///
+    /// The hash covers the names and kinds of everything in the tree, with files hashed as
+    /// by [`of_file`](Self::of_file) and subdirectories as by this function. Symlinks inside
+    /// the tree are hashed by their targets, without following them. Permissions and
+    /// timestamps are ignored.
+    pub fn of_directory(path: &Path) -> io::Result<Self> {
+        let mut entries = BTreeMap::new();
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let tree_entry = if file_type.is_symlink() {
+                TreeEntry::Symlink(fs::read_link(entry.path())?)
+            } else if file_type.is_dir() {
+                TreeEntry::Directory(Self::of_directory(&entry.path())?)
+            } else {
+                TreeEntry::File(Self::of_file(&entry.path())?)
+            };
+            entries.insert(entry.file_name(), tree_entry);
+        }
+        Ok(Self::of_tree(&entries))
+    }
+
+    /// Hash a directory from the hashes of its entries, as [`of_directory`](Self::of_directory)
+    /// does.
+    pub(crate) fn of_tree(entries: &BTreeMap<OsString, TreeEntry>) -> Self {
+        let mut hasher = ContentHasher::new(DIRECTORY_DOMAIN);
+        hasher.u64(entries.len() as u64);
+        for (name, entry) in entries {
+            hasher.bytes(name.as_encoded_bytes());
+            match entry {
+                TreeEntry::File(hash) => hasher.str("file").bytes(hash.as_bytes()),
+                TreeEntry::Directory(hash) => hasher.str("directory").bytes(hash.as_bytes()),
+                TreeEntry::Symlink(target) => hasher
+                    .str("symlink")
+                    .bytes(target.as_os_str().as_encoded_bytes()),
+            };
+        }
+        hasher.finish()
+    }
+}
+
+/// An entry of a directory, as it takes part in the directory's hash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum TreeEntry {
+    /// A file with the given [`ContentHash::of_file`].
+    File(ContentHash),
+
+    /// A directory with the given [`ContentHash::of_directory`].
+    Directory(ContentHash),
+
+    /// A symlink to the given target.
+    Symlink(PathBuf),
+}
+
+/// Copy the contents of a file to a writer, returning their [`ContentHash::of_file`].
+///
+/// Fails if the file is shorter than its metadata said, which means it changed while it was
+/// read.
+pub(crate) fn copy_file(path: &Path, writer: &mut impl Write) -> io::Result<ContentHash> {
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut hasher = ContentHasher::new(FILE_DOMAIN);
+    hasher.u64(len);
+    let mut tee = Tee {
+        hasher: &mut hasher.0,
+        writer,
+    };
+    let copied = io::copy(&mut file.take(len), &mut tee)?;
+    if copied != len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("{} changed while it was read", path.display()),
+        ));
+    }
+    Ok(hasher.finish())
+}
+
+/// A writer that hashes everything written through it.
+struct Tee<'a, W> {
+    hasher: &'a mut Sha256,
+    writer: &'a mut W,
+}
+
+impl<W: Write> Write for Tee<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl fmt::Display for ContentHash {
+    /// Format the hash as lowercase hex.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for ContentHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ContentHash({self})")
+    }
+}
+
+impl FromStr for ContentHash {
+    type Err = ContentHashParseError;
+
+    /// Parse a hash from 64 lowercase hex characters.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ContentHashParseError::InvalidFormat(s.to_string());
+        let is_hex = |c: char| c.is_ascii_digit() || ('a'..='f').contains(&c);
+        if s.len() != 64 || !s.chars().all(is_hex) {
+            return Err(invalid());
+        }
+        let mut bytes = [0; 32];
+        for (index, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[index * 2..index * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(Self(bytes))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for ContentHash {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ContentHash {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hash = String::deserialize(deserializer)?;
+        ContentHash::from_str(&hash).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Builds a [`ContentHash`] from a sequence of fields.
+///
+/// Every field is length-prefixed, so no two different sequences of fields produce the same
+/// input to the hash function. The domain passed to [`new`](Self::new) separates hashes of
+/// different kinds of definitions, and should carry a version that changes with the encoding.
+pub(crate) struct ContentHasher(Sha256);
+
+impl ContentHasher {
+    /// Start a hash for the given domain.
+    pub(crate) fn new(domain: &str) -> Self {
+        let mut hasher = Self(Sha256::new());
+        hasher.str(domain);
+        hasher
+    }
+
+    /// Add a string field.
+    pub(crate) fn str(&mut self, s: &str) -> &mut Self {
+        self.bytes(s.as_bytes())
+    }
+
+    /// Add a byte string field.
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.u64(bytes.len() as u64);
+        self.0.update(bytes);
+        self
+    }
+
+    /// Add an integer field.
+    pub(crate) fn u64(&mut self, n: u64) -> &mut Self {
+        self.0.update(n.to_le_bytes());
+        self
+    }
+
+    /// Finish the hash.
+    pub(crate) fn finish(self) -> ContentHash {
+        ContentHash(self.0.finalize().into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fields_are_length_prefixed() {
+        let mut a = ContentHasher::new("test");
+        a.str("ab").str("c");
+        let mut b = ContentHasher::new("test");
+        b.str("a").str("bc");
+        assert_ne!(a.finish(), b.finish());
+    }
+
+    #[test]
+    fn test_domains_are_separated() {
+        let mut a = ContentHasher::new("one");
+        a.str("x");
+        let mut b = ContentHasher::new("two");
+        b.str("x");
+        assert_ne!(a.finish(), b.finish());
+    }
+
+    #[test]
+    fn test_hex_round_trip() {
+        let hash = ContentHasher::new("test").finish();
+        let hex = hash.to_string();
+        assert_eq!(hex.len(), 64);
+        assert_eq!(ContentHash::from_str(&hex), Ok(hash));
+        assert_eq!(format!("{hash:?}"), format!("ContentHash({hex})"));
+    }
+
+    #[test]
+    fn test_file_and_directory_hashes() {
+        let dir = tempfile::tempdir().unwrap();
+        let tree = dir.path().join("tree");
+        fs::create_dir_all(tree.join("sub")).unwrap();
+        fs::write(tree.join("a.txt"), "a").unwrap();
+        fs::write(tree.join("sub/b.txt"), "b").unwrap();
+        fs::write(dir.path().join("copy.txt"), "a").unwrap();
+
+        let file = ContentHash::of_file(&tree.join("a.txt")).unwrap();
+        assert_eq!(
+            ContentHash::of_file(&dir.path().join("copy.txt")).unwrap(),
+            file
+        );
+        let before = ContentHash::of_directory(&tree).unwrap();
+        assert_ne!(before, file);
+
+        fs::write(tree.join("sub/b.txt"), "B").unwrap();
+        let changed = ContentHash::of_directory(&tree).unwrap();
+        assert_ne!(changed, before);
+        fs::write(tree.join("sub/b.txt"), "b").unwrap();
+        assert_eq!(ContentHash::of_directory(&tree).unwrap(), before);
+        fs::rename(tree.join("a.txt"), tree.join("c.txt")).unwrap();
+        assert_ne!(ContentHash::of_directory(&tree).unwrap(), before);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for input in ["", "abc", &"A".repeat(64), &"g".repeat(64), &"0".repeat(65)] {
+            assert!(matches!(
+                ContentHash::from_str(input),
+                Err(ContentHashParseError::InvalidFormat(_))
+            ));
+        }
+    }
+}
+
+// EOF
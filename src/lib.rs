@@ -12,11 +12,13 @@
 //!
 //! ## Key Features
 //!
-//! - **Scientific Workflow Management**: Define complex multi-step data analysis pipelines
+//! - **Scientific Workflow Management**: Define complex multi-step data analysis pipelines as
+//!   graphs of containerized steps
 //! - **Container Integration**: Create and connect containers for analysis steps
 //! - **Data Provenance Tracking**: Automatically track the origin and transformation history of data
 //! - **Computation Efficiency**: Prevent redundant recomputation of unchanged data paths
-//! - **HPC Integration**: Designed for high-performance computing environments
+//! - **HPC Integration**: Designed for high-performance computing environments, with container
+//!   chains rendered as Apptainer definition files as well as Containerfiles
 //! - **Reproducible Research**: Maintain consistent, reproducible scientific workflows
 //!
 //! ## Getting Started
@@ -31,6 +33,11 @@
 //!
 //! // Create a nested container for a specialized step
 //! let custom_analysis = Container::from(&analysis);
+//! custom_analysis
+//!     .write()
+//!     .unwrap()
+//!     .run("pip install --no-cache-dir scikit-learn")
+//!     .env("TF_CPP_MIN_LOG_LEVEL", "2");
 //!
 //! // Additional workflow setup would connect these containers and configure data flow
 //! ```
@@ -39,7 +46,7 @@
 //!
 //! Rivulet parses Docker image references following the pattern:
 //! ```text
-//! [registry/][namespace/]repository[:tag][@algorithm=hash]
+//! [registry[:port]/][namespace/]repository[:tag][@algorithm:hash]
 //! ```
 //!
 //! Example of working with image references:
@@ -50,11 +57,25 @@
 //!
 //! // Parse an image reference for a scientific computing container
 //! let image = ImageSelector::from_str("quay.io/biocontainers/salmon:1.5.2").unwrap();
-//! assert_eq!(image.namespace, Some("quay.io/biocontainers".to_string()));
+//! assert_eq!(image.registry, Some("quay.io".to_string()));
+//! assert_eq!(image.namespace, Some("biocontainers".to_string()));
 //! assert_eq!(image.repository, "salmon");
 //! assert_eq!(image.tag, Some("1.5.2".to_string()));
+//!
+//! // Image references format back to their canonical string form
+//! assert_eq!(image.to_string(), "quay.io/biocontainers/salmon:1.5.2");
+//!
+//! // Short references are normalized using Docker's defaults
+//! let image = ImageSelector::from_str("ubuntu").unwrap();
+//! assert_eq!(image.normalize().to_string(), "docker.io/library/ubuntu:latest");
 //! ```
 //!
+//! ## Cargo Features
+//!
+//! - `serde`: Implements `Serialize` and `Deserialize` for image references and containers,
+//!   so container definitions can be stored alongside results in JSON, TOML or YAML, and
+//!   writes the provenance of runs as PROV-JSON
+//!
 //! ## Workflow Design
 //!
 //! Rivulet manages scientific workflows by connecting container-based processing steps:
@@ -67,11 +88,60 @@
 //! let alignment_container = Container::from("biocontainers/star:2.7.9a");
 //! let counting_container = Container::from("biocontainers/salmon:1.5.2");
 //!
-//! // Additional code would connect these containers into a workflow
-//! // and configure input/output paths and data provenance tracking
+//! // Declare the steps and the data they consume and produce
+//! let mut workflow = Workflow::new("rnaseq");
+//! let reads = workflow.input("reads", PortType::File).unwrap();
+//! let genome = workflow.input("genome", PortType::Directory).unwrap();
+//! let transcripts = workflow.input("transcripts", PortType::File).unwrap();
+//!
+//! let mut qc = Step::new("qc", &qc_container, "fastqc {reads} -o .");
+//! qc.input("reads", PortType::File)
+//!     .output("report", PortType::File);
+//! let qc = workflow.add_step(qc).unwrap();
+//!
+//! let mut align = Step::new(
+//!     "align",
+//!     &alignment_container,
+//!     "STAR --genomeDir {genome} --readFilesIn {reads} --quantMode TranscriptomeSAM",
+//! );
+//! align
+//!     .input("genome", PortType::Directory)
+//!     .input("reads", PortType::File)
+//!     .output("alignments", PortType::File);
+//! let align = workflow.add_step(align).unwrap();
+//!
+//! let mut count = Step::new(
+//!     "count",
+//!     &counting_container,
+//!     "salmon quant -t {transcripts} -l A -a {alignments} -o quant",
+//! );
+//! count
+//!     .input("transcripts", PortType::File)
+//!     .input("alignments", PortType::File)
+//!     .output("quant", PortType::Directory);
+//! let count = workflow.add_step(count).unwrap();
+//!
+//! // Connect the data flow between them
+//! workflow.connect(reads.clone(), qc.input("reads")).unwrap();
+//! workflow.connect(reads, align.input("reads")).unwrap();
+//! workflow.connect(genome, align.input("genome")).unwrap();
+//! workflow.connect(transcripts, count.input("transcripts")).unwrap();
+//! workflow.connect(align.output("alignments"), count.input("alignments")).unwrap();
+//! workflow.output("report", qc.output("report")).unwrap();
+//! workflow.output("quant", count.output("quant")).unwrap();
+//!
+//! workflow.validate().unwrap();
+//! assert_eq!(workflow.topological_order().unwrap(), [qc, align, count]);
 //! ```
 
 pub mod container;
+pub mod executor;
+pub mod hash;
+pub mod store;
+pub mod workflow;
+
+mod shell;
+mod timestamp;
 
 /// The prelude module re-exports the most commonly used types and traits.
 ///
@@ -89,7 +159,11 @@ pub mod container;
 /// let container = Container::from("biocontainers/fastqc:latest");
 /// ```
 pub mod prelude {
-    pub use super::container::{Container, ContainerBase, ImageSelector, ImageSelectorParseError};
+    pub use super::container::{
+        Container, ContainerBase, ContainerError, ImageSelector, ImageSelectorParseError,
+    };
+    pub use super::executor::{ExecutionError, Executor, LocalExecutor};
+    pub use super::workflow::{PortType, Runner, Step, StepId, Value, Workflow, WorkflowError};
 }
 
 // EOF
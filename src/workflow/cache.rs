@@ -0,0 +1,361 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+mod gc;
+mod key;
+
+pub use gc::{Freed, GcReport, RetentionPolicy};
+pub use key::{CacheMiss, InputComponent, KeyChange, KeyComponents};
+
+use super::Step;
+use crate::executor::{ExecutionError, Job};
+use crate::hash::ContentHash;
+use crate::store::{ArtifactStore, StoreError};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// A directory keeping the results of jobs, so a job that already ran does not run again.
+///
+/// Every job gets a key, a [`ContentHash`] of everything that determines its results, its
+/// [`KeyComponents`]:
+///
+/// - the [content hash](crate::container::ResolvedContainer::content_hash) of its container,
+/// - the contents of the sources its container's `COPY` steps take from the executor's
+///   [build context](crate::executor::Executor::copy_sources),
+/// - the command template of its step,
+/// - the names, types and glob patterns of the step's outputs,
+/// - the names and values of its inputs, with files and directories hashed by content.
+///
+/// Paths do not take part in the key, so a job whose inputs are moved or staged elsewhere
+/// still finds its results. After a job succeeds, the files matching its output patterns
+/// are kept in an [`ArtifactStore`] with its standard output and standard error, and an
+/// entry naming them is written to `steps/<key>` in the store's directory. Files are stored
+/// once however many jobs produce them, and restored with the store's
+/// [materialization](ArtifactStore::materialization). Entries are written atomically, so
+/// runs sharing a cache never see a partial entry.
+///
+/// The components of every kept result are written to `keys/<step>/<key>`. When a job
+/// misses the cache, it is compared with the kept result of its step that differs least
+/// from it, to [explain](Self::explain) what changed.
+///
+/// Every run is recorded in `runs/<id>` with the keys of the results it used, added as it
+/// keeps or reuses them, so a run that fails still records the results it kept.
+/// Runs can be [pinned](Self::pin) and results [named](Self::name_result), to keep them
+/// through [garbage collection](Self::collect_garbage), which removes results following a
+/// [`RetentionPolicy`]. Reusing a result counts as using it for policies that remove the
+/// least recently used results.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rivulet::executor::LocalExecutor;
+/// use rivulet::prelude::*;
+/// use rivulet::workflow::{StepCache, Value};
+///
+/// # let workflow = Workflow::new("example");
+/// let cache = StepCache::new("/scratch/rivulet-cache");
+/// let executor = LocalExecutor::new();
+/// let result = Runner::new(&executor, "runs/2")
+///     .cache(&cache)
+///     .run(&workflow, [("reads", Value::File("reads.fq".into()))])?;
+/// let reused = result.steps.iter().filter(|step| step.cached).count();
+/// # Ok::<(), rivulet::executor::ExecutionError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct StepCache {
+    store: ArtifactStore,
+}
+
+impl StepCache {
+    /// Create a cache in a directory, which is created when the first result is kept.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self::from(ArtifactStore::new(dir))
+    }
+
+    /// The directory of the cache.
+    pub fn dir(&self) -> &Path {
+        self.store.dir()
+    }
+
+    /// The store keeping the files of the cached results.
+    pub fn store(&self) -> &ArtifactStore {
+        &self.store
+    }
+
+    /// Whether the results of the job with the given key are kept.
+    pub fn contains(&self, key: &ContentHash) -> bool {
+        self.entry(key).is_file()
+    }
+
+    /// Explain why a job of a step, with the given key components, misses the cache.
+    ///
+    /// The job is compared with the kept result of the step whose components differ least
+    /// from its own, and the most recent one among those that differ equally.
+    pub fn explain(
+        &self,
+        step: &str,
+        components: &KeyComponents,
+    ) -> Result<CacheMiss, ExecutionError> {
+        let mut miss = CacheMiss {
+            nearest: None,
+            changes: Vec::new(),
+        };
+        let dir = self.dir().join("keys").join(step);
+        let entries = match fs::read_dir(&dir) {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(miss),
+            result => result.map_err(ExecutionError::io(&dir))?,
+        };
+
+        let mut latest = SystemTime::UNIX_EPOCH;
+        for entry in entries {
+            let path = entry.map_err(ExecutionError::io(&dir))?.path();
+            let Some(key) = path
+                .file_name()
+                .and_then(|name| name.to_str()?.parse::<ContentHash>().ok())
+            else {
+                continue;
+            };
+            // Records are only ever replaced whole, so one that cannot be read is skipped
+            let Ok(record) = fs::read_to_string(&path) else {
+                continue;
+            };
+            let Some(earlier) = KeyComponents::from_record(&record) else {
+                continue;
+            };
+            let modified = fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            let changes = components.changes_since(&earlier);
+            let closer = match miss.nearest {
+                None => true,
+                Some(_) => (changes.len(), latest) < (miss.changes.len(), modified),
+            };
+            if closer {
+                miss.nearest = Some(key);
+                miss.changes = changes;
+                latest = modified;
+            }
+        }
+        Ok(miss)
+    }
+
+    /// The path of the entry for a key.
+    fn entry(&self, key: &ContentHash) -> PathBuf {
+        self.dir().join("steps").join(key.to_string())
+    }
+
+    /// Read the entry for a key, if there is a readable one.
+    fn read_entry(&self, key: &ContentHash) -> Result<Option<Entry>, ExecutionError> {
+        let path = self.entry(key);
+        let entry = match fs::read_to_string(&path) {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            result => result.map_err(ExecutionError::io(&path))?,
+        };
+        let objects: BTreeMap<_, _> = entry
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .filter_map(|(name, hash)| Some((name, hash.parse::<ContentHash>().ok()?)))
+            .collect();
+        Ok(
+            match (
+                objects.get("work"),
+                objects.get("stdout"),
+                objects.get("stderr"),
+            ) {
+                (Some(&work), Some(&stdout), Some(&stderr)) => Some(Entry {
+                    work,
+                    stdout,
+                    stderr,
+                }),
+                _ => None,
+            },
+        )
+    }
+
+    /// Restore the files of a job from its entry, returning whether there was one.
+    ///
+    /// An entry whose files are no longer stored counts as missing. Restoring an entry
+    /// updates its last access time, which [garbage collection](Self::collect_garbage) goes by.
+    pub(super) fn restore(&self, key: &ContentHash, job: &Job) -> Result<bool, ExecutionError> {
+        let _lock = self.store.lock_shared()?;
+        let Some(entry) = self.read_entry(key)? else {
+            return Ok(false);
+        };
+        let restored = self
+            .store
+            .materialize_directory(&entry.work, &job.workdir)
+            .and_then(|()| self.store.materialize_file(&entry.stdout, &job.stdout))
+            .and_then(|()| self.store.materialize_file(&entry.stderr, &job.stderr));
+        match restored {
+            Ok(()) => {
+                // A stale access time only makes the entry an earlier candidate for collection
+                let _ = File::options()
+                    .write(true)
+                    .open(self.entry(key))
+                    .and_then(|file| file.set_modified(SystemTime::now()));
+                Ok(true)
+            }
+            Err(StoreError::MissingObject(_)) => {
+                for path in [&job.stdout, &job.stderr] {
+                    let _ = fs::remove_file(path);
+                }
+                fs::remove_dir_all(&job.workdir).map_err(ExecutionError::io(&job.workdir))?;
+                fs::create_dir(&job.workdir).map_err(ExecutionError::io(&job.workdir))?;
+                Ok(false)
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Keep the output files, standard output and standard error of a successful job.
+    pub(super) fn keep(
+        &self,
+        components: &KeyComponents,
+        step: &Step,
+        job: &Job,
+    ) -> Result<(), ExecutionError> {
+        let _lock = self.store.lock_shared()?;
+        let key = components.key();
+        let prefix = glob::Pattern::escape(&job.workdir.to_string_lossy());
+        let mut selected = BTreeSet::new();
+        for port in step.outputs() {
+            let pattern = format!("{prefix}/{}", step.output_glob(&port.name));
+            let Ok(paths) = glob::glob(&pattern) else {
+                continue;
+            };
+            selected.extend(
+                paths
+                    .filter_map(Result::ok)
+                    .filter_map(|path| Some(path.strip_prefix(&job.workdir).ok()?.to_path_buf())),
+            );
+        }
+        let work = self.store.put_selected(&job.workdir, &selected)?;
+        let stdout = self.store.put_file(&job.stdout)?;
+        let stderr = self.store.put_file(&job.stderr)?;
+        let entry = format!("work {work}\nstdout {stdout}\nstderr {stderr}\n");
+        let record = self
+            .dir()
+            .join("keys")
+            .join(step.name())
+            .join(key.to_string());
+        self.store
+            .write_atomic(&record, components.to_record().as_bytes())?;
+        self.store
+            .write_atomic(&self.entry(&key), entry.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// The objects holding a cached result.
+struct Entry {
+    /// The tree of output files, relative to the working directory.
+    work: ContentHash,
+    /// The standard output of the job.
+    stdout: ContentHash,
+    /// The standard error of the job.
+    stderr: ContentHash,
+}
+
+impl From<ArtifactStore> for StepCache {
+    /// Create a cache keeping its files in a store, and its entries in the store's directory.
+    fn from(store: ArtifactStore) -> Self {
+        Self { store }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::Container;
+    use crate::workflow::{PortType, Value};
+    use tempfile::TempDir;
+
+    fn job(step: &Step, dir: &Path) -> Job {
+        Job::example(step.name(), step.container(), step.command(), dir)
+    }
+
+    #[test]
+    fn test_keys() {
+        let dir = TempDir::new().unwrap();
+        let busybox = Container::from("busybox:1.36");
+        let mut step = Step::new("count", &busybox, "wc -l < {text} > lines");
+        step.input("text", PortType::File)
+            .output("lines", PortType::Int);
+        let job = job(&step, dir.path());
+        let key = |step: &Step, path: &str| {
+            let inputs = BTreeMap::from([("text".to_string(), Value::File(dir.path().join(path)))]);
+            KeyComponents::of_job(step, &job, BTreeMap::new(), &inputs, &mut BTreeMap::new())
+                .unwrap()
+                .key()
+        };
+        fs::write(dir.path().join("a.txt"), "a\nb\n").unwrap();
+        fs::write(dir.path().join("b.txt"), "a\nb\n").unwrap();
+        fs::write(dir.path().join("c.txt"), "a\n").unwrap();
+
+        // Only the contents of inputs matter, not their paths
+        assert_eq!(key(&step, "a.txt"), key(&step, "b.txt"));
+        assert_ne!(key(&step, "a.txt"), key(&step, "c.txt"));
+
+        let mut renamed = step.clone();
+        renamed.glob("lines", "count.txt");
+        assert_ne!(key(&step, "a.txt"), key(&renamed, "a.txt"));
+    }
+
+    #[test]
+    fn test_keep_and_restore() {
+        let cache_dir = TempDir::new().unwrap();
+        let first = TempDir::new().unwrap();
+        let second = TempDir::new().unwrap();
+        let cache = StepCache::new(cache_dir.path());
+        let busybox = Container::from("busybox:1.36");
+        let mut step = Step::new("split", &busybox, "split");
+        step.output("parts", PortType::Array(Box::new(PortType::File)))
+            .glob("parts", "parts/*");
+
+        let job = job(&step, first.path());
+        fs::create_dir_all(job.workdir.join("parts")).unwrap();
+        fs::write(job.workdir.join("parts/a"), "a").unwrap();
+        fs::write(job.workdir.join("scratch"), "tmp").unwrap();
+        fs::write(&job.stdout, "done\n").unwrap();
+        fs::write(&job.stderr, "").unwrap();
+        let components = KeyComponents::of_job(
+            &step,
+            &job,
+            BTreeMap::new(),
+            &BTreeMap::new(),
+            &mut BTreeMap::new(),
+        )
+        .unwrap();
+        let key = components.key();
+        assert!(!cache.contains(&key));
+        let miss = cache.explain("split", &components).unwrap();
+        assert_eq!(miss.to_string(), "no earlier results of the step");
+        cache.keep(&components, &step, &job).unwrap();
+        assert!(cache.contains(&key));
+
+        let restored = self::job(&step, second.path());
+        fs::create_dir_all(&restored.workdir).unwrap();
+        assert!(cache.restore(&key, &restored).unwrap());
+        let part = fs::read_to_string(restored.workdir.join("parts/a")).unwrap();
+        assert_eq!(part, "a");
+        assert!(!restored.workdir.join("scratch").exists());
+        assert_eq!(fs::read_to_string(&restored.stdout).unwrap(), "done\n");
+
+        // Entries whose files are gone count as missing, and leave nothing behind
+        fs::remove_dir_all(second.path()).unwrap();
+        fs::create_dir_all(&restored.workdir).unwrap();
+        fs::remove_dir_all(cache_dir.path().join("blobs")).unwrap();
+        assert!(!cache.restore(&key, &restored).unwrap());
+        assert_eq!(fs::read_dir(&restored.workdir).unwrap().count(), 0);
+        assert!(!restored.stdout.exists());
+        let miss = cache.explain("split", &components).unwrap();
+        assert_eq!(miss.nearest, Some(key));
+        assert!(miss.changes.is_empty());
+    }
+}
+
+// EOF
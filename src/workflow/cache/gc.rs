@@ -0,0 +1,580 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::StepCache;
+use super::key::{escape, unescape};
+use crate::executor::ExecutionError;
+use crate::hash::ContentHash;
+use crate::store::StoreError;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Which cached results [garbage collection](StepCache::collect_garbage) removes.
+///
+/// The results used by [pinned](StepCache::pin) runs and the [named](StepCache::name_result)
+/// results are always kept. Of the others, a result is removed if any limit calls for it:
+///
+/// - With [`keep_last_runs`](Self::keep_last_runs), the results no kept run used.
+/// - With [`max_age`](Self::max_age), the results not used for longer than the age.
+/// - With [`max_bytes`](Self::max_bytes), the least recently used results, until the store
+///   fits in the size.
+///
+/// Without any limit, only stored files that no result refers to any more are removed.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rivulet::workflow::{RetentionPolicy, StepCache};
+/// use std::time::Duration;
+///
+/// let cache = StepCache::new("/scratch/rivulet-cache");
+/// let mut policy = RetentionPolicy::new();
+/// policy
+///     .keep_last_runs(5)
+///     .max_age(Duration::from_secs(30 * 86_400))
+///     .max_bytes(500 << 30);
+/// println!("{}", cache.plan_garbage_collection(&policy)?);
+/// # Ok::<(), rivulet::executor::ExecutionError>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    keep_last_runs: Option<usize>,
+    max_age: Option<Duration>,
+    max_bytes: Option<u64>,
+}
+
+impl RetentionPolicy {
+    /// Create a policy without limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep the last `runs` runs of every workflow, and remove the results no kept run used.
+    ///
+    /// Runs are recorded as they keep and reuse results, so the last runs include failed
+    /// runs and runs still in progress. Results no run records, such as those kept by a run
+    /// about to record them, are kept if they were used since the oldest kept run started.
+    /// The records of older runs are removed too, unless they are pinned.
+    pub fn keep_last_runs(&mut self, runs: usize) -> &mut Self {
+        self.keep_last_runs = Some(runs);
+        self
+    }
+
+    /// Remove the results that were not kept or reused for longer than `age`.
+    pub fn max_age(&mut self, age: Duration) -> &mut Self {
+        self.max_age = Some(age);
+        self
+    }
+
+    /// Remove the least recently used results until the stored files take up at most
+    /// `bytes`.
+    pub fn max_bytes(&mut self, bytes: u64) -> &mut Self {
+        self.max_bytes = Some(bytes);
+        self
+    }
+}
+
+/// What garbage collection removed, or would remove in a dry run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// Whether this is the report of a dry run, which removed nothing.
+    pub dry_run: bool,
+
+    /// The keys of the removed results.
+    pub results: Vec<ContentHash>,
+
+    /// The ids of the removed run records.
+    pub runs: Vec<String>,
+
+    /// The number of removed files and directory manifests in the store.
+    pub objects: usize,
+
+    /// The number of bytes freed in the store.
+    ///
+    /// Files materialized by hard link are only freed once nothing else links to them.
+    pub bytes: u64,
+
+    /// What was freed for each workflow whose runs used a removed result.
+    ///
+    /// A file shared by the results of several workflows counts toward each of them.
+    pub workflows: BTreeMap<String, Freed>,
+}
+
+/// What garbage collection freed for a workflow.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Freed {
+    /// The number of removed results the workflow's runs used.
+    pub results: usize,
+
+    /// The number of bytes freed by removing those results.
+    pub bytes: u64,
+}
+
+impl fmt::Display for GcReport {
+    /// Format the totals on one line, followed by one line per workflow.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} bytes: {} results, {} runs, {} objects",
+            if self.dry_run { "would free" } else { "freed" },
+            self.bytes,
+            self.results.len(),
+            self.runs.len(),
+            self.objects
+        )?;
+        for (workflow, freed) in &self.workflows {
+            write!(
+                f,
+                "\n{workflow}: {} results, {} bytes",
+                freed.results, freed.bytes
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A run recorded in the cache.
+struct Run {
+    id: String,
+    workflow: String,
+    /// When the run started, since the Unix epoch.
+    started: Duration,
+    results: BTreeSet<ContentHash>,
+}
+
+/// A cached result, as garbage collection sees it.
+struct CachedResult {
+    /// The objects holding the result.
+    objects: BTreeSet<ContentHash>,
+    /// When the result was last kept or reused.
+    accessed: SystemTime,
+    /// Whether some of the objects are missing or corrupt, so the result cannot be restored.
+    broken: bool,
+}
+
+impl StepCache {
+    /// Record the start of a run of a workflow, before it uses any result.
+    pub(crate) fn record_run(
+        &self,
+        id: &str,
+        workflow: &str,
+        started: SystemTime,
+    ) -> Result<(), ExecutionError> {
+        let started = started.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let record = format!(
+            "workflow {}\nstarted {}.{:09}\n",
+            escape(workflow),
+            started.as_secs(),
+            started.subsec_nanos()
+        );
+        let path = self.dir().join("runs").join(id);
+        self.store.write_atomic(&path, record.as_bytes())?;
+        Ok(())
+    }
+
+    /// Add a result a recorded run kept or reused to its record.
+    pub(crate) fn record_result(&self, run: &str, key: &ContentHash) -> Result<(), ExecutionError> {
+        let path = self.dir().join("runs").join(run);
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut record| writeln!(record, "result {key}"))
+            .map_err(ExecutionError::io(&path))
+    }
+
+    /// Pin a recorded run, so garbage collection keeps its record and the results it used.
+    pub fn pin(&self, run: &str) -> Result<(), ExecutionError> {
+        check_name(run)?;
+        if !self.dir().join("runs").join(run).is_file() {
+            return Err(ExecutionError::UnknownRun(run.to_string()));
+        }
+        self.store
+            .write_atomic(&self.dir().join("pins").join(run), &[])?;
+        Ok(())
+    }
+
+    /// Unpin a run pinned with [`pin`](Self::pin).
+    pub fn unpin(&self, run: &str) -> Result<(), ExecutionError> {
+        check_name(run)?;
+        remove_file(self.dir().join("pins").join(run))
+    }
+
+    /// Give a cached result a name, so garbage collection keeps it.
+    ///
+    /// Names are unique: naming another result with the same name moves the name to it.
+    /// Names follow the same rules as step names.
+    pub fn name_result(&self, name: &str, key: &ContentHash) -> Result<(), ExecutionError> {
+        check_name(name)?;
+        if !self.contains(key) {
+            return Err(ExecutionError::UnknownResult(*key));
+        }
+        let path = self.dir().join("names").join(name);
+        self.store
+            .write_atomic(&path, format!("{key}\n").as_bytes())?;
+        Ok(())
+    }
+
+    /// Remove a name given with [`name_result`](Self::name_result).
+    pub fn remove_name(&self, name: &str) -> Result<(), ExecutionError> {
+        check_name(name)?;
+        remove_file(self.dir().join("names").join(name))
+    }
+
+    /// Remove the results the policy calls for, and the stored files no remaining result
+    /// refers to.
+    ///
+    /// Waits until no other process is using the store, and keeps others from using it
+    /// until done.
+    pub fn collect_garbage(&self, policy: &RetentionPolicy) -> Result<GcReport, ExecutionError> {
+        self.garbage(policy, false)
+    }
+
+    /// Report what [`collect_garbage`](Self::collect_garbage) would remove, without removing
+    /// anything.
+    pub fn plan_garbage_collection(
+        &self,
+        policy: &RetentionPolicy,
+    ) -> Result<GcReport, ExecutionError> {
+        self.garbage(policy, true)
+    }
+
+    fn garbage(&self, policy: &RetentionPolicy, dry_run: bool) -> Result<GcReport, ExecutionError> {
+        let _lock = self.store.lock_exclusive()?;
+        let results = self.cached_results()?;
+        let runs = self.runs()?;
+        let pins = self.file_names("pins")?;
+        let kept = kept_runs(policy, &runs, &pins);
+        let mut protected = protected_results(policy, &runs, &pins, &kept, &results);
+        protected.extend(self.names()?.into_values());
+
+        // Remove what the limits call for, then least recently used results while too big
+        let mut candidates: Vec<_> = results
+            .iter()
+            .filter(|(key, _)| !protected.contains(key))
+            .collect();
+        candidates.sort_by_key(|(key, result)| (result.accessed, **key));
+        let mut removed = select_expired(policy, &candidates, SystemTime::now());
+        let sizes = self.store.objects()?;
+        let references = select_lru_until_fits(policy, &results, &candidates, &sizes, &mut removed);
+        let freed: BTreeMap<ContentHash, u64> = sizes
+            .into_iter()
+            .filter(|(object, _)| !references.contains_key(object))
+            .collect();
+
+        let report = GcReport {
+            dry_run,
+            results: removed.iter().copied().collect(),
+            runs: runs
+                .iter()
+                .filter(|run| !kept.contains(run.id.as_str()))
+                .map(|run| run.id.clone())
+                .collect(),
+            objects: freed.len(),
+            bytes: freed.values().sum(),
+            workflows: per_workflow_report(&runs, &results, &removed, &freed),
+        };
+        if !dry_run {
+            self.apply(&report, &freed)?;
+        }
+        Ok(report)
+    }
+
+    /// Remove the results, run records and objects a report lists.
+    fn apply(
+        &self,
+        report: &GcReport,
+        freed: &BTreeMap<ContentHash, u64>,
+    ) -> Result<(), ExecutionError> {
+        for key in &report.results {
+            remove_file(self.entry(key))?;
+            for step in self.file_names("keys")? {
+                remove_file(self.dir().join("keys").join(step).join(key.to_string()))?;
+            }
+        }
+        for run in &report.runs {
+            remove_file(self.dir().join("runs").join(run))?;
+        }
+        for object in freed.keys() {
+            self.store.remove_object(object)?;
+        }
+        self.store.remove_tmp()?;
+        Ok(())
+    }
+
+    fn cached_results(&self) -> Result<BTreeMap<ContentHash, CachedResult>, ExecutionError> {
+        let mut results = BTreeMap::new();
+        for name in self.file_names("steps")? {
+            let Ok(key) = name.parse() else {
+                continue;
+            };
+            let path = self.entry(&key);
+            let accessed = fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .map_err(ExecutionError::io(&path))?;
+            let mut objects = BTreeSet::new();
+            let broken = match self.read_entry(&key)? {
+                Some(entry) => {
+                    objects.extend([entry.stdout, entry.stderr]);
+                    match self.store.tree_objects(&entry.work, &mut objects) {
+                        Ok(()) => ![entry.stdout, entry.stderr]
+                            .iter()
+                            .all(|hash| self.store.contains_file(hash)),
+                        Err(StoreError::MissingObject(_) | StoreError::CorruptTree { .. }) => true,
+                        Err(error) => return Err(error.into()),
+                    }
+                }
+                None => true,
+            };
+            results.insert(
+                key,
+                CachedResult {
+                    objects,
+                    accessed,
+                    broken,
+                },
+            );
+        }
+        Ok(results)
+    }
+
+    /// The recorded runs.
+    fn runs(&self) -> Result<Vec<Run>, ExecutionError> {
+        let mut runs = Vec::new();
+        for id in self.file_names("runs")? {
+            let path = self.dir().join("runs").join(&id);
+            let record = fs::read_to_string(&path).map_err(ExecutionError::io(&path))?;
+            let mut run = Run {
+                id,
+                workflow: String::new(),
+                started: Duration::ZERO,
+                results: BTreeSet::new(),
+            };
+            for line in record.lines() {
+                match line.split_once(' ') {
+                    Some(("workflow", name)) => run.workflow = unescape(name),
+                    Some(("started", time)) => run.started = parse_started(time),
+                    Some(("result", key)) => run.results.extend(key.parse::<ContentHash>().ok()),
+                    _ => {}
+                }
+            }
+            runs.push(run);
+        }
+        Ok(runs)
+    }
+
+    /// The named results, by name.
+    fn names(&self) -> Result<BTreeMap<String, ContentHash>, ExecutionError> {
+        let mut names = BTreeMap::new();
+        for name in self.file_names("names")? {
+            let path = self.dir().join("names").join(&name);
+            let key = fs::read_to_string(&path).map_err(ExecutionError::io(&path))?;
+            if let Ok(key) = key.trim().parse() {
+                names.insert(name, key);
+            }
+        }
+        Ok(names)
+    }
+
+    /// The names of the entries of a directory of the cache, which may not exist.
+    fn file_names(&self, dir: &str) -> Result<Vec<String>, ExecutionError> {
+        let dir = self.dir().join(dir);
+        let entries = match fs::read_dir(&dir) {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            result => result.map_err(ExecutionError::io(&dir))?,
+        };
+        let mut names = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(ExecutionError::io(&dir))?;
+            names.extend(entry.file_name().to_str().map(String::from));
+        }
+        names.sort();
+        Ok(names)
+    }
+}
+
+/// The ids of the runs whose records are kept: all of them, or the pinned runs and the last
+/// runs of every workflow if the policy limits the number of runs.
+fn kept_runs<'a>(
+    policy: &RetentionPolicy,
+    runs: &'a [Run],
+    pins: &'a [String],
+) -> BTreeSet<&'a str> {
+    let Some(count) = policy.keep_last_runs else {
+        return runs.iter().map(|run| run.id.as_str()).collect();
+    };
+    let mut by_workflow: BTreeMap<&str, Vec<&Run>> = BTreeMap::new();
+    for run in runs {
+        by_workflow.entry(&run.workflow).or_default().push(run);
+    }
+    let mut kept: BTreeSet<&str> = pins.iter().map(String::as_str).collect();
+    for mut runs in by_workflow.into_values() {
+        runs.sort_by(|a, b| (b.started, &b.id).cmp(&(a.started, &a.id)));
+        kept.extend(runs.iter().take(count).map(|run| run.id.as_str()));
+    }
+    kept
+}
+
+/// The keys of the results that are never removed besides named results: the results of
+/// pinned runs and, if the policy limits the number of runs, the results of kept runs and
+/// the results no run records that were used since the oldest kept run started.
+fn protected_results(
+    policy: &RetentionPolicy,
+    runs: &[Run],
+    pins: &[String],
+    kept: &BTreeSet<&str>,
+    results: &BTreeMap<ContentHash, CachedResult>,
+) -> BTreeSet<ContentHash> {
+    let mut protected = BTreeSet::new();
+    for run in runs {
+        let pinned = pins.contains(&run.id);
+        if pinned || (policy.keep_last_runs.is_some() && kept.contains(run.id.as_str())) {
+            protected.extend(run.results.iter().copied());
+        }
+    }
+    let oldest_kept = runs
+        .iter()
+        .filter(|run| kept.contains(run.id.as_str()) && !pins.contains(&run.id))
+        .map(|run| UNIX_EPOCH + run.started)
+        .min();
+    if let (Some(_), Some(oldest_kept)) = (policy.keep_last_runs, oldest_kept) {
+        let recorded: BTreeSet<_> = runs.iter().flat_map(|run| &run.results).collect();
+        protected.extend(
+            results
+                .iter()
+                .filter(|(key, result)| !recorded.contains(key) && result.accessed >= oldest_kept)
+                .map(|(key, _)| *key),
+        );
+    }
+    protected
+}
+
+/// The keys of the candidates to remove regardless of size: broken results, results older
+/// than the maximum age, and all unprotected results if the policy limits the number of runs.
+fn select_expired(
+    policy: &RetentionPolicy,
+    candidates: &[(&ContentHash, &CachedResult)],
+    now: SystemTime,
+) -> BTreeSet<ContentHash> {
+    let expired = |result: &CachedResult| {
+        policy.max_age.is_some_and(|age| {
+            now.duration_since(result.accessed)
+                .is_ok_and(|elapsed| elapsed > age)
+        })
+    };
+    candidates
+        .iter()
+        .filter(|(_, result)| result.broken || policy.keep_last_runs.is_some() || expired(result))
+        .map(|(key, _)| **key)
+        .collect()
+}
+
+/// Select the least recently used candidates for removal until the objects of the remaining
+/// results fit in the maximum size, returning how many remaining results use every object.
+fn select_lru_until_fits(
+    policy: &RetentionPolicy,
+    results: &BTreeMap<ContentHash, CachedResult>,
+    candidates: &[(&ContentHash, &CachedResult)],
+    sizes: &BTreeMap<ContentHash, u64>,
+    removed: &mut BTreeSet<ContentHash>,
+) -> BTreeMap<ContentHash, usize> {
+    let mut references: BTreeMap<ContentHash, usize> = BTreeMap::new();
+    for (key, result) in results {
+        if !removed.contains(key) {
+            for object in &result.objects {
+                *references.entry(*object).or_default() += 1;
+            }
+        }
+    }
+    let Some(max_bytes) = policy.max_bytes else {
+        return references;
+    };
+    let mut total: u64 = references
+        .keys()
+        .filter_map(|object| sizes.get(object))
+        .sum();
+    for (key, result) in candidates {
+        if total <= max_bytes {
+            break;
+        }
+        if !removed.insert(**key) {
+            continue;
+        }
+        for object in &result.objects {
+            let count = references.get_mut(object).expect("objects are referenced");
+            *count -= 1;
+            if *count == 0 {
+                references.remove(object);
+                total -= sizes.get(object).copied().unwrap_or(0);
+            }
+        }
+    }
+    references
+}
+
+/// What removing results frees for each workflow whose runs used one of them.
+fn per_workflow_report(
+    runs: &[Run],
+    results: &BTreeMap<ContentHash, CachedResult>,
+    removed: &BTreeSet<ContentHash>,
+    freed: &BTreeMap<ContentHash, u64>,
+) -> BTreeMap<String, Freed> {
+    let mut used: BTreeMap<&str, BTreeSet<&ContentHash>> = BTreeMap::new();
+    for run in runs {
+        let mut keys = run.results.intersection(removed).peekable();
+        if keys.peek().is_some() {
+            used.entry(&run.workflow).or_default().extend(keys);
+        }
+    }
+    used.into_iter()
+        .map(|(workflow, keys)| {
+            let objects: BTreeSet<_> = keys
+                .iter()
+                .flat_map(|key| &results[*key].objects)
+                .filter(|object| freed.contains_key(object))
+                .collect();
+            let freed = Freed {
+                results: keys.len(),
+                bytes: objects.iter().map(|object| freed[*object]).sum(),
+            };
+            (workflow.to_string(), freed)
+        })
+        .collect()
+}
+
+/// Check that the name of a run or result is usable as a file name.
+fn check_name(name: &str) -> Result<(), ExecutionError> {
+    let valid = !name.is_empty()
+        && !name.starts_with(['.', '-'])
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(ExecutionError::InvalidName(name.to_string()))
+    }
+}
+
+/// Parse the start time of a run: seconds since the Unix epoch, optionally with `.` and
+/// nanoseconds.
+fn parse_started(time: &str) -> Duration {
+    let (seconds, nanos) = time.split_once('.').unwrap_or((time, "0"));
+    let seconds = seconds.parse().unwrap_or(0);
+    Duration::new(seconds, nanos.parse().unwrap_or(0))
+}
+
+/// Remove a file if it exists.
+fn remove_file(path: PathBuf) -> Result<(), ExecutionError> {
+    match fs::remove_file(&path) {
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result.map_err(ExecutionError::io(path)),
+    }
+}
+
+// EOF
@@ -0,0 +1,612 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use crate::executor::{ExecutionError, Job};
+use crate::hash::{ContentHash, ContentHasher};
+use crate::workflow::{Step, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Domain of job keys; bump the version whenever the encoding changes.
+const KEY_DOMAIN: &str = "rivulet.step.v3";
+
+/// Domain of input value hashes; bump the version whenever the encoding changes.
+const VALUE_DOMAIN: &str = "rivulet.value.v1";
+
+/// Everything the cache key of a job is computed from.
+///
+/// The components of every cached result are kept next to it, so a job that misses the
+/// cache can be compared with the results it was expected to reuse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyComponents {
+    /// The image at the root of the job's container, without its digest.
+    pub image: String,
+
+    /// The digest the image is pinned to, if any.
+    pub digest: Option<String>,
+
+    /// The content hash of the job's container.
+    pub container: ContentHash,
+
+    /// The content hashes of the sources of the container's `COPY` steps in the executor's
+    /// build context, by source as written in the step.
+    pub sources: BTreeMap<String, ContentHash>,
+
+    /// The command template of the step.
+    pub command: String,
+
+    /// The outputs of the step, each as its name, type and glob pattern.
+    pub outputs: Vec<String>,
+
+    /// The inputs of the job, by name.
+    pub inputs: BTreeMap<String, InputComponent>,
+}
+
+/// An input value, as it takes part in a cache key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputComponent {
+    /// A value holding files or directories, hashed by their contents.
+    Artifact {
+        /// The names of the files and directories, without the directories they are in.
+        names: String,
+        /// The hash of the value.
+        hash: ContentHash,
+    },
+
+    /// Any other value.
+    Parameter {
+        /// The value, formatted for messages.
+        value: String,
+        /// The hash of the value.
+        hash: ContentHash,
+    },
+}
+
+impl InputComponent {
+    /// The component of a value, with files and directories hashed by content.
+    fn of_value(
+        value: &Value,
+        hashes: &mut BTreeMap<PathBuf, ContentHash>,
+    ) -> Result<Self, ExecutionError> {
+        let mut hasher = ContentHasher::new(VALUE_DOMAIN);
+        hash_value(&mut hasher, value, hashes)?;
+        let hash = hasher.finish();
+        Ok(if has_paths(value) {
+            InputComponent::Artifact {
+                names: names(value),
+                hash,
+            }
+        } else {
+            InputComponent::Parameter {
+                value: value.to_string(),
+                hash,
+            }
+        })
+    }
+
+    /// The hash of the value.
+    pub fn hash(&self) -> &ContentHash {
+        match self {
+            InputComponent::Artifact { hash, .. } | InputComponent::Parameter { hash, .. } => hash,
+        }
+    }
+
+    /// How the input `name` changed from an earlier value to this one.
+    fn change_since(&self, name: &str, before: &InputComponent) -> KeyChange {
+        match (before, self) {
+            (
+                InputComponent::Parameter { value: before, .. },
+                InputComponent::Parameter { value: after, .. },
+            ) => KeyChange::Parameter {
+                input: name.to_string(),
+                before: before.clone(),
+                after: after.clone(),
+            },
+            (_, InputComponent::Artifact { names, .. }) => KeyChange::Artifact {
+                input: name.to_string(),
+                names: names.clone(),
+            },
+            (InputComponent::Artifact { names, .. }, InputComponent::Parameter { value, .. }) => {
+                KeyChange::Parameter {
+                    input: name.to_string(),
+                    before: names.clone(),
+                    after: value.clone(),
+                }
+            }
+        }
+    }
+}
+
+/// A difference between the components of two cache keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyChange {
+    /// The container is based on another image.
+    Image {
+        /// The earlier image.
+        before: String,
+        /// The current image.
+        after: String,
+    },
+
+    /// The container's image is pinned to another digest, or was pinned or unpinned.
+    Digest {
+        /// The tag of the image.
+        tag: String,
+    },
+
+    /// The container's build steps or settings changed.
+    Container,
+
+    /// The contents of a source of the container's `COPY` steps changed.
+    Source(String),
+
+    /// The command template changed.
+    Command,
+
+    /// The outputs of the step changed.
+    Outputs,
+
+    /// The contents of the files or directories given to an input changed.
+    Artifact {
+        /// The input name.
+        input: String,
+        /// The names of the current files and directories.
+        names: String,
+    },
+
+    /// The value of a parameter changed.
+    Parameter {
+        /// The input name.
+        input: String,
+        /// The earlier value.
+        before: String,
+        /// The current value.
+        after: String,
+    },
+
+    /// An input was given that was not before.
+    InputAdded(String),
+
+    /// An input that was given before is not any more.
+    InputRemoved(String),
+}
+
+impl fmt::Display for KeyChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyChange::Image { before, after } => {
+                write!(f, "container image changed {before}→{after}")
+            }
+            KeyChange::Digest { tag } => {
+                write!(f, "digest of container tag {tag} changed")
+            }
+            KeyChange::Container => f.write_str("container build steps or settings changed"),
+            KeyChange::Source(source) => write!(f, "container COPY source {source} changed hash"),
+            KeyChange::Command => f.write_str("command changed"),
+            KeyChange::Outputs => f.write_str("outputs changed"),
+            KeyChange::Artifact { names, .. } => write!(f, "input {names} changed hash"),
+            KeyChange::Parameter {
+                input,
+                before,
+                after,
+            } => write!(f, "parameter {input} changed {before}→{after}"),
+            KeyChange::InputAdded(input) => write!(f, "input {input} was added"),
+            KeyChange::InputRemoved(input) => write!(f, "input {input} was removed"),
+        }
+    }
+}
+
+/// Why a job missed the cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheMiss {
+    /// The key of the earlier result of the step that differs least from the job, if the
+    /// step has any cached results.
+    pub nearest: Option<ContentHash>,
+
+    /// What differs between that result and the job.
+    ///
+    /// Empty if the job's key was cached before, but its files are no longer stored.
+    pub changes: Vec<KeyChange>,
+}
+
+impl fmt::Display for CacheMiss {
+    /// Format the changes, separated by semicolons.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.nearest.is_none() {
+            return f.write_str("no earlier results of the step");
+        }
+        if self.changes.is_empty() {
+            return f.write_str("earlier results of the same job are no longer stored");
+        }
+        for (index, change) in self.changes.iter().enumerate() {
+            if index > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{change}")?;
+        }
+        Ok(())
+    }
+}
+
+impl KeyComponents {
+    /// Gather the components of a job's key, from its step, the hashes of its container's
+    /// `COPY` sources and its staged inputs, adding the content hashes of the files and
+    /// directories among the inputs to `hashes`, by path.
+    pub(crate) fn of_job(
+        step: &Step,
+        job: &Job,
+        sources: BTreeMap<String, ContentHash>,
+        inputs: &BTreeMap<String, Value>,
+        hashes: &mut BTreeMap<PathBuf, ContentHash>,
+    ) -> Result<Self, ExecutionError> {
+        let mut image = job.container.image().clone();
+        let digest = image.digest.take().map(|digest| digest.to_string());
+        let outputs = step
+            .outputs()
+            .iter()
+            .map(|port| {
+                let glob = step.output_glob(&port.name);
+                format!("{}: {} = {glob}", port.name, port.port_type)
+            })
+            .collect();
+        let inputs = inputs
+            .iter()
+            .map(|(name, value)| Ok((name.clone(), InputComponent::of_value(value, hashes)?)))
+            .collect::<Result<_, ExecutionError>>()?;
+        Ok(Self {
+            image: image.to_string(),
+            digest,
+            container: job.container.content_hash(),
+            sources,
+            command: step.command().to_string(),
+            outputs,
+            inputs,
+        })
+    }
+
+    /// The cache key.
+    ///
+    /// The image and digest take part through the container hash.
+    pub fn key(&self) -> ContentHash {
+        let mut hasher = ContentHasher::new(KEY_DOMAIN);
+        hasher
+            .bytes(self.container.as_bytes())
+            .u64(self.sources.len() as u64);
+        for (source, hash) in &self.sources {
+            hasher.str(source).bytes(hash.as_bytes());
+        }
+        hasher.str(&self.command).u64(self.outputs.len() as u64);
+        for output in &self.outputs {
+            hasher.str(output);
+        }
+        hasher.u64(self.inputs.len() as u64);
+        for (name, input) in &self.inputs {
+            hasher.str(name).bytes(input.hash().as_bytes());
+        }
+        hasher.finish()
+    }
+
+    /// The changes from earlier components to these.
+    pub fn changes_since(&self, earlier: &KeyComponents) -> Vec<KeyChange> {
+        let mut changes = Vec::new();
+        if self.image != earlier.image {
+            changes.push(KeyChange::Image {
+                before: earlier.image.clone(),
+                after: self.image.clone(),
+            });
+        } else if self.digest != earlier.digest {
+            let tag = self.image.rsplit_once(':').map_or("latest", |(_, tag)| tag);
+            changes.push(KeyChange::Digest {
+                tag: if tag.contains('/') { "latest" } else { tag }.to_string(),
+            });
+        } else if self.container != earlier.container {
+            changes.push(KeyChange::Container);
+        } else {
+            for (source, hash) in &self.sources {
+                if earlier
+                    .sources
+                    .get(source)
+                    .is_some_and(|before| before != hash)
+                {
+                    changes.push(KeyChange::Source(source.clone()));
+                }
+            }
+        }
+        if self.command != earlier.command {
+            changes.push(KeyChange::Command);
+        }
+        if self.outputs != earlier.outputs {
+            changes.push(KeyChange::Outputs);
+        }
+
+        for (name, input) in &self.inputs {
+            let Some(before) = earlier.inputs.get(name) else {
+                changes.push(KeyChange::InputAdded(name.clone()));
+                continue;
+            };
+            if input.hash() == before.hash() {
+                continue;
+            }
+            changes.push(input.change_since(name, before));
+        }
+        for name in earlier.inputs.keys() {
+            if !self.inputs.contains_key(name) {
+                changes.push(KeyChange::InputRemoved(name.clone()));
+            }
+        }
+        changes
+    }
+
+    /// Write the components as text, one per line.
+    pub(super) fn to_record(&self) -> String {
+        let mut record = format!("image {}\n", escape(&self.image));
+        if let Some(digest) = &self.digest {
+            record.push_str(&format!("digest {}\n", escape(digest)));
+        }
+        record.push_str(&format!("container {}\n", self.container));
+        for (source, hash) in &self.sources {
+            record.push_str(&format!("source {hash} {}\n", escape(source)));
+        }
+        record.push_str(&format!("command {}\n", escape(&self.command)));
+        for output in &self.outputs {
+            record.push_str(&format!("output {}\n", escape(output)));
+        }
+        for (name, input) in &self.inputs {
+            let (kind, text) = match input {
+                InputComponent::Artifact { names, .. } => ("artifact", names),
+                InputComponent::Parameter { value, .. } => ("parameter", value),
+            };
+            let hash = input.hash();
+            record.push_str(&format!("{kind} {name} {hash} {}\n", escape(text)));
+        }
+        record
+    }
+
+    /// Read components written by [`to_record`](Self::to_record).
+    pub(super) fn from_record(record: &str) -> Option<Self> {
+        let mut image = None;
+        let mut digest = None;
+        let mut container = None;
+        let mut sources = BTreeMap::new();
+        let mut command = None;
+        let mut outputs = Vec::new();
+        let mut inputs = BTreeMap::new();
+        for line in record.lines() {
+            let (field, rest) = line.split_once(' ')?;
+            match field {
+                "image" => image = Some(unescape(rest)),
+                "digest" => digest = Some(unescape(rest)),
+                "container" => container = Some(rest.parse().ok()?),
+                "source" => {
+                    let (hash, source) = rest.split_once(' ')?;
+                    sources.insert(unescape(source), hash.parse().ok()?);
+                }
+                "command" => command = Some(unescape(rest)),
+                "output" => outputs.push(unescape(rest)),
+                "artifact" | "parameter" => {
+                    let mut parts = rest.splitn(3, ' ');
+                    let (name, hash, text) = (parts.next()?, parts.next()?, parts.next()?);
+                    let hash = hash.parse().ok()?;
+                    let text = unescape(text);
+                    let input = if field == "artifact" {
+                        InputComponent::Artifact { names: text, hash }
+                    } else {
+                        InputComponent::Parameter { value: text, hash }
+                    };
+                    inputs.insert(name.to_string(), input);
+                }
+                _ => return None,
+            }
+        }
+        Some(Self {
+            image: image?,
+            digest,
+            container: container?,
+            sources,
+            command: command?,
+            outputs,
+            inputs,
+        })
+    }
+}
+
+/// Add a value to a hash, with files and directories hashed by content, and their hashes
+/// added to `hashes`.
+fn hash_value(
+    hasher: &mut ContentHasher,
+    value: &Value,
+    hashes: &mut BTreeMap<PathBuf, ContentHash>,
+) -> Result<(), ExecutionError> {
+    match value {
+        Value::File(path) => {
+            let hash = ContentHash::of_file(path).map_err(ExecutionError::io(path))?;
+            hasher.str("file").bytes(hash.as_bytes());
+            hashes.insert(path.clone(), hash);
+        }
+        Value::Directory(path) => {
+            let hash = ContentHash::of_directory(path).map_err(ExecutionError::io(path))?;
+            hasher.str("directory").bytes(hash.as_bytes());
+            hashes.insert(path.clone(), hash);
+        }
+        Value::Int(n) => {
+            hasher.str("int").u64(*n as u64);
+        }
+        Value::Float(x) => {
+            hasher.str("float").u64(x.to_bits());
+        }
+        Value::String(s) => {
+            hasher.str("string").str(s);
+        }
+        Value::Bool(b) => {
+            hasher.str("bool").u64(u64::from(*b));
+        }
+        Value::Array(items) => {
+            hasher.str("array").u64(items.len() as u64);
+            for item in items {
+                hash_value(hasher, item, hashes)?;
+            }
+        }
+        Value::Null => {
+            hasher.str("null");
+        }
+    }
+    Ok(())
+}
+
+/// Whether a value holds files or directories.
+fn has_paths(value: &Value) -> bool {
+    match value {
+        Value::File(_) | Value::Directory(_) => true,
+        Value::Array(items) => items.iter().any(has_paths),
+        _ => false,
+    }
+}
+
+/// A value with its files and directories shown by name only.
+fn names(value: &Value) -> String {
+    match value {
+        Value::File(path) | Value::Directory(path) => path.file_name().map_or_else(
+            || path.display().to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        ),
+        Value::Array(items) => {
+            let names: Vec<_> = items.iter().map(names).collect();
+            format!("[{}]", names.join(", "))
+        }
+        scalar => scalar.to_string(),
+    }
+}
+
+/// Escape backslashes and line breaks, so a value fits on one line of a record.
+pub(super) fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Undo [`escape`].
+pub(super) fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn components(image: &str, threads: i64, reads: &str) -> KeyComponents {
+        let hash = |text: &str| {
+            let mut hasher = ContentHasher::new("test");
+            hasher.str(text);
+            hasher.finish()
+        };
+        let selector = crate::container::ImageSelector::from_str(image).unwrap();
+        let mut unpinned = selector.clone();
+        unpinned.digest = None;
+        KeyComponents {
+            image: unpinned.to_string(),
+            digest: selector.digest.map(|digest| digest.to_string()),
+            container: hash(image),
+            sources: BTreeMap::from([("quant.sh".to_string(), hash("mv quant out"))]),
+            command: "salmon quant -p {threads} -r {reads}\nmv quant out".to_string(),
+            outputs: vec!["quant: Directory = out".to_string()],
+            inputs: BTreeMap::from([
+                (
+                    "reads".to_string(),
+                    InputComponent::Artifact {
+                        names: "reads.fq".to_string(),
+                        hash: hash(reads),
+                    },
+                ),
+                (
+                    "threads".to_string(),
+                    InputComponent::Parameter {
+                        value: threads.to_string(),
+                        hash: hash(&threads.to_string()),
+                    },
+                ),
+            ]),
+        }
+    }
+
+    fn pinned(n: char) -> String {
+        format!("salmon:latest@sha256:{}", n.to_string().repeat(64))
+    }
+
+    #[test]
+    fn test_changes() {
+        let before = components(&pinned('a'), 8, "ACGT");
+        assert!(before.changes_since(&before).is_empty());
+
+        let after = components(&pinned('b'), 16, "ACGA");
+        let changes: Vec<_> = after
+            .changes_since(&before)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                "digest of container tag latest changed",
+                "input reads.fq changed hash",
+                "parameter threads changed 8→16",
+            ]
+        );
+        assert_ne!(after.key(), before.key());
+
+        let mut edited = before.clone();
+        edited
+            .sources
+            .insert("quant.sh".to_string(), edited.container);
+        assert!(matches!(
+            edited.changes_since(&before).as_slice(),
+            [KeyChange::Source(source)] if source == "quant.sh"
+        ));
+        assert_eq!(
+            KeyChange::Source("quant.sh".to_string()).to_string(),
+            "container COPY source quant.sh changed hash"
+        );
+        assert_ne!(edited.key(), before.key());
+
+        let mut removed = components("salmon:1.10", 8, "ACGT");
+        removed.inputs.remove("threads");
+        removed.command.push_str(" --quiet");
+        let miss = CacheMiss {
+            nearest: Some(before.key()),
+            changes: removed.changes_since(&before),
+        };
+        assert_eq!(
+            miss.to_string(),
+            "container image changed salmon:latest→salmon:1.10; command changed; \
+             input threads was removed"
+        );
+    }
+
+    #[test]
+    fn test_records() {
+        let components = components(&pinned('a'), 8, "ACGT");
+        let record = components.to_record();
+        assert_eq!(record.lines().count(), 8);
+        assert_eq!(KeyComponents::from_record(&record), Some(components));
+        assert_eq!(KeyComponents::from_record("image x\n"), None);
+        assert_eq!(unescape(&escape("a\\n\nb\r")), "a\\n\nb\r");
+    }
+}
+
+// EOF
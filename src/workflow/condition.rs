@@ -0,0 +1,210 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::{PortType, Step, Value, WorkflowError};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops;
+
+/// A condition on the inputs of a step, deciding whether the step runs.
+///
+/// Conditions name inputs of the step they guard. To make a step depend on a workflow
+/// parameter, connect the parameter to an input of the step; the command does not have to use
+/// it. Steps with a condition are set with [`Step::when`].
+///
+/// # Examples
+///
+/// ```
+/// use rivulet::workflow::{Condition, Value};
+///
+/// // Trim adapters only if QC found them and trimming was not turned off
+/// let condition = Condition::all([
+///     Condition::is_true("adapters"),
+///     !Condition::equals("trimmer", Value::from("none")),
+/// ]);
+/// assert_eq!(condition.to_string(), "(adapters and not trimmer == \"none\")");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    /// The input is not null.
+    Present(String),
+
+    /// The input, a `Bool`, is true.
+    True(String),
+
+    /// The input equals a value.
+    Equals(String, Value),
+
+    /// The inner condition does not hold.
+    Not(Box<Condition>),
+
+    /// All inner conditions hold; true if there are none.
+    All(Vec<Condition>),
+
+    /// At least one inner condition holds; false if there are none.
+    Any(Vec<Condition>),
+}
+
+impl Condition {
+    /// Require an input to be present, not null.
+    pub fn present(input: impl Into<String>) -> Self {
+        Self::Present(input.into())
+    }
+
+    /// Require a `Bool` input to be true.
+    pub fn is_true(input: impl Into<String>) -> Self {
+        Self::True(input.into())
+    }
+
+    /// Require an input to equal a value.
+    pub fn equals(input: impl Into<String>, value: Value) -> Self {
+        Self::Equals(input.into(), value)
+    }
+
+    /// Require all of several conditions to hold.
+    pub fn all(conditions: impl IntoIterator<Item = Condition>) -> Self {
+        Self::All(conditions.into_iter().collect())
+    }
+
+    /// Require at least one of several conditions to hold.
+    pub fn any(conditions: impl IntoIterator<Item = Condition>) -> Self {
+        Self::Any(conditions.into_iter().collect())
+    }
+
+    /// Check that the condition only names inputs of a step, with types it can be evaluated
+    /// on.
+    pub(crate) fn check(&self, step: &Step) -> Result<(), WorkflowError> {
+        let input_type = |input: &String| match step.input_port(input) {
+            Some(port) => Ok(&port.port_type),
+            None => Err(WorkflowError::UnknownStepInput {
+                step: step.name().to_string(),
+                port: input.clone(),
+            }),
+        };
+        let invalid = |input: &String, port_type: &PortType| WorkflowError::InvalidCondition {
+            step: step.name().to_string(),
+            port: input.clone(),
+            port_type: port_type.clone(),
+        };
+        match self {
+            Condition::Present(input) => input_type(input).map(drop),
+            Condition::True(input) => {
+                let port_type = input_type(input)?;
+                if PortType::optional(PortType::Bool).accepts(port_type) {
+                    Ok(())
+                } else {
+                    Err(invalid(input, port_type))
+                }
+            }
+            Condition::Equals(input, value) => {
+                let port_type = input_type(input)?;
+                if value.conforms_to(port_type) {
+                    Ok(())
+                } else {
+                    Err(invalid(input, port_type))
+                }
+            }
+            Condition::Not(condition) => condition.check(step),
+            Condition::All(conditions) | Condition::Any(conditions) => conditions
+                .iter()
+                .try_for_each(|condition| condition.check(step)),
+        }
+    }
+
+    /// Evaluate the condition against the input values of a job.
+    pub(crate) fn evaluate(&self, inputs: &BTreeMap<String, Value>) -> bool {
+        match self {
+            Condition::Present(input) => !matches!(inputs.get(input), None | Some(Value::Null)),
+            Condition::True(input) => inputs.get(input) == Some(&Value::Bool(true)),
+            Condition::Equals(input, value) => inputs.get(input) == Some(value),
+            Condition::Not(condition) => !condition.evaluate(inputs),
+            Condition::All(conditions) => conditions.iter().all(|c| c.evaluate(inputs)),
+            Condition::Any(conditions) => conditions.iter().any(|c| c.evaluate(inputs)),
+        }
+    }
+}
+
+impl ops::Not for Condition {
+    type Output = Condition;
+
+    fn not(self) -> Condition {
+        Condition::Not(Box::new(self))
+    }
+}
+
+impl fmt::Display for Condition {
+    /// Format the condition for logs and messages.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |f: &mut fmt::Formatter<'_>, conditions: &[Condition], separator| {
+            f.write_str("(")?;
+            for (index, condition) in conditions.iter().enumerate() {
+                if index > 0 {
+                    f.write_str(separator)?;
+                }
+                write!(f, "{condition}")?;
+            }
+            f.write_str(")")
+        };
+        match self {
+            Condition::Present(input) => write!(f, "{input} is present"),
+            Condition::True(input) => f.write_str(input),
+            Condition::Equals(input, value) => write!(f, "{input} == {value}"),
+            Condition::Not(condition) => write!(f, "not {condition}"),
+            Condition::All(conditions) => join(f, conditions, " and "),
+            Condition::Any(conditions) => join(f, conditions, " or "),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::Container;
+
+    #[test]
+    fn test_evaluate() {
+        let inputs = BTreeMap::from([
+            ("adapters".to_string(), Value::Bool(true)),
+            ("trimmer".to_string(), Value::from("cutadapt")),
+            ("report".to_string(), Value::Null),
+        ]);
+        assert!(Condition::is_true("adapters").evaluate(&inputs));
+        assert!(Condition::present("trimmer").evaluate(&inputs));
+        assert!(!Condition::present("report").evaluate(&inputs));
+        assert!(Condition::equals("trimmer", Value::from("cutadapt")).evaluate(&inputs));
+        assert!((!Condition::is_true("trimmer")).evaluate(&inputs));
+        assert!(Condition::all([]).evaluate(&inputs));
+        assert!(!Condition::any([]).evaluate(&inputs));
+        assert!(
+            Condition::any([Condition::present("report"), Condition::is_true("adapters")])
+                .evaluate(&inputs)
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let mut step = Step::new("trim", &Container::from("alpine"), "true");
+        step.input("adapters", PortType::optional(PortType::Bool))
+            .input("trimmer", PortType::String);
+        assert_eq!(Condition::is_true("adapters").check(&step), Ok(()));
+        assert_eq!(
+            (!Condition::is_true("trimmer")).check(&step),
+            Err(WorkflowError::InvalidCondition {
+                step: "trim".to_string(),
+                port: "trimmer".to_string(),
+                port_type: PortType::String,
+            })
+        );
+        assert!(matches!(
+            Condition::equals("trimmer", Value::Int(1)).check(&step),
+            Err(WorkflowError::InvalidCondition { .. })
+        ));
+        assert!(matches!(
+            Condition::all([Condition::present("missing")]).check(&step),
+            Err(WorkflowError::UnknownStepInput { .. })
+        ));
+    }
+}
+
+// EOF
@@ -0,0 +1,669 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::{StepResult, Value};
+use crate::container::ImageSelector;
+use crate::executor::ExecutionError;
+use crate::hash::ContentHash;
+use crate::timestamp;
+#[cfg(feature = "serde")]
+use serde_json::{Map, Value as Json, json};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The provenance of a run, in the terms of the [W3C PROV] data model.
+///
+/// - The files and directories the jobs used and generated are *entities*, identified by
+///   their [`ContentHash`]. A file generated by one step and used by the next is the same
+///   entity, so the records chain from the workflow inputs to its outputs.
+/// - The jobs are *activities*, with the times the command started and finished and the
+///   host it ran on.
+/// - The containers the jobs ran in are *software agents*, identified by their content hash
+///   and described by their image, with its pinned digest if the image has one. They act on
+///   behalf of the user who started the run, a *person*.
+///
+/// [`Runner::run`](super::Runner::run) writes the provenance of every run next to its log,
+/// as [PROV-N] in `provenance.provn` and, with the `serde` feature, as [PROV-JSON] in
+/// `provenance.json`. A failed run records the jobs that completed before the failure.
+///
+/// [W3C PROV]: https://www.w3.org/TR/prov-dm/
+/// [PROV-JSON]: https://www.w3.org/submissions/prov-json/
+/// [PROV-N]: https://www.w3.org/TR/prov-n/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    /// The id of the run.
+    pub run: String,
+
+    /// The name of the workflow.
+    pub workflow: String,
+
+    /// The user who started the run, from the `USER` or `USERNAME` environment variable.
+    pub user: Option<String>,
+
+    /// The files and directories the jobs used or generated, by content hash.
+    pub artifacts: BTreeMap<ContentHash, Artifact>,
+
+    /// The jobs, in the order they ran.
+    pub jobs: Vec<JobActivity>,
+
+    /// The images of the containers the jobs ran in, by the content hash of the container.
+    pub containers: BTreeMap<ContentHash, ImageSelector>,
+}
+
+/// A file or directory in the [`Provenance`] of a run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Artifact {
+    /// Where a job generated the artifact, or where it was read from if no job generated it.
+    pub path: PathBuf,
+
+    /// Whether the artifact is a directory.
+    pub directory: bool,
+}
+
+/// A job in the [`Provenance`] of a run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobActivity {
+    /// The step name.
+    pub step: String,
+
+    /// The position of the job among the jobs of a scattered step, or `None` if the step is
+    /// not scattered.
+    pub index: Option<usize>,
+
+    /// When the command was started.
+    pub started: SystemTime,
+
+    /// When the command finished.
+    pub finished: SystemTime,
+
+    /// The host the command ran on, if the executor knows it.
+    pub host: Option<String>,
+
+    /// Whether the outputs were restored from a cache instead of running the command.
+    pub cached: bool,
+
+    /// The content hash of the container the command ran in.
+    pub container: ContentHash,
+
+    /// The artifacts the job used, with the input ports they were passed to.
+    pub used: Vec<(String, ContentHash)>,
+
+    /// The artifacts the job generated, with the output ports they were collected for.
+    pub generated: Vec<(String, ContentHash)>,
+}
+
+impl JobActivity {
+    /// The name of the job, as the executor knew it.
+    fn name(&self) -> String {
+        match self.index {
+            Some(index) => format!("{}_{index}", self.step),
+            None => self.step.clone(),
+        }
+    }
+}
+
+impl Provenance {
+    /// Record the provenance of the steps of a run, hashing the artifacts they used and
+    /// generated unless their hashes are known from the cache keys of the jobs.
+    pub(crate) fn of_run(
+        run: &str,
+        workflow: &str,
+        steps: &[StepResult],
+    ) -> Result<Self, ExecutionError> {
+        let mut provenance = Self {
+            run: run.to_string(),
+            workflow: workflow.to_string(),
+            user: ["USER", "USERNAME"]
+                .into_iter()
+                .find_map(|name| env::var(name).ok().filter(|user| !user.is_empty())),
+            artifacts: BTreeMap::new(),
+            jobs: Vec::new(),
+            containers: BTreeMap::new(),
+        };
+        // Staged symlinks lead back to the outputs they stage, so their hashes are known too
+        let mut known = steps
+            .iter()
+            .flat_map(|step| &step.input_hashes)
+            .map(|(path, hash)| (fs::canonicalize(path).unwrap_or(path.clone()), *hash))
+            .collect();
+        for step in steps {
+            provenance.add_step(step, &mut known)?;
+        }
+        Ok(provenance)
+    }
+
+    /// Record the job of a step, with its container and the artifacts it used and generated.
+    fn add_step(
+        &mut self,
+        step: &StepResult,
+        known: &mut BTreeMap<PathBuf, ContentHash>,
+    ) -> Result<(), ExecutionError> {
+        let container = step.container.content_hash();
+        self.containers
+            .entry(container)
+            .or_insert_with(|| step.container.image().clone());
+        let mut job = JobActivity {
+            step: step.name.clone(),
+            index: step.index,
+            started: step.started,
+            finished: step.finished,
+            host: step.host.clone(),
+            cached: step.cached,
+            container,
+            used: Vec::new(),
+            generated: Vec::new(),
+        };
+        for (port, value) in &step.inputs {
+            for (path, artifact) in hash_artifacts(value, known)? {
+                self.artifacts.entry(artifact).or_insert_with(|| Artifact {
+                    directory: path.is_dir(),
+                    // Follow staged symlinks back to the original
+                    path: fs::canonicalize(&path).unwrap_or(path),
+                });
+                job.used.push((port.clone(), artifact));
+            }
+        }
+        for (port, value) in &step.outputs {
+            for (path, artifact) in hash_artifacts(value, known)? {
+                let directory = path.is_dir();
+                self.artifacts
+                    .insert(artifact, Artifact { path, directory });
+                job.generated.push((port.clone(), artifact));
+            }
+        }
+        self.jobs.push(job);
+        Ok(())
+    }
+
+    /// Write the provenance to `provenance.provn` in a directory and, with the `serde`
+    /// feature, to `provenance.json`.
+    pub(crate) fn write(&self, dir: &Path) -> Result<(), ExecutionError> {
+        let write = |name: &str, contents: String| {
+            let path = dir.join(name);
+            fs::write(&path, contents).map_err(ExecutionError::io(&path))
+        };
+        write("provenance.provn", self.to_prov_n())?;
+        #[cfg(feature = "serde")]
+        write("provenance.json", self.to_prov_json())?;
+        Ok(())
+    }
+
+    /// Serialize the provenance as a [PROV-JSON](https://www.w3.org/submissions/prov-json/)
+    /// document.
+    #[cfg(feature = "serde")]
+    pub fn to_prov_json(&self) -> String {
+        let prefixes = self
+            .prefixes()
+            .into_iter()
+            .map(|(prefix, iri)| (prefix.to_string(), Json::String(iri)))
+            .collect();
+        let mut document = Map::new();
+        document.insert("prefix".to_string(), Json::Object(prefixes));
+        document.insert("entity".to_string(), Json::Object(self.json_entities()));
+        document.insert("activity".to_string(), Json::Object(self.json_activities()));
+        document.insert("agent".to_string(), Json::Object(self.json_agents()));
+        for (kind, relations) in self.json_relations() {
+            document.insert(kind.to_string(), Json::Object(relations));
+        }
+        let mut json = serde_json::to_string_pretty(&document).expect("JSON values serialize");
+        json.push('\n');
+        json
+    }
+
+    /// The entities of the artifacts, as PROV-JSON.
+    #[cfg(feature = "serde")]
+    fn json_entities(&self) -> Map<String, Json> {
+        self.artifacts
+            .iter()
+            .map(|(hash, artifact)| {
+                let attributes = json_attributes(artifact_attributes(hash, artifact));
+                (artifact_id(hash), Json::Object(attributes))
+            })
+            .collect()
+    }
+
+    /// The activities of the jobs, as PROV-JSON.
+    #[cfg(feature = "serde")]
+    fn json_activities(&self) -> Map<String, Json> {
+        self.jobs
+            .iter()
+            .map(|job| {
+                let mut attributes = json_attributes(self.job_attributes(job));
+                attributes.insert("prov:startTime".to_string(), time(job.started).into());
+                attributes.insert("prov:endTime".to_string(), time(job.finished).into());
+                (job_id(job), Json::Object(attributes))
+            })
+            .collect()
+    }
+
+    /// The agents of the containers and the user, as PROV-JSON.
+    #[cfg(feature = "serde")]
+    fn json_agents(&self) -> Map<String, Json> {
+        let mut agents: Map<String, Json> = self
+            .containers
+            .iter()
+            .map(|(hash, image)| {
+                let attributes = json_attributes(container_attributes(image));
+                (container_id(hash), Json::Object(attributes))
+            })
+            .collect();
+        if let Some(user) = &self.user {
+            let person = Literal::QualifiedName("prov:Person").to_json();
+            agents.insert(user_id(user), json!({ "prov:type": person }));
+        }
+        agents
+    }
+
+    /// The relations between the entities, activities and agents as PROV-JSON, by kind.
+    ///
+    /// Relations are identified by blank nodes, numbered per kind.
+    #[cfg(feature = "serde")]
+    fn json_relations(&self) -> Vec<(&'static str, Map<String, Json>)> {
+        let (mut used, mut generated, mut associations) = (Vec::new(), Vec::new(), Vec::new());
+        for job in &self.jobs {
+            for (port, artifact) in &job.used {
+                used.push(json!({
+                    "prov:activity": job_id(job),
+                    "prov:entity": artifact_id(artifact),
+                    "prov:time": time(job.started),
+                    "prov:role": port,
+                }));
+            }
+            for (port, artifact) in &job.generated {
+                generated.push(json!({
+                    "prov:entity": artifact_id(artifact),
+                    "prov:activity": job_id(job),
+                    "prov:time": time(job.finished),
+                    "prov:role": port,
+                }));
+            }
+            associations.push(json!({
+                "prov:activity": job_id(job),
+                "prov:agent": container_id(&job.container),
+            }));
+        }
+        let delegations = self.user.iter().flat_map(|user| {
+            self.containers.keys().map(move |hash| {
+                json!({ "prov:delegate": container_id(hash), "prov:responsible": user_id(user) })
+            })
+        });
+        [
+            ("used", "u", used),
+            ("wasGeneratedBy", "g", generated),
+            ("wasAssociatedWith", "a", associations),
+            ("actedOnBehalfOf", "d", delegations.collect()),
+        ]
+        .into_iter()
+        .filter(|(_, _, relations)| !relations.is_empty())
+        .map(|(kind, prefix, relations)| {
+            let relations = relations
+                .into_iter()
+                .enumerate()
+                .map(|(n, relation)| (format!("_:{prefix}{}", n + 1), relation))
+                .collect();
+            (kind, relations)
+        })
+        .collect()
+    }
+
+    /// Serialize the provenance as a [PROV-N](https://www.w3.org/TR/prov-n/) document.
+    pub fn to_prov_n(&self) -> String {
+        let mut provn = String::from("document\n");
+        for (prefix, iri) in self.prefixes() {
+            writeln!(provn, "  prefix {prefix} <{iri}>").unwrap();
+        }
+        provn.push('\n');
+        self.write_prov_n_elements(&mut provn);
+        self.write_prov_n_relations(&mut provn);
+        provn.push_str("endDocument\n");
+        provn
+    }
+
+    /// Write the entities, activities and agents as PROV-N.
+    fn write_prov_n_elements(&self, provn: &mut String) {
+        for (hash, artifact) in &self.artifacts {
+            let attributes = prov_n_attributes(&artifact_attributes(hash, artifact));
+            let id = qualified_name(&artifact_id(hash));
+            writeln!(provn, "  entity({id}, {attributes})").unwrap();
+        }
+        for job in &self.jobs {
+            writeln!(
+                provn,
+                "  activity({}, {}, {}, {})",
+                qualified_name(&job_id(job)),
+                timestamp::rfc3339(job.started),
+                timestamp::rfc3339(job.finished),
+                prov_n_attributes(&self.job_attributes(job))
+            )
+            .unwrap();
+        }
+        for (hash, image) in &self.containers {
+            let attributes = prov_n_attributes(&container_attributes(image));
+            let id = qualified_name(&container_id(hash));
+            writeln!(provn, "  agent({id}, {attributes})").unwrap();
+        }
+        if let Some(user) = &self.user {
+            let id = qualified_name(&user_id(user));
+            writeln!(provn, "  agent({id}, [prov:type='prov:Person'])").unwrap();
+        }
+    }
+
+    /// Write the relations between the entities, activities and agents as PROV-N.
+    fn write_prov_n_relations(&self, provn: &mut String) {
+        for job in &self.jobs {
+            let activity = qualified_name(&job_id(job));
+            for (port, artifact) in &job.used {
+                let entity = qualified_name(&artifact_id(artifact));
+                let time = timestamp::rfc3339(job.started);
+                let role = string_literal(port);
+                writeln!(
+                    provn,
+                    "  used({activity}, {entity}, {time}, [prov:role={role}])"
+                )
+                .unwrap();
+            }
+            for (port, artifact) in &job.generated {
+                let entity = qualified_name(&artifact_id(artifact));
+                let time = timestamp::rfc3339(job.finished);
+                let role = string_literal(port);
+                writeln!(
+                    provn,
+                    "  wasGeneratedBy({entity}, {activity}, {time}, [prov:role={role}])"
+                )
+                .unwrap();
+            }
+            let agent = qualified_name(&container_id(&job.container));
+            writeln!(provn, "  wasAssociatedWith({activity}, {agent}, -)").unwrap();
+        }
+        if let Some(user) = &self.user {
+            let responsible = qualified_name(&user_id(user));
+            for hash in self.containers.keys() {
+                let delegate = qualified_name(&container_id(hash));
+                writeln!(provn, "  actedOnBehalfOf({delegate}, {responsible}, -)").unwrap();
+            }
+        }
+    }
+
+    /// The namespace prefixes of the identifiers and attributes, with their IRIs.
+    fn prefixes(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("rivulet", "urn:rivulet:".to_string()),
+            ("artifact", "urn:rivulet:artifact:".to_string()),
+            ("container", "urn:rivulet:container:".to_string()),
+            ("run", format!("urn:rivulet:run:{}:", self.run)),
+            ("user", "urn:rivulet:user:".to_string()),
+        ]
+    }
+
+    /// The attributes of a job's activity, besides its start and end times.
+    fn job_attributes(&self, job: &JobActivity) -> Vec<(&'static str, Literal)> {
+        let mut attributes = vec![
+            ("prov:label", Literal::String(job.name())),
+            ("rivulet:workflow", Literal::String(self.workflow.clone())),
+            ("rivulet:step", Literal::String(job.step.clone())),
+        ];
+        if let Some(index) = job.index {
+            attributes.push(("rivulet:index", Literal::Int(index as u64)));
+        }
+        if let Some(host) = &job.host {
+            attributes.push(("rivulet:host", Literal::String(host.clone())));
+        }
+        attributes.push(("rivulet:cached", Literal::Bool(job.cached)));
+        attributes
+    }
+}
+
+/// The attributes of an artifact's entity.
+fn artifact_attributes(hash: &ContentHash, artifact: &Artifact) -> Vec<(&'static str, Literal)> {
+    let kind = if artifact.directory {
+        "rivulet:Directory"
+    } else {
+        "rivulet:File"
+    };
+    let label = artifact
+        .path
+        .file_name()
+        .unwrap_or(artifact.path.as_os_str());
+    vec![
+        ("prov:type", Literal::QualifiedName(kind)),
+        (
+            "prov:label",
+            Literal::String(label.to_string_lossy().into_owned()),
+        ),
+        (
+            "prov:location",
+            Literal::String(artifact.path.display().to_string()),
+        ),
+        ("rivulet:contentHash", Literal::String(hash.to_string())),
+    ]
+}
+
+/// The attributes of a container's agent.
+fn container_attributes(image: &ImageSelector) -> Vec<(&'static str, Literal)> {
+    let mut attributes = vec![
+        ("prov:type", Literal::QualifiedName("prov:SoftwareAgent")),
+        ("rivulet:image", Literal::String(image.to_string())),
+    ];
+    if let Some(digest) = &image.digest {
+        attributes.push(("rivulet:digest", Literal::String(digest.to_string())));
+    }
+    attributes
+}
+
+/// The identifier of an artifact's entity.
+fn artifact_id(hash: &ContentHash) -> String {
+    format!("artifact:{hash}")
+}
+
+/// The identifier of a job's activity.
+fn job_id(job: &JobActivity) -> String {
+    format!("run:{}", job.name())
+}
+
+/// The identifier of a container's agent.
+fn container_id(hash: &ContentHash) -> String {
+    format!("container:{hash}")
+}
+
+/// The identifier of the user's agent.
+fn user_id(user: &str) -> String {
+    format!("user:{user}")
+}
+
+/// Hash the files and directories of a value, returning their paths and hashes.
+///
+/// Hashes are looked up in and added to `known`, by canonical path.
+fn hash_artifacts(
+    value: &Value,
+    known: &mut BTreeMap<PathBuf, ContentHash>,
+) -> Result<Vec<(PathBuf, ContentHash)>, ExecutionError> {
+    let (path, directory) = match value {
+        Value::File(path) => (path, false),
+        Value::Directory(path) => (path, true),
+        Value::Array(items) => {
+            let mut artifacts = Vec::new();
+            for item in items {
+                artifacts.extend(hash_artifacts(item, known)?);
+            }
+            return Ok(artifacts);
+        }
+        Value::Int(_) | Value::Float(_) | Value::String(_) | Value::Bool(_) | Value::Null => {
+            return Ok(Vec::new());
+        }
+    };
+    let canonical = fs::canonicalize(path).map_err(ExecutionError::io(path))?;
+    let hash = match known.get(&canonical) {
+        Some(hash) => *hash,
+        None if directory => ContentHash::of_directory(path).map_err(ExecutionError::io(path))?,
+        None => ContentHash::of_file(path).map_err(ExecutionError::io(path))?,
+    };
+    known.insert(canonical, hash);
+    Ok(vec![(path.clone(), hash)])
+}
+
+/// The value of an attribute.
+enum Literal {
+    String(String),
+    QualifiedName(&'static str),
+    Int(u64),
+    Bool(bool),
+}
+
+impl Literal {
+    #[cfg(feature = "serde")]
+    fn to_json(&self) -> Json {
+        match self {
+            Literal::String(text) => Json::from(text.as_str()),
+            Literal::QualifiedName(name) => json!({ "$": name, "type": "prov:QUALIFIED_NAME" }),
+            Literal::Int(n) => Json::from(*n),
+            Literal::Bool(b) => Json::from(*b),
+        }
+    }
+
+    fn to_prov_n(&self) -> String {
+        match self {
+            Literal::String(text) => string_literal(text),
+            Literal::QualifiedName(name) => format!("'{name}'"),
+            Literal::Int(n) => n.to_string(),
+            Literal::Bool(b) => format!("\"{b}\" %% xsd:boolean"),
+        }
+    }
+}
+
+/// Format attributes as a PROV-N attribute list.
+fn prov_n_attributes(attributes: &[(&str, Literal)]) -> String {
+    let attributes: Vec<_> = attributes
+        .iter()
+        .map(|(name, value)| format!("{name}={}", value.to_prov_n()))
+        .collect();
+    format!("[{}]", attributes.join(", "))
+}
+
+/// Format a text as a PROV-N string literal.
+fn string_literal(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\r' => literal.push_str("\\r"),
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// Format an identifier as a PROV-N qualified name, escaping its local part.
+///
+/// Letters, digits, `_`, `-` and `:` are kept, as is `.` except at the end; the characters
+/// PROV-N allows to escape are escaped with `\` and others are percent-encoded.
+fn qualified_name(id: &str) -> String {
+    let (prefix, local) = id.split_once(':').expect("identifiers have a prefix");
+    let mut name = format!("{prefix}:");
+    let last = local.chars().count().saturating_sub(1);
+    for (i, c) in local.chars().enumerate() {
+        match c {
+            '-' if i == 0 => name.push_str("\\-"),
+            '.' if i == 0 || i == last => name.push_str("\\."),
+            c if c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | ':' | '.') => name.push(c),
+            '~' | '!' | '$' | '&' | '\'' | '(' | ')' | '*' | '+' | ',' | ';' | '=' | '/' | '?'
+            | '#' | '@' | '%' => {
+                name.push('\\');
+                name.push(c);
+            }
+            c => {
+                for byte in c.to_string().bytes() {
+                    write!(name, "%{byte:02X}").unwrap();
+                }
+            }
+        }
+    }
+    name
+}
+
+/// Format attributes as PROV-JSON attributes.
+#[cfg(feature = "serde")]
+fn json_attributes(attributes: Vec<(&str, Literal)>) -> Map<String, Json> {
+    attributes
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_json()))
+        .collect()
+}
+
+/// Format a time as a PROV-JSON time.
+#[cfg(feature = "serde")]
+fn time(time: SystemTime) -> String {
+    timestamp::rfc3339(time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::Container;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_known_hashes_are_reused() {
+        let dir = TempDir::new().unwrap();
+        let (input, output) = (dir.path().join("in.txt"), dir.path().join("out.txt"));
+        fs::write(&input, "in").unwrap();
+        fs::write(&output, "out").unwrap();
+        // A hash that hashing the input again would not give
+        let known = ContentHash::of_file(&output).unwrap();
+        let container = Container::from("busybox:1.36")
+            .read()
+            .unwrap()
+            .resolve()
+            .unwrap();
+        let step = StepResult {
+            name: "copy".to_string(),
+            index: None,
+            dir: dir.path().to_path_buf(),
+            stdout: dir.path().join("stdout"),
+            stderr: dir.path().join("stderr"),
+            exit_code: Some(0),
+            container,
+            host: None,
+            cached: false,
+            cache_miss: None,
+            cache_key: None,
+            inputs: BTreeMap::from([("text".to_string(), Value::File(input.clone()))]),
+            input_hashes: BTreeMap::from([(input, known)]),
+            outputs: BTreeMap::from([("copy".to_string(), Value::File(output.clone()))]),
+            started: SystemTime::UNIX_EPOCH,
+            finished: SystemTime::UNIX_EPOCH,
+        };
+
+        let provenance = Provenance::of_run("r", "copy", &[step]).unwrap();
+        assert_eq!(provenance.jobs[0].used, [("text".to_string(), known)]);
+        let generated = ContentHash::of_file(&output).unwrap();
+        assert_eq!(
+            provenance.jobs[0].generated,
+            [("copy".to_string(), generated)]
+        );
+    }
+
+    #[test]
+    fn test_qualified_names() {
+        assert_eq!(qualified_name("run:align_3"), "run:align_3");
+        assert_eq!(qualified_name("run:v1.2."), "run:v1.2\\.");
+        assert_eq!(qualified_name("user:-x"), "user:\\-x");
+        assert_eq!(qualified_name("user:a b/c"), "user:a%20b\\/c");
+        assert_eq!(qualified_name("user:é"), "user:%C3%A9");
+    }
+
+    #[test]
+    fn test_literals() {
+        assert_eq!(string_literal("say \"hi\"\n"), "\"say \\\"hi\\\"\\n\"");
+        assert_eq!(Literal::Bool(true).to_prov_n(), "\"true\" %% xsd:boolean");
+    }
+}
+
+// EOF
@@ -0,0 +1,44 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use std::time::Duration;
+
+/// The compute resources a step asks for.
+///
+/// Batch executors turn these into scheduler directives; executors running on the local
+/// machine ignore them. Unset fields use the scheduler's defaults.
+///
+/// # Examples
+///
+/// ```
+/// use rivulet::prelude::*;
+/// use std::time::Duration;
+///
+/// let star = Container::from("quay.io/biocontainers/star:2.7.11b--h43eeafb_0");
+/// let mut align = Step::new("align", &star, "STAR --runThreadN 16 --genomeDir {index}");
+/// align
+///     .input("index", PortType::Directory)
+///     .cpus(16)
+///     .memory(32 << 30)
+///     .time(Duration::from_secs(4 * 3600));
+///
+/// assert_eq!(align.resources().cpus, Some(16));
+/// assert_eq!(align.resources().gpus, None);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Resources {
+    /// The number of CPU cores.
+    pub cpus: Option<u32>,
+
+    /// The amount of memory, in bytes.
+    pub memory: Option<u64>,
+
+    /// The wall-clock time limit.
+    pub time: Option<Duration>,
+
+    /// The number of GPUs.
+    pub gpus: Option<u32>,
+}
+
+// EOF
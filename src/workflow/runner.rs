@@ -0,0 +1,774 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+mod collect;
+mod scatter;
+
+use super::{
+    CacheMiss, KeyComponents, Provenance, Source, Step, StepCache, Value, Workflow, template,
+};
+use crate::container::ResolvedContainer;
+use crate::executor::{self, ExecutionError, Executor, Job, JobOutcome};
+use crate::hash::ContentHash;
+use crate::{shell, store, timestamp};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::PoisonError;
+use std::time::SystemTime;
+
+/// How input files and directories are placed in a step's directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Staging {
+    /// Symlink to the original path, falling back to a copy on platforms without symlinks.
+    #[default]
+    Symlink,
+
+    /// Copy the original path, so the step cannot modify its inputs in place.
+    Copy,
+}
+
+/// Runs a workflow step by step with an [`Executor`].
+///
+/// Every run uses a run directory. Each step gets its own directory inside it,
+/// `steps/<step>/`, which is emptied before the step runs and contains:
+///
+/// - `inputs/<port>/`: the staged input files and directories; array items are staged in
+///   numbered subdirectories
+/// - `work/`: the working directory of the command, where outputs are collected from
+/// - `command.sh`: the command with its placeholders replaced
+/// - `stdout` and `stderr`: the output of the command
+///
+/// The jobs of a [scattered](Step::scatter) step are run together as a
+/// [`JobArray`](crate::executor::JobArray), each in a numbered directory
+/// `steps/<step>/<index>/` with the same layout.
+///
+/// Progress is appended to `run.log` in the run directory, one timestamped line per event.
+/// The [provenance](Provenance) of the jobs that completed is written next to the log, to
+/// `provenance.provn` and, with the `serde` feature, `provenance.json`, whether the run
+/// succeeds or not.
+/// Steps run one at a time in [topological order](Workflow::topological_order), and the run
+/// stops at the first step that fails. Steps whose [condition](Step::when) does not hold are
+/// logged as skipped and produce null outputs, but no [`StepResult`]. With a
+/// [cache](Self::cache), jobs that ran before with the same container, command and inputs are
+/// not run again.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rivulet::executor::LocalExecutor;
+/// use rivulet::prelude::*;
+/// use rivulet::workflow::{Runner, Value};
+///
+/// let mut workflow = Workflow::new("count");
+/// let text = workflow.input("text", PortType::File).unwrap();
+/// let shell = Container::from("docker.io/library/busybox:1.36");
+/// let mut count = Step::new("count", &shell, "wc -l < {text} > lines");
+/// count.input("text", PortType::File).output("lines", PortType::Int);
+/// let count = workflow.add_step(count).unwrap();
+/// workflow.connect(text, count.input("text")).unwrap();
+/// workflow.output("lines", count.output("lines")).unwrap();
+///
+/// let executor = LocalExecutor::new();
+/// let result = Runner::new(&executor, "runs/count")
+///     .run(&workflow, [("text", Value::File("notes.txt".into()))])
+///     .unwrap();
+/// println!("{}", result.outputs["lines"]);
+/// ```
+pub struct Runner<'a> {
+    executor: &'a dyn Executor,
+    dir: PathBuf,
+    staging: Staging,
+    cache: Option<&'a StepCache>,
+}
+
+/// The result of a successful run.
+#[derive(Debug, Clone)]
+pub struct RunResult {
+    /// The workflow outputs, by name.
+    pub outputs: BTreeMap<String, Value>,
+
+    /// The steps that ran, in the order they ran, with one result per job for scattered
+    /// steps.
+    pub steps: Vec<StepResult>,
+
+    /// The id of the run, which identifies it in its provenance and in the cache, to
+    /// [pin](StepCache::pin) it.
+    pub run_id: String,
+
+    /// What the steps used and generated, and how.
+    pub provenance: Provenance,
+}
+
+/// The record of a step, or a job of a scattered step, that ran successfully.
+#[derive(Debug, Clone)]
+pub struct StepResult {
+    /// The step name.
+    pub name: String,
+
+    /// The position of the job among the jobs of a scattered step, or `None` if the step is
+    /// not scattered.
+    pub index: Option<usize>,
+
+    /// The job's directory.
+    pub dir: PathBuf,
+
+    /// The file holding the command's standard output.
+    pub stdout: PathBuf,
+
+    /// The file holding the command's standard error.
+    pub stderr: PathBuf,
+
+    /// The exit code of the command.
+    pub exit_code: Option<i32>,
+
+    /// The effective configuration of the container the command ran in.
+    pub container: ResolvedContainer,
+
+    /// The host the command ran on, or `None` if the executor does not know it or the
+    /// outputs were restored from the cache.
+    pub host: Option<String>,
+
+    /// Whether the outputs were restored from the cache instead of running the command.
+    pub cached: bool,
+
+    /// Why the job missed the cache, if the run has a cache and the command ran.
+    pub cache_miss: Option<CacheMiss>,
+
+    /// The key of the job's results in the cache, to [name](StepCache::name_result) them,
+    /// or `None` if the run has no cache.
+    pub cache_key: Option<ContentHash>,
+
+    /// The staged input values, by port name.
+    pub inputs: BTreeMap<String, Value>,
+
+    /// The content hashes of the files and directories among the inputs, by path, if they
+    /// were hashed for the cache key.
+    pub input_hashes: BTreeMap<PathBuf, ContentHash>,
+
+    /// The collected output values, by port name.
+    pub outputs: BTreeMap<String, Value>,
+
+    /// When the command was started.
+    pub started: SystemTime,
+
+    /// When the command finished.
+    pub finished: SystemTime,
+}
+
+impl<'a> Runner<'a> {
+    /// Create a runner using the given run directory, which is created if needed.
+    pub fn new(executor: &'a dyn Executor, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            executor,
+            dir: dir.into(),
+            staging: Staging::default(),
+            cache: None,
+        }
+    }
+
+    /// Set how inputs are staged.
+    pub fn staging(&mut self, staging: Staging) -> &mut Self {
+        self.staging = staging;
+        self
+    }
+
+    /// Reuse the results of jobs that ran before, and keep the results of new jobs.
+    ///
+    /// A job whose [key](StepCache) is found in the cache does not run: its output files,
+    /// standard output and standard error are restored into its directory instead.
+    pub fn cache(&mut self, cache: &'a StepCache) -> &mut Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Run a workflow with the given input values.
+    ///
+    /// The workflow is validated and every declared input must be given a conforming value;
+    /// `File` and `Directory` values must name existing paths of the right kind. With a
+    /// [cache](Self::cache), the run is recorded in it once all steps have succeeded, so
+    /// [garbage collection](StepCache::collect_garbage) can keep the results of recent runs.
+    pub fn run<I, S>(&self, workflow: &Workflow, inputs: I) -> Result<RunResult, ExecutionError>
+    where
+        I: IntoIterator<Item = (S, Value)>,
+        S: Into<String>,
+    {
+        workflow.validate()?;
+        let inputs = check_inputs(workflow, inputs)?;
+        let dir = std::path::absolute(&self.dir).map_err(ExecutionError::io(&self.dir))?;
+        fs::create_dir_all(&dir).map_err(ExecutionError::io(&dir))?;
+        let started = SystemTime::now();
+        let mut log = RunLog::open(dir.join("run.log"), run_id(started))?;
+        log.record(workflow.name(), "run started")?;
+        match self.run_steps(workflow, inputs, &dir, started, &mut log) {
+            Ok(result) => {
+                log.record(workflow.name(), "run finished")?;
+                Ok(result)
+            }
+            Err(error) => {
+                // The original error is more useful than a failure to log it
+                let _ = log.record(workflow.name(), format_args!("run failed: {error}"));
+                Err(error)
+            }
+        }
+    }
+
+    fn run_steps(
+        &self,
+        workflow: &Workflow,
+        inputs: BTreeMap<String, Value>,
+        dir: &Path,
+        started: SystemTime,
+        log: &mut RunLog,
+    ) -> Result<RunResult, ExecutionError> {
+        let run_id = log.run_id.clone();
+        if let Some(cache) = self.cache {
+            cache.record_run(&run_id, workflow.name(), started)?;
+            log.record(workflow.name(), format_args!("recorded run {run_id}"))?;
+        }
+        let mut steps = Vec::new();
+        let values = match self.run_in_order(workflow, inputs, dir, log, &mut steps) {
+            Ok(values) => values,
+            Err(error) => {
+                // Keep the provenance of the steps that completed, but the original error is
+                // more useful than a failure to write it
+                let _ = Provenance::of_run(&run_id, workflow.name(), &steps)
+                    .and_then(|provenance| provenance.write(dir));
+                return Err(error);
+            }
+        };
+        let outputs = workflow
+            .outputs()
+            .iter()
+            .map(|output| (output.name.clone(), values[&output.from].clone()))
+            .collect();
+        let provenance = Provenance::of_run(&run_id, workflow.name(), &steps)?;
+        provenance.write(dir)?;
+        Ok(RunResult {
+            outputs,
+            steps,
+            run_id,
+            provenance,
+        })
+    }
+
+    /// Run the steps in topological order, adding the result of every job to `steps`, and
+    /// return the values of the workflow inputs and step outputs.
+    fn run_in_order(
+        &self,
+        workflow: &Workflow,
+        inputs: BTreeMap<String, Value>,
+        dir: &Path,
+        log: &mut RunLog,
+        steps: &mut Vec<StepResult>,
+    ) -> Result<BTreeMap<Source, Value>, ExecutionError> {
+        let mut values: BTreeMap<Source, Value> = inputs
+            .into_iter()
+            .map(|(name, value)| (Source::WorkflowInput(name), value))
+            .collect();
+        for id in workflow.topological_order()? {
+            let step = workflow.step(id);
+            let inputs = step
+                .inputs()
+                .iter()
+                .map(|port| {
+                    let source = workflow
+                        .source_of(&id.input(&port.name))
+                        .expect("validated workflows have every input connected");
+                    (port.name.clone(), values[source].clone())
+                })
+                .collect();
+            let dir = dir.join("steps").join(step.name());
+            let outputs = if step.is_scattered() {
+                let results = self.run_scattered(step, inputs, &dir, log)?;
+                let outputs = scatter::gather(step, &results);
+                steps.extend(results.into_iter().flatten());
+                outputs
+            } else if let Some(result) = self.run_step(step, inputs, &dir, log)? {
+                let outputs = result.outputs.clone();
+                steps.push(result);
+                outputs
+            } else {
+                null_outputs(step)
+            };
+            for (port, value) in outputs {
+                values.insert(id.output(port), value);
+            }
+        }
+        Ok(values)
+    }
+
+    /// Run a step that is not scattered, returning `None` if its condition does not hold.
+    fn run_step(
+        &self,
+        step: &Step,
+        inputs: BTreeMap<String, Value>,
+        dir: &Path,
+        log: &mut RunLog,
+    ) -> Result<Option<StepResult>, ExecutionError> {
+        empty_dir(dir)?;
+        if skip(step, None, &inputs, log)? {
+            return Ok(None);
+        }
+        let container = self.container(step, log)?;
+        let spec = JobSpec {
+            step,
+            index: None,
+            container: &container,
+        };
+        let prepared = self.prepare(spec, inputs, dir, log)?;
+        let execution = if prepared.cached {
+            Execution::cached()
+        } else {
+            log.record(step.name(), "started")?;
+            let started = SystemTime::now();
+            let outcome = self.executor.execute(&prepared.job)?;
+            Execution {
+                outcome,
+                started,
+                finished: SystemTime::now(),
+            }
+        };
+        self.finish(step, prepared, execution, log).map(Some)
+    }
+
+    /// Resolve the container of a step, pinning its image to a digest and, with a cache,
+    /// hashing the sources of its `COPY` steps.
+    ///
+    /// Jobs in images the executor cannot pin do not use the cache, as a tag may have moved
+    /// since their results were cached. With a cache, this is logged.
+    fn container(&self, step: &Step, log: &mut RunLog) -> Result<StepContainer, ExecutionError> {
+        let mut resolved = step
+            .container()
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .resolve()?;
+        let cacheable = match self.executor.pin_image(resolved.image()) {
+            Some(image) => {
+                resolved.pin(image);
+                true
+            }
+            None => {
+                if self.cache.is_some() {
+                    let reason = format!("image {} is not pinned to a digest", resolved.image());
+                    log.record(step.name(), format_args!("not cached, {reason}"))?;
+                }
+                false
+            }
+        };
+        let sources = match &self.cache {
+            Some(_) if cacheable => self.executor.copy_sources(&resolved)?,
+            _ => BTreeMap::new(),
+        };
+        Ok(StepContainer {
+            resolved,
+            cacheable,
+            sources,
+        })
+    }
+
+    /// Create the directories of a job in `dir`, stage its inputs and render its command.
+    ///
+    /// With a cache, the files of a job that ran before with the same key are restored
+    /// into its directory. Otherwise, why the job misses the cache is logged.
+    fn prepare(
+        &self,
+        spec: JobSpec<'_>,
+        inputs: BTreeMap<String, Value>,
+        dir: &Path,
+        log: &mut RunLog,
+    ) -> Result<PreparedJob, ExecutionError> {
+        let JobSpec {
+            step,
+            index,
+            container,
+        } = spec;
+        let workdir = dir.join("work");
+        fs::create_dir_all(&workdir).map_err(ExecutionError::io(&workdir))?;
+        let (staged, mounts) = self.stage_inputs(inputs, dir)?;
+
+        let command = template::render(step.command(), |name| {
+            let words = staged[name].words();
+            let quoted: Vec<_> = words.iter().map(|word| shell::quote(word)).collect();
+            quoted.join(" ")
+        });
+        let script = dir.join("command.sh");
+        fs::write(&script, format!("{command}\n")).map_err(ExecutionError::io(&script))?;
+
+        let job = Job {
+            name: match index {
+                Some(index) => format!("{}_{index}", step.name()),
+                None => step.name().to_string(),
+            },
+            container: container.resolved.clone(),
+            command,
+            inputs: staged,
+            dir: dir.to_path_buf(),
+            workdir,
+            stdout: dir.join("stdout"),
+            stderr: dir.join("stderr"),
+            resources: *step.resources(),
+            mounts,
+        };
+        self.look_up(spec, job, log)
+    }
+
+    /// Stage the input values of a job in its directory, returning the staged values and
+    /// the paths to mount for them.
+    fn stage_inputs(
+        &self,
+        inputs: BTreeMap<String, Value>,
+        dir: &Path,
+    ) -> Result<(BTreeMap<String, Value>, Vec<PathBuf>), ExecutionError> {
+        let mut mounts = Vec::new();
+        let mut staged = BTreeMap::new();
+        for (port, value) in inputs {
+            let value = self.stage(&value, &dir.join("inputs").join(&port), &mut mounts)?;
+            staged.insert(port, value);
+        }
+        mounts.sort();
+        mounts.dedup();
+        Ok((staged, mounts))
+    }
+
+    /// Look a job up in the cache, if there is one and the job may use it, restoring its
+    /// files if it is found and logging why it is not otherwise.
+    fn look_up(
+        &self,
+        spec: JobSpec<'_>,
+        job: Job,
+        log: &mut RunLog,
+    ) -> Result<PreparedJob, ExecutionError> {
+        let JobSpec { step, index, .. } = spec;
+        let mut prepared = PreparedJob {
+            job,
+            index,
+            components: None,
+            hashes: BTreeMap::new(),
+            cached: false,
+            miss: None,
+        };
+        let Some(cache) = self.cache.filter(|_| spec.container.cacheable) else {
+            return Ok(prepared);
+        };
+        let job = &prepared.job;
+        let sources = spec.container.sources.clone();
+        let components =
+            KeyComponents::of_job(step, job, sources, &job.inputs, &mut prepared.hashes)?;
+        prepared.cached = cache.restore(&components.key(), job)?;
+        if !prepared.cached {
+            let miss = cache.explain(step.name(), &components)?;
+            log.record(&subject(step, index), format_args!("not cached, {miss}"))?;
+            prepared.miss = Some(miss);
+        }
+        prepared.components = Some(components);
+        Ok(prepared)
+    }
+
+    /// Check how a job ended, collect its outputs and add them to the cache.
+    fn finish(
+        &self,
+        step: &Step,
+        prepared: PreparedJob,
+        execution: Execution,
+        log: &mut RunLog,
+    ) -> Result<StepResult, ExecutionError> {
+        let outcome = execution.outcome;
+        if !outcome.success() {
+            let subject = subject(step, prepared.index);
+            log.record(&subject, format_args!("failed, {outcome}"))?;
+            return Err(ExecutionError::StepFailed {
+                step: subject,
+                outcome,
+                stderr: prepared.job.stderr,
+            });
+        }
+
+        let outputs = collect::outputs(step, &prepared.job.workdir)?;
+        self.keep(step, &prepared, &outcome, log)?;
+        let job = prepared.job;
+        Ok(StepResult {
+            name: step.name().to_string(),
+            index: prepared.index,
+            dir: job.dir,
+            stdout: job.stdout,
+            stderr: job.stderr,
+            exit_code: outcome.exit_code,
+            container: job.container,
+            host: if prepared.cached {
+                None
+            } else {
+                self.executor.host()
+            },
+            cached: prepared.cached,
+            cache_miss: prepared.miss,
+            cache_key: prepared.components.as_ref().map(KeyComponents::key),
+            inputs: job.inputs,
+            input_hashes: prepared.hashes,
+            outputs,
+            started: execution.started,
+            finished: execution.finished,
+        })
+    }
+
+    /// Log how a successful job finished and keep its results in the cache, unless they were
+    /// restored from it, adding them to the record of the run either way.
+    fn keep(
+        &self,
+        step: &Step,
+        prepared: &PreparedJob,
+        outcome: &JobOutcome,
+        log: &mut RunLog,
+    ) -> Result<(), ExecutionError> {
+        let subject = subject(step, prepared.index);
+        match (self.cache, &prepared.components) {
+            (Some(cache), Some(components)) if prepared.cached => {
+                let key = components.key();
+                log.record(&subject, format_args!("reused cached results {key}"))?;
+                cache.record_result(&log.run_id, &key)
+            }
+            (Some(cache), Some(components)) => {
+                log.record(&subject, format_args!("finished, {outcome}"))?;
+                cache.keep(components, step, &prepared.job)?;
+                cache.record_result(&log.run_id, &components.key())
+            }
+            _ => log.record(&subject, format_args!("finished, {outcome}")),
+        }
+    }
+
+    /// Place the files and directories of a value in `dest`, returning the staged value.
+    fn stage(
+        &self,
+        value: &Value,
+        dest: &Path,
+        mounts: &mut Vec<PathBuf>,
+    ) -> Result<Value, ExecutionError> {
+        let (path, is_directory) = match value {
+            Value::File(path) => (path, false),
+            Value::Directory(path) => (path, true),
+            Value::Array(items) => {
+                let staged = items
+                    .iter()
+                    .enumerate()
+                    .map(|(index, item)| self.stage(item, &dest.join(index.to_string()), mounts))
+                    .collect::<Result<_, _>>()?;
+                return Ok(Value::Array(staged));
+            }
+            scalar => return Ok(scalar.clone()),
+        };
+        let original = fs::canonicalize(path).map_err(ExecutionError::io(path))?;
+        fs::create_dir_all(dest).map_err(ExecutionError::io(dest))?;
+        let staged = dest.join(original.file_name().unwrap_or("input".as_ref()));
+        match self.staging {
+            #[cfg(unix)]
+            Staging::Symlink => {
+                std::os::unix::fs::symlink(&original, &staged)
+                    .map_err(ExecutionError::io(&staged))?;
+                mounts.push(original);
+            }
+            _ => executor::copy(&original, &staged)?,
+        }
+        Ok(if is_directory {
+            Value::Directory(staged)
+        } else {
+            Value::File(staged)
+        })
+    }
+}
+
+/// The container the jobs of a step run in.
+struct StepContainer {
+    resolved: ResolvedContainer,
+    /// Whether the image is pinned to a digest, which jobs need to use the cache.
+    cacheable: bool,
+    /// The hashes of the sources of the container's `COPY` steps, when jobs use the cache.
+    sources: BTreeMap<String, ContentHash>,
+}
+
+/// The step, scatter index and container a job is prepared for.
+#[derive(Clone, Copy)]
+struct JobSpec<'a> {
+    step: &'a Step,
+    index: Option<usize>,
+    container: &'a StepContainer,
+}
+
+/// A job ready to run, or whose files were restored from the cache.
+struct PreparedJob {
+    job: Job,
+    index: Option<usize>,
+    components: Option<KeyComponents>,
+    hashes: BTreeMap<PathBuf, ContentHash>,
+    cached: bool,
+    miss: Option<CacheMiss>,
+}
+
+/// How a job ended.
+struct Execution {
+    outcome: JobOutcome,
+    started: SystemTime,
+    finished: SystemTime,
+}
+
+impl Execution {
+    /// The execution of a job found in the cache, which ends successfully right away.
+    fn cached() -> Self {
+        let now = SystemTime::now();
+        Self {
+            outcome: JobOutcome::exited(0),
+            started: now,
+            finished: now,
+        }
+    }
+}
+
+/// How a job is referred to in the run log and errors: the step name, followed by the index
+/// of the job in brackets for scattered steps.
+fn subject(step: &Step, index: Option<usize>) -> String {
+    match index {
+        Some(index) => format!("{}[{index}]", step.name()),
+        None => step.name().to_string(),
+    }
+}
+
+/// Whether a job is skipped because the condition of its step does not hold, logging it if so.
+fn skip(
+    step: &Step,
+    index: Option<usize>,
+    inputs: &BTreeMap<String, Value>,
+    log: &mut RunLog,
+) -> Result<bool, ExecutionError> {
+    match step.condition() {
+        Some(condition) if !condition.evaluate(inputs) => {
+            log.record(
+                &subject(step, index),
+                format_args!("skipped, {condition} does not hold"),
+            )?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// The outputs of a skipped step: null for every port.
+fn null_outputs(step: &Step) -> BTreeMap<String, Value> {
+    step.outputs()
+        .iter()
+        .map(|port| (port.name.clone(), Value::Null))
+        .collect()
+}
+
+/// Remove everything in a directory left from an earlier run.
+fn empty_dir(dir: &Path) -> Result<(), ExecutionError> {
+    match fs::remove_dir_all(dir) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(ExecutionError::Io {
+            path: dir.to_path_buf(),
+            source: error,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Check the input values of a run against the inputs the workflow declares.
+///
+/// Optional inputs without a value are set to null.
+fn check_inputs<I, S>(
+    workflow: &Workflow,
+    inputs: I,
+) -> Result<BTreeMap<String, Value>, ExecutionError>
+where
+    I: IntoIterator<Item = (S, Value)>,
+    S: Into<String>,
+{
+    let mut inputs: BTreeMap<String, Value> = inputs
+        .into_iter()
+        .map(|(name, value)| (name.into(), value))
+        .collect();
+    if let Some(name) = inputs
+        .keys()
+        .find(|name| workflow.input_port(name).is_none())
+    {
+        return Err(ExecutionError::UnknownInput(name.clone()));
+    }
+    for port in workflow.inputs() {
+        if port.port_type.is_optional() {
+            inputs.entry(port.name.clone()).or_insert(Value::Null);
+        }
+        let Some(value) = inputs.get(&port.name) else {
+            return Err(ExecutionError::MissingInput(port.name.clone()));
+        };
+        if !value.conforms_to(&port.port_type) || !paths_exist(value) {
+            return Err(ExecutionError::InvalidInput {
+                input: port.name.clone(),
+                expected: port.port_type.clone(),
+            });
+        }
+    }
+    Ok(inputs)
+}
+
+/// Whether the files and directories of a value exist and are of the right kind.
+fn paths_exist(value: &Value) -> bool {
+    match value {
+        Value::File(path) => path.is_file(),
+        Value::Directory(path) => path.is_dir(),
+        Value::Array(items) => items.iter().all(paths_exist),
+        _ => true,
+    }
+}
+
+/// The `run.log` file of a run directory.
+struct RunLog {
+    path: PathBuf,
+    file: File,
+    /// The id of the run being logged.
+    run_id: String,
+}
+
+impl RunLog {
+    fn open(path: PathBuf, run_id: String) -> Result<Self, ExecutionError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(ExecutionError::io(&path))?;
+        Ok(Self { path, file, run_id })
+    }
+
+    /// Append a line about a workflow or step.
+    fn record(&mut self, subject: &str, message: impl Display) -> Result<(), ExecutionError> {
+        let now = timestamp::rfc3339(SystemTime::now());
+        writeln!(self.file, "[{now}] {subject}: {message}").map_err(ExecutionError::io(&self.path))
+    }
+}
+
+/// A new id for a run started at a given time.
+///
+/// The id is the time the run started, followed by a [unique name](store::unique_name) so
+/// runs started at once on hosts sharing a cache get different ids.
+fn run_id(started: SystemTime) -> String {
+    let time = timestamp::rfc3339(started).replace(['-', ':'], "");
+    format!("{time}-{}", store::unique_name())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths_exist() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = Value::Directory(dir.path().to_path_buf());
+        assert!(paths_exist(&directory));
+        assert!(!paths_exist(&Value::File(dir.path().to_path_buf())));
+        assert!(!paths_exist(&Value::Array(vec![
+            directory,
+            Value::Directory(dir.path().join("missing")),
+        ])));
+    }
+}
+
+// EOF
@@ -0,0 +1,151 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use crate::executor::ExecutionError;
+use crate::workflow::{PortType, Step, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Collect the values of the outputs of a step from its working directory, by port name.
+pub(super) fn outputs(
+    step: &Step,
+    workdir: &Path,
+) -> Result<BTreeMap<String, Value>, ExecutionError> {
+    step.outputs()
+        .iter()
+        .map(|port| {
+            let value = collect(step, &port.name, &port.port_type, workdir)?;
+            Ok((port.name.clone(), value))
+        })
+        .collect()
+}
+
+/// Collect the value of a step output from its working directory.
+fn collect(
+    step: &Step,
+    port: &str,
+    port_type: &PortType,
+    workdir: &Path,
+) -> Result<Value, ExecutionError> {
+    let pattern = step.output_glob(port);
+    let invalid = |reason: String| ExecutionError::InvalidOutput {
+        step: step.name().to_string(),
+        port: port.to_string(),
+        reason,
+    };
+    let (port_type, optional) = match port_type {
+        PortType::Optional(inner) => (inner.as_ref(), true),
+        port_type => (port_type, false),
+    };
+    let item_type = match port_type {
+        PortType::Array(item_type) => item_type.as_ref(),
+        single => single,
+    };
+    let matches = matching_paths(pattern, item_type, workdir).map_err(&invalid)?;
+    if optional && matches.is_empty() {
+        return Ok(Value::Null);
+    }
+
+    if let PortType::Array(item_type) = port_type {
+        return matches
+            .iter()
+            .map(|path| read_value(item_type, path).map_err(&invalid))
+            .collect::<Result<_, _>>()
+            .map(Value::Array);
+    }
+    match matches.as_slice() {
+        [path] => read_value(port_type, path).map_err(invalid),
+        [] => Err(ExecutionError::MissingOutput {
+            step: step.name().to_string(),
+            port: port.to_string(),
+            pattern: pattern.to_string(),
+        }),
+        _ => Err(ExecutionError::AmbiguousOutput {
+            step: step.name().to_string(),
+            port: port.to_string(),
+            pattern: pattern.to_string(),
+            count: matches.len(),
+        }),
+    }
+}
+
+/// The paths in a working directory matching an output pattern, in order, keeping only
+/// directories for directory items and files for other items.
+fn matching_paths(
+    pattern: &str,
+    item_type: &PortType,
+    workdir: &Path,
+) -> Result<Vec<PathBuf>, String> {
+    let full_pattern = format!(
+        "{}/{pattern}",
+        glob::Pattern::escape(&workdir.to_string_lossy())
+    );
+    let mut matches: Vec<PathBuf> = glob::glob(&full_pattern)
+        .map_err(|error| error.to_string())?
+        .filter_map(Result::ok)
+        .filter(|path| match item_type {
+            PortType::Directory => path.is_dir(),
+            PortType::Optional(inner) if **inner == PortType::Directory => path.is_dir(),
+            _ => path.is_file(),
+        })
+        .collect();
+    matches.sort();
+    Ok(matches)
+}
+
+/// Turn a collected path into a value: paths are taken as is and scalars are read from the
+/// file's contents.
+fn read_value(port_type: &PortType, path: &Path) -> Result<Value, String> {
+    let text = match port_type {
+        PortType::Optional(inner) => return read_value(inner, path),
+        PortType::File => return Ok(Value::File(path.to_path_buf())),
+        PortType::Directory => return Ok(Value::Directory(path.to_path_buf())),
+        PortType::Array(_) => return Err("nested arrays cannot be collected".to_string()),
+        _ => fs::read_to_string(path).map_err(|error| format!("{}: {error}", path.display()))?,
+    };
+    let unparsable = || format!("cannot read {:?} as {port_type}", text.trim());
+    match port_type {
+        PortType::Int => text
+            .trim()
+            .parse()
+            .map(Value::Int)
+            .map_err(|_| unparsable()),
+        PortType::Float => text
+            .trim()
+            .parse()
+            .map(Value::Float)
+            .map_err(|_| unparsable()),
+        PortType::Bool => text
+            .trim()
+            .parse()
+            .map(Value::Bool)
+            .map_err(|_| unparsable()),
+        _ => Ok(Value::String(
+            text.strip_suffix('\n').unwrap_or(&text).to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_value() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("value");
+        fs::write(&path, " 42\n").unwrap();
+        assert_eq!(read_value(&PortType::Int, &path), Ok(Value::Int(42)));
+        assert_eq!(read_value(&PortType::Float, &path), Ok(Value::Float(42.0)));
+        assert_eq!(read_value(&PortType::String, &path), Ok(Value::from(" 42")));
+        assert!(read_value(&PortType::Bool, &path).is_err());
+        assert_eq!(
+            read_value(&PortType::File, &path),
+            Ok(Value::File(path.clone()))
+        );
+    }
+}
+
+// EOF
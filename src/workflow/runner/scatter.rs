@@ -0,0 +1,232 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::{Execution, JobSpec, RunLog, Runner, StepResult, empty_dir, skip};
+use crate::executor::{ExecutionError, JobArray};
+use crate::workflow::{ScatterMethod, Step, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::SystemTime;
+
+impl Runner<'_> {
+    /// Run the jobs of a scattered step as one [`JobArray`], returning their results in
+    /// order, with `None` for jobs whose condition does not hold.
+    ///
+    /// Jobs found in the cache are left out of the array.
+    pub(super) fn run_scattered(
+        &self,
+        step: &Step,
+        inputs: BTreeMap<String, Value>,
+        dir: &Path,
+        log: &mut RunLog,
+    ) -> Result<Vec<Option<StepResult>>, ExecutionError> {
+        let elements = scatter(step, inputs)?;
+        empty_dir(dir)?;
+        let count = elements.len();
+        let container = self.container(step, log)?;
+        let mut prepared = Vec::with_capacity(count);
+        for (index, inputs) in elements.into_iter().enumerate() {
+            if !skip(step, Some(index), &inputs, log)? {
+                let job_dir = dir.join(index.to_string());
+                let spec = JobSpec {
+                    step,
+                    index: Some(index),
+                    container: &container,
+                };
+                prepared.push(self.prepare(spec, inputs, &job_dir, log)?);
+            }
+        }
+        let array = JobArray {
+            name: step.name().to_string(),
+            dir: dir.to_path_buf(),
+            jobs: prepared
+                .iter()
+                .filter(|prepared| !prepared.cached)
+                .map(|prepared| prepared.job.clone())
+                .collect(),
+        };
+        let mut executions = self.execute_array(step, &array, log)?.into_iter();
+        let mut results: Vec<_> = (0..count).map(|_| None).collect();
+        for prepared in prepared {
+            let execution = if prepared.cached {
+                Execution::cached()
+            } else {
+                executions
+                    .next()
+                    .expect("executors return one outcome per job")
+            };
+            let index = prepared.index.expect("scattered jobs have an index");
+            results[index] = Some(self.finish(step, prepared, execution, log)?);
+        }
+        Ok(results)
+    }
+
+    /// Run the jobs of an array, unless it is empty, returning how each ended.
+    fn execute_array(
+        &self,
+        step: &Step,
+        array: &JobArray,
+        log: &mut RunLog,
+    ) -> Result<Vec<Execution>, ExecutionError> {
+        if array.jobs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let jobs = array.jobs.len();
+        log.record(step.name(), format_args!("started {jobs} jobs"))?;
+        let started = SystemTime::now();
+        let outcomes = self.executor.execute_array(array)?;
+        let finished = SystemTime::now();
+        Ok(outcomes
+            .into_iter()
+            .map(|outcome| Execution {
+                outcome,
+                started,
+                finished,
+            })
+            .collect())
+    }
+}
+
+/// Split the inputs of a scattered step into the inputs of its jobs.
+///
+/// Scattered inputs are replaced by one of their items, other inputs are passed to every job
+/// unchanged.
+pub(super) fn scatter(
+    step: &Step,
+    inputs: BTreeMap<String, Value>,
+) -> Result<Vec<BTreeMap<String, Value>>, ExecutionError> {
+    let arrays: Vec<(&String, &[Value])> = step
+        .scatter_inputs()
+        .iter()
+        .map(|port| match &inputs[port] {
+            Value::Array(items) => (port, items.as_slice()),
+            _ => unreachable!("scattered inputs are connected to arrays"),
+        })
+        .collect();
+    let combinations = combinations(step, &arrays)?;
+    Ok(combinations
+        .into_iter()
+        .map(|combination| {
+            let mut job_inputs = inputs.clone();
+            for ((port, items), index) in arrays.iter().zip(combination) {
+                job_inputs.insert(port.to_string(), items[index].clone());
+            }
+            job_inputs
+        })
+        .collect())
+}
+
+/// The indices of the items of the scattered inputs each job gets, in job order.
+fn combinations(
+    step: &Step,
+    arrays: &[(&String, &[Value])],
+) -> Result<Vec<Vec<usize>>, ExecutionError> {
+    match step.scatter_method() {
+        ScatterMethod::DotProduct => {
+            let expected = arrays[0].1.len();
+            if let Some((port, items)) = arrays.iter().find(|(_, items)| items.len() != expected) {
+                return Err(ExecutionError::ScatterLength {
+                    step: step.name().to_string(),
+                    port: port.to_string(),
+                    expected,
+                    found: items.len(),
+                });
+            }
+            Ok((0..expected)
+                .map(|index| vec![index; arrays.len()])
+                .collect())
+        }
+        ScatterMethod::CrossProduct => {
+            Ok(arrays
+                .iter()
+                .fold(vec![Vec::new()], |combinations, (_, items)| {
+                    combinations
+                        .into_iter()
+                        .flat_map(|combination| {
+                            (0..items.len()).map(move |index| {
+                                let mut combination = combination.clone();
+                                combination.push(index);
+                                combination
+                            })
+                        })
+                        .collect()
+                }))
+        }
+    }
+}
+
+/// Gather the outputs of the jobs of a scattered step into arrays, in job order, with null
+/// for skipped jobs.
+pub(super) fn gather(step: &Step, results: &[Option<StepResult>]) -> BTreeMap<String, Value> {
+    step.outputs()
+        .iter()
+        .map(|port| {
+            let items = results
+                .iter()
+                .map(|result| match result {
+                    Some(result) => result.outputs[&port.name].clone(),
+                    None => Value::Null,
+                })
+                .collect();
+            (port.name.clone(), Value::Array(items))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::Container;
+    use crate::workflow::PortType;
+
+    #[test]
+    fn test_scatter() {
+        let mut step = Step::new("pair", &Container::from("alpine"), "true");
+        step.input("a", PortType::Int)
+            .input("b", PortType::Int)
+            .input("c", PortType::Bool)
+            .scatter("a")
+            .scatter("b");
+        let inputs = BTreeMap::from([
+            (
+                "a".to_string(),
+                Value::Array(vec![Value::Int(1), Value::Int(2)]),
+            ),
+            (
+                "b".to_string(),
+                Value::Array(vec![Value::Int(3), Value::Int(4)]),
+            ),
+            ("c".to_string(), Value::Bool(true)),
+        ]);
+        let pairs = |jobs: Vec<BTreeMap<String, Value>>| -> Vec<String> {
+            jobs.iter()
+                .map(|job| format!("{}{}{}", job["a"], job["b"], job["c"]))
+                .collect()
+        };
+
+        let jobs = scatter(&step, inputs.clone()).unwrap();
+        assert_eq!(pairs(jobs), ["13true", "24true"]);
+        step.cross_product();
+        let jobs = scatter(&step, inputs.clone()).unwrap();
+        assert_eq!(pairs(jobs), ["13true", "14true", "23true", "24true"]);
+
+        let mut step = Step::new("pair", &Container::from("alpine"), "true");
+        step.input("a", PortType::Int)
+            .input("b", PortType::Int)
+            .scatter("a")
+            .scatter("b");
+        let mut uneven = inputs;
+        uneven.insert("b".to_string(), Value::Array(vec![Value::Int(3)]));
+        assert!(matches!(
+            scatter(&step, uneven),
+            Err(ExecutionError::ScatterLength {
+                expected: 2,
+                found: 1,
+                ..
+            })
+        ));
+    }
+}
+
+// EOF
@@ -0,0 +1,288 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::{Condition, PortType, Resources};
+use crate::container::Container;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// A named, typed input or output of a step or workflow.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Port {
+    /// The port name, unique among the inputs or outputs it belongs to.
+    pub name: String,
+
+    /// The type of data the port carries.
+    pub port_type: PortType,
+}
+
+impl Port {
+    /// Create a port.
+    pub fn new(name: impl Into<String>, port_type: PortType) -> Self {
+        Self {
+            name: name.into(),
+            port_type,
+        }
+    }
+}
+
+/// How the scattered inputs of a step are combined into jobs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ScatterMethod {
+    /// Pair the arrays item by item; they must all have the same length.
+    #[default]
+    DotProduct,
+
+    /// Run every combination of items, varying the last scattered input fastest.
+    CrossProduct,
+}
+
+/// A single analysis step: a command run inside a container.
+///
+/// The command is a shell command template. Inputs are referenced as `{name}` placeholders,
+/// which are replaced with the input values when the step runs; shell syntax such as
+/// `${VAR}` or `awk '{print $1}'` is left as is.
+///
+/// Outputs are collected after the command finishes by matching a glob pattern against the
+/// step's working directory. The pattern defaults to the output name and can be changed with
+/// [`glob`](Self::glob).
+///
+/// A step can [`scatter`](Self::scatter) over inputs: each scattered input takes an array of
+/// its declared type, and the command runs once per item, or per combination of items with
+/// [`cross_product`](Self::cross_product). Every output of a scattered step is gathered into
+/// an array, in the order of the jobs.
+///
+/// A step can run only [`when`](Self::when) a condition on its inputs holds. A skipped step,
+/// or a skipped job of a scattered step, produces null for every output, so the outputs of a
+/// conditional step are optional and can only be connected to inputs that accept absence.
+/// Outputs can also be declared [optional](PortType::optional) themselves, in which case a
+/// glob matching nothing yields null instead of failing.
+///
+/// # Examples
+///
+/// ```
+/// use rivulet::prelude::*;
+/// use rivulet::workflow::PortType;
+///
+/// let salmon = Container::from("quay.io/biocontainers/salmon:1.5.2");
+/// let mut quant = Step::new(
+///     "quant",
+///     &salmon,
+///     "salmon quant -i {index} -l A -r {reads} -o quant",
+/// );
+/// quant
+///     .input("index", PortType::Directory)
+///     .input("reads", PortType::File)
+///     .output("quant", PortType::Directory)
+///     .output("counts", PortType::File)
+///     .glob("counts", "quant/quant.sf");
+///
+/// assert_eq!(quant.inputs().len(), 2);
+/// assert_eq!(quant.output_glob("quant"), "quant");
+/// assert_eq!(quant.output_glob("counts"), "quant/quant.sf");
+/// assert_eq!(quant.output_port("quant").unwrap().port_type, PortType::Directory);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Step {
+    name: String,
+    container: Arc<RwLock<Container>>,
+    command: String,
+    inputs: Vec<Port>,
+    outputs: Vec<Port>,
+    globs: BTreeMap<String, String>,
+    resources: Resources,
+    scatter: Vec<String>,
+    scatter_method: ScatterMethod,
+    condition: Option<Condition>,
+}
+
+impl Step {
+    /// Create a step without ports.
+    pub fn new(
+        name: impl Into<String>,
+        container: &Arc<RwLock<Container>>,
+        command: impl Into<String>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            container: container.clone(),
+            command: command.into(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            globs: BTreeMap::new(),
+            resources: Resources::default(),
+            scatter: Vec::new(),
+            scatter_method: ScatterMethod::default(),
+            condition: None,
+        }
+    }
+
+    /// Declare an input port.
+    pub fn input(&mut self, name: impl Into<String>, port_type: PortType) -> &mut Self {
+        self.inputs.push(Port::new(name, port_type));
+        self
+    }
+
+    /// Declare an output port.
+    pub fn output(&mut self, name: impl Into<String>, port_type: PortType) -> &mut Self {
+        self.outputs.push(Port::new(name, port_type));
+        self
+    }
+
+    /// Set the glob pattern an output is collected with, relative to the working directory.
+    ///
+    /// A `File` or `Directory` output, or a scalar output read from a file, must match exactly
+    /// one path of the right kind. An array output takes every match, in sorted order.
+    pub fn glob(&mut self, output: impl Into<String>, pattern: impl Into<String>) -> &mut Self {
+        self.globs.insert(output.into(), pattern.into());
+        self
+    }
+
+    /// Run the command once per item of an array given to an input.
+    ///
+    /// The input is declared with the type of a single item; it takes an array of that type
+    /// instead. Scattering over several inputs pairs their items, see [`ScatterMethod`].
+    pub fn scatter(&mut self, input: impl Into<String>) -> &mut Self {
+        let input = input.into();
+        if !self.scatter.contains(&input) {
+            self.scatter.push(input);
+        }
+        self
+    }
+
+    /// Combine the scattered inputs in a cross product instead of a dot product.
+    pub fn cross_product(&mut self) -> &mut Self {
+        self.scatter_method = ScatterMethod::CrossProduct;
+        self
+    }
+
+    /// Run the command only when a condition on the inputs holds.
+    ///
+    /// The condition is evaluated separately for every job of a scattered step.
+    pub fn when(&mut self, condition: Condition) -> &mut Self {
+        self.condition = Some(condition);
+        self
+    }
+
+    /// Request a number of CPU cores.
+    pub fn cpus(&mut self, cpus: u32) -> &mut Self {
+        self.resources.cpus = Some(cpus);
+        self
+    }
+
+    /// Request an amount of memory, in bytes.
+    pub fn memory(&mut self, bytes: u64) -> &mut Self {
+        self.resources.memory = Some(bytes);
+        self
+    }
+
+    /// Set the wall-clock time limit.
+    pub fn time(&mut self, limit: Duration) -> &mut Self {
+        self.resources.time = Some(limit);
+        self
+    }
+
+    /// Request a number of GPUs.
+    pub fn gpus(&mut self, gpus: u32) -> &mut Self {
+        self.resources.gpus = Some(gpus);
+        self
+    }
+
+    /// The step name, unique within its workflow.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The container the command runs in.
+    pub fn container(&self) -> &Arc<RwLock<Container>> {
+        &self.container
+    }
+
+    /// The command template.
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// The input ports, in declaration order.
+    pub fn inputs(&self) -> &[Port] {
+        &self.inputs
+    }
+
+    /// The output ports, in declaration order.
+    pub fn outputs(&self) -> &[Port] {
+        &self.outputs
+    }
+
+    /// Look up an input port by name.
+    pub fn input_port(&self, name: &str) -> Option<&Port> {
+        self.inputs.iter().find(|port| port.name == name)
+    }
+
+    /// Look up an output port by name.
+    pub fn output_port(&self, name: &str) -> Option<&Port> {
+        self.outputs.iter().find(|port| port.name == name)
+    }
+
+    /// The glob pattern an output is collected with.
+    pub fn output_glob<'a>(&'a self, output: &'a str) -> &'a str {
+        self.globs.get(output).map_or(output, String::as_str)
+    }
+
+    /// The compute resources the step asks for.
+    pub fn resources(&self) -> &Resources {
+        &self.resources
+    }
+
+    /// The inputs the step scatters over, in the order they were added.
+    pub fn scatter_inputs(&self) -> &[String] {
+        &self.scatter
+    }
+
+    /// How the scattered inputs are combined.
+    pub fn scatter_method(&self) -> ScatterMethod {
+        self.scatter_method
+    }
+
+    /// Whether the step scatters over any input.
+    pub fn is_scattered(&self) -> bool {
+        !self.scatter.is_empty()
+    }
+
+    /// The condition set with [`when`](Self::when), if any.
+    pub fn condition(&self) -> Option<&Condition> {
+        self.condition.as_ref()
+    }
+
+    /// The type of data an input takes: an array of the declared type if it is scattered.
+    pub fn input_type(&self, name: &str) -> Option<PortType> {
+        let port = self.input_port(name)?;
+        Some(if self.scatter.contains(&port.name) {
+            PortType::array(port.port_type.clone())
+        } else {
+            port.port_type.clone()
+        })
+    }
+
+    /// The type of data an output provides: the declared type, made optional if the step has
+    /// a condition, in an array if the step is scattered.
+    pub fn output_type(&self, name: &str) -> Option<PortType> {
+        let port = self.output_port(name)?;
+        let mut port_type = port.port_type.clone();
+        if self.condition.is_some() {
+            port_type = PortType::optional(port_type);
+        }
+        if self.is_scattered() {
+            port_type = PortType::array(port_type);
+        }
+        Some(port_type)
+    }
+
+    /// The glob patterns set with [`glob`](Self::glob), by output name.
+    pub fn globs(&self) -> &BTreeMap<String, String> {
+        &self.globs
+    }
+}
+
+// EOF
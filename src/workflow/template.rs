@@ -0,0 +1,141 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+//! Parsing of step command templates.
+//!
+//! A placeholder is an identifier in braces, such as `{reads}`. Braces preceded by `$` are
+//! shell parameter expansions and braces around anything other than an identifier (for
+//! example an `awk` program) are left alone, so ordinary shell commands need no escaping.
+
+/// A piece of a command template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Segment<'a> {
+    /// Text passed through unchanged.
+    Literal(&'a str),
+
+    /// A reference to a port, by name.
+    Placeholder(&'a str),
+}
+
+/// Split a command template into literal text and placeholders.
+pub(crate) fn segments(template: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut literal_start = 0;
+    let mut search_from = 0;
+    while let Some(offset) = template[search_from..].find('{') {
+        let open = search_from + offset;
+        search_from = open + 1;
+        if template[..open].ends_with('$') {
+            continue;
+        }
+        let Some(length) = template[open + 1..].find('}') else {
+            break;
+        };
+        let name = &template[open + 1..open + 1 + length];
+        if !is_identifier(name) {
+            continue;
+        }
+        if literal_start < open {
+            segments.push(Segment::Literal(&template[literal_start..open]));
+        }
+        segments.push(Segment::Placeholder(name));
+        literal_start = open + length + 2;
+        search_from = literal_start;
+    }
+    if literal_start < template.len() {
+        segments.push(Segment::Literal(&template[literal_start..]));
+    }
+    segments
+}
+
+/// Replace the placeholders of a command template.
+pub(crate) fn render(template: &str, mut replace: impl FnMut(&str) -> String) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    for segment in segments(template) {
+        match segment {
+            Segment::Literal(text) => rendered.push_str(text),
+            Segment::Placeholder(name) => rendered.push_str(&replace(name)),
+        }
+    }
+    rendered
+}
+
+/// The names referenced by a command template, in order of appearance.
+pub(crate) fn placeholders(template: &str) -> impl Iterator<Item = &str> {
+    segments(template)
+        .into_iter()
+        .filter_map(|segment| match segment {
+            Segment::Placeholder(name) => Some(name),
+            Segment::Literal(_) => None,
+        })
+}
+
+/// Whether a name is a valid identifier: an ASCII letter or underscore followed by ASCII
+/// letters, digits and underscores.
+pub(crate) fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Segment::{Literal, Placeholder};
+
+    #[test]
+    fn test_placeholders() {
+        assert_eq!(
+            segments("salmon quant -i {index} -r {reads} -o out"),
+            [
+                Literal("salmon quant -i "),
+                Placeholder("index"),
+                Literal(" -r "),
+                Placeholder("reads"),
+                Literal(" -o out"),
+            ]
+        );
+        assert_eq!(segments("{a}{b}"), [Placeholder("a"), Placeholder("b")]);
+    }
+
+    #[test]
+    fn test_shell_braces_are_literal() {
+        for command in [
+            "echo ${HOME}",
+            "awk '{print $1}' data.tsv",
+            "cp file.{txt,bak}",
+            "echo {",
+            "echo {1}",
+        ] {
+            assert_eq!(segments(command), [Literal(command)], "{command}");
+        }
+    }
+
+    #[test]
+    fn test_placeholder_after_shell_braces() {
+        assert_eq!(
+            placeholders("echo ${HOME} {x} '{print $1}' {y}").collect::<Vec<_>>(),
+            ["x", "y"]
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let rendered = render("echo {a} ${b} {c}", |name| name.to_uppercase());
+        assert_eq!(rendered, "echo A ${b} C");
+    }
+
+    #[test]
+    fn test_is_identifier() {
+        assert!(is_identifier("reads"));
+        assert!(is_identifier("_reads_1"));
+        assert!(!is_identifier(""));
+        assert!(!is_identifier("1reads"));
+        assert!(!is_identifier("reads-1"));
+    }
+}
+
+// EOF
@@ -0,0 +1,156 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use std::fmt;
+
+/// The type of data a port carries.
+///
+/// # Examples
+///
+/// ```
+/// use rivulet::workflow::PortType;
+///
+/// let samples = PortType::array(PortType::File);
+/// assert_eq!(samples.to_string(), "Array<File>");
+/// assert!(PortType::Int.accepts(&PortType::Int));
+/// assert!(PortType::Float.accepts(&PortType::Int));
+/// assert!(!PortType::File.accepts(&PortType::Directory));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum PortType {
+    /// A single file, such as a FASTQ file.
+    File,
+
+    /// A directory tree, such as a reference index.
+    Directory,
+
+    /// A signed integer, such as a thread count.
+    Int,
+
+    /// A floating point number.
+    Float,
+
+    /// A text value.
+    String,
+
+    /// A boolean flag.
+    Bool,
+
+    /// An ordered list of values of one type, such as the reads of every sample.
+    Array(Box<PortType>),
+
+    /// A value of the inner type, or null, such as the output of a step that may be skipped.
+    Optional(Box<PortType>),
+}
+
+impl PortType {
+    /// Create an array type.
+    pub fn array(items: PortType) -> Self {
+        Self::Array(Box::new(items))
+    }
+
+    /// Create an optional type; optional types are returned unchanged.
+    pub fn optional(inner: PortType) -> Self {
+        match inner {
+            PortType::Optional(_) => inner,
+            inner => Self::Optional(Box::new(inner)),
+        }
+    }
+
+    /// Whether data of this type may be null.
+    pub fn is_optional(&self) -> bool {
+        matches!(self, PortType::Optional(_))
+    }
+
+    /// Whether a port of this type can take data of type `found`.
+    ///
+    /// Types must match exactly, except that an integer is accepted where a float is expected,
+    /// arrays are accepted when their items are, and an optional port accepts data of its
+    /// inner type. A port that is not optional never accepts optional data, which may be
+    /// null.
+    pub fn accepts(&self, found: &PortType) -> bool {
+        match (self, found) {
+            (PortType::Float, PortType::Int) => true,
+            (PortType::Array(expected), PortType::Array(found)) => expected.accepts(found),
+            (PortType::Optional(expected), PortType::Optional(found)) => expected.accepts(found),
+            (PortType::Optional(expected), found) => expected.accepts(found),
+            (expected, found) => expected == found,
+        }
+    }
+}
+
+impl fmt::Display for PortType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortType::File => f.write_str("File"),
+            PortType::Directory => f.write_str("Directory"),
+            PortType::Int => f.write_str("Int"),
+            PortType::Float => f.write_str("Float"),
+            PortType::String => f.write_str("String"),
+            PortType::Bool => f.write_str("Bool"),
+            PortType::Array(items) => write!(f, "Array<{items}>"),
+            PortType::Optional(inner) => write!(f, "Optional<{inner}>"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact_matches() {
+        let types = [
+            PortType::File,
+            PortType::Directory,
+            PortType::Int,
+            PortType::Float,
+            PortType::String,
+            PortType::Bool,
+            PortType::array(PortType::File),
+        ];
+        for (i, expected) in types.iter().enumerate() {
+            for (j, found) in types.iter().enumerate() {
+                let widening = *expected == PortType::Float && *found == PortType::Int;
+                assert_eq!(
+                    expected.accepts(found),
+                    i == j || widening,
+                    "{expected} <- {found}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_arrays() {
+        let floats = PortType::array(PortType::Float);
+        assert!(floats.accepts(&PortType::array(PortType::Int)));
+        assert!(!PortType::array(PortType::Int).accepts(&floats));
+        assert!(!floats.accepts(&PortType::Float));
+        assert!(!PortType::Float.accepts(&floats));
+
+        let nested = PortType::array(PortType::array(PortType::File));
+        assert_eq!(nested.to_string(), "Array<Array<File>>");
+        assert!(!nested.accepts(&PortType::array(PortType::File)));
+    }
+
+    #[test]
+    fn test_optionals() {
+        let maybe_float = PortType::optional(PortType::Float);
+        assert_eq!(maybe_float.to_string(), "Optional<Float>");
+        assert!(maybe_float.accepts(&PortType::Float));
+        assert!(maybe_float.accepts(&PortType::optional(PortType::Int)));
+        assert!(!PortType::Float.accepts(&maybe_float));
+        assert!(!maybe_float.accepts(&PortType::String));
+        assert_eq!(PortType::optional(maybe_float.clone()), maybe_float);
+
+        let maybe_files = PortType::array(PortType::optional(PortType::File));
+        assert!(maybe_files.accepts(&PortType::array(PortType::File)));
+        assert!(!PortType::array(PortType::File).accepts(&maybe_files));
+    }
+}
+
+// EOF
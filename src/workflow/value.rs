@@ -0,0 +1,188 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::PortType;
+use std::fmt;
+use std::path::PathBuf;
+
+/// A piece of data flowing through a workflow at run time.
+///
+/// # Examples
+///
+/// ```
+/// use rivulet::workflow::{PortType, Value};
+///
+/// let samples = Value::Array(vec![
+///     Value::File("a_R1.fq.gz".into()),
+///     Value::File("b_R1.fq.gz".into()),
+/// ]);
+/// assert!(samples.conforms_to(&PortType::array(PortType::File)));
+/// assert!(Value::Int(8).conforms_to(&PortType::Float));
+/// assert!(!Value::Directory("index".into()).conforms_to(&PortType::File));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// A path to a file.
+    File(PathBuf),
+
+    /// A path to a directory.
+    Directory(PathBuf),
+
+    /// A signed integer.
+    Int(i64),
+
+    /// A floating point number.
+    Float(f64),
+
+    /// A text value.
+    String(String),
+
+    /// A boolean flag.
+    Bool(bool),
+
+    /// An ordered list of values.
+    Array(Vec<Value>),
+
+    /// No value, such as an output of a skipped step.
+    Null,
+}
+
+impl Value {
+    /// Whether this value can be passed to a port of the given type.
+    ///
+    /// The rules follow [`PortType::accepts`]: an integer conforms to a float port, an array
+    /// conforms when all of its items do, and null conforms only to optional ports.
+    pub fn conforms_to(&self, port_type: &PortType) -> bool {
+        match (self, port_type) {
+            (Value::Null, PortType::Optional(_)) => true,
+            (value, PortType::Optional(inner)) => value.conforms_to(inner),
+            (Value::File(_), PortType::File)
+            | (Value::Directory(_), PortType::Directory)
+            | (Value::Int(_), PortType::Int | PortType::Float)
+            | (Value::Float(_), PortType::Float)
+            | (Value::String(_), PortType::String)
+            | (Value::Bool(_), PortType::Bool) => true,
+            (Value::Array(items), PortType::Array(item_type)) => {
+                items.iter().all(|item| item.conforms_to(item_type))
+            }
+            _ => false,
+        }
+    }
+
+    /// The words this value contributes to a command line, before quoting.
+    ///
+    /// Arrays contribute one word per item, null contributes nothing, and everything else
+    /// contributes a single word.
+    pub(crate) fn words(&self) -> Vec<String> {
+        match self {
+            Value::Array(items) => items.iter().flat_map(Value::words).collect(),
+            Value::File(path) | Value::Directory(path) => vec![path.display().to_string()],
+            Value::Int(n) => vec![n.to_string()],
+            Value::Float(x) => vec![x.to_string()],
+            Value::String(s) => vec![s.clone()],
+            Value::Bool(b) => vec![b.to_string()],
+            Value::Null => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    /// Format the value for logs and messages.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::File(path) | Value::Directory(path) => write!(f, "{}", path.display()),
+            Value::Int(n) => write!(f, "{n}"),
+            Value::Float(x) => write!(f, "{x}"),
+            Value::String(s) => write!(f, "{s:?}"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Null => f.write_str("null"),
+            Value::Array(items) => {
+                f.write_str("[")?;
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                f.write_str("]")
+            }
+        }
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Value::Int(n)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(x: f64) -> Self {
+        Value::Float(x)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scalars_conform_to_their_types() {
+        assert!(Value::from(3).conforms_to(&PortType::Int));
+        assert!(Value::from(3.5).conforms_to(&PortType::Float));
+        assert!(!Value::from(3.5).conforms_to(&PortType::Int));
+        assert!(Value::from("x").conforms_to(&PortType::String));
+        assert!(Value::from(true).conforms_to(&PortType::Bool));
+        assert!(!Value::from("true").conforms_to(&PortType::Bool));
+    }
+
+    #[test]
+    fn test_arrays_conform_by_item() {
+        let array = PortType::array(PortType::Int);
+        assert!(Value::Array(vec![]).conforms_to(&array));
+        assert!(Value::Array(vec![1.into(), 2.into()]).conforms_to(&array));
+        assert!(!Value::Array(vec![1.into(), "2".into()]).conforms_to(&array));
+        assert!(!Value::from(1).conforms_to(&array));
+    }
+
+    #[test]
+    fn test_words() {
+        let value = Value::Array(vec![Value::File("a b.fq".into()), Value::Int(2)]);
+        assert_eq!(value.words(), ["a b.fq", "2"]);
+        assert_eq!(Value::Float(0.5).words(), ["0.5"]);
+        assert_eq!(value.to_string(), "[a b.fq, 2]");
+        assert_eq!(Value::from("x y").to_string(), "\"x y\"");
+        assert!(Value::Null.words().is_empty());
+    }
+
+    #[test]
+    fn test_null() {
+        let maybe_file = PortType::optional(PortType::File);
+        assert!(Value::Null.conforms_to(&maybe_file));
+        assert!(Value::File("a.fq".into()).conforms_to(&maybe_file));
+        assert!(!Value::Null.conforms_to(&PortType::File));
+        assert!(!Value::Int(1).conforms_to(&maybe_file));
+        assert_eq!(Value::Array(vec![Value::Null]).to_string(), "[null]");
+    }
+}
+
+// EOF
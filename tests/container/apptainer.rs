@@ -0,0 +1,86 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::common::analysis_chain;
+use rivulet::container::{ApptainerBootstrap, ApptainerBuild};
+use rivulet::prelude::*;
+
+#[test]
+fn test_apptainer_golden_multi_stage() {
+    let chain = analysis_chain("python:3.12-slim");
+    let build = ApptainerBuild::new(&chain.read().unwrap()).unwrap();
+    let definitions = build.definitions();
+    assert_eq!(definitions.len(), 3);
+    assert_eq!(
+        definitions[0].to_string(),
+        include_str!("golden/python_analysis.stage0.def")
+    );
+    assert_eq!(
+        definitions[1].to_string(),
+        include_str!("golden/python_analysis.stage1.def")
+    );
+    assert_eq!(
+        definitions[2].to_string(),
+        include_str!("golden/python_analysis.stage2.def")
+    );
+    assert_eq!(build.target().image(), "stage2.sif");
+}
+
+#[test]
+fn test_apptainer_golden_single_image() {
+    let container = Container::from("quay.io/biocontainers/salmon:1.5.2");
+    let build = ApptainerBuild::new(&container.read().unwrap()).unwrap();
+    assert_eq!(
+        build.target().to_string(),
+        include_str!("golden/salmon.def")
+    );
+}
+
+#[test]
+fn test_apptainer_intermediates_bootstrap_from_local_images() {
+    let chain = analysis_chain("python:3.12-slim");
+    let build = ApptainerBuild::new(&chain.read().unwrap()).unwrap();
+    let definitions = build.definitions();
+    assert!(matches!(
+        definitions[0].bootstrap,
+        ApptainerBootstrap::Docker(_)
+    ));
+    for (index, definition) in definitions.iter().enumerate().skip(1) {
+        assert_eq!(
+            definition.bootstrap,
+            ApptainerBootstrap::LocalImage(definitions[index - 1].image())
+        );
+    }
+}
+
+#[test]
+fn test_apptainer_for_intermediate_container() {
+    // Generating for a container in the middle of a chain ignores its descendants
+    let base = Container::from("alpine:3.19");
+    let middle = Container::from(&base);
+    middle.write().unwrap().run("apk add bash");
+    let top = Container::from(&middle);
+    top.write().unwrap().run("apk add python3");
+
+    let build = ApptainerBuild::new(&middle.read().unwrap()).unwrap();
+    assert_eq!(build.definitions().len(), 2);
+    assert!(!build.target().to_string().contains("python3"));
+}
+
+#[test]
+fn test_apptainer_from_resolved() {
+    let chain = analysis_chain("python:3.12-slim");
+    let resolved = chain.read().unwrap().resolve().unwrap();
+    let build = ApptainerBuild::from_resolved(&resolved);
+
+    assert_eq!(build.definitions().len(), 1);
+    let definition = build.target();
+    assert_eq!(
+        definition.bootstrap,
+        ApptainerBootstrap::Docker("docker.io/library/python:3.12-slim".to_string())
+    );
+    assert_eq!(definition.steps, resolved.layers().concat());
+}
+
+// EOF
@@ -0,0 +1,35 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use rivulet::prelude::*;
+use std::sync::{Arc, RwLock};
+
+/// Build a three-level chain on `image`: the base image, a Python layer and an analysis layer.
+pub fn analysis_chain(image: &str) -> Arc<RwLock<Container>> {
+    let base = Container::from(image);
+    base.write()
+        .unwrap()
+        .label("org.opencontainers.image.source", "https://example.org/lab")
+        .env("PIP_NO_CACHE_DIR", "1");
+
+    let deps = Container::from(&base);
+    deps.write()
+        .unwrap()
+        .workdir("/opt/analysis")
+        .copy(["requirements.txt"], "/opt/analysis/")
+        .run("pip install -r requirements.txt");
+
+    let analysis = Container::from(&deps);
+    analysis
+        .write()
+        .unwrap()
+        .copy(["src", "config/defaults.toml"], "/opt/analysis/")
+        .run("set -e\npython -m compileall src\npython -m src.selftest")
+        .env("ANALYSIS_CONFIG", "/opt/analysis/defaults.toml")
+        .entrypoint(["python", "-m", "src.main"]);
+
+    analysis
+}
+
+// EOF
@@ -13,7 +13,7 @@ fn test_container_creation_from_image_references() {
         "python:3.9-slim",
         "docker.io/library/redis:6.2",
         "codeberg.org/forgejo/forgejo:10.0.1",
-        "ubuntu@sha256=a1b2c3d4e5f6",
+        "ubuntu@sha256:01ba4719c80b6fe911b091a7c05124b64eeece964e09c058ef8f9805daca546b",
     ];
 
     for image in images {
@@ -30,6 +30,9 @@ fn test_container_creation_from_image_references() {
                     s.repository == base_name
                         || base_name.ends_with(&s.repository)
                         || s.namespace.as_ref().is_some_and(|n| n.contains(base_name))
+                        || s.registry
+                            .as_ref()
+                            .is_some_and(|r| base_name.starts_with(r))
                 );
             }
             _ => panic!("Expected ContainerBase::External"),
@@ -41,7 +44,8 @@ fn test_container_creation_from_image_references() {
 fn test_container_from_custom_selector() {
     // Manually construct an ImageSelector
     let selector = ImageSelector {
-        namespace: Some("custom.registry".to_string()),
+        registry: Some("custom.registry".to_string()),
+        namespace: None,
         repository: "myapp".to_string(),
         tag: Some("v1.0".to_string()),
         digest: None,
@@ -54,7 +58,7 @@ fn test_container_from_custom_selector() {
     let container_guard = container.read().unwrap();
     assert!(matches!(container_guard.base,
         ContainerBase::External(ref s) if
-            s.namespace == Some("custom.registry".to_string()) &&
+            s.registry == Some("custom.registry".to_string()) &&
             s.repository == "myapp" &&
             s.tag == Some("v1.0".to_string())
     ));
@@ -70,8 +74,9 @@ fn test_container_parse_errors() {
         "/",         // Missing repository
         "registry/", // Missing repository
         "@invalid",  // Invalid digest format
-        "@algo=",    // Empty hash
-        "@=value",   // Empty algorithm
+        "@algo:",    // Empty hash
+        "@:value",   // Empty algorithm
+        "@sha256:a", // Truncated hash
     ];
 
     for invalid_ref in invalid_refs {
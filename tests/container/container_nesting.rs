@@ -2,7 +2,9 @@
 // If a copy of the MPL was not distributed with this file,
 // You can obtain one at <https://mozilla.org/MPL/2.0/>.
 
+use rivulet::container::{BuildStep, ContainerError, Containerfile};
 use rivulet::prelude::*;
+use std::sync::Arc;
 
 #[test]
 fn test_container_chaining() {
@@ -32,41 +34,105 @@ fn test_container_chaining() {
 fn test_container_deep_nesting() {
     // Create a series of nested containers (like a container builder pattern)
     let base = Container::from("alpine:latest");
-    let with_python = Container::from(&base); // Imagine this adds Python
-    let with_deps = Container::from(&with_python); // Adds dependencies
-    let with_app = Container::from(&with_deps); // Adds application code
-    let with_config = Container::from(&with_app); // Adds configuration
+    let with_python = Container::from(&base);
+    with_python
+        .write()
+        .unwrap()
+        .run("apk add --no-cache python3 py3-pip");
+    let with_deps = Container::from(&with_python);
+    with_deps
+        .write()
+        .unwrap()
+        .copy(["requirements.txt"], "/opt/app/")
+        .run("pip install -r /opt/app/requirements.txt");
+    let with_app = Container::from(&with_deps);
+    with_app
+        .write()
+        .unwrap()
+        .copy(["src"], "/opt/app/src")
+        .workdir("/opt/app");
+    let with_config = Container::from(&with_app);
+    with_config
+        .write()
+        .unwrap()
+        .env("APP_CONFIG", "/etc/app.toml")
+        .entrypoint(["python3", "-m", "app"]);
 
     // Verify we can traverse the entire chain of containers
-    let mut current = with_config;
-    let mut depth = 0;
-
-    loop {
-        let guard = current.read().unwrap();
-        match &guard.base {
-            ContainerBase::Internal(arc) => {
-                // Move to the next container in the chain
-                let next_container = arc.clone();
-                drop(guard); // Release the borrow before reassigning
-                current = next_container;
-                depth += 1;
-            }
-            ContainerBase::External(selector) => {
-                // We've reached the base container
-                assert_eq!(selector.repository, "alpine");
-                assert_eq!(selector.tag, Some("latest".to_string()));
-                break;
-            }
-        }
-
-        // Avoid infinite loops in test (shouldn't happen, but safety first)
-        if depth > 10 {
-            panic!("Too much nesting, possible cycle detected");
-        }
-    }
+    let guard = with_config.read().unwrap();
+    let ancestors = guard
+        .ancestors()
+        .collect::<Result<Vec<_>, _>>()
+        .expect("chain has no cycle");
+    let depth = ancestors.len();
+    let mut steps_per_layer = vec![guard.steps.len()];
+    steps_per_layer.extend(ancestors.iter().map(|a| a.read().unwrap().steps.len()));
+
+    // The last ancestor is the base container
+    assert!(Arc::ptr_eq(&ancestors[depth - 1], &base));
+    let root = guard.root_image().unwrap();
+    assert_eq!(root.repository, "alpine");
+    assert_eq!(root.tag, Some("latest".to_string()));
 
     // Verify we found the expected depth (should be 4 levels deep)
     assert_eq!(depth, 4);
+
+    // Each derived layer carries its own customizations; the base image carries none
+    assert_eq!(steps_per_layer, [2, 2, 2, 1, 0]);
+}
+
+#[test]
+fn test_container_steps_describe_customizations() {
+    let base = Container::from("ubuntu:20.04");
+    let dev = Container::from(&base);
+    dev.write()
+        .unwrap()
+        .run("apt-get update && apt-get install -y build-essential")
+        .label("stage", "dev");
+
+    let dev_guard = dev.read().unwrap();
+    assert!(matches!(dev_guard.steps.as_slice(),
+        [BuildStep::Run(command), BuildStep::Label { key, value }]
+            if command.starts_with("apt-get update") && key == "stage" && value == "dev"
+    ));
+    assert!(matches!(dev_guard.base,
+        ContainerBase::Internal(ref parent) if parent.read().unwrap().steps.is_empty()
+    ));
+}
+
+#[test]
+fn test_container_cycle_is_detected() {
+    let base = Container::from("alpine:latest");
+    let middle = Container::from(&base);
+    let top = Container::from(&middle);
+
+    // Close the loop: the base now builds on its own grandchild
+    base.write().unwrap().base = ContainerBase::Internal(top.clone());
+
+    let guard = top.read().unwrap();
+    let results: Vec<_> = guard.ancestors().collect();
+    assert_eq!(results.len(), 3);
+    assert!(matches!(results[2], Err(ContainerError::Cycle)));
+    assert_eq!(guard.root_image(), Err(ContainerError::Cycle));
+    assert_eq!(Containerfile::new(&guard), Err(ContainerError::Cycle));
+}
+
+#[test]
+fn test_container_self_reference_is_detected() {
+    let container = Container::from("alpine:latest");
+    let snapshot = container.read().unwrap().clone();
+    container.write().unwrap().base = ContainerBase::Internal(container.clone());
+
+    let guard = container.read().unwrap();
+    assert!(matches!(
+        guard.ancestors().next(),
+        Some(Err(ContainerError::Cycle))
+    ));
+
+    // Restoring the base makes the container usable again
+    drop(guard);
+    *container.write().unwrap() = snapshot;
+    assert!(container.read().unwrap().root_image().is_ok());
 }
 
 #[test]
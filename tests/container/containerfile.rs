@@ -0,0 +1,68 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::common::analysis_chain;
+use rivulet::container::Containerfile;
+use rivulet::prelude::*;
+
+#[test]
+fn test_containerfile_golden_multi_stage() {
+    let chain = analysis_chain("python:3.12-slim");
+    let containerfile = Containerfile::new(&chain.read().unwrap()).unwrap();
+    assert_eq!(
+        containerfile.to_string(),
+        include_str!("golden/python_analysis.Containerfile")
+    );
+    assert_eq!(containerfile.target(), "stage2");
+}
+
+#[test]
+fn test_containerfile_golden_single_image() {
+    let container = Container::from("quay.io/biocontainers/salmon:1.5.2");
+    let containerfile = Containerfile::new(&container.read().unwrap()).unwrap();
+    assert_eq!(
+        containerfile.to_string(),
+        include_str!("golden/salmon.Containerfile")
+    );
+}
+
+#[test]
+fn test_containerfile_for_intermediate_container() {
+    // Generating for a container in the middle of a chain ignores its descendants
+    let base = Container::from("alpine:3.19");
+    let middle = Container::from(&base);
+    middle.write().unwrap().run("apk add bash");
+    let top = Container::from(&middle);
+    top.write().unwrap().run("apk add python3");
+
+    let containerfile = Containerfile::new(&middle.read().unwrap()).unwrap();
+    assert_eq!(containerfile.stages().len(), 2);
+    assert!(!containerfile.to_string().contains("python3"));
+}
+
+#[test]
+fn test_containerfile_stage_names_are_unique() {
+    let chain = analysis_chain("python:3.12-slim");
+    let containerfile = Containerfile::new(&chain.read().unwrap()).unwrap();
+    let stages = containerfile.stages();
+    for (index, stage) in stages.iter().enumerate().skip(1) {
+        assert_eq!(stage.from, stages[index - 1].name);
+    }
+}
+
+#[test]
+fn test_containerfile_from_resolved() {
+    let chain = analysis_chain("python:3.12-slim");
+    let resolved = chain.read().unwrap().resolve().unwrap();
+    let containerfile = Containerfile::from_resolved(&resolved);
+
+    let [stage] = containerfile.stages() else {
+        panic!("expected a single stage");
+    };
+    assert_eq!(stage.from, "docker.io/library/python:3.12-slim");
+    assert_eq!(stage.steps, resolved.layers().concat());
+    assert_eq!(containerfile.target(), "stage0");
+}
+
+// EOF
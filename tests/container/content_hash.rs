@@ -0,0 +1,69 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::common::analysis_chain;
+use rivulet::container::ContainerError;
+use rivulet::prelude::*;
+use std::sync::Arc;
+
+#[test]
+fn test_content_hash_is_structural() {
+    let first = analysis_chain("python:3.12-slim");
+    let second = analysis_chain("docker.io/library/python:3.12-slim");
+    assert!(!Arc::ptr_eq(&first, &second));
+    assert_eq!(
+        first.read().unwrap().content_hash(),
+        second.read().unwrap().content_hash()
+    );
+}
+
+#[test]
+fn test_content_hash_is_stable() {
+    // Hashes are persisted by caches, so the encoding must not change unnoticed
+    let chain = analysis_chain("python:3.12-slim");
+    assert_eq!(
+        chain.read().unwrap().content_hash().unwrap().to_string(),
+        "c33ff78e2078c8411237ac8c8978180cbee4849ca906c5d4e343220083bd7736"
+    );
+}
+
+#[test]
+fn test_content_hash_covers_every_layer() {
+    let chain = analysis_chain("python:3.12-slim");
+    let original = chain.read().unwrap().content_hash().unwrap();
+
+    // Changing a step in an ancestor changes the hash of every descendant
+    let ancestors: Vec<_> = chain
+        .read()
+        .unwrap()
+        .ancestors()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    ancestors[0].write().unwrap().run("pip check");
+    let changed = chain.read().unwrap().content_hash().unwrap();
+    assert_ne!(original, changed);
+
+    // So does moving a step to a different layer
+    ancestors[0].write().unwrap().steps.pop();
+    let steps = ancestors[1].write().unwrap().steps.split_off(0);
+    ancestors[0].write().unwrap().steps.splice(0..0, steps);
+    assert_ne!(original, chain.read().unwrap().content_hash().unwrap());
+
+    // And a different root image
+    let other = analysis_chain("python:3.13-slim");
+    assert_ne!(original, other.read().unwrap().content_hash().unwrap());
+}
+
+#[test]
+fn test_content_hash_cycle() {
+    let base = Container::from("alpine:3.19");
+    let derived = Container::from(&base);
+    base.write().unwrap().base = ContainerBase::Internal(derived.clone());
+    assert_eq!(
+        derived.read().unwrap().content_hash(),
+        Err(ContainerError::Cycle)
+    );
+}
+
+// EOF
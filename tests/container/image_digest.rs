@@ -0,0 +1,160 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use rivulet::container::{DigestAlgorithm, ImageDigest};
+use rivulet::prelude::*;
+
+const SHA256_HEX: &str = "01ba4719c80b6fe911b091a7c05124b64eeece964e09c058ef8f9805daca546b";
+
+#[test]
+fn test_sha512_digest() {
+    let hash = SHA256_HEX.repeat(2);
+    let digest = ImageDigest::parse(&format!("sha512:{hash}")).unwrap();
+    assert!(matches!(digest,
+        ImageDigest {
+            algorithm: DigestAlgorithm::Sha512,
+            hash: h,
+        } if h == hash
+    ));
+}
+
+#[test]
+fn test_unknown_algorithm_is_opaque() {
+    let digest =
+        ImageDigest::parse("multihash+base58:QmRZxt2b1FVZPNqd8hsiykDL3TdBDeTSPX9Kv46HmX4Gx8")
+            .unwrap();
+    assert!(matches!(digest,
+        ImageDigest {
+            algorithm: DigestAlgorithm::Other(a),
+            hash: h,
+        } if a == "multihash+base58" && h == "QmRZxt2b1FVZPNqd8hsiykDL3TdBDeTSPX9Kv46HmX4Gx8"
+    ));
+}
+
+#[test]
+fn test_digest_with_registry_port_and_tag() {
+    let selector = ImageSelector::parse(&format!(
+        "localhost:5000/lab/salmon:1.5.2@sha256:{SHA256_HEX}"
+    ))
+    .unwrap();
+    assert!(matches!(selector,
+        ImageSelector {
+            registry: Some(h),
+            namespace: Some(n),
+            repository: r,
+            tag: Some(t),
+            digest: Some(d),
+        } if h == "localhost:5000"
+            && n == "lab"
+            && r == "salmon"
+            && t == "1.5.2"
+            && d.algorithm == DigestAlgorithm::Sha256
+            && d.hash == SHA256_HEX
+    ));
+}
+
+#[test]
+fn test_digest_algorithm_from_str() {
+    assert_eq!(DigestAlgorithm::from("sha256"), DigestAlgorithm::Sha256);
+    assert_eq!(DigestAlgorithm::from("sha512"), DigestAlgorithm::Sha512);
+    assert!(matches!(
+        DigestAlgorithm::from("blake3"),
+        DigestAlgorithm::Other(a) if a == "blake3"
+    ));
+    assert_eq!(
+        DigestAlgorithm::from("blake3"),
+        DigestAlgorithm::from("blake3")
+    );
+}
+
+#[test]
+fn test_invalid_digest_formats() {
+    // Empty algorithm
+    let result = ImageSelector::parse("ubuntu@:hash");
+    assert!(matches!(result,
+        Err(ImageSelectorParseError::InvalidDigestFormat(s)) if s == ":hash"
+    ));
+
+    // Empty hash
+    let result = ImageSelector::parse("ubuntu@sha256:");
+    assert!(matches!(result,
+        Err(ImageSelectorParseError::InvalidDigestFormat(s)) if s == "sha256:"
+    ));
+
+    // No colon
+    let result = ImageSelector::parse("ubuntu@sha256");
+    assert!(matches!(result,
+        Err(ImageSelectorParseError::InvalidDigestFormat(s)) if s == "sha256"
+    ));
+
+    // Legacy equals separator
+    let result = ImageSelector::parse("ubuntu@sha256=a1b2c3");
+    assert!(matches!(result,
+        Err(ImageSelectorParseError::InvalidDigestFormat(s)) if s == "sha256=a1b2c3"
+    ));
+
+    // Uppercase algorithm
+    let result = ImageSelector::parse("ubuntu@SHA256:a1b2c3");
+    assert!(matches!(result,
+        Err(ImageSelectorParseError::InvalidDigestFormat(s)) if s == "SHA256:a1b2c3"
+    ));
+
+    // Empty algorithm component
+    let result = ImageSelector::parse("ubuntu@sha+:a1b2c3");
+    assert!(matches!(result,
+        Err(ImageSelectorParseError::InvalidDigestFormat(s)) if s == "sha+:a1b2c3"
+    ));
+
+    // Invalid hash characters
+    let result = ImageSelector::parse("ubuntu@blake3:a1b2.c3");
+    assert!(matches!(result,
+        Err(ImageSelectorParseError::InvalidDigestFormat(s)) if s == "blake3:a1b2.c3"
+    ));
+
+    // Empty digest
+    let result = ImageSelector::parse("ubuntu@");
+    assert!(matches!(result,
+        Err(ImageSelectorParseError::InvalidDigestFormat(s)) if s.is_empty()
+    ));
+}
+
+#[test]
+fn test_invalid_digest_lengths() {
+    let result = ImageSelector::parse("ubuntu@sha256:a1b2c3d4e5f6");
+    assert!(matches!(
+        result,
+        Err(ImageSelectorParseError::InvalidDigestLength {
+            algorithm: DigestAlgorithm::Sha256,
+            expected: 64,
+            found: 12,
+        })
+    ));
+
+    let result = ImageSelector::parse(&format!("ubuntu@sha512:{SHA256_HEX}"));
+    assert!(matches!(
+        result,
+        Err(ImageSelectorParseError::InvalidDigestLength {
+            algorithm: DigestAlgorithm::Sha512,
+            expected: 128,
+            found: 64,
+        })
+    ));
+}
+
+#[test]
+fn test_invalid_digest_encodings() {
+    let uppercase = SHA256_HEX.to_uppercase();
+    let result = ImageSelector::parse(&format!("ubuntu@sha256:{uppercase}"));
+    assert!(matches!(result,
+        Err(ImageSelectorParseError::InvalidDigestEncoding(s)) if s == uppercase
+    ));
+
+    let non_hex = "z".repeat(64);
+    let result = ImageSelector::parse(&format!("ubuntu@sha256:{non_hex}"));
+    assert!(matches!(result,
+        Err(ImageSelectorParseError::InvalidDigestEncoding(s)) if s == non_hex
+    ));
+}
+
+// EOF
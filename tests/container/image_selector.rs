@@ -2,106 +2,183 @@
 // If a copy of the MPL was not distributed with this file,
 // You can obtain one at <https://mozilla.org/MPL/2.0/>.
 
+use rivulet::container::{DigestAlgorithm, ImageDigest};
 use rivulet::prelude::*;
 use std::str::FromStr;
 
+const SHA256_HEX: &str = "01ba4719c80b6fe911b091a7c05124b64eeece964e09c058ef8f9805daca546b";
+
+/// Valid image references exercised throughout this file, used for round-trip properties.
+const CORPUS: [&str; 23] = [
+    "ubuntu",
+    "ubuntu:20.04",
+    "library/ubuntu",
+    "docker.io/ubuntu",
+    "docker.io/library/ubuntu:22.04",
+    "quay.io/prometheus/alertmanager:v0.24.0",
+    "k8s.gcr.io/kube-apiserver:v1.23.0",
+    "localhost:5000/lab/salmon",
+    "localhost/salmon:1.5.2",
+    "[::1]:5000/lab/salmon:1.5.2",
+    "ubuntu@sha256:HEX",
+    "docker.io/library/ubuntu@sha256:HEX",
+    "docker.io/library/ubuntu:20.04@sha256:HEX",
+    "registry.internal:8443/lab/salmon:1.5.2@sha256:HEX",
+    "nginx",
+    "nginx:latest",
+    "python:3.9-slim",
+    "registry.example.com/myapp:1.0",
+    "ghcr.io/owner/project/image:tag",
+    "index.docker.io/rocker/tidyverse:4.3",
+    "localhost:5000",
+    "docker.io/my.org/tool",
+    "docker.io/localhost/salmon:1.5.2",
+];
+
+/// Expand the digest placeholder in a corpus entry.
+fn corpus() -> impl Iterator<Item = String> {
+    CORPUS.iter().map(|input| input.replace("HEX", SHA256_HEX))
+}
+
+/// A reference and its expected registry, namespace, repository, tag and digest.
+type ParseCase = (
+    &'static str,
+    Option<&'static str>,
+    Option<&'static str>,
+    &'static str,
+    Option<&'static str>,
+    Option<(&'static str, &'static str)>,
+);
+
+/// Common image reference formats used in Docker, with the digest placeholder of [`CORPUS`].
+const PARSE_CASES: [ParseCase; 14] = [
+    ("ubuntu", None, None, "ubuntu", None, None),
+    ("ubuntu:20.04", None, None, "ubuntu", Some("20.04"), None),
+    (
+        "library/ubuntu",
+        None,
+        Some("library"),
+        "ubuntu",
+        None,
+        None,
+    ),
+    (
+        "docker.io/ubuntu",
+        Some("docker.io"),
+        None,
+        "ubuntu",
+        None,
+        None,
+    ),
+    (
+        "docker.io/library/ubuntu:22.04",
+        Some("docker.io"),
+        Some("library"),
+        "ubuntu",
+        Some("22.04"),
+        None,
+    ),
+    (
+        "quay.io/prometheus/alertmanager:v0.24.0",
+        Some("quay.io"),
+        Some("prometheus"),
+        "alertmanager",
+        Some("v0.24.0"),
+        None,
+    ),
+    (
+        "k8s.gcr.io/kube-apiserver:v1.23.0",
+        Some("k8s.gcr.io"),
+        None,
+        "kube-apiserver",
+        Some("v1.23.0"),
+        None,
+    ),
+    (
+        "localhost:5000/lab/salmon",
+        Some("localhost:5000"),
+        Some("lab"),
+        "salmon",
+        None,
+        None,
+    ),
+    (
+        "localhost/salmon:1.5.2",
+        Some("localhost"),
+        None,
+        "salmon",
+        Some("1.5.2"),
+        None,
+    ),
+    (
+        "[::1]:5000/lab/salmon:1.5.2",
+        Some("[::1]:5000"),
+        Some("lab"),
+        "salmon",
+        Some("1.5.2"),
+        None,
+    ),
+    (
+        "ubuntu@sha256:HEX",
+        None,
+        None,
+        "ubuntu",
+        None,
+        Some(("sha256", SHA256_HEX)),
+    ),
+    (
+        "docker.io/library/ubuntu@sha256:HEX",
+        Some("docker.io"),
+        Some("library"),
+        "ubuntu",
+        None,
+        Some(("sha256", SHA256_HEX)),
+    ),
+    (
+        "docker.io/library/ubuntu:20.04@sha256:HEX",
+        Some("docker.io"),
+        Some("library"),
+        "ubuntu",
+        Some("20.04"),
+        Some(("sha256", SHA256_HEX)),
+    ),
+    (
+        "registry.internal:8443/lab/salmon:1.5.2@sha256:HEX",
+        Some("registry.internal:8443"),
+        Some("lab"),
+        "salmon",
+        Some("1.5.2"),
+        Some(("sha256", SHA256_HEX)),
+    ),
+];
+
 #[test]
 fn test_image_selector_parse_all_formats() {
-    // Common image reference formats used in Docker
-    let test_cases = [
-        // Format: (input, expected_namespace, expected_repository, expected_tag, expected_digest)
-        ("ubuntu", None, "ubuntu", None, None),
-        ("ubuntu:20.04", None, "ubuntu", Some("20.04"), None),
-        ("library/ubuntu", Some("library"), "ubuntu", None, None),
-        ("docker.io/ubuntu", Some("docker.io"), "ubuntu", None, None),
-        (
-            "docker.io/library/ubuntu:22.04",
-            Some("docker.io/library"),
-            "ubuntu",
-            Some("22.04"),
-            None,
-        ),
-        (
-            "quay.io/prometheus/alertmanager:v0.24.0",
-            Some("quay.io/prometheus"),
-            "alertmanager",
-            Some("v0.24.0"),
-            None,
-        ),
-        (
-            "k8s.gcr.io/kube-apiserver:v1.23.0",
-            Some("k8s.gcr.io"),
-            "kube-apiserver",
-            Some("v1.23.0"),
-            None,
-        ),
-        (
-            "ubuntu@sha256=ab01",
-            None,
-            "ubuntu",
-            None,
-            Some(("sha256", "ab01")),
-        ),
-        (
-            "docker.io/library/ubuntu@sha256=ab01",
-            Some("docker.io/library"),
-            "ubuntu",
-            None,
-            Some(("sha256", "ab01")),
-        ),
-        (
-            "docker.io/library/ubuntu:20.04@sha256=ab01",
-            Some("docker.io/library"),
-            "ubuntu",
-            Some("20.04"),
-            Some(("sha256", "ab01")),
-        ),
-    ];
-
-    for (input, expected_namespace, expected_repository, expected_tag, expected_digest) in
-        test_cases
-    {
+    for (input, registry, namespace, repository, tag, digest) in PARSE_CASES {
+        let input = input.replace("HEX", SHA256_HEX);
         let selector =
-            ImageSelector::from_str(input).unwrap_or_else(|_| panic!("Failed to parse: {input}"));
+            ImageSelector::from_str(&input).unwrap_or_else(|_| panic!("Failed to parse: {input}"));
 
         assert_eq!(
-            selector.namespace.as_deref(),
-            expected_namespace,
-            "Namespace mismatch for {}",
-            input
+            selector.registry.as_deref(),
+            registry,
+            "Registry mismatch for {input}"
         );
         assert_eq!(
-            selector.repository, expected_repository,
-            "Repository mismatch for {}",
-            input
+            selector.namespace.as_deref(),
+            namespace,
+            "Namespace mismatch for {input}"
         );
         assert_eq!(
-            selector.tag.as_deref(),
-            expected_tag,
-            "Tag mismatch for {}",
-            input
+            selector.repository, repository,
+            "Repository mismatch for {input}"
         );
-
-        match (selector.digest, expected_digest) {
-            (Some(digest), Some((expected_algo, expected_hash))) => {
-                assert_eq!(
-                    digest.algorithm, expected_algo,
-                    "Digest algorithm mismatch for {}",
-                    input
-                );
-                assert_eq!(
-                    digest.hash, expected_hash,
-                    "Digest hash mismatch for {}",
-                    input
-                );
-            }
-            (None, None) => {
-                // Both are None, which is expected
-            }
-            _ => {
-                panic!("Digest mismatch for {}", input);
-            }
-        }
+        assert_eq!(selector.tag.as_deref(), tag, "Tag mismatch for {input}");
+        let found = selector
+            .digest
+            .as_ref()
+            .map(|digest| (digest.algorithm.as_str(), digest.hash.as_str()));
+        assert_eq!(found, digest, "Digest mismatch for {input}");
     }
 }
 
@@ -126,27 +203,126 @@ fn test_image_selector_error_handling() {
             ImageSelectorParseError::InvalidDigestFormat("invalid".to_string()),
         ),
         (
-            "repo@=hash",
-            ImageSelectorParseError::InvalidDigestFormat("=hash".to_string()),
+            "repo@:hash",
+            ImageSelectorParseError::InvalidDigestFormat(":hash".to_string()),
+        ),
+        (
+            "repo@algo:",
+            ImageSelectorParseError::InvalidDigestFormat("algo:".to_string()),
         ),
         (
-            "repo@algo=",
-            ImageSelectorParseError::InvalidDigestFormat("algo=".to_string()),
+            "repo@sha256=ab01",
+            ImageSelectorParseError::InvalidDigestFormat("sha256=ab01".to_string()),
+        ),
+        (
+            "repo@sha256:ab01",
+            ImageSelectorParseError::InvalidDigestLength {
+                algorithm: DigestAlgorithm::Sha256,
+                expected: 64,
+                found: 4,
+            },
         ),
     ];
 
     for (input, expected_error) in test_cases {
         let result = ImageSelector::from_str(input);
-        assert!(result.is_err(), "Expected error for {}", input);
         assert_eq!(
-            result.unwrap_err(),
-            expected_error,
-            "Error type mismatch for {}",
-            input
+            result,
+            Err(expected_error),
+            "Error type mismatch for {input}"
         );
     }
 }
 
+#[test]
+fn test_image_selector_registry_errors() {
+    // Registries and names that cannot be told apart from them, and the component at fault
+    let test_cases = [
+        (
+            "localhost:http/salmon",
+            ImageSelectorParseError::InvalidRegistryPort("localhost:http".to_string()),
+        ),
+        (
+            "[::1/salmon",
+            ImageSelectorParseError::InvalidRegistry("[::1".to_string()),
+        ),
+        (
+            "quay.io/lab:1/salmon",
+            ImageSelectorParseError::AmbiguousReference("lab:1/salmon".to_string()),
+        ),
+        (
+            "my_registry.example.com/salmon",
+            ImageSelectorParseError::InvalidDomainComponent {
+                component: "my_registry".to_string(),
+                offset: 0,
+            },
+        ),
+    ];
+
+    for (input, expected_error) in test_cases {
+        let result = ImageSelector::from_str(input);
+        assert_eq!(
+            result,
+            Err(expected_error),
+            "Error type mismatch for {input}"
+        );
+    }
+}
+
+#[test]
+fn test_image_selector_path_and_tag_errors() {
+    // Repository paths and tags outside the reference grammar, and where they start
+    let test_cases = [
+        (
+            "quay.io/Lab/salmon",
+            ImageSelectorParseError::InvalidPathComponent {
+                component: "Lab".to_string(),
+                offset: 8,
+            },
+        ),
+        (
+            "lab//salmon",
+            ImageSelectorParseError::InvalidPathComponent {
+                component: "".to_string(),
+                offset: 4,
+            },
+        ),
+        (
+            "lab/my salmon:1.0",
+            ImageSelectorParseError::InvalidPathComponent {
+                component: "my salmon".to_string(),
+                offset: 4,
+            },
+        ),
+        (
+            "salmon:",
+            ImageSelectorParseError::InvalidTag {
+                tag: "".to_string(),
+                offset: 7,
+            },
+        ),
+    ];
+
+    for (input, expected_error) in test_cases {
+        let result = ImageSelector::from_str(input);
+        assert_eq!(
+            result,
+            Err(expected_error),
+            "Error type mismatch for {input}"
+        );
+    }
+}
+
+#[test]
+fn test_image_selector_rejects_long_tags() {
+    // Workflow files with overlong tags fail when loaded, not when pulled on the cluster
+    let tag = "1".repeat(200);
+    let result = ImageSelector::from_str(&format!("quay.io/biocontainers/salmon:{tag}"));
+    assert!(matches!(result,
+        Err(ImageSelectorParseError::InvalidTag { tag: t, offset: 29 }) if t == tag
+    ));
+}
+
 #[test]
 fn test_image_selector_practical_use_cases() {
     // Test some practical use cases for parsing image references
@@ -163,76 +339,393 @@ fn test_image_selector_practical_use_cases() {
 
     // Case 3: Image from a private registry
     let selector = ImageSelector::from_str("registry.example.com/myapp:1.0").unwrap();
-    assert_eq!(selector.namespace, Some("registry.example.com".to_string()));
+    assert_eq!(selector.registry, Some("registry.example.com".to_string()));
+    assert_eq!(selector.namespace, None);
     assert_eq!(selector.repository, "myapp");
     assert_eq!(selector.tag, Some("1.0".to_string()));
 
-    // Case 4: Image with digest for immutable reference
-    let selector = ImageSelector::from_str("ubuntu@sha256=a1b2c3d4e5f6").unwrap();
+    // Case 4: Image with digest for immutable reference, as printed by `docker inspect`
+    let selector = ImageSelector::from_str(&format!("ubuntu@sha256:{SHA256_HEX}")).unwrap();
     assert_eq!(selector.repository, "ubuntu");
     assert!(selector.digest.is_some());
     let digest = selector.digest.unwrap();
-    assert_eq!(digest.algorithm, "sha256");
-    assert_eq!(digest.hash, "a1b2c3d4e5f6");
+    assert_eq!(digest.algorithm, DigestAlgorithm::Sha256);
+    assert_eq!(digest.hash, SHA256_HEX);
 
     // Case 5: Multi-level namespace
     let selector = ImageSelector::from_str("ghcr.io/owner/project/image:tag").unwrap();
-    assert_eq!(
-        selector.namespace,
-        Some("ghcr.io/owner/project".to_string())
-    );
+    assert_eq!(selector.registry, Some("ghcr.io".to_string()));
+    assert_eq!(selector.namespace, Some("owner/project".to_string()));
     assert_eq!(selector.repository, "image");
     assert_eq!(selector.tag, Some("tag".to_string()));
 }
 
+/// Reconstruct a reference from its components, the way the canonical formatter should.
+fn reconstruct(selector: &ImageSelector) -> String {
+    let mut result = String::new();
+
+    // Add registry if present
+    if let Some(registry) = &selector.registry {
+        result.push_str(registry);
+        result.push('/');
+    }
+
+    // Add namespace if present
+    if let Some(namespace) = &selector.namespace {
+        result.push_str(namespace);
+        result.push('/');
+    }
+
+    // Add repository (always present)
+    result.push_str(&selector.repository);
+
+    // Add tag if present
+    if let Some(tag) = &selector.tag {
+        result.push(':');
+        result.push_str(tag);
+    }
+
+    // Add digest if present
+    if let Some(digest) = &selector.digest {
+        result.push('@');
+        result.push_str(digest.algorithm.as_str());
+        result.push(':');
+        result.push_str(&digest.hash);
+    }
+
+    result
+}
+
 #[test]
 fn test_image_selector_component_display() {
     // Create some image selectors
     let simple = ImageSelector::from_str("nginx").unwrap();
     let with_tag = ImageSelector::from_str("nginx:latest").unwrap();
-    let with_digest = ImageSelector::from_str("nginx@sha256=abcdef").unwrap();
+    let with_digest = ImageSelector::from_str(&format!("nginx@sha256:{SHA256_HEX}")).unwrap();
     let with_namespace = ImageSelector::from_str("docker.io/library/nginx").unwrap();
-    let complex = ImageSelector::from_str("docker.io/library/nginx:latest@sha256=abcdef").unwrap();
-
-    // Demonstrate how to reconstruct the original reference from components
-    let reconstruct = |selector: &ImageSelector| -> String {
-        let mut result = String::new();
-
-        // Add namespace if present
-        if let Some(namespace) = &selector.namespace {
-            result.push_str(namespace);
-            result.push('/');
-        }
-
-        // Add repository (always present)
-        result.push_str(&selector.repository);
-
-        // Add tag if present
-        if let Some(tag) = &selector.tag {
-            result.push(':');
-            result.push_str(tag);
-        }
-
-        // Add digest if present
-        if let Some(digest) = &selector.digest {
-            result.push('@');
-            result.push_str(&digest.algorithm);
-            result.push('=');
-            result.push_str(&digest.hash);
-        }
-
-        result
-    };
+    let with_port = ImageSelector::from_str("localhost:5000/lab/nginx:1.0").unwrap();
+    let complex = ImageSelector::from_str(&format!(
+        "docker.io/library/nginx:latest@sha256:{SHA256_HEX}"
+    ))
+    .unwrap();
+
+    // The canonical formatter produces the same string as the manual reconstruction
+    for selector in [
+        &simple,
+        &with_tag,
+        &with_digest,
+        &with_namespace,
+        &with_port,
+        &complex,
+    ] {
+        assert_eq!(selector.to_string(), reconstruct(selector));
+    }
 
     // Verify reconstructed references
     assert_eq!(reconstruct(&simple), "nginx");
     assert_eq!(reconstruct(&with_tag), "nginx:latest");
-    assert_eq!(reconstruct(&with_digest), "nginx@sha256=abcdef");
+    assert_eq!(
+        reconstruct(&with_digest),
+        format!("nginx@sha256:{SHA256_HEX}")
+    );
     assert_eq!(reconstruct(&with_namespace), "docker.io/library/nginx");
+    assert_eq!(reconstruct(&with_port), "localhost:5000/lab/nginx:1.0");
     assert_eq!(
         reconstruct(&complex),
-        "docker.io/library/nginx:latest@sha256=abcdef"
+        format!("docker.io/library/nginx:latest@sha256:{SHA256_HEX}")
     );
 }
 
+#[test]
+fn test_image_selector_format_is_identity_on_corpus() {
+    for input in corpus() {
+        let selector = ImageSelector::from_str(&input).unwrap();
+        assert_eq!(selector.to_string(), input, "Format mismatch for {input}");
+    }
+}
+
+#[test]
+fn test_image_selector_parse_format_parse_round_trip() {
+    for input in corpus() {
+        let selector = ImageSelector::from_str(&input).unwrap();
+        let reparsed = ImageSelector::from_str(&selector.to_string()).unwrap();
+        assert_eq!(reparsed, selector, "Round trip mismatch for {input}");
+    }
+}
+
+#[test]
+fn test_image_selector_normalize_properties() {
+    for input in corpus() {
+        let normalized = ImageSelector::from_str(&input).unwrap().normalize();
+
+        // Normalized references are fully qualified
+        assert!(normalized.registry.is_some(), "No registry for {input}");
+        assert!(
+            normalized.tag.is_some() || normalized.digest.is_some(),
+            "No tag or digest for {input}"
+        );
+
+        // Normalization is idempotent and survives formatting
+        assert_eq!(
+            normalized.normalize(),
+            normalized,
+            "Not idempotent for {input}"
+        );
+        let reparsed = ImageSelector::from_str(&normalized.to_string()).unwrap();
+        assert_eq!(reparsed, normalized, "Round trip mismatch for {input}");
+    }
+}
+
+#[test]
+fn test_image_selector_familiar_properties() {
+    for input in corpus() {
+        let selector = ImageSelector::from_str(&input).unwrap();
+        let familiar = ImageSelector::from_str(&selector.familiar()).unwrap();
+
+        // The familiar form names the same image
+        assert_eq!(
+            familiar.normalize(),
+            selector.normalize(),
+            "Familiar form mismatch for {input}"
+        );
+
+        // The familiar form is never longer than the normalized form
+        assert!(selector.familiar().len() <= selector.normalize().to_string().len());
+    }
+}
+
+#[test]
+fn test_image_selector_normalize_docker_defaults() {
+    let selector = ImageSelector::from_str("ubuntu").unwrap();
+    assert_eq!(
+        selector.normalize().to_string(),
+        "docker.io/library/ubuntu:latest"
+    );
+    assert_eq!(selector.familiar(), "ubuntu:latest");
+}
+
+#[test]
+fn test_simple_repository() {
+    let selector = ImageSelector::parse("ubuntu").unwrap();
+    assert!(matches!(selector,
+        ImageSelector {
+            registry: None,
+            namespace: None,
+            repository: r,
+            tag: None,
+            digest: None,
+        } if r == "ubuntu"
+    ));
+}
+
+#[test]
+fn test_with_tag() {
+    let selector = ImageSelector::parse("python:3.9-slim").unwrap();
+    assert!(matches!(selector,
+        ImageSelector {
+            registry: None,
+            namespace: None,
+            repository: r,
+            tag: Some(t),
+            digest: None,
+        } if r == "python" && t == "3.9-slim"
+    ));
+}
+
+#[test]
+fn test_with_namespace() {
+    let selector = ImageSelector::parse("docker.io/library/redis").unwrap();
+    assert!(matches!(selector,
+        ImageSelector {
+            registry: Some(h),
+            namespace: Some(n),
+            repository: r,
+            tag: None,
+            digest: None,
+        } if h == "docker.io" && n == "library" && r == "redis"
+    ));
+}
+
+#[test]
+fn test_with_namespace_and_tag() {
+    let selector = ImageSelector::parse("docker.io/library/redis:6.2").unwrap();
+    assert!(matches!(selector,
+        ImageSelector {
+            registry: Some(h),
+            namespace: Some(n),
+            repository: r,
+            tag: Some(t),
+            digest: None,
+        } if h == "docker.io" && n == "library" && r == "redis" && t == "6.2"
+    ));
+}
+
+#[test]
+fn test_with_digest() {
+    let selector = ImageSelector::parse(&format!("ubuntu@sha256:{SHA256_HEX}")).unwrap();
+    assert!(matches!(selector,
+        ImageSelector {
+            registry: None,
+            namespace: None,
+            repository: r,
+            tag: None,
+            digest: Some(d),
+        } if r == "ubuntu" && d.algorithm == DigestAlgorithm::Sha256 && d.hash == SHA256_HEX
+    ));
+}
+
+#[test]
+fn test_complex_image_reference() {
+    let selector = ImageSelector::parse("codeberg.org/forgejo/forgejo:10.0.1").unwrap();
+    assert!(matches!(selector,
+        ImageSelector {
+            registry: Some(h),
+            namespace: Some(n),
+            repository: r,
+            tag: Some(t),
+            digest: None,
+        } if h == "codeberg.org" && n == "forgejo" && r == "forgejo" && t == "10.0.1"
+    ));
+}
+
+#[test]
+fn test_multi_level_namespace() {
+    let selector = ImageSelector::parse("docker.io/library/user/repo:tag").unwrap();
+    assert!(matches!(selector,
+        ImageSelector {
+            registry: Some(h),
+            namespace: Some(n),
+            repository: r,
+            tag: Some(t),
+            digest: None,
+        } if h == "docker.io" && n == "library/user" && r == "repo" && t == "tag"
+    ));
+}
+
+#[test]
+fn test_with_tag_and_digest() {
+    // When both tag and digest are present, only digest should be used
+    let selector = ImageSelector::parse(&format!("ubuntu:latest@sha256:{SHA256_HEX}")).unwrap();
+    assert!(matches!(selector,
+        ImageSelector {
+            registry: None,
+            namespace: None,
+            repository: r,
+            tag: Some(t),
+            digest: Some(d),
+        } if r == "ubuntu"
+            && t == "latest"
+            && d.algorithm == DigestAlgorithm::Sha256
+            && d.hash == SHA256_HEX
+    ));
+}
+
+#[test]
+fn test_digest_display() {
+    let digest = ImageDigest::parse(&format!("sha256:{SHA256_HEX}")).unwrap();
+    assert_eq!(digest.to_string(), format!("sha256:{SHA256_HEX}"));
+}
+
+#[test]
+fn test_display_preserves_components() {
+    let inputs = [
+        "ubuntu",
+        "library/ubuntu",
+        "docker.io/ubuntu:22.04",
+        "[::1]:5000/lab/salmon:1.5.2",
+        &format!("localhost:5000/lab/salmon:1.5.2@sha256:{SHA256_HEX}"),
+    ];
+
+    for input in inputs {
+        let selector = ImageSelector::parse(input).unwrap();
+        assert_eq!(selector.to_string(), input);
+    }
+}
+
+#[test]
+fn test_normalize_defaults() {
+    let cases = [
+        ("ubuntu", "docker.io/library/ubuntu:latest"),
+        ("ubuntu:22.04", "docker.io/library/ubuntu:22.04"),
+        ("rocker/tidyverse", "docker.io/rocker/tidyverse:latest"),
+        ("docker.io/ubuntu", "docker.io/library/ubuntu:latest"),
+        ("index.docker.io/ubuntu", "docker.io/library/ubuntu:latest"),
+        (
+            "registry-1.docker.io/library/ubuntu",
+            "docker.io/library/ubuntu:latest",
+        ),
+        ("quay.io/salmon", "quay.io/salmon:latest"),
+        (
+            "localhost:5000/lab/salmon",
+            "localhost:5000/lab/salmon:latest",
+        ),
+    ];
+
+    for (input, expected) in cases {
+        let selector = ImageSelector::parse(input).unwrap();
+        assert_eq!(selector.normalize().to_string(), expected);
+    }
+}
+
+#[test]
+fn test_normalize_keeps_digest_without_tag() {
+    let selector = ImageSelector::parse(&format!("ubuntu@sha256:{SHA256_HEX}")).unwrap();
+    assert_eq!(
+        selector.normalize().to_string(),
+        format!("docker.io/library/ubuntu@sha256:{SHA256_HEX}")
+    );
+}
+
+#[test]
+fn test_familiar() {
+    let cases = [
+        ("ubuntu", "ubuntu:latest"),
+        ("docker.io/library/ubuntu:22.04", "ubuntu:22.04"),
+        ("docker.io/rocker/tidyverse", "rocker/tidyverse:latest"),
+        (
+            "quay.io/library/salmon:1.5.2",
+            "quay.io/library/salmon:1.5.2",
+        ),
+        ("localhost/salmon", "localhost/salmon:latest"),
+        ("docker.io/my.org/tool", "docker.io/my.org/tool:latest"),
+        (
+            "docker.io/localhost/salmon",
+            "docker.io/localhost/salmon:latest",
+        ),
+    ];
+
+    for (input, expected) in cases {
+        let selector = ImageSelector::parse(input).unwrap();
+        assert_eq!(selector.familiar(), expected);
+    }
+}
+
+#[test]
+fn test_image_selector_from_str() {
+    let selector: ImageSelector = "nginx:latest".parse().unwrap();
+    assert!(matches!(selector,
+        ImageSelector {
+            registry: None,
+            namespace: None,
+            repository: r,
+            tag: Some(t),
+            digest: None,
+        } if r == "nginx" && t == "latest"
+    ));
+}
+
+#[test]
+fn test_image_selector_try_from() {
+    let selector = <ImageSelector as TryFrom<&str>>::try_from("redis:6.2").unwrap();
+    assert!(matches!(selector,
+        ImageSelector {
+            registry: None,
+            namespace: None,
+            repository: r,
+            tag: Some(t),
+            digest: None,
+        } if r == "redis" && t == "6.2"
+    ));
+}
+
 // EOF
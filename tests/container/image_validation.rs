@@ -0,0 +1,318 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use rivulet::container::{MAX_NAME_LENGTH, MAX_TAG_LENGTH};
+use rivulet::prelude::*;
+
+const SHA256_HEX: &str = "01ba4719c80b6fe911b091a7c05124b64eeece964e09c058ef8f9805daca546b";
+
+#[test]
+fn test_namespace_without_registry() {
+    let selector = ImageSelector::parse("library/ubuntu").unwrap();
+    assert!(matches!(selector,
+        ImageSelector {
+            registry: None,
+            namespace: Some(n),
+            repository: r,
+            tag: None,
+            digest: None,
+        } if n == "library" && r == "ubuntu"
+    ));
+}
+
+#[test]
+fn test_registry_without_namespace() {
+    let selector = ImageSelector::parse("docker.io/ubuntu").unwrap();
+    assert!(matches!(selector,
+        ImageSelector {
+            registry: Some(h),
+            namespace: None,
+            repository: r,
+            tag: None,
+            digest: None,
+        } if h == "docker.io" && r == "ubuntu"
+    ));
+}
+
+#[test]
+fn test_registry_with_port() {
+    let selector = ImageSelector::parse("localhost:5000/lab/salmon").unwrap();
+    assert!(matches!(selector,
+        ImageSelector {
+            registry: Some(h),
+            namespace: Some(n),
+            repository: r,
+            tag: None,
+            digest: None,
+        } if h == "localhost:5000" && n == "lab" && r == "salmon"
+    ));
+}
+
+#[test]
+fn test_registry_with_port_and_tag() {
+    let selector = ImageSelector::parse("registry.internal:8443/lab/salmon:1.5.2").unwrap();
+    assert!(matches!(selector,
+        ImageSelector {
+            registry: Some(h),
+            namespace: Some(n),
+            repository: r,
+            tag: Some(t),
+            digest: None,
+        } if h == "registry.internal:8443" && n == "lab" && r == "salmon" && t == "1.5.2"
+    ));
+}
+
+#[test]
+fn test_localhost_registry() {
+    let selector = ImageSelector::parse("localhost/salmon:1.5.2").unwrap();
+    assert!(matches!(selector,
+        ImageSelector {
+            registry: Some(h),
+            namespace: None,
+            repository: r,
+            tag: Some(t),
+            digest: None,
+        } if h == "localhost" && r == "salmon" && t == "1.5.2"
+    ));
+}
+
+#[test]
+fn test_ipv6_registry() {
+    let selector = ImageSelector::parse("[::1]:5000/lab/salmon:1.5.2").unwrap();
+    assert!(matches!(selector,
+        ImageSelector {
+            registry: Some(h),
+            namespace: Some(n),
+            repository: r,
+            tag: Some(t),
+            digest: None,
+        } if h == "[::1]:5000" && n == "lab" && r == "salmon" && t == "1.5.2"
+    ));
+
+    let selector = ImageSelector::parse("[fe80::1]/salmon").unwrap();
+    assert!(matches!(selector,
+        ImageSelector {
+            registry: Some(h),
+            namespace: None,
+            repository: r,
+            tag: None,
+            digest: None,
+        } if h == "[fe80::1]" && r == "salmon"
+    ));
+}
+
+#[test]
+fn test_bare_host_is_repository() {
+    // Without a path, `localhost:5000` is the repository `localhost` tagged `5000`
+    let selector = ImageSelector::parse("localhost:5000").unwrap();
+    assert!(matches!(selector,
+        ImageSelector {
+            registry: None,
+            namespace: None,
+            repository: r,
+            tag: Some(t),
+            digest: None,
+        } if r == "localhost" && t == "5000"
+    ));
+}
+
+#[test]
+fn test_invalid_registries() {
+    let inputs = [
+        // Unbracketed IPv6 address
+        "::1/salmon",
+        // Unterminated IPv6 literal
+        "[::1/salmon",
+        // Not an IPv6 address
+        "[localhost]/salmon",
+        // Garbage after IPv6 literal
+        "[::1]5000/salmon",
+        // Empty host
+        ":5000/salmon",
+    ];
+
+    for input in inputs {
+        let result = ImageSelector::parse(input);
+        assert!(
+            matches!(result, Err(ImageSelectorParseError::InvalidRegistry(_))),
+            "Expected invalid registry for {input}"
+        );
+    }
+}
+
+#[test]
+fn test_invalid_registry_ports() {
+    let inputs = [
+        ("localhost:http/salmon", "localhost:http"),
+        ("localhost:/salmon", "localhost:"),
+        ("localhost:70000/salmon", "localhost:70000"),
+        ("[::1]:port/salmon", "[::1]:port"),
+        ("foo:bar/baz", "foo:bar"),
+    ];
+
+    for (input, registry) in inputs {
+        let result = ImageSelector::parse(input);
+        assert!(matches!(result,
+            Err(ImageSelectorParseError::InvalidRegistryPort(s)) if s == registry
+        ));
+    }
+}
+
+#[test]
+fn test_ambiguous_references() {
+    let inputs = [
+        ("quay.io/lab:1/salmon", "lab:1/salmon"),
+        ("salmon:1.5:2", "salmon:1.5:2"),
+        ("localhost:5000/salmon:1:2", "salmon:1:2"),
+    ];
+
+    for (input, rest) in inputs {
+        let result = ImageSelector::parse(input);
+        assert!(matches!(result,
+            Err(ImageSelectorParseError::AmbiguousReference(s)) if s == rest
+        ));
+    }
+}
+
+#[test]
+fn test_valid_path_separators() {
+    let inputs = [
+        "lab/salmon_quant",
+        "lab/salmon__quant",
+        "lab/salmon.quant",
+        "lab/salmon-quant",
+        "lab/salmon---quant",
+        "la-b_c__d/salmon2.x",
+    ];
+
+    for input in inputs {
+        assert!(
+            ImageSelector::parse(input).is_ok(),
+            "Expected {input} to parse"
+        );
+    }
+}
+
+#[test]
+fn test_invalid_path_components() {
+    let cases = [
+        ("Ubuntu", "Ubuntu", 0),
+        ("lab/Salmon", "Salmon", 4),
+        ("lab//salmon", "", 4),
+        ("lab/my salmon", "my salmon", 4),
+        ("lab/-salmon", "-salmon", 4),
+        ("lab/salmon-", "salmon-", 4),
+        ("lab/salmon___quant", "salmon___quant", 4),
+        ("lab/salmon._quant", "salmon._quant", 4),
+        ("quay.io/Lab/salmon:1.0", "Lab", 8),
+        ("localhost:5000/lab/sal+mon", "sal+mon", 19),
+    ];
+
+    for (input, component, offset) in cases {
+        let result = ImageSelector::parse(input);
+        assert!(
+            matches!(&result,
+                Err(ImageSelectorParseError::InvalidPathComponent { component: c, offset: o })
+                    if c == component && *o == offset
+            ),
+            "Unexpected result for {input}: {result:?}"
+        );
+    }
+}
+
+#[test]
+fn test_invalid_tags() {
+    let long_tag = "a".repeat(MAX_TAG_LENGTH + 1);
+    let long_input = format!("ubuntu:{long_tag}");
+    let cases = [
+        ("ubuntu:", "", 7),
+        ("ubuntu:.22", ".22", 7),
+        ("ubuntu:-22", "-22", 7),
+        ("ubuntu:22 04", "22 04", 7),
+        ("quay.io/lab/salmon:1+2", "1+2", 19),
+        (long_input.as_str(), long_tag.as_str(), 7),
+    ];
+
+    for (input, tag, offset) in cases {
+        let result = ImageSelector::parse(input);
+        assert!(
+            matches!(&result,
+                Err(ImageSelectorParseError::InvalidTag { tag: t, offset: o })
+                    if t == tag && *o == offset
+            ),
+            "Unexpected result for {input}: {result:?}"
+        );
+    }
+}
+
+#[test]
+fn test_max_length_tag() {
+    let tag = format!("_{}", "A.-".repeat((MAX_TAG_LENGTH - 1) / 3));
+    let selector = ImageSelector::parse(&format!("ubuntu:{tag}")).unwrap();
+    assert_eq!(selector.tag, Some(tag));
+}
+
+#[test]
+fn test_invalid_domain_components() {
+    let cases = [
+        ("my_registry.example.com/salmon", "my_registry", 0),
+        ("registry..example.com/salmon", "", 9),
+        ("registry.-example.com/salmon", "-example", 9),
+        ("registry.example-.com:5000/salmon", "example-", 9),
+    ];
+
+    for (input, component, offset) in cases {
+        let result = ImageSelector::parse(input);
+        assert!(
+            matches!(&result,
+                Err(ImageSelectorParseError::InvalidDomainComponent { component: c, offset: o })
+                    if c == component && *o == offset
+            ),
+            "Unexpected result for {input}: {result:?}"
+        );
+    }
+}
+
+#[test]
+fn test_uppercase_registry_is_allowed() {
+    let selector = ImageSelector::parse("Registry.Example.com/salmon").unwrap();
+    assert_eq!(selector.registry, Some("Registry.Example.com".to_string()));
+}
+
+#[test]
+fn test_name_too_long() {
+    let namespace = "a".repeat(MAX_NAME_LENGTH);
+    let result = ImageSelector::parse(&format!("quay.io/{namespace}/salmon:1.0"));
+    assert_eq!(
+        result,
+        Err(ImageSelectorParseError::NameTooLong(MAX_NAME_LENGTH + 15))
+    );
+
+    // Exactly at the limit
+    let repository = "a".repeat(MAX_NAME_LENGTH - 8);
+    assert!(ImageSelector::parse(&format!("quay.io/{repository}:1.0")).is_ok());
+}
+
+#[test]
+fn test_missing_repository() {
+    let inputs = [
+        // Empty string
+        "",
+        // Only namespace
+        "namespace/",
+        // Multiple trailing slashes
+        "namespace///",
+        // Only tag
+        ":tag",
+        // Only digest
+        &format!("@sha256:{SHA256_HEX}"),
+    ];
+
+    for input in inputs {
+        let result = ImageSelector::parse(input);
+        assert_eq!(result, Err(ImageSelectorParseError::MissingRepository));
+    }
+}
+
+// EOF
@@ -0,0 +1,107 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use rivulet::container::{BuildStep, ContainerError};
+use rivulet::prelude::*;
+use std::collections::BTreeMap;
+use std::thread;
+
+#[test]
+fn test_resolve_deep_chain() {
+    let base = Container::from("python:3.12-slim");
+    base.write()
+        .unwrap()
+        .env("PIP_NO_CACHE_DIR", "1")
+        .env("LANG", "C.UTF-8")
+        .label("org.opencontainers.image.source", "https://example.org/lab")
+        .cmd(["python3"]);
+    let deps = Container::from(&base);
+    deps.write()
+        .unwrap()
+        .workdir("/opt/analysis")
+        .run("pip install numpy");
+    let analysis = Container::from(&deps);
+    analysis
+        .write()
+        .unwrap()
+        .env("LANG", "en_US.UTF-8")
+        .workdir("src")
+        .entrypoint(["python", "-m", "main"])
+        .cmd(["--config", "defaults.toml"])
+        .label("org.opencontainers.image.title", "analysis");
+
+    let resolved = analysis.read().unwrap().resolve().unwrap();
+    assert_eq!(resolved.image().repository, "python");
+    assert_eq!(
+        *resolved.env(),
+        BTreeMap::from([
+            ("LANG".to_string(), "en_US.UTF-8".to_string()),
+            ("PIP_NO_CACHE_DIR".to_string(), "1".to_string()),
+        ])
+    );
+    assert_eq!(resolved.workdir(), Some("/opt/analysis/src"));
+    assert_eq!(
+        resolved.entrypoint().unwrap(),
+        ["python", "-m", "main"].map(String::from)
+    );
+    assert_eq!(
+        resolved.cmd().unwrap(),
+        ["--config", "defaults.toml"].map(String::from)
+    );
+    assert_eq!(resolved.labels().len(), 2);
+
+    let steps_per_layer: Vec<_> = resolved.layers().iter().map(Vec::len).collect();
+    assert_eq!(steps_per_layer, [4, 2, 5]);
+    assert_eq!(
+        resolved.layers()[1][1],
+        BuildStep::Run("pip install numpy".to_string())
+    );
+}
+
+#[test]
+fn test_resolved_container_is_a_snapshot() {
+    let base = Container::from("alpine:3.19");
+    let derived = Container::from(&base);
+    derived.write().unwrap().env("STAGE", "dev");
+
+    let resolved = derived.read().unwrap().resolve().unwrap();
+    base.write().unwrap().env("STAGE", "base").workdir("/srv");
+    derived.write().unwrap().env("STAGE", "prod");
+
+    // Later changes do not leak in, and the snapshot can move to another thread
+    let handle = thread::spawn(move || resolved);
+    let resolved = handle.join().unwrap();
+    assert_eq!(resolved.env()["STAGE"], "dev");
+    assert_eq!(resolved.workdir(), None);
+}
+
+#[test]
+fn test_resolve_cycle() {
+    let base = Container::from("alpine:3.19");
+    let derived = Container::from(&base);
+    base.write().unwrap().base = ContainerBase::Internal(derived.clone());
+
+    let result = derived.read().unwrap().resolve();
+    assert_eq!(result, Err(ContainerError::Cycle));
+}
+
+#[test]
+fn test_resolved_content_hash_and_build() {
+    let base = Container::from("alpine:3.19");
+    base.write().unwrap().env("LANG", "C.UTF-8");
+    let derived = Container::from(&base);
+    derived.write().unwrap().workdir("/srv");
+
+    let resolved = derived.read().unwrap().resolve().unwrap();
+    assert_eq!(
+        resolved.content_hash(),
+        derived.read().unwrap().content_hash().unwrap()
+    );
+    assert!(!resolved.needs_build());
+
+    derived.write().unwrap().run("apk add bash");
+    assert!(derived.read().unwrap().resolve().unwrap().needs_build());
+}
+
+// EOF
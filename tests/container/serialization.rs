@@ -0,0 +1,251 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use rivulet::container::{ContainerGraph, ImageDigest};
+use rivulet::hash::ContentHash;
+use rivulet::prelude::*;
+use serde_json::json;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+/// Return the parent of a container, panicking if it is based on an external image.
+fn parent(container: &Arc<RwLock<Container>>) -> Arc<RwLock<Container>> {
+    match &container.read().unwrap().base {
+        ContainerBase::Internal(parent) => parent.clone(),
+        ContainerBase::External(_) => panic!("Expected ContainerBase::Internal"),
+    }
+}
+
+#[test]
+fn test_image_selector_serializes_as_canonical_string() {
+    let selector = ImageSelector::from_str("localhost:5000/lab/salmon:1.5.2").unwrap();
+    let value = serde_json::to_value(&selector).unwrap();
+    assert_eq!(value, json!("localhost:5000/lab/salmon:1.5.2"));
+
+    let restored: ImageSelector = serde_json::from_value(value).unwrap();
+    assert_eq!(restored, selector);
+}
+
+#[test]
+fn test_image_selector_deserialization_validates() {
+    let result = serde_json::from_value::<ImageSelector>(json!("lab//salmon"));
+    assert!(result.is_err());
+
+    let result = serde_json::from_value::<ImageSelector>(json!(42));
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_image_digest_round_trip() {
+    let input = "sha256:01ba4719c80b6fe911b091a7c05124b64eeece964e09c058ef8f9805daca546b";
+    let digest = ImageDigest::from_str(input).unwrap();
+    let value = serde_json::to_value(&digest).unwrap();
+    assert_eq!(value, json!(input));
+    assert_eq!(
+        serde_json::from_value::<ImageDigest>(value).unwrap(),
+        digest
+    );
+}
+
+#[test]
+fn test_content_hash_serializes_as_hex() {
+    let hash = Container::from("alpine:latest")
+        .read()
+        .unwrap()
+        .content_hash()
+        .unwrap();
+    let value = serde_json::to_value(hash).unwrap();
+    assert_eq!(value, json!(hash.to_string()));
+    assert_eq!(serde_json::from_value::<ContentHash>(value).unwrap(), hash);
+    assert!(serde_json::from_value::<ContentHash>(json!("not-a-hash")).is_err());
+}
+
+#[test]
+fn test_container_chain_serialization_format() {
+    let base = Container::from("alpine:latest");
+    let derived = Container::from(&base);
+
+    let value = serde_json::to_value(&*derived.read().unwrap()).unwrap();
+    assert_eq!(
+        value,
+        json!({
+            "containers": [
+                { "base": { "image": "alpine:latest" } },
+                { "base": { "parent": 0 } },
+            ],
+            "roots": [1],
+        })
+    );
+}
+
+#[test]
+fn test_container_steps_round_trip() {
+    let base = Container::from("alpine:latest");
+    let derived = Container::from(&base);
+    derived
+        .write()
+        .unwrap()
+        .run("apk add python3")
+        .copy(["app.py"], "/app/")
+        .env("LANG", "C.UTF-8")
+        .workdir("/app")
+        .entrypoint(["python3", "app.py"])
+        .label("maintainer", "lab");
+
+    let value = serde_json::to_value(&*derived.read().unwrap()).unwrap();
+    assert_eq!(
+        value["containers"][1]["steps"],
+        json!([
+            { "run": "apk add python3" },
+            { "copy": { "sources": ["app.py"], "destination": "/app/" } },
+            { "env": { "key": "LANG", "value": "C.UTF-8" } },
+            { "workdir": "/app" },
+            { "entrypoint": ["python3", "app.py"] },
+            { "label": { "key": "maintainer", "value": "lab" } },
+        ])
+    );
+
+    let restored: Container = serde_json::from_value(value).unwrap();
+    assert_eq!(restored.steps, derived.read().unwrap().steps);
+}
+
+#[test]
+fn test_container_chain_round_trip() {
+    let base = Container::from("ubuntu:20.04");
+    let dev = Container::from(&base);
+    let prod = Container::from(&dev);
+
+    let json = serde_json::to_string(&*prod.read().unwrap()).unwrap();
+    let restored: Container = serde_json::from_str(&json).unwrap();
+
+    let dev = match restored.base {
+        ContainerBase::Internal(dev) => dev,
+        ContainerBase::External(_) => panic!("Expected ContainerBase::Internal"),
+    };
+    let base = parent(&dev);
+    assert!(matches!(base.read().unwrap().base,
+        ContainerBase::External(ref s)
+            if s.repository == "ubuntu" && s.tag.as_deref() == Some("20.04")
+    ));
+}
+
+#[test]
+fn test_shared_container_round_trip() {
+    // Serde's `rc` support lets wrapped containers serialize directly
+    let container = Container::from("nginx:latest");
+    let json = serde_json::to_string(&container).unwrap();
+    let restored: Arc<RwLock<Container>> = serde_json::from_str(&json).unwrap();
+    assert!(matches!(restored.read().unwrap().base,
+        ContainerBase::External(ref s) if s.repository == "nginx"
+    ));
+}
+
+#[test]
+fn test_container_graph_deduplicates_shared_bases() {
+    let base = Container::from("alpine:latest");
+    let python = Container::from(&base);
+    let rust = Container::from(&base);
+    let unrelated = Container::from("rocker/tidyverse:latest");
+
+    let graph = ContainerGraph::new([python, rust, unrelated]);
+    let value = serde_json::to_value(&graph).unwrap();
+    assert_eq!(
+        value,
+        json!({
+            "containers": [
+                { "base": { "image": "alpine:latest" } },
+                { "base": { "parent": 0 } },
+                { "base": { "parent": 0 } },
+                { "base": { "image": "rocker/tidyverse:latest" } },
+            ],
+            "roots": [1, 2, 3],
+        })
+    );
+
+    let restored: ContainerGraph = serde_json::from_value(value).unwrap();
+    let roots = restored.roots();
+    assert_eq!(roots.len(), 3);
+    assert!(Arc::ptr_eq(&parent(&roots[0]), &parent(&roots[1])));
+}
+
+#[test]
+fn test_container_graph_with_root_and_descendant() {
+    let base = Container::from("alpine:latest");
+    let derived = Container::from(&base);
+
+    let json = serde_json::to_string(&ContainerGraph::new([derived, base])).unwrap();
+    let restored = serde_json::from_str::<ContainerGraph>(&json)
+        .unwrap()
+        .into_roots();
+    assert!(Arc::ptr_eq(&parent(&restored[0]), &restored[1]));
+}
+
+#[test]
+fn test_container_cycle_fails_to_serialize() {
+    let base = Container::from("alpine:latest");
+    let derived = Container::from(&base);
+    base.write().unwrap().base = ContainerBase::Internal(derived.clone());
+
+    assert!(serde_json::to_string(&ContainerGraph::new([derived])).is_err());
+}
+
+#[test]
+fn test_self_ancestor_fails_to_serialize_under_write_guard() {
+    let container = Container::from("alpine:latest");
+    container.write().unwrap().base = ContainerBase::Internal(container.clone());
+
+    let guard = container.write().unwrap();
+    assert!(serde_json::to_string(&*guard).is_err());
+}
+
+#[test]
+fn test_invalid_container_documents() {
+    let documents = [
+        // Parent defined after child
+        json!({
+            "containers": [{ "base": { "parent": 1 } }, { "base": { "image": "alpine" } }],
+            "roots": [0]
+        }),
+        // Root out of range
+        json!({ "containers": [{ "base": { "image": "alpine" } }], "roots": [1] }),
+        // Invalid image reference
+        json!({ "containers": [{ "base": { "image": "Alpine" } }], "roots": [0] }),
+        // Multiple roots for a single container
+        json!({ "containers": [{ "base": { "image": "alpine" } }], "roots": [0, 0] }),
+    ];
+
+    for document in documents {
+        assert!(
+            serde_json::from_value::<Container>(document.clone()).is_err(),
+            "Expected error for {document}"
+        );
+    }
+}
+
+#[test]
+fn test_container_base_round_trip() {
+    let base = ContainerBase::try_from("alpine:latest").unwrap();
+    let value = serde_json::to_value(&base).unwrap();
+    assert_eq!(value, json!({ "image": "alpine:latest" }));
+
+    let internal = ContainerBase::from(Container::from("alpine:latest"));
+    let value = serde_json::to_value(&internal).unwrap();
+    assert_eq!(
+        value,
+        json!({
+            "container": {
+                "containers": [{ "base": { "image": "alpine:latest" } }],
+                "roots": [0],
+            }
+        })
+    );
+    let restored: ContainerBase = serde_json::from_value(value).unwrap();
+    assert!(matches!(restored,
+        ContainerBase::Internal(ref arc) if matches!(arc.read().unwrap().base,
+            ContainerBase::External(ref s) if s.repository == "alpine"
+        )
+    ));
+}
+
+// EOF
@@ -4,9 +4,18 @@
 
 // Import container tests
 mod container {
+    mod apptainer;
+    mod common;
     mod container_api;
     mod container_nesting;
+    mod containerfile;
+    mod content_hash;
+    mod image_digest;
     mod image_selector;
+    mod image_validation;
+    mod resolve;
+    #[cfg(feature = "serde")]
+    mod serialization;
 }
 
 // EOF
@@ -0,0 +1,260 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::common::write_script;
+use rivulet::executor::{
+    ExecutionError, Job, JobOutcome, JobStatus, LsfExecutor, PbsExecutor, PbsFlavor, Scheduler,
+};
+use rivulet::prelude::*;
+use rivulet::workflow::Value;
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tempfile::TempDir;
+
+/// Write a stand-in for a status command that prints the lines of `states` in turn, one per
+/// call, repeating the last one. `$code` expands to the exit status of the job script and
+/// `$lsf_state` to the matching LSF state.
+fn write_poller(dir: &Path, name: &str, states: &[&str]) {
+    let dir_str = dir.display();
+    let mut cases = String::new();
+    for (index, state) in states.iter().enumerate() {
+        let pattern = if index + 1 == states.len() {
+            "*".to_string()
+        } else {
+            index.to_string()
+        };
+        cases.push_str(&format!("{pattern}) printf \"{state}\\n\" ;;\n"));
+    }
+    write_script(
+        &dir.join(name),
+        &format!(
+            "printf '%s\\n' \"$@\" > '{dir_str}/{name}.args'\n\
+             polls=$(cat '{dir_str}/polls' 2> /dev/null || echo 0)\n\
+             echo $((polls + 1)) > '{dir_str}/polls'\n\
+             code=$(cat '{dir_str}/exit')\n\
+             if [ \"$code\" -eq 0 ]; then lsf_state=DONE; else lsf_state=EXIT; fi\n\
+             case $polls in\n{cases}esac\n"
+        ),
+    );
+}
+
+/// A single-step workflow running a command that writes its output to `out`.
+fn single_step(command: &str) -> Workflow {
+    let mut workflow = Workflow::new("single");
+    let busybox = Container::from("busybox:1.36");
+    let mut step = Step::new("step", &busybox, command);
+    step.output("out", PortType::String).cpus(2);
+    let step = workflow.add_step(step).unwrap();
+    workflow.output("out", step.output("out")).unwrap();
+    workflow
+}
+
+fn no_inputs() -> Vec<(String, Value)> {
+    Vec::new()
+}
+
+/// Stand-ins for PBS: `qsub` runs the script right away as job `77.pbs01`, following its
+/// `-o` and `-e` directives, and `qstat` reports the job queued, running, then finished.
+fn fake_pbs(dir: &Path) -> PbsExecutor {
+    let dir_str = dir.display();
+    write_script(
+        &dir.join("qsub"),
+        &format!(
+            "printf '%s\\n' \"$@\" > '{dir_str}/qsub.args'\n\
+             out=$(sed -n 's/^#PBS -o //p' \"$1\")\n\
+             err=$(sed -n 's/^#PBS -e //p' \"$1\")\n\
+             sh \"$1\" > \"$out\" 2> \"$err\"\n\
+             echo $? > '{dir_str}/exit'\n\
+             echo 77.pbs01\n"
+        ),
+    );
+    write_poller(
+        dir,
+        "qstat",
+        &[
+            "Job Id: 77.pbs01\\n    job_state = Q",
+            "Job Id: 77.pbs01\\n    job_state = R",
+            "Job Id: 77.pbs01\\n    job_state = F\\n    Exit_status = $code",
+        ],
+    );
+    let mut pbs = PbsExecutor::new(PbsFlavor::Pro);
+    pbs.qsub(dir.join("qsub"))
+        .qstat(dir.join("qstat"))
+        .queue("workq")
+        .poll_interval(Duration::ZERO);
+    pbs
+}
+
+/// Stand-ins for LSF: `bsub` runs the script on its standard input right away as job 88,
+/// following its `-cwd`, `-oo` and `-eo` directives, and `bjobs` reports the job pending,
+/// running, then done or exited.
+fn fake_lsf(dir: &Path) -> LsfExecutor {
+    let dir_str = dir.display();
+    write_script(
+        &dir.join("bsub"),
+        &format!(
+            "cat > '{dir_str}/submitted.lsf'\n\
+             script='{dir_str}/submitted.lsf'\n\
+             cwd=$(sed -n 's/^#BSUB -cwd //p' \"$script\")\n\
+             out=$(sed -n 's/^#BSUB -oo //p' \"$script\")\n\
+             err=$(sed -n 's/^#BSUB -eo //p' \"$script\")\n\
+             (cd \"$cwd\" && sh \"$script\" > \"$out\" 2> \"$err\")\n\
+             echo $? > '{dir_str}/exit'\n\
+             echo 'Job <88> is submitted to default queue <normal>.'\n"
+        ),
+    );
+    write_poller(dir, "bjobs", &["PEND|-", "RUN|-", "$lsf_state|$code"]);
+    let mut lsf = LsfExecutor::new();
+    lsf.bsub(dir.join("bsub"))
+        .bjobs(dir.join("bjobs"))
+        .poll_interval(Duration::ZERO);
+    lsf
+}
+
+#[test]
+fn test_pbs() {
+    let pbs_dir = TempDir::new().unwrap();
+    let run = TempDir::new().unwrap();
+    let pbs = fake_pbs(pbs_dir.path());
+
+    let result = Runner::new(&pbs, run.path())
+        .run(&single_step("echo hello > out"), no_inputs())
+        .unwrap();
+    assert_eq!(result.outputs["out"], Value::from("hello"));
+
+    let script = fs::read_to_string(result.steps[0].dir.join("job.pbs")).unwrap();
+    assert!(script.contains("#PBS -l select=1:ncpus=2\n"), "{script}");
+    assert!(script.contains("#PBS -q workq\n"), "{script}");
+    let qstat = fs::read_to_string(pbs_dir.path().join("qstat.args")).unwrap();
+    assert_eq!(qstat, "-x\n-f\n77.pbs01\n");
+    let polls = fs::read_to_string(pbs_dir.path().join("polls")).unwrap();
+    assert_eq!(polls.trim(), "3");
+
+    let error = Runner::new(&fake_pbs(pbs_dir.path()), run.path())
+        .run(&single_step("exit 4"), no_inputs())
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        ExecutionError::StepFailed { step, outcome, .. }
+            if step == "step" && outcome == JobOutcome::exited(4)
+    ));
+}
+
+#[test]
+fn test_lsf() {
+    let lsf_dir = TempDir::new().unwrap();
+    let run = TempDir::new().unwrap();
+    let lsf = fake_lsf(lsf_dir.path());
+
+    let result = Runner::new(&lsf, run.path())
+        .run(&single_step("echo hello > out"), no_inputs())
+        .unwrap();
+    assert_eq!(result.outputs["out"], Value::from("hello"));
+
+    let submitted = fs::read_to_string(lsf_dir.path().join("submitted.lsf")).unwrap();
+    let script = fs::read_to_string(result.steps[0].dir.join("job.lsf")).unwrap();
+    assert_eq!(
+        submitted, script,
+        "the script is submitted on standard input"
+    );
+    assert!(script.contains("#BSUB -n 2\n"), "{script}");
+    let bjobs = fs::read_to_string(lsf_dir.path().join("bjobs.args")).unwrap();
+    assert!(bjobs.ends_with("\n88\n"), "{bjobs}");
+
+    let error = Runner::new(&fake_lsf(lsf_dir.path()), run.path())
+        .run(&single_step("exit 4"), no_inputs())
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        ExecutionError::StepFailed { step, outcome, .. }
+            if step == "step" && outcome == JobOutcome::exited(4)
+    ));
+}
+
+/// A scheduler whose status command breaks after submission.
+#[derive(Default)]
+struct Unreachable {
+    cancelled: RefCell<Vec<String>>,
+}
+
+impl Scheduler for Unreachable {
+    fn submit(&self, _job: &Job) -> Result<String, ExecutionError> {
+        Ok("job-1".to_string())
+    }
+
+    fn poll(&self, _id: &str) -> Result<JobStatus, ExecutionError> {
+        Err(ExecutionError::Scheduler {
+            program: "status".into(),
+            message: "connection refused".to_string(),
+        })
+    }
+
+    fn cancel(&self, id: &str) -> Result<(), ExecutionError> {
+        self.cancelled.borrow_mut().push(id.to_string());
+        Ok(())
+    }
+}
+
+#[test]
+fn test_failed_poll_cancels_the_job() {
+    let run = TempDir::new().unwrap();
+    let scheduler = Unreachable::default();
+
+    let error = Runner::new(&scheduler, run.path())
+        .run(&single_step("true"), no_inputs())
+        .unwrap_err();
+    assert!(matches!(error, ExecutionError::Scheduler { .. }));
+    assert_eq!(*scheduler.cancelled.borrow(), ["job-1"]);
+}
+
+/// A scheduler whose jobs finish on the third poll.
+struct Counting {
+    polls: RefCell<u32>,
+}
+
+impl Scheduler for Counting {
+    fn submit(&self, _job: &Job) -> Result<String, ExecutionError> {
+        Ok("job-1".to_string())
+    }
+
+    fn poll(&self, _id: &str) -> Result<JobStatus, ExecutionError> {
+        let mut polls = self.polls.borrow_mut();
+        *polls += 1;
+        Ok(match *polls {
+            1 => JobStatus::Queued,
+            2 => JobStatus::Running,
+            _ => JobStatus::Finished(JobOutcome::exited(2)),
+        })
+    }
+
+    fn cancel(&self, _id: &str) -> Result<(), ExecutionError> {
+        unreachable!("finished jobs are not cancelled")
+    }
+
+    fn polling_interval(&self) -> Duration {
+        Duration::ZERO
+    }
+}
+
+#[test]
+fn test_scheduler_is_an_executor() {
+    let run = TempDir::new().unwrap();
+    let scheduler = Counting {
+        polls: RefCell::new(0),
+    };
+
+    let error = Runner::new(&scheduler, run.path())
+        .run(&single_step("true"), no_inputs())
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        ExecutionError::StepFailed { step, outcome, .. }
+            if step == "step" && outcome == JobOutcome::exited(2)
+    ));
+    assert_eq!(*scheduler.polls.borrow(), 3);
+}
+
+// EOF
@@ -0,0 +1,209 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use rivulet::prelude::*;
+use rivulet::workflow::{Condition, Value, WorkflowError};
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// Check reads for adapters, trim them only if some were found, and report whether trimming
+/// happened.
+fn trim_if_needed() -> Workflow {
+    let shell = Container::from("docker.io/library/busybox:1.36");
+    let mut workflow = Workflow::new("trim_if_needed");
+    let reads = workflow.input("reads", PortType::File).unwrap();
+
+    let mut qc = Step::new(
+        "qc",
+        &shell,
+        "if grep -q AGATCGGAAGAGC {reads}; then echo true; else echo false; fi > adapters",
+    );
+    qc.input("reads", PortType::File)
+        .output("adapters", PortType::Bool);
+    let qc = workflow.add_step(qc).unwrap();
+
+    let mut trim = Step::new("trim", &shell, "sed s/AGATCGGAAGAGC// {reads} > trimmed.fq");
+    trim.input("reads", PortType::File)
+        .input("adapters", PortType::Bool)
+        .output("trimmed", PortType::File)
+        .glob("trimmed", "trimmed.fq")
+        .when(Condition::is_true("adapters"));
+    let trim = workflow.add_step(trim).unwrap();
+
+    let mut report = Step::new(
+        "report",
+        &shell,
+        "trimmed={trimmed}; if [ -n \"$trimmed\" ]; then echo trimmed; else echo untrimmed; fi \
+         > status",
+    );
+    report
+        .input("trimmed", PortType::optional(PortType::File))
+        .output("status", PortType::String);
+    let report = workflow.add_step(report).unwrap();
+
+    workflow.connect(reads.clone(), qc.input("reads")).unwrap();
+    workflow.connect(reads, trim.input("reads")).unwrap();
+    workflow
+        .connect(qc.output("adapters"), trim.input("adapters"))
+        .unwrap();
+    workflow
+        .connect(trim.output("trimmed"), report.input("trimmed"))
+        .unwrap();
+    workflow.output("trimmed", trim.output("trimmed")).unwrap();
+    workflow.output("status", report.output("status")).unwrap();
+    workflow
+}
+
+fn log_events(run: &Path) -> Vec<String> {
+    let log = fs::read_to_string(run.join("run.log")).unwrap();
+    log.lines()
+        .map(|line| line.split_once("] ").unwrap().1.to_string())
+        .collect()
+}
+
+#[test]
+fn test_conditional_step() {
+    let data = TempDir::new().unwrap();
+    let executor = LocalExecutor::new();
+    for (contents, ran) in [("ACGTAGATCGGAAGAGC\n", true), ("ACGT\n", false)] {
+        let run = TempDir::new().unwrap();
+        let reads = data.path().join("reads.fq");
+        fs::write(&reads, contents).unwrap();
+
+        let result = Runner::new(&executor, run.path())
+            .run(&trim_if_needed(), [("reads", Value::File(reads))])
+            .unwrap();
+        let steps: Vec<_> = result.steps.iter().map(|step| step.name.as_str()).collect();
+        if ran {
+            assert_eq!(steps, ["qc", "trim", "report"]);
+            let Value::File(trimmed) = &result.outputs["trimmed"] else {
+                panic!("trimmed is not a file");
+            };
+            assert_eq!(fs::read_to_string(trimmed).unwrap(), "ACGT\n");
+            assert_eq!(result.outputs["status"], Value::from("trimmed"));
+        } else {
+            assert_eq!(steps, ["qc", "report"]);
+            assert_eq!(result.outputs["trimmed"], Value::Null);
+            assert_eq!(result.outputs["status"], Value::from("untrimmed"));
+            assert!(
+                log_events(run.path()).contains(&"trim: skipped, adapters does not hold".into())
+            );
+        }
+    }
+}
+
+#[test]
+fn test_consumers_must_accept_absence() {
+    let mut workflow = trim_if_needed();
+    let shell = Container::from("docker.io/library/busybox:1.36");
+    let mut count = Step::new("count", &shell, "wc -l < {reads} > lines");
+    count
+        .input("reads", PortType::File)
+        .output("lines", PortType::Int);
+    let count = workflow.add_step(count).unwrap();
+    let trim = workflow.step_id("trim").unwrap();
+
+    assert_eq!(
+        workflow.connect(trim.output("trimmed"), count.input("reads")),
+        Err(WorkflowError::IncompatibleTypes {
+            from: "output 'trimmed' of step 'trim'".to_string(),
+            to: "input 'reads' of step 'count'".to_string(),
+            found: PortType::optional(PortType::File),
+            expected: PortType::File,
+        })
+    );
+
+    let mut invalid = Step::new("invalid", &shell, "true");
+    invalid
+        .input("reads", PortType::File)
+        .when(Condition::is_true("reads"));
+    assert!(matches!(
+        workflow.add_step(invalid),
+        Err(WorkflowError::InvalidCondition { .. })
+    ));
+}
+
+#[test]
+fn test_optional_parameter() {
+    let shell = Container::from("docker.io/library/busybox:1.36");
+    let mut workflow = Workflow::new("greet");
+    let name = workflow
+        .input("name", PortType::optional(PortType::String))
+        .unwrap();
+    let mut greet = Step::new("greet", &shell, "echo hello {name} > greeting");
+    greet
+        .input("name", PortType::optional(PortType::String))
+        .output("greeting", PortType::String)
+        .when(Condition::present("name"));
+    let greet = workflow.add_step(greet).unwrap();
+    workflow.connect(name, greet.input("name")).unwrap();
+    workflow
+        .output("greeting", greet.output("greeting"))
+        .unwrap();
+
+    let run = TempDir::new().unwrap();
+    let executor = LocalExecutor::new();
+    let runner = Runner::new(&executor, run.path());
+    let result = runner
+        .run(&workflow, [("name", Value::from("world"))])
+        .unwrap();
+    assert_eq!(result.outputs["greeting"], Value::from("hello world"));
+    let result = runner
+        .run(&workflow, Vec::<(String, Value)>::new())
+        .unwrap();
+    assert_eq!(result.outputs["greeting"], Value::Null);
+    assert!(result.steps.is_empty());
+}
+
+#[test]
+fn test_optional_outputs_and_skipped_jobs() {
+    let shell = Container::from("docker.io/library/busybox:1.36");
+    let mut workflow = Workflow::new("warnings");
+    let numbers = workflow
+        .input("numbers", PortType::array(PortType::Int))
+        .unwrap();
+    let mut check = Step::new(
+        "check",
+        &shell,
+        "if [ {number} -gt 5 ]; then echo too big > warning.log; fi",
+    );
+    check
+        .input("number", PortType::Int)
+        .output("warning", PortType::optional(PortType::File))
+        .glob("warning", "*.log")
+        .scatter("number")
+        .when(!Condition::equals("number", Value::Int(0)));
+    let check = workflow.add_step(check).unwrap();
+    workflow.connect(numbers, check.input("number")).unwrap();
+    workflow
+        .output("warnings", check.output("warning"))
+        .unwrap();
+    assert_eq!(
+        workflow.source_type(&check.output("warning")),
+        Ok(PortType::array(PortType::optional(PortType::File)))
+    );
+
+    let run = TempDir::new().unwrap();
+    let executor = LocalExecutor::new();
+    let numbers = Value::Array(vec![Value::Int(7), Value::Int(0), Value::Int(3)]);
+    let result = Runner::new(&executor, run.path())
+        .run(&workflow, [("numbers", numbers)])
+        .unwrap();
+
+    let Value::Array(warnings) = &result.outputs["warnings"] else {
+        panic!("warnings is not an array");
+    };
+    assert!(matches!(
+        warnings.as_slice(),
+        [Value::File(_), Value::Null, Value::Null]
+    ));
+    let indices: Vec<_> = result.steps.iter().map(|step| step.index).collect();
+    assert_eq!(indices, [Some(0), Some(2)]);
+    assert!(
+        log_events(run.path()).contains(&"check[1]: skipped, not number == 0 does not hold".into())
+    );
+}
+
+// EOF
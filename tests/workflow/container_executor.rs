@@ -0,0 +1,433 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use rivulet::executor::{ContainerExecutor, Runtime};
+use rivulet::prelude::*;
+use rivulet::workflow::{KeyChange, StepCache, Value};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tempfile::TempDir;
+
+/// The digest the fake runtime resolves image tags to.
+const DIGEST: &str = "sha256:a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1";
+
+/// Write a stand-in for a runtime's tool that appends its arguments to `calls`, one per line
+/// with a blank line after each call. Image tags resolve to the digest in `digest`, which
+/// pulls copy from `registry`, and start out resolving to [`DIGEST`]. Builds exit with
+/// `build_status`, creating the image file Apptainer builds to; runs execute the command on
+/// the host.
+fn fake_runtime(dir: &Path, build_status: i32) -> PathBuf {
+    let program = dir.join("runtime");
+    let script = format!(
+        "#!/bin/sh\n\
+         printf '%s\\n' \"$@\" '' >> '{calls}'\n\
+         case \"$1\" in\n\
+         image) [ \"$3\" = --format ] && exec cat '{digest}'; exit 1 ;;\n\
+         pull) exec cp '{registry}' '{digest}' ;;\n\
+         build) [ \"$2\" = --force ] && touch \"$3\"; exit {build_status} ;;\n\
+         esac\n\
+         for last; do :; done\n\
+         exec /bin/sh -c \"$last\"\n",
+        calls = dir.join("calls").display(),
+        digest = dir.join("digest").display(),
+        registry = dir.join("registry").display(),
+    );
+    fs::write(&program, script).unwrap();
+    fs::set_permissions(&program, fs::Permissions::from_mode(0o755)).unwrap();
+    publish(&dir.join("digest"), DIGEST);
+    program
+}
+
+/// Write the digest of an image for [`fake_runtime`] to find in `file`.
+fn publish(file: &Path, digest: &str) {
+    fs::write(file, format!("registry.example/image@{digest}\n")).unwrap();
+}
+
+/// The calls recorded by [`fake_runtime`], as lists of arguments.
+fn calls(dir: &Path) -> Vec<Vec<String>> {
+    let calls = fs::read_to_string(dir.join("calls")).unwrap_or_default();
+    calls
+        .split_terminator("\n\n")
+        .map(|call| call.lines().map(String::from).collect())
+        .collect()
+}
+
+/// A single-step workflow counting the lines of a file.
+fn count_lines(container: &Arc<RwLock<Container>>) -> Workflow {
+    let mut workflow = Workflow::new("count_lines");
+    let text = workflow.input("text", PortType::File).unwrap();
+    let mut count = Step::new("count", container, "wc -l < {text} > lines");
+    count
+        .input("text", PortType::File)
+        .output("lines", PortType::Int);
+    let count = workflow.add_step(count).unwrap();
+    workflow.connect(text, count.input("text")).unwrap();
+    workflow.output("lines", count.output("lines")).unwrap();
+    workflow
+}
+
+fn text(dir: &Path) -> (&'static str, Value) {
+    let path = dir.join("text.txt");
+    fs::write(&path, "a\nb\n").unwrap();
+    ("text", Value::File(path))
+}
+
+#[test]
+fn test_run_in_image() {
+    let tools = TempDir::new().unwrap();
+    let data = TempDir::new().unwrap();
+    let run = TempDir::new().unwrap();
+    let busybox = Container::from("busybox:1.36");
+    busybox.write().unwrap().env("LC_ALL", "C");
+
+    let mut executor = ContainerExecutor::new(Runtime::Docker);
+    executor.program(fake_runtime(tools.path(), 0));
+    let result = Runner::new(&executor, run.path())
+        .run(&count_lines(&busybox), [text(data.path())])
+        .unwrap();
+    assert_eq!(result.outputs["lines"], Value::Int(2));
+
+    let calls = calls(tools.path());
+    let [pin, call] = calls.as_slice() else {
+        panic!("expected two calls, got {calls:?}");
+    };
+    assert_eq!(pin[..3], ["image", "inspect", "--format"]);
+    assert_eq!(pin[4], "docker.io/library/busybox:1.36");
+    let data_dir = fs::canonicalize(data.path()).unwrap();
+    let step_dir = &result.steps[0].dir;
+    let has = |pair: [&str; 2]| call.windows(2).any(|window| window == pair);
+    assert_eq!(call[..2], ["run", "--rm"]);
+    assert!(has(["--volume", &format!("{0}:{0}", step_dir.display())]));
+    assert!(has([
+        "--volume",
+        &format!("{0}/text.txt:{0}/text.txt:ro", data_dir.display())
+    ]));
+    assert!(has([
+        "--workdir",
+        &step_dir.join("work").display().to_string()
+    ]));
+    assert!(has(["--env", "LC_ALL=C"]));
+    assert!(call.contains(&"--user".to_string()));
+    let pinned = format!("docker.io/library/busybox:1.36@{DIGEST}");
+    assert!(has(["/bin/sh", &pinned]));
+    assert!(call.last().unwrap().starts_with("wc -l < "));
+}
+
+#[test]
+fn test_moved_tag_misses_the_cache() {
+    let tools = TempDir::new().unwrap();
+    let data = TempDir::new().unwrap();
+    let scratch = TempDir::new().unwrap();
+    let cache = StepCache::new(scratch.path().join("cache"));
+    let busybox = Container::from("busybox:latest");
+    let workflow = count_lines(&busybox);
+
+    let mut executor = ContainerExecutor::new(Runtime::Docker);
+    executor.program(fake_runtime(tools.path(), 0));
+    let run = |name: &str| {
+        Runner::new(&executor, scratch.path().join(name))
+            .cache(&cache)
+            .run(&workflow, [text(data.path())])
+            .unwrap()
+    };
+    let first = run("first");
+    let image = first.steps[0].container.image();
+    assert_eq!(image.digest.as_ref().unwrap().to_string(), DIGEST);
+    assert!(run("second").steps[0].cached);
+
+    let moved = DIGEST.replace('a', "b");
+    publish(&tools.path().join("digest"), &moved);
+    let third = run("third");
+    assert!(!third.steps[0].cached);
+    let miss = third.steps[0].cache_miss.as_ref().unwrap();
+    assert!(matches!(
+        miss.changes.as_slice(),
+        [KeyChange::Digest { tag }] if tag == "latest"
+    ));
+}
+
+#[test]
+fn test_edited_copy_source_misses_the_cache() {
+    let tools = TempDir::new().unwrap();
+    let data = TempDir::new().unwrap();
+    let scratch = TempDir::new().unwrap();
+    let context = TempDir::new().unwrap();
+    let cache = StepCache::new(scratch.path().join("cache"));
+    fs::write(context.path().join("count.sh"), "wc -l").unwrap();
+    let tools_image = Container::from("alpine:3.19");
+    tools_image.write().unwrap().copy(["count.sh"], "/opt/");
+    let workflow = count_lines(&tools_image);
+
+    let mut executor = ContainerExecutor::new(Runtime::Docker);
+    executor
+        .program(fake_runtime(tools.path(), 0))
+        .image_dir(scratch.path().join("images"))
+        .context(context.path());
+    let run = |name: &str| {
+        Runner::new(&executor, scratch.path().join(name))
+            .cache(&cache)
+            .run(&workflow, [text(data.path())])
+            .unwrap()
+    };
+    assert!(!run("first").steps[0].cached);
+    assert!(run("second").steps[0].cached);
+
+    fs::write(context.path().join("count.sh"), "wc -w").unwrap();
+    let third = run("third");
+    assert!(!third.steps[0].cached);
+    let miss = third.steps[0].cache_miss.as_ref().unwrap();
+    assert!(matches!(
+        miss.changes.as_slice(),
+        [KeyChange::Source(source)] if source == "count.sh"
+    ));
+}
+
+#[test]
+fn test_image_is_pulled_to_pin_it() {
+    let tools = TempDir::new().unwrap();
+    let data = TempDir::new().unwrap();
+    let run = TempDir::new().unwrap();
+    let busybox = Container::from("busybox:1.36");
+
+    let mut executor = ContainerExecutor::new(Runtime::Podman);
+    executor.program(fake_runtime(tools.path(), 0));
+    fs::remove_file(tools.path().join("digest")).unwrap();
+    publish(&tools.path().join("registry"), DIGEST);
+    let result = Runner::new(&executor, run.path())
+        .run(&count_lines(&busybox), [text(data.path())])
+        .unwrap();
+
+    let commands: Vec<_> = calls(tools.path())
+        .into_iter()
+        .map(|call| call[0].clone())
+        .collect();
+    assert_eq!(commands, ["image", "pull", "image", "run"]);
+    let image = result.steps[0].container.image();
+    assert_eq!(image.digest.as_ref().unwrap().to_string(), DIGEST);
+}
+
+#[test]
+fn test_unpinned_images_are_not_cached() {
+    let tools = TempDir::new().unwrap();
+    let data = TempDir::new().unwrap();
+    let scratch = TempDir::new().unwrap();
+    let cache = StepCache::new(scratch.path().join("cache"));
+    let busybox = Container::from("busybox:1.36");
+    let workflow = count_lines(&busybox);
+
+    let mut executor = ContainerExecutor::new(Runtime::Apptainer);
+    executor.program(fake_runtime(tools.path(), 0));
+    for name in ["first", "second"] {
+        let run = scratch.path().join(name);
+        let result = Runner::new(&executor, &run)
+            .cache(&cache)
+            .run(&workflow, [text(data.path())])
+            .unwrap();
+        assert!(!result.steps[0].cached);
+        assert_eq!(result.steps[0].cache_key, None);
+        let log = fs::read_to_string(run.join("run.log")).unwrap();
+        assert!(
+            log.contains("count: not cached, image busybox:1.36 is not pinned to a digest"),
+            "{log}"
+        );
+    }
+    assert_eq!(calls(tools.path()).len(), 2, "both runs execute the job");
+}
+
+#[test]
+fn test_build_before_run() {
+    let tools = TempDir::new().unwrap();
+    let data = TempDir::new().unwrap();
+    let run = TempDir::new().unwrap();
+    let images = TempDir::new().unwrap();
+    let tools_image = Container::from("alpine:3.19");
+    tools_image
+        .write()
+        .unwrap()
+        .run("apk add --no-cache coreutils");
+
+    let mut executor = ContainerExecutor::new(Runtime::Podman);
+    executor
+        .program(fake_runtime(tools.path(), 0))
+        .image_dir(images.path());
+    let result = Runner::new(&executor, run.path())
+        .run(&count_lines(&tools_image), [text(data.path())])
+        .unwrap();
+
+    // The image is built on the pinned digest, so it is rebuilt when the tag moves
+    let hash = result.steps[0].container.content_hash();
+    let tag = format!("localhost/rivulet:{hash}");
+    let build_dir = images.path().join(hash.to_string());
+    let calls = calls(tools.path());
+    assert_eq!(calls.len(), 4, "{calls:?}");
+    assert_eq!(calls[1], ["image", "inspect", &tag]);
+    assert_eq!(calls[2][..3], ["build", "--tag", &tag]);
+    assert!(calls[3].contains(&tag));
+    let containerfile = fs::read_to_string(build_dir.join("Containerfile")).unwrap();
+    assert_eq!(
+        containerfile,
+        format!(
+            "FROM docker.io/library/alpine:3.19@{DIGEST} AS stage0\n\
+             RUN apk add --no-cache coreutils\n"
+        )
+    );
+}
+
+#[test]
+fn test_copy_sources_are_staged() {
+    let tools = TempDir::new().unwrap();
+    let data = TempDir::new().unwrap();
+    let run = TempDir::new().unwrap();
+    let images = TempDir::new().unwrap();
+    let context = TempDir::new().unwrap();
+    fs::create_dir_all(context.path().join("scripts/lib")).unwrap();
+    fs::write(context.path().join("scripts/count.sh"), "wc -l").unwrap();
+    fs::write(context.path().join("scripts/lib/util.sh"), "true").unwrap();
+    fs::write(context.path().join("README"), "unused").unwrap();
+    let tools_image = Container::from("alpine:3.19");
+    tools_image
+        .write()
+        .unwrap()
+        .copy(["scripts", "/config.toml"], "/opt/")
+        .run("chmod +x /opt/scripts/count.sh");
+    fs::write(context.path().join("config.toml"), "lines = true").unwrap();
+
+    let mut executor = ContainerExecutor::new(Runtime::Docker);
+    executor
+        .program(fake_runtime(tools.path(), 0))
+        .image_dir(images.path())
+        .context(context.path());
+    Runner::new(&executor, run.path())
+        .run(&count_lines(&tools_image), [text(data.path())])
+        .unwrap();
+
+    // The image is built from the context directory in its build directory
+    let calls = calls(tools.path());
+    let staged = PathBuf::from(calls[2].last().unwrap());
+    assert_eq!(staged.file_name().unwrap(), "context");
+    assert!(staged.starts_with(images.path()));
+    let read = |path: &str| fs::read_to_string(staged.join(path)).unwrap();
+    assert_eq!(read("scripts/count.sh"), "wc -l");
+    assert_eq!(read("scripts/lib/util.sh"), "true");
+    assert_eq!(read("config.toml"), "lines = true");
+    assert!(!staged.join("README").exists(), "only sources are staged");
+}
+
+#[test]
+fn test_edited_copy_source_is_rebuilt() {
+    let tools = TempDir::new().unwrap();
+    let data = TempDir::new().unwrap();
+    let run = TempDir::new().unwrap();
+    let images = TempDir::new().unwrap();
+    let context = TempDir::new().unwrap();
+    fs::write(context.path().join("count.sh"), "wc -l").unwrap();
+    let tools_image = Container::from("alpine:3.19");
+    tools_image.write().unwrap().copy(["count.sh"], "/opt/");
+
+    let mut executor = ContainerExecutor::new(Runtime::Apptainer);
+    executor
+        .program(fake_runtime(tools.path(), 0))
+        .image_dir(images.path())
+        .context(context.path());
+    let runner = Runner::new(&executor, run.path());
+    let workflow = count_lines(&tools_image);
+    runner.run(&workflow, [text(data.path())]).unwrap();
+    runner.run(&workflow, [text(data.path())]).unwrap();
+    fs::write(context.path().join("count.sh"), "wc -w").unwrap();
+    runner.run(&workflow, [text(data.path())]).unwrap();
+
+    let calls = calls(tools.path());
+    let commands: Vec<_> = calls.iter().map(|call| call[0].as_str()).collect();
+    assert_eq!(commands, ["build", "exec", "exec", "build", "exec"]);
+    let image = |call: &[String]| call.iter().find(|arg| arg.ends_with(".sif")).cloned();
+    assert_eq!(image(&calls[1]), image(&calls[2]));
+    assert_ne!(
+        image(&calls[1]),
+        image(&calls[4]),
+        "the rebuilt image is run"
+    );
+}
+
+#[test]
+fn test_copy_source_outside_context() {
+    let tools = TempDir::new().unwrap();
+    let data = TempDir::new().unwrap();
+    let run = TempDir::new().unwrap();
+    let tools_image = Container::from("alpine:3.19");
+    tools_image.write().unwrap().copy(["../secret"], "/opt/");
+
+    let mut executor = ContainerExecutor::new(Runtime::Apptainer);
+    executor.program(fake_runtime(tools.path(), 0));
+    let error = Runner::new(&executor, run.path())
+        .run(&count_lines(&tools_image), [text(data.path())])
+        .unwrap_err();
+
+    assert!(matches!(error, ExecutionError::Io { path, .. } if path == Path::new("../secret")));
+    assert!(calls(tools.path()).is_empty(), "nothing is built");
+}
+
+#[test]
+fn test_apptainer_images_are_built_once() {
+    let tools = TempDir::new().unwrap();
+    let data = TempDir::new().unwrap();
+    let run = TempDir::new().unwrap();
+    let images = TempDir::new().unwrap();
+    let tools_image = Container::from("alpine:3.19");
+    tools_image
+        .write()
+        .unwrap()
+        .run("apk add --no-cache coreutils");
+    let hash = tools_image.read().unwrap().content_hash().unwrap();
+
+    let mut executor = ContainerExecutor::new(Runtime::Apptainer);
+    executor
+        .program(fake_runtime(tools.path(), 0))
+        .image_dir(images.path());
+    let runner = Runner::new(&executor, run.path());
+    let workflow = count_lines(&tools_image);
+    runner.run(&workflow, [text(data.path())]).unwrap();
+    runner.run(&workflow, [text(data.path())]).unwrap();
+
+    let image = images.path().join(hash.to_string()).join("image.sif");
+    assert!(image.is_file());
+    let commands: Vec<_> = calls(tools.path())
+        .into_iter()
+        .map(|call| call[0].clone())
+        .collect();
+    assert_eq!(commands, ["build", "exec", "exec"]);
+    assert!(
+        calls(tools.path())[1].contains(&image.display().to_string()),
+        "the built image is run"
+    );
+}
+
+#[test]
+fn test_failed_build() {
+    let tools = TempDir::new().unwrap();
+    let data = TempDir::new().unwrap();
+    let run = TempDir::new().unwrap();
+    let tools_image = Container::from("alpine:3.19");
+    tools_image.write().unwrap().run("apk add --no-such-flag");
+
+    let mut executor = ContainerExecutor::new(Runtime::Docker);
+    executor.program(fake_runtime(tools.path(), 2));
+    let error = Runner::new(&executor, run.path())
+        .run(&count_lines(&tools_image), [text(data.path())])
+        .unwrap_err();
+
+    let ExecutionError::ImageBuild { exit_code, log, .. } = error else {
+        panic!("unexpected error {error}");
+    };
+    assert_eq!(exit_code, Some(2));
+    assert!(log.is_file());
+    assert_eq!(
+        calls(tools.path()).len(),
+        3,
+        "nothing runs after a failed build"
+    );
+}
+
+// EOF
@@ -0,0 +1,269 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use rivulet::executor::JobOutcome;
+use rivulet::prelude::*;
+use rivulet::workflow::{Staging, Value};
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// A two-step workflow: split a text file into one file per line, then count the parts.
+fn split_and_count() -> Workflow {
+    let shell = Container::from("docker.io/library/busybox:1.36");
+    let mut workflow = Workflow::new("split_and_count");
+    let text = workflow.input("text", PortType::File).unwrap();
+    let prefix = workflow.input("prefix", PortType::String).unwrap();
+
+    let mut split = Step::new(
+        "split",
+        &shell,
+        "mkdir parts && split -l 1 {text} parts/{prefix} && echo split {text}",
+    );
+    split
+        .input("text", PortType::File)
+        .input("prefix", PortType::String)
+        .output("parts", PortType::array(PortType::File))
+        .glob("parts", "parts/*");
+    let split = workflow.add_step(split).unwrap();
+
+    let mut count = Step::new("count", &shell, "ls {parts} | wc -l > count");
+    count
+        .input("parts", PortType::array(PortType::File))
+        .output("count", PortType::Int);
+    let count = workflow.add_step(count).unwrap();
+
+    workflow.connect(text, split.input("text")).unwrap();
+    workflow.connect(prefix, split.input("prefix")).unwrap();
+    workflow
+        .connect(split.output("parts"), count.input("parts"))
+        .unwrap();
+    workflow.output("parts", split.output("parts")).unwrap();
+    workflow.output("count", count.output("count")).unwrap();
+    workflow
+}
+
+fn write(dir: &Path, name: &str, contents: &str) -> Value {
+    let path = dir.join(name);
+    fs::write(&path, contents).unwrap();
+    Value::File(path)
+}
+
+#[test]
+fn test_run_collects_outputs() {
+    let data = TempDir::new().unwrap();
+    let run = TempDir::new().unwrap();
+    let text = write(data.path(), "lines of text.txt", "a\nb\nc\n");
+
+    let executor = LocalExecutor::new();
+    let result = Runner::new(&executor, run.path())
+        .run(
+            &split_and_count(),
+            [("text", text), ("prefix", Value::from("part "))],
+        )
+        .unwrap();
+
+    assert_eq!(result.outputs["count"], Value::Int(3));
+    let Value::Array(parts) = &result.outputs["parts"] else {
+        panic!("parts is not an array");
+    };
+    let names: Vec<_> = parts
+        .iter()
+        .map(|part| match part {
+            Value::File(path) => path.file_name().unwrap().to_string_lossy().into_owned(),
+            other => panic!("unexpected part {other}"),
+        })
+        .collect();
+    assert_eq!(names, ["part aa", "part ab", "part ac"]);
+
+    let steps: Vec<_> = result.steps.iter().map(|step| step.name.as_str()).collect();
+    assert_eq!(steps, ["split", "count"]);
+    let split = &result.steps[0];
+    assert_eq!(split.exit_code, Some(0));
+    assert!(split.started <= split.finished);
+    let stdout = fs::read_to_string(&split.stdout).unwrap();
+    assert!(stdout.starts_with("split "), "{stdout}");
+    assert!(
+        stdout.trim_end().ends_with("/lines of text.txt"),
+        "{stdout}"
+    );
+    assert!(split.dir.join("command.sh").is_file());
+}
+
+#[test]
+fn test_staging() {
+    let data = TempDir::new().unwrap();
+    let text = write(data.path(), "text.txt", "a\n");
+
+    for (staging, symlinked) in [(Staging::Symlink, true), (Staging::Copy, false)] {
+        let run = TempDir::new().unwrap();
+        let executor = LocalExecutor::new();
+        let result = Runner::new(&executor, run.path())
+            .staging(staging)
+            .run(
+                &split_and_count(),
+                [("text", text.clone()), ("prefix", Value::from("x"))],
+            )
+            .unwrap();
+
+        let Value::File(staged) = &result.steps[0].inputs["text"] else {
+            panic!("text is not a file");
+        };
+        assert!(staged.starts_with(run.path().join("steps/split/inputs/text")));
+        assert_eq!(staged.is_symlink(), symlinked, "{staging:?}");
+        assert_eq!(fs::read_to_string(staged).unwrap(), "a\n");
+
+        let Value::Array(parts) = &result.steps[1].inputs["parts"] else {
+            panic!("parts is not an array");
+        };
+        let Value::File(part) = &parts[0] else {
+            panic!("part is not a file");
+        };
+        assert!(part.starts_with(run.path().join("steps/count/inputs/parts/0")));
+    }
+}
+
+#[test]
+fn test_failing_step_stops_the_run() {
+    let shell = Container::from("docker.io/library/busybox:1.36");
+    let mut workflow = Workflow::new("failing");
+    let mut fail = Step::new("fail", &shell, "echo broken >&2; exit 3");
+    fail.output("out", PortType::File);
+    let fail = workflow.add_step(fail).unwrap();
+    let mut after = Step::new("after", &shell, "touch ran");
+    after.input("out", PortType::File);
+    let after = workflow.add_step(after).unwrap();
+    workflow
+        .connect(fail.output("out"), after.input("out"))
+        .unwrap();
+
+    let run = TempDir::new().unwrap();
+    let executor = LocalExecutor::new();
+    let error = Runner::new(&executor, run.path())
+        .run(&workflow, Vec::<(String, Value)>::new())
+        .unwrap_err();
+
+    let ExecutionError::StepFailed {
+        step,
+        outcome,
+        stderr,
+    } = error
+    else {
+        panic!("unexpected error {error}");
+    };
+    assert_eq!(step, "fail");
+    assert_eq!(outcome, JobOutcome::exited(3));
+    assert_eq!(fs::read_to_string(stderr).unwrap(), "broken\n");
+    assert!(!run.path().join("steps/after").exists());
+
+    let log = fs::read_to_string(run.path().join("run.log")).unwrap();
+    let events: Vec<_> = log
+        .lines()
+        .map(|line| line.split_once("] ").unwrap().1)
+        .collect();
+    assert_eq!(
+        events,
+        [
+            "failing: run started",
+            "fail: started",
+            "fail: failed, exit code 3",
+            "failing: run failed: Step 'fail' failed with exit code 3",
+        ]
+    );
+}
+
+#[test]
+fn test_killed_step() {
+    let mut workflow = Workflow::new("killed");
+    let shell = Container::from("docker.io/library/busybox:1.36");
+    workflow
+        .add_step(Step::new("suicide", &shell, "kill -9 $$"))
+        .unwrap();
+
+    let run = TempDir::new().unwrap();
+    let executor = LocalExecutor::new();
+    let error = Runner::new(&executor, run.path())
+        .run(&workflow, Vec::<(String, Value)>::new())
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        ExecutionError::StepFailed { step, outcome, .. }
+            if step == "suicide"
+                && outcome.exit_code.is_none()
+                && outcome.reason.as_deref() == Some("signal 9")
+    ));
+}
+
+#[test]
+fn test_output_matching() {
+    let shell = Container::from("docker.io/library/busybox:1.36");
+    let run = TempDir::new().unwrap();
+    let executor = LocalExecutor::new();
+    let runner = Runner::new(&executor, run.path());
+
+    let run_with = |command: &str, port_type: PortType| {
+        let mut workflow = Workflow::new("outputs");
+        let mut step = Step::new("make", &shell, command);
+        step.output("out", port_type).glob("out", "*.txt");
+        workflow.add_step(step).unwrap();
+        runner.run(&workflow, Vec::<(String, Value)>::new())
+    };
+
+    assert!(matches!(
+        run_with("true", PortType::File),
+        Err(ExecutionError::MissingOutput { .. })
+    ));
+    assert!(matches!(
+        run_with("touch a.txt b.txt", PortType::File),
+        Err(ExecutionError::AmbiguousOutput { count: 2, .. })
+    ));
+    assert!(matches!(
+        run_with("mkdir a.txt", PortType::File),
+        Err(ExecutionError::MissingOutput { .. })
+    ));
+    assert!(matches!(
+        run_with("echo maybe > a.txt", PortType::Bool),
+        Err(ExecutionError::InvalidOutput { .. })
+    ));
+    assert_eq!(
+        run_with("true", PortType::array(PortType::File))
+            .unwrap()
+            .steps[0]
+            .outputs["out"],
+        Value::Array(vec![])
+    );
+}
+
+#[test]
+fn test_invalid_inputs() {
+    let data = TempDir::new().unwrap();
+    let run = TempDir::new().unwrap();
+    let executor = LocalExecutor::new();
+    let runner = Runner::new(&executor, run.path());
+    let workflow = split_and_count();
+    let text = write(data.path(), "text.txt", "a\n");
+    let prefix = ("prefix", Value::from("x"));
+
+    assert!(matches!(
+        runner.run(&workflow, [prefix.clone()]),
+        Err(ExecutionError::MissingInput(name)) if name == "text"
+    ));
+    assert!(matches!(
+        runner.run(&workflow, [("text", text.clone()), prefix.clone(), ("extra", Value::Int(1))]),
+        Err(ExecutionError::UnknownInput(name)) if name == "extra"
+    ));
+    assert!(matches!(
+        runner.run(&workflow, [("text", Value::Int(1)), prefix.clone()]),
+        Err(ExecutionError::InvalidInput { .. })
+    ));
+    assert!(matches!(
+        runner.run(
+            &workflow,
+            [("text", Value::File(data.path().join("missing"))), prefix]
+        ),
+        Err(ExecutionError::InvalidInput { .. })
+    ));
+}
+
+// EOF
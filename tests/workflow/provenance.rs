@@ -0,0 +1,299 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use rivulet::container::ImageSelector;
+use rivulet::executor::{Executor, Job, JobOutcome};
+use rivulet::hash::ContentHash;
+use rivulet::prelude::*;
+use rivulet::workflow::{RunResult, Value};
+#[cfg(feature = "serde")]
+use serde_json::Value as Json;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+const DIGEST: &str = "sha256:3fbc632167424a6d997e74f52b878d7cc478225cffac6bc977eedfe51c7f4e79";
+
+/// Upper-case every part in a pinned container and join the parts in an unpinned one.
+fn shout() -> Workflow {
+    let pinned = Container::from(format!("docker.io/library/busybox:1.36@{DIGEST}").as_str());
+    let unpinned = Container::from("docker.io/library/busybox:1.36");
+    let mut workflow = Workflow::new("shout");
+    let parts = workflow
+        .input("parts", PortType::array(PortType::File))
+        .unwrap();
+
+    let mut upper = Step::new("upper", &pinned, "tr a-z A-Z < {part} > upper.txt");
+    upper
+        .input("part", PortType::File)
+        .output("upper", PortType::File)
+        .glob("upper", "upper.txt")
+        .scatter("part");
+    let upper = workflow.add_step(upper).unwrap();
+
+    let mut join = Step::new("join", &unpinned, "cat {parts} > joined.txt");
+    join.input("parts", PortType::array(PortType::File))
+        .output("joined", PortType::File)
+        .glob("joined", "joined.txt");
+    let join = workflow.add_step(join).unwrap();
+
+    workflow.connect(parts, upper.input("part")).unwrap();
+    workflow
+        .connect(upper.output("upper"), join.input("parts"))
+        .unwrap();
+    workflow.output("joined", join.output("joined")).unwrap();
+    workflow
+}
+
+/// Find the relations of a kind between two elements.
+#[cfg(feature = "serde")]
+fn relations<'a>(
+    document: &'a Json,
+    kind: &str,
+    from: (&str, &str),
+    to: (&str, &str),
+) -> Vec<&'a Json> {
+    document[kind]
+        .as_object()
+        .unwrap()
+        .values()
+        .filter(|relation| relation[from.0] == from.1 && relation[to.0] == to.1)
+        .collect()
+}
+
+/// The digest [`Pinning`] resolves image tags to.
+const RESOLVED: &str = "sha256:8f2d4c6b1a3e5d7f9b0c2e4a6d8f1b3c5e7a9d0f2b4c6e8a1d3f5b7c9e0a2d4f";
+
+/// Runs jobs on the host, pinning images to [`RESOLVED`] like a container runtime would.
+struct Pinning(LocalExecutor);
+
+impl Executor for Pinning {
+    fn execute(&self, job: &Job) -> Result<JobOutcome, ExecutionError> {
+        self.0.execute(job)
+    }
+
+    fn pin_image(&self, image: &ImageSelector) -> Option<ImageSelector> {
+        let digest = image.digest.clone().or_else(|| RESOLVED.parse().ok());
+        Some(ImageSelector {
+            digest,
+            ..image.clone()
+        })
+    }
+}
+
+/// Run [`shout`] on two parts in a scratch directory, returning the result and the first
+/// part.
+fn run_shout(scratch: &Path) -> (RunResult, PathBuf) {
+    run_shout_with(&LocalExecutor::new(), scratch)
+}
+
+/// Run [`shout`] like [`run_shout`], with the given executor.
+fn run_shout_with(executor: &dyn Executor, scratch: &Path) -> (RunResult, PathBuf) {
+    let (a, b) = (scratch.join("a.txt"), scratch.join("b.txt"));
+    fs::write(&a, "a\n").unwrap();
+    fs::write(&b, "b\n").unwrap();
+    let result = Runner::new(executor, scratch.join("run"))
+        .run(
+            &shout(),
+            [(
+                "parts",
+                Value::Array(vec![Value::File(a.clone()), Value::File(b)]),
+            )],
+        )
+        .unwrap();
+    (result, a)
+}
+
+/// The identifier of the entity of a file.
+fn artifact(path: &Path) -> String {
+    format!("artifact:{}", ContentHash::of_file(path).unwrap())
+}
+
+#[test]
+fn test_provenance_of_run() {
+    let scratch = TempDir::new().unwrap();
+    let (result, _) = run_shout(scratch.path());
+
+    let provenance = &result.provenance;
+    assert_eq!(provenance.run, result.run_id);
+    assert_eq!(provenance.workflow, "shout");
+    assert_eq!(provenance.artifacts.len(), 5);
+    let jobs: Vec<_> = provenance
+        .jobs
+        .iter()
+        .map(|job| job.step.as_str())
+        .collect();
+    assert_eq!(jobs, ["upper", "upper", "join"]);
+    assert_eq!(provenance.containers.len(), 2);
+
+    // The output of one job is the input of the next, identified by content
+    let Value::File(joined) = &result.outputs["joined"] else {
+        panic!("joined is not a file");
+    };
+    assert_eq!(fs::read_to_string(joined).unwrap(), "A\nB\n");
+    let upper_a = artifact(&result.steps[0].dir.join("work/upper.txt"));
+    let joined = artifact(joined.as_path());
+    let pinned = format!("container:{}", provenance.jobs[0].container);
+    let provn = fs::read_to_string(scratch.path().join("run/provenance.provn")).unwrap();
+    assert_eq!(provn, provenance.to_prov_n());
+    assert!(provn.starts_with("document\n  prefix rivulet <urn:rivulet:>\n"));
+    assert!(provn.ends_with("endDocument\n"));
+    assert!(provn.contains(&format!("  wasGeneratedBy({joined}, run:join, ")));
+    assert!(provn.contains(&format!("  used(run:join, {upper_a}, ")));
+    assert!(provn.contains(&format!("rivulet:digest=\"{DIGEST}\"")));
+    assert!(provn.contains("rivulet:index=1, "));
+    assert!(provn.contains(&format!("  wasAssociatedWith(run:upper_0, {pinned}, -)\n")));
+}
+
+#[test]
+fn test_resolved_digest_is_recorded() {
+    let scratch = TempDir::new().unwrap();
+    let (result, _) = run_shout_with(&Pinning(LocalExecutor::new()), scratch.path());
+
+    let provenance = &result.provenance;
+    let digests: Vec<_> = provenance
+        .jobs
+        .iter()
+        .map(|job| {
+            provenance.containers[&job.container]
+                .digest
+                .as_ref()
+                .unwrap()
+        })
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(digests, [DIGEST, DIGEST, RESOLVED]);
+    let provn = provenance.to_prov_n();
+    assert!(provn.contains(&format!("rivulet:digest=\"{RESOLVED}\"")));
+}
+
+#[test]
+fn test_provenance_of_failed_run() {
+    let scratch = TempDir::new().unwrap();
+    let busybox = Container::from("docker.io/library/busybox:1.36");
+    let mut workflow = Workflow::new("fail");
+    let mut greet = Step::new("greet", &busybox, "echo hello > hello.txt");
+    greet
+        .output("hello", PortType::File)
+        .glob("hello", "hello.txt");
+    let greet = workflow.add_step(greet).unwrap();
+    let mut fail = Step::new("fail", &busybox, "exit 3");
+    fail.input("hello", PortType::File);
+    let fail = workflow.add_step(fail).unwrap();
+    workflow
+        .connect(greet.output("hello"), fail.input("hello"))
+        .unwrap();
+
+    let run = scratch.path().join("run");
+    let error = Runner::new(&LocalExecutor::new(), &run)
+        .run(&workflow, Vec::<(String, Value)>::new())
+        .unwrap_err();
+
+    assert!(matches!(error, ExecutionError::StepFailed { step, .. } if step == "fail"));
+    let provn = fs::read_to_string(run.join("provenance.provn")).unwrap();
+    assert!(provn.contains("  activity(run:greet, "));
+    assert!(
+        !provn.contains("run:fail"),
+        "the failed job is not recorded"
+    );
+}
+
+/// Run [`shout`] like [`run_shout`], also returning the PROV-JSON document of the run.
+#[cfg(feature = "serde")]
+fn run_shout_json(scratch: &Path) -> (RunResult, PathBuf, Json) {
+    let (result, a) = run_shout(scratch);
+    let json = fs::read_to_string(scratch.join("run/provenance.json")).unwrap();
+    (result, a, serde_json::from_str(&json).unwrap())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_prov_json_artifacts() {
+    let scratch = TempDir::new().unwrap();
+    let (result, a, document) = run_shout_json(scratch.path());
+    assert_eq!(
+        document["prefix"]["run"],
+        format!("urn:rivulet:run:{}:", result.run_id)
+    );
+
+    // The output of one job is the input of the next, identified by content
+    let Value::File(joined) = &result.outputs["joined"] else {
+        panic!("joined is not a file");
+    };
+    let upper_a = artifact(&result.steps[0].dir.join("work/upper.txt"));
+    let joined = artifact(joined.as_path());
+    assert_eq!(
+        document["entity"][&joined]["rivulet:contentHash"],
+        joined["artifact:".len()..]
+    );
+    assert_eq!(
+        document["entity"][&joined]["prov:type"]["$"],
+        "rivulet:File"
+    );
+    let input = &document["entity"][artifact(&a)];
+    assert_eq!(
+        input["prov:location"],
+        a.canonicalize().unwrap().to_str().unwrap()
+    );
+    assert_eq!(input["prov:label"], "a.txt");
+
+    let generated = relations(
+        &document,
+        "wasGeneratedBy",
+        ("prov:entity", &upper_a),
+        ("prov:activity", "run:upper_0"),
+    );
+    assert_eq!(generated.len(), 1);
+    assert_eq!(generated[0]["prov:role"], "upper");
+    let used = relations(
+        &document,
+        "used",
+        ("prov:activity", "run:join"),
+        ("prov:entity", &upper_a),
+    );
+    assert_eq!(used.len(), 1);
+    assert_eq!(used[0]["prov:role"], "parts");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_prov_json_jobs() {
+    let scratch = TempDir::new().unwrap();
+    let (result, _, document) = run_shout_json(scratch.path());
+    let provenance = &result.provenance;
+
+    let activity = &document["activity"]["run:upper_1"];
+    assert_eq!(activity["rivulet:step"], "upper");
+    assert_eq!(activity["rivulet:index"], 1);
+    assert_eq!(activity["rivulet:cached"], false);
+    assert_eq!(
+        activity["rivulet:host"],
+        LocalExecutor::new().host().unwrap().as_str()
+    );
+    assert!(activity["prov:startTime"].as_str().unwrap().ends_with('Z'));
+
+    // The pinned container is identified by its digest, and both act for the user
+    let pinned = format!("container:{}", provenance.jobs[0].container);
+    let unpinned = format!("container:{}", provenance.jobs[2].container);
+    assert_eq!(document["agent"][&pinned]["rivulet:digest"], DIGEST);
+    assert_eq!(
+        document["agent"][&pinned]["prov:type"]["$"],
+        "prov:SoftwareAgent"
+    );
+    assert!(document["agent"][&unpinned].get("rivulet:digest").is_none());
+    let associated = relations(
+        &document,
+        "wasAssociatedWith",
+        ("prov:activity", "run:join"),
+        ("prov:agent", &unpinned),
+    );
+    assert_eq!(associated.len(), 1);
+    if let Some(user) = &provenance.user {
+        let user = format!("user:{user}");
+        assert_eq!(document["agent"][&user]["prov:type"]["$"], "prov:Person");
+        assert_eq!(document["actedOnBehalfOf"].as_object().unwrap().len(), 2);
+    }
+}
+
+// EOF
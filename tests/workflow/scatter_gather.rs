@@ -0,0 +1,238 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use rivulet::prelude::*;
+use rivulet::workflow::Value;
+use std::fs;
+use std::sync::{Arc, RwLock};
+use tempfile::TempDir;
+
+/// Count the lines of a sample and label it, scattered over the samples and their names.
+fn count_step(shell: &Arc<RwLock<Container>>) -> Step {
+    let mut count = Step::new(
+        "count",
+        shell,
+        "wc -l < {reads} > lines && echo {name} > label",
+    );
+    count
+        .input("reads", PortType::File)
+        .input("name", PortType::String)
+        .output("lines", PortType::Int)
+        .output("label", PortType::String)
+        .scatter("reads")
+        .scatter("name");
+    count
+}
+
+/// Count the lines of every sample, double each count while staying scattered, and sum the
+/// gathered counts.
+fn count_samples() -> Workflow {
+    let shell = Container::from("docker.io/library/busybox:1.36");
+    let mut workflow = Workflow::new("count_samples");
+    let samples = workflow
+        .input("samples", PortType::array(PortType::File))
+        .unwrap();
+    let names = workflow
+        .input("names", PortType::array(PortType::String))
+        .unwrap();
+
+    let count = workflow.add_step(count_step(&shell)).unwrap();
+
+    let mut double = Step::new("double", &shell, "echo $(({lines} * 2)) > doubled");
+    double
+        .input("lines", PortType::Int)
+        .output("doubled", PortType::Int)
+        .scatter("lines");
+    let double = workflow.add_step(double).unwrap();
+
+    let mut total = Step::new(
+        "total",
+        &shell,
+        "sum=0; for n in {counts}; do sum=$((sum + n)); done; echo $sum > total",
+    );
+    total
+        .input("counts", PortType::array(PortType::Int))
+        .output("total", PortType::Int);
+    let total = workflow.add_step(total).unwrap();
+
+    workflow.connect(samples, count.input("reads")).unwrap();
+    workflow.connect(names, count.input("name")).unwrap();
+    workflow
+        .connect(count.output("lines"), double.input("lines"))
+        .unwrap();
+    workflow
+        .connect(count.output("lines"), total.input("counts"))
+        .unwrap();
+    workflow.output("labels", count.output("label")).unwrap();
+    workflow
+        .output("doubled", double.output("doubled"))
+        .unwrap();
+    workflow.output("total", total.output("total")).unwrap();
+    workflow
+}
+
+#[test]
+fn test_scatter_and_gather() {
+    let data = TempDir::new().unwrap();
+    let run = TempDir::new().unwrap();
+    let mut samples = Vec::new();
+    for (name, lines) in [("b", 3), ("a", 1), ("c", 2)] {
+        let path = data.path().join(format!("{name}.fq"));
+        fs::write(&path, "read\n".repeat(lines)).unwrap();
+        samples.push(Value::File(path));
+    }
+    let names = ["b", "a", "c"].map(Value::from).to_vec();
+
+    let executor = LocalExecutor::new();
+    let result = Runner::new(&executor, run.path())
+        .run(
+            &count_samples(),
+            [
+                ("samples", Value::Array(samples)),
+                ("names", Value::Array(names.clone())),
+            ],
+        )
+        .unwrap();
+
+    assert_eq!(result.outputs["labels"], Value::Array(names));
+    assert_eq!(
+        result.outputs["doubled"],
+        Value::Array(vec![Value::Int(6), Value::Int(2), Value::Int(4)])
+    );
+    assert_eq!(result.outputs["total"], Value::Int(6));
+
+    let jobs: Vec<_> = result
+        .steps
+        .iter()
+        .map(|step| (step.name.as_str(), step.index))
+        .collect();
+    assert_eq!(
+        jobs,
+        [
+            ("count", Some(0)),
+            ("count", Some(1)),
+            ("count", Some(2)),
+            ("double", Some(0)),
+            ("double", Some(1)),
+            ("double", Some(2)),
+            ("total", None),
+        ]
+    );
+    assert_eq!(result.steps[1].dir, run.path().join("steps/count/1"));
+    assert_eq!(result.steps[1].inputs["name"], Value::from("a"));
+    assert!(run.path().join("steps/double/2/work/doubled").is_file());
+}
+
+#[test]
+fn test_cross_product() {
+    let shell = Container::from("docker.io/library/busybox:1.36");
+    let mut workflow = Workflow::new("pairs");
+    let numbers = workflow
+        .input("numbers", PortType::array(PortType::Int))
+        .unwrap();
+    let letters = workflow
+        .input("letters", PortType::array(PortType::String))
+        .unwrap();
+    let mut pair = Step::new("pair", &shell, "echo {number}{letter} > pair");
+    pair.input("number", PortType::Int)
+        .input("letter", PortType::String)
+        .output("pair", PortType::String)
+        .scatter("number")
+        .scatter("letter")
+        .cross_product();
+    let pair = workflow.add_step(pair).unwrap();
+    workflow.connect(numbers, pair.input("number")).unwrap();
+    workflow.connect(letters, pair.input("letter")).unwrap();
+    workflow.output("pairs", pair.output("pair")).unwrap();
+
+    let run = TempDir::new().unwrap();
+    let executor = LocalExecutor::new();
+    let runner = Runner::new(&executor, run.path());
+    let run_with = |numbers: &[i64], letters: &[&str]| {
+        let numbers = numbers.iter().copied().map(Value::Int).collect();
+        let letters = letters.iter().copied().map(Value::from).collect();
+        runner
+            .run(
+                &workflow,
+                [
+                    ("numbers", Value::Array(numbers)),
+                    ("letters", Value::Array(letters)),
+                ],
+            )
+            .unwrap()
+            .outputs["pairs"]
+            .clone()
+    };
+
+    let pairs = ["1x", "1y", "1z", "2x", "2y", "2z"]
+        .map(Value::from)
+        .to_vec();
+    assert_eq!(run_with(&[1, 2], &["x", "y", "z"]), Value::Array(pairs));
+    assert_eq!(run_with(&[], &["x"]), Value::Array(Vec::new()));
+}
+
+#[test]
+fn test_failing_job() {
+    let shell = Container::from("docker.io/library/busybox:1.36");
+    let mut workflow = Workflow::new("exits");
+    let codes = workflow
+        .input("codes", PortType::array(PortType::Int))
+        .unwrap();
+    let mut exit = Step::new("exit", &shell, "exit {code}");
+    exit.input("code", PortType::Int).scatter("code");
+    let exit = workflow.add_step(exit).unwrap();
+    workflow.connect(codes, exit.input("code")).unwrap();
+
+    let run = TempDir::new().unwrap();
+    let executor = LocalExecutor::new();
+    let codes = Value::Array(vec![Value::Int(0), Value::Int(5)]);
+    let error = Runner::new(&executor, run.path())
+        .run(&workflow, [("codes", codes)])
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        ExecutionError::StepFailed { step, outcome, .. }
+            if step == "exit[1]" && outcome.exit_code == Some(5)
+    ));
+
+    let log = fs::read_to_string(run.path().join("run.log")).unwrap();
+    let events: Vec<_> = log
+        .lines()
+        .map(|line| line.split_once("] ").unwrap().1)
+        .collect();
+    assert_eq!(
+        events[1..4],
+        [
+            "exit: started 2 jobs",
+            "exit[0]: finished, exit code 0",
+            "exit[1]: failed, exit code 5",
+        ]
+    );
+}
+
+#[test]
+fn test_uneven_dot_product() {
+    let data = TempDir::new().unwrap();
+    let run = TempDir::new().unwrap();
+    let path = data.path().join("a.fq");
+    fs::write(&path, "read\n").unwrap();
+
+    let executor = LocalExecutor::new();
+    let error = Runner::new(&executor, run.path())
+        .run(
+            &count_samples(),
+            [
+                ("samples", Value::Array(vec![Value::File(path)])),
+                ("names", Value::Array(vec![])),
+            ],
+        )
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        ExecutionError::ScatterLength { step, port, expected: 1, found: 0 }
+            if step == "count" && port == "name"
+    ));
+}
+
+// EOF
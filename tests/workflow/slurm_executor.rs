@@ -0,0 +1,330 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use super::common::write_script;
+use rivulet::executor::{Executor, Job, JobArray, JobOutcome, SlurmExecutor};
+use rivulet::prelude::*;
+use rivulet::workflow::Resources;
+use rivulet::workflow::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tempfile::TempDir;
+
+/// Write stand-ins for the Slurm commands to a directory, returning an executor using them.
+///
+/// Every command records its arguments in `<command>.args`.
+fn fake_slurm(dir: &Path) -> SlurmExecutor {
+    fake_sbatch(dir);
+    fake_squeue(dir);
+    fake_sacct(dir);
+    let mut slurm = SlurmExecutor::new();
+    slurm
+        .sbatch(dir.join("sbatch"))
+        .squeue(dir.join("squeue"))
+        .sacct(dir.join("sacct"))
+        .partition("debug")
+        .poll_interval(Duration::ZERO);
+    slurm
+}
+
+/// Write an `sbatch` that runs the script right away as job 4242, following its `--chdir`,
+/// `--output` and `--error` directives. Array scripts are run once for every index in the
+/// manifest next to them, and submitted as job 4343.
+fn fake_sbatch(dir: &Path) {
+    let dir_str = dir.display();
+    write_script(
+        &dir.join("sbatch"),
+        &format!(
+            "printf '%s\\n' \"$@\" > '{dir_str}/sbatch.args'\n\
+             script=\"$2\"\n\
+             if grep -q '^#SBATCH --array=' \"$script\"; then\n\
+             for task in $(cut -f 1 \"$(dirname \"$script\")/array.manifest\"); do\n\
+             SLURM_ARRAY_TASK_ID=$task sh \"$script\" > /dev/null 2>&1\n\
+             echo $? > '{dir_str}/exit_'$task\n\
+             done\n\
+             echo 4343\n\
+             exit\n\
+             fi\n\
+             directive() {{ sed -n \"s/^#SBATCH --$1=//p\" \"$script\"; }}\n\
+             (cd \"$(directive chdir)\" && sh \"$script\" > \"$(directive output)\" \
+             2> \"$(directive error)\")\n\
+             echo $? > '{dir_str}/exit'\n\
+             echo '4242;cluster'\n"
+        ),
+    );
+}
+
+/// Write an `squeue` that reports the job pending, then running, then forgets it.
+fn fake_squeue(dir: &Path) {
+    let dir_str = dir.display();
+    write_script(
+        &dir.join("squeue"),
+        &format!(
+            "printf '%s\\n' \"$@\" > '{dir_str}/squeue.args'\n\
+             polls=$(cat '{dir_str}/polls' 2> /dev/null || echo 0)\n\
+             echo $((polls + 1)) > '{dir_str}/polls'\n\
+             case $polls in\n\
+             0) echo PENDING ;;\n\
+             1) echo RUNNING ;;\n\
+             *) echo 'slurm_load_jobs error: Invalid job id specified' >&2; exit 1 ;;\n\
+             esac\n"
+        ),
+    );
+}
+
+/// Write an `sacct` that reports the state in the `state` file if there is one, or the exit
+/// status of the script otherwise. For array jobs, it reports the states in the `array_state`
+/// file if there is one, or first all elements pending and then the exit status of every
+/// element.
+fn fake_sacct(dir: &Path) {
+    let dir_str = dir.display();
+    write_script(
+        &dir.join("sacct"),
+        &format!(
+            "printf '%s\\n' \"$@\" > '{dir_str}/sacct.args'\n\
+             if [ \"$5\" = --jobs=4343 ]; then\n\
+             if [ -f '{dir_str}/array_state' ]; then cat '{dir_str}/array_state'; exit; fi\n\
+             if [ ! -f '{dir_str}/array_polled' ]; then\n\
+             touch '{dir_str}/array_polled'; echo '4343_[0-2%2]|PENDING|0:0'; exit\n\
+             fi\n\
+             for file in '{dir_str}'/exit_*; do\n\
+             code=$(cat \"$file\"); task=${{file##*_}}\n\
+             if [ \"$code\" -eq 0 ]; then state=COMPLETED; else state=FAILED; fi\n\
+             echo \"4343_$task|$state|$code:0\"\n\
+             done\n\
+             exit\n\
+             fi\n\
+             if [ -f '{dir_str}/state' ]; then cat '{dir_str}/state'; exit; fi\n\
+             code=$(cat '{dir_str}/exit')\n\
+             if [ \"$code\" -eq 0 ]; then echo 'COMPLETED|0:0'; else echo \"FAILED|$code:0\"; fi\n"
+        ),
+    );
+}
+
+fn args(dir: &Path, command: &str) -> Vec<String> {
+    let args = fs::read_to_string(dir.join(format!("{command}.args"))).unwrap();
+    args.lines().map(String::from).collect()
+}
+
+/// An array of jobs running the given commands in numbered directories of `dir`.
+fn job_array(dir: &Path, name: &str, commands: &[&str]) -> JobArray {
+    let busybox = Container::from("busybox:1.36");
+    let container = busybox.read().unwrap().resolve().unwrap();
+    let jobs = commands
+        .iter()
+        .enumerate()
+        .map(|(index, command)| {
+            let job_dir = dir.join(index.to_string());
+            fs::create_dir_all(job_dir.join("work")).unwrap();
+            Job {
+                name: format!("{name}_{index}"),
+                container: container.clone(),
+                command: command.to_string(),
+                inputs: BTreeMap::from([
+                    ("index".to_string(), Value::from(index as i64)),
+                    ("label".to_string(), Value::String(format!("job {index}"))),
+                ]),
+                workdir: job_dir.join("work"),
+                stdout: job_dir.join("stdout"),
+                stderr: job_dir.join("stderr"),
+                dir: job_dir,
+                resources: Resources::default(),
+                mounts: Vec::new(),
+            }
+        })
+        .collect();
+    JobArray {
+        name: name.to_string(),
+        dir: dir.to_path_buf(),
+        jobs,
+    }
+}
+
+/// A single-step workflow counting the lines of a file, with resource requests.
+fn count_lines(dir: &Path) -> (Workflow, [(&'static str, Value); 1]) {
+    let mut workflow = Workflow::new("count_lines");
+    let text = workflow.input("text", PortType::File).unwrap();
+    let busybox = Container::from("busybox:1.36");
+    let mut count = Step::new("count", &busybox, "wc -l < {text} > lines");
+    count
+        .input("text", PortType::File)
+        .output("lines", PortType::Int)
+        .cpus(2)
+        .memory(512 << 20)
+        .time(Duration::from_secs(600));
+    let count = workflow.add_step(count).unwrap();
+    workflow.connect(text, count.input("text")).unwrap();
+    workflow.output("lines", count.output("lines")).unwrap();
+
+    let path = dir.join("text.txt");
+    fs::write(&path, "a\nb\n").unwrap();
+    (workflow, [("text", Value::File(path))])
+}
+
+#[test]
+fn test_submit_and_poll() {
+    let slurm_dir = TempDir::new().unwrap();
+    let data = TempDir::new().unwrap();
+    let run = TempDir::new().unwrap();
+    let slurm = fake_slurm(slurm_dir.path());
+    let (workflow, inputs) = count_lines(data.path());
+
+    let result = Runner::new(&slurm, run.path())
+        .run(&workflow, inputs)
+        .unwrap();
+    assert_eq!(result.outputs["lines"], Value::Int(2));
+    assert_eq!(result.steps[0].exit_code, Some(0));
+
+    let script_path = result.steps[0].dir.join("job.sbatch");
+    assert_eq!(
+        args(slurm_dir.path(), "sbatch"),
+        ["--parsable".to_string(), script_path.display().to_string()]
+    );
+    let script = fs::read_to_string(script_path).unwrap();
+    for directive in [
+        "#SBATCH --job-name=count",
+        "#SBATCH --cpus-per-task=2",
+        "#SBATCH --mem=512M",
+        "#SBATCH --time=0-00:10:00",
+        "#SBATCH --partition=debug",
+    ] {
+        assert!(script.lines().any(|line| line == directive), "{directive}");
+    }
+
+    let polls = fs::read_to_string(slurm_dir.path().join("polls")).unwrap();
+    assert_eq!(polls.trim(), "3");
+    assert!(args(slurm_dir.path(), "squeue").contains(&"--jobs=4242".to_string()));
+    assert!(args(slurm_dir.path(), "sacct").contains(&"--jobs=4242".to_string()));
+}
+
+#[test]
+fn test_exit_states() {
+    let ended = |reason: &str| JobOutcome {
+        exit_code: None,
+        reason: Some(reason.to_string()),
+    };
+    for (command, state, expected) in [
+        ("exit 1", None, JobOutcome::exited(1)),
+        ("true", Some("TIMEOUT|0:15"), ended("TIMEOUT")),
+        ("true", Some("CANCELLED by 1000|0:9"), ended("CANCELLED")),
+    ] {
+        let slurm_dir = TempDir::new().unwrap();
+        let run = TempDir::new().unwrap();
+        let slurm = fake_slurm(slurm_dir.path());
+        if let Some(state) = state {
+            fs::write(slurm_dir.path().join("state"), state).unwrap();
+        }
+        let mut workflow = Workflow::new("sleep");
+        let busybox = Container::from("busybox:1.36");
+        workflow
+            .add_step(Step::new("sleep", &busybox, command))
+            .unwrap();
+
+        let error = Runner::new(&slurm, run.path())
+            .run(&workflow, Vec::<(String, Value)>::new())
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ExecutionError::StepFailed { step, outcome, .. }
+                if step == "sleep" && outcome == expected
+        ));
+        let log = fs::read_to_string(run.path().join("run.log")).unwrap();
+        assert!(log.contains("sleep: failed, "), "{log}");
+    }
+}
+
+#[test]
+fn test_array_job() {
+    let slurm_dir = TempDir::new().unwrap();
+    let run = TempDir::new().unwrap();
+    let mut slurm = fake_slurm(slurm_dir.path());
+    slurm.array_limit(2);
+
+    let commands = ["echo 0 > out", "echo broken >&2; exit 3", "echo 4 > out"];
+    let array = job_array(run.path(), "square", &commands);
+
+    let outcomes = slurm.execute_array(&array).unwrap();
+    assert_eq!(
+        outcomes,
+        [
+            JobOutcome::exited(0),
+            JobOutcome::exited(3),
+            JobOutcome::exited(0)
+        ]
+    );
+    let out = fs::read_to_string(run.path().join("2/work/out")).unwrap();
+    assert_eq!(out, "4\n");
+    let stderr = fs::read_to_string(run.path().join("1/stderr")).unwrap();
+    assert_eq!(stderr, "broken\n");
+
+    let manifest = fs::read_to_string(run.path().join("array.manifest")).unwrap();
+    let first = manifest.lines().next().unwrap();
+    assert_eq!(
+        first,
+        format!(
+            "0\tsquare_0\t{}\tindex=0\tlabel=\"job 0\"",
+            run.path().join("0").display()
+        )
+    );
+    let script = fs::read_to_string(run.path().join("array.sbatch")).unwrap();
+    assert!(script.lines().any(|line| line == "#SBATCH --array=0-2%2"));
+    assert!(args(slurm_dir.path(), "sacct").contains(&"--jobs=4343".to_string()));
+    assert!(!slurm_dir.path().join("squeue.args").exists());
+}
+
+#[test]
+fn test_cancelled_pending_array() {
+    let slurm_dir = TempDir::new().unwrap();
+    let run = TempDir::new().unwrap();
+    let slurm = fake_slurm(slurm_dir.path());
+    // Elements that never started are only listed as a range
+    fs::write(
+        slurm_dir.path().join("array_state"),
+        "4343_0|COMPLETED|0:0\n4343_[1-2%2]|CANCELLED by 1000|0:0\n",
+    )
+    .unwrap();
+
+    let array = job_array(run.path(), "wait", &["true"; 3]);
+
+    let outcomes = slurm.execute_array(&array).unwrap();
+    let cancelled = JobOutcome {
+        exit_code: Some(0),
+        reason: Some("CANCELLED".to_string()),
+    };
+    assert_eq!(
+        outcomes,
+        [JobOutcome::exited(0), cancelled.clone(), cancelled]
+    );
+}
+
+#[test]
+fn test_rejected_submission() {
+    let slurm_dir = TempDir::new().unwrap();
+    let data = TempDir::new().unwrap();
+    let run = TempDir::new().unwrap();
+    let mut slurm = fake_slurm(slurm_dir.path());
+    let sbatch = slurm_dir.path().join("reject");
+    write_script(
+        &sbatch,
+        "echo 'sbatch: error: Batch job submission failed: Invalid account' >&2\nexit 1\n",
+    );
+    slurm.sbatch(&sbatch);
+    let (workflow, inputs) = count_lines(data.path());
+
+    let error = Runner::new(&slurm, run.path())
+        .run(&workflow, inputs)
+        .unwrap_err();
+    let ExecutionError::Scheduler { program, message } = error else {
+        panic!("unexpected error {error}");
+    };
+    assert_eq!(program, sbatch);
+    assert_eq!(
+        message,
+        "sbatch: error: Batch job submission failed: Invalid account"
+    );
+}
+
+// EOF
@@ -0,0 +1,418 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use rivulet::hash::ContentHash;
+use rivulet::prelude::*;
+use rivulet::workflow::{KeyChange, RetentionPolicy, RunResult, StepCache, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tempfile::TempDir;
+
+/// Upper-case a text, count its lines and report the count with a given format.
+///
+/// Every step appends its name to `executions` when it runs.
+fn pipeline(executions: &Path, format: &str) -> Workflow {
+    let record = |name: &str| format!("; echo {name} >> '{}'", executions.display());
+    let shell = Container::from("docker.io/library/busybox:1.36");
+    let mut workflow = Workflow::new("pipeline");
+    let text = workflow.input("text", PortType::File).unwrap();
+
+    let mut upper = Step::new(
+        "upper",
+        &shell,
+        format!("tr a-z A-Z < {{text}} > upper.txt{}", record("upper")),
+    );
+    upper
+        .input("text", PortType::File)
+        .output("upper", PortType::File)
+        .glob("upper", "upper.txt");
+    let upper = workflow.add_step(upper).unwrap();
+
+    let mut count = Step::new(
+        "count",
+        &shell,
+        format!("wc -l < {{text}} > lines{}", record("count")),
+    );
+    count
+        .input("text", PortType::File)
+        .output("lines", PortType::Int);
+    let count = workflow.add_step(count).unwrap();
+
+    let mut report = Step::new(
+        "report",
+        &shell,
+        format!("printf '{format}' {{lines}} > summary{}", record("report")),
+    );
+    report
+        .input("lines", PortType::Int)
+        .output("summary", PortType::String);
+    let report = workflow.add_step(report).unwrap();
+
+    workflow.connect(text, upper.input("text")).unwrap();
+    workflow
+        .connect(upper.output("upper"), count.input("text"))
+        .unwrap();
+    workflow
+        .connect(count.output("lines"), report.input("lines"))
+        .unwrap();
+    workflow.output("upper", upper.output("upper")).unwrap();
+    workflow
+        .output("summary", report.output("summary"))
+        .unwrap();
+    workflow
+}
+
+/// Run a workflow with a cache in a new run directory, returning the result and the steps
+/// that ran.
+fn run(workflow: &Workflow, cache: &StepCache, text: &Path, scratch: &Path) -> (RunResult, String) {
+    let executions = scratch.join("executions");
+    let _ = fs::remove_file(&executions);
+    let run = TempDir::new_in(scratch).unwrap().keep();
+    let result = Runner::new(&LocalExecutor::new(), &run)
+        .cache(cache)
+        .run(workflow, [("text", Value::File(text.to_path_buf()))])
+        .unwrap();
+    let ran = fs::read_to_string(&executions).unwrap_or_default();
+    (result, ran.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+#[test]
+fn test_unchanged_rerun() {
+    let scratch = TempDir::new().unwrap();
+    let cache = StepCache::new(scratch.path().join("cache"));
+    let text = scratch.path().join("text.txt");
+    fs::write(&text, "a\nb\nc\n").unwrap();
+    let workflow = pipeline(&scratch.path().join("executions"), "%s lines");
+
+    let (first, ran) = run(&workflow, &cache, &text, scratch.path());
+    assert_eq!(ran, "upper count report");
+    assert!(first.steps.iter().all(|step| !step.cached));
+    let miss = first.steps[0].cache_miss.as_ref().unwrap();
+    assert_eq!(miss.to_string(), "no earlier results of the step");
+
+    let (second, ran) = run(&workflow, &cache, &text, scratch.path());
+    assert_eq!(ran, "");
+    assert!(second.steps.iter().all(|step| step.cached));
+    assert_eq!(
+        second.outputs["summary"],
+        Value::String("3 lines".to_string())
+    );
+
+    // Restored outputs live in the new run, not in the cache or the first run
+    let Value::File(upper) = &second.outputs["upper"] else {
+        panic!("upper is not a file");
+    };
+    assert!(upper.starts_with(&second.steps[0].dir));
+    assert_eq!(fs::read_to_string(upper).unwrap(), "A\nB\nC\n");
+    let run = second.steps[0].dir.ancestors().nth(2).unwrap();
+    let log = fs::read_to_string(run.join("run.log")).unwrap();
+    assert!(log.contains("upper: reused cached results "), "{log}");
+}
+
+#[test]
+fn test_edited_last_step() {
+    let scratch = TempDir::new().unwrap();
+    let cache = StepCache::new(scratch.path().join("cache"));
+    let text = scratch.path().join("text.txt");
+    fs::write(&text, "a\nb\n").unwrap();
+    let executions = scratch.path().join("executions");
+    run(
+        &pipeline(&executions, "%s lines"),
+        &cache,
+        &text,
+        scratch.path(),
+    );
+
+    let (result, ran) = run(
+        &pipeline(&executions, "lines: %s"),
+        &cache,
+        &text,
+        scratch.path(),
+    );
+    assert_eq!(ran, "report");
+    assert_eq!(
+        result.outputs["summary"],
+        Value::String("lines: 2".to_string())
+    );
+    let cached: Vec<_> = result.steps.iter().map(|step| step.cached).collect();
+    assert_eq!(cached, [true, true, false]);
+
+    let miss = result.steps[2].cache_miss.as_ref().unwrap();
+    assert_eq!(miss.changes, [KeyChange::Command]);
+    let run = result.steps[2].dir.ancestors().nth(2).unwrap();
+    let log = fs::read_to_string(run.join("run.log")).unwrap();
+    assert!(
+        log.contains("report: not cached, command changed\n"),
+        "{log}"
+    );
+}
+
+#[test]
+fn test_changed_input() {
+    let scratch = TempDir::new().unwrap();
+    let cache = StepCache::new(scratch.path().join("cache"));
+    let text = scratch.path().join("text.txt");
+    let workflow = pipeline(&scratch.path().join("executions"), "%s lines");
+    fs::write(&text, "a\nb\n").unwrap();
+    run(&workflow, &cache, &text, scratch.path());
+
+    fs::write(&text, "x\ny\nz\n").unwrap();
+    let (result, ran) = run(&workflow, &cache, &text, scratch.path());
+    assert_eq!(ran, "upper count report");
+    let misses: Vec<_> = result
+        .steps
+        .iter()
+        .map(|step| step.cache_miss.as_ref().unwrap().to_string())
+        .collect();
+    assert_eq!(
+        misses,
+        [
+            "input text.txt changed hash",
+            "input upper.txt changed hash",
+            "parameter lines changed 2→3",
+        ]
+    );
+    assert_eq!(
+        result.outputs["summary"],
+        Value::String("3 lines".to_string())
+    );
+
+    // The same number of lines reaches the last step, which is not run again
+    fs::write(&text, "x\ny\n").unwrap();
+    let (result, ran) = run(&workflow, &cache, &text, scratch.path());
+    assert_eq!(ran, "upper count");
+    assert_eq!(
+        result.outputs["summary"],
+        Value::String("2 lines".to_string())
+    );
+
+    // A copy with the same contents elsewhere hits the cache
+    let copy = scratch.path().join("copy.txt");
+    fs::copy(&text, &copy).unwrap();
+    let (_, ran) = run(&workflow, &cache, &copy, scratch.path());
+    assert_eq!(ran, "");
+}
+
+#[test]
+fn test_shared_outputs_stored_once() {
+    let scratch = TempDir::new().unwrap();
+    let cache = StepCache::new(scratch.path().join("cache"));
+    let shell = Container::from("docker.io/library/busybox:1.36");
+    for (name, command) in [
+        ("fetch", "printf '>chr1\\nACGT\\n' > genome.fa"),
+        (
+            "download",
+            "echo '>chr1' > genome.fa; echo ACGT >> genome.fa",
+        ),
+    ] {
+        let mut workflow = Workflow::new(name);
+        let mut step = Step::new(name, &shell, command);
+        step.output("genome", PortType::File)
+            .glob("genome", "genome.fa");
+        let step = workflow.add_step(step).unwrap();
+        workflow.output("genome", step.output("genome")).unwrap();
+        let run = TempDir::new().unwrap();
+        Runner::new(&LocalExecutor::new(), run.path())
+            .cache(&cache)
+            .run(&workflow, Vec::<(String, Value)>::new())
+            .unwrap();
+    }
+
+    // One blob for the genome, one for the empty standard output and error
+    let blobs: usize = fs::read_dir(cache.dir().join("blobs"))
+        .unwrap()
+        .map(|dir| fs::read_dir(dir.unwrap().path()).unwrap().count())
+        .sum();
+    assert_eq!(blobs, 2);
+    assert_eq!(fs::read_dir(cache.dir().join("steps")).unwrap().count(), 2);
+}
+
+/// Copy a text, in a workflow sharing the cache with the pipeline.
+fn copy_workflow() -> Workflow {
+    let shell = Container::from("docker.io/library/busybox:1.36");
+    let mut workflow = Workflow::new("other");
+    let input = workflow.input("text", PortType::File).unwrap();
+    let mut copy = Step::new("copy", &shell, "cp {text} copy.txt");
+    copy.input("text", PortType::File)
+        .output("copy", PortType::File)
+        .glob("copy", "copy.txt");
+    let copy = workflow.add_step(copy).unwrap();
+    workflow.connect(input, copy.input("text")).unwrap();
+    workflow
+}
+
+/// A cache holding two runs of the pipeline, on different texts, and one of the copy workflow.
+struct Garbage {
+    cache: StepCache,
+    text: PathBuf,
+    workflow: Workflow,
+    first_run: String,
+    first: Vec<ContentHash>,
+    second: Vec<ContentHash>,
+    third: Vec<ContentHash>,
+}
+
+impl Garbage {
+    fn new(scratch: &Path) -> Self {
+        let cache = StepCache::new(scratch.join("cache"));
+        let text = scratch.join("text.txt");
+        let workflow = pipeline(&scratch.join("executions"), "%s lines");
+        fs::write(&text, "a\nb\n").unwrap();
+        let (first, _) = run(&workflow, &cache, &text, scratch);
+        fs::write(&text, "c\nd\n").unwrap();
+        let (second, ran) = run(&workflow, &cache, &text, scratch);
+        assert_eq!(ran, "upper count");
+        let (third, _) = run(&copy_workflow(), &cache, &text, scratch);
+
+        let keys = |result: &RunResult| -> Vec<_> {
+            result
+                .steps
+                .iter()
+                .map(|step| step.cache_key.unwrap())
+                .collect()
+        };
+        let (first_keys, second_keys) = (keys(&first), keys(&second));
+        assert_eq!(first_keys[2], second_keys[2]);
+        Self {
+            cache,
+            text,
+            workflow,
+            first_run: first.run_id,
+            first: first_keys,
+            second: second_keys,
+            third: keys(&third),
+        }
+    }
+}
+
+#[test]
+fn test_garbage_collection_plan() {
+    let scratch = TempDir::new().unwrap();
+    let Garbage {
+        cache,
+        first_run,
+        first,
+        ..
+    } = Garbage::new(scratch.path());
+
+    // Only the first run of the pipeline is too old to keep
+    let mut policy = RetentionPolicy::new();
+    policy.keep_last_runs(1);
+    let plan = cache.plan_garbage_collection(&policy).unwrap();
+    assert!(plan.dry_run);
+    assert_eq!(plan.results, sorted(&first[..2]));
+    assert_eq!(plan.runs, [first_run.as_str()]);
+    assert_eq!(plan.workflows.keys().collect::<Vec<_>>(), ["pipeline"]);
+    assert_eq!(plan.workflows["pipeline"].results, 2);
+    assert_eq!(plan.workflows["pipeline"].bytes, plan.bytes);
+    assert!(plan.bytes > 0);
+    assert!(plan.to_string().starts_with("would free "), "{plan}");
+    assert!(first.iter().all(|key| cache.contains(key)));
+
+    cache.pin(&first_run).unwrap();
+    let plan = cache.plan_garbage_collection(&policy).unwrap();
+    assert!(plan.results.is_empty() && plan.runs.is_empty());
+    assert_eq!(plan.objects, 0);
+}
+
+#[test]
+fn test_garbage_collection_named_result() {
+    let scratch = TempDir::new().unwrap();
+    let Garbage {
+        cache,
+        first_run,
+        first,
+        ..
+    } = Garbage::new(scratch.path());
+
+    // A named result outlives its run
+    let mut policy = RetentionPolicy::new();
+    policy.keep_last_runs(1);
+    cache.name_result("first-upper", &first[0]).unwrap();
+    fs::write(cache.dir().join("tmp").join("0.0"), "partial").unwrap();
+    let report = cache.collect_garbage(&policy).unwrap();
+    assert!(!report.dry_run);
+    assert_eq!(report.results, [first[1]]);
+    assert_eq!(report.runs, [first_run.as_str()]);
+    assert!(cache.contains(&first[0]));
+    assert!(!cache.contains(&first[1]));
+    assert!(!cache.dir().join("tmp").join("0.0").exists());
+    assert!(matches!(
+        cache.pin(&first_run),
+        Err(ExecutionError::UnknownRun(_))
+    ));
+    assert!(matches!(
+        cache.name_result("../escape", &first[0]),
+        Err(ExecutionError::InvalidName(_))
+    ));
+
+    // Without limits, or with limits everything is within, nothing more is removed
+    let mut policy = RetentionPolicy::new();
+    assert_eq!(cache.collect_garbage(&policy).unwrap().objects, 0);
+    policy.max_age(Duration::from_secs(3600)).max_bytes(1 << 30);
+    assert!(cache.collect_garbage(&policy).unwrap().results.is_empty());
+}
+
+#[test]
+fn test_garbage_collection_max_bytes() {
+    let scratch = TempDir::new().unwrap();
+    let garbage = Garbage::new(scratch.path());
+    let cache = &garbage.cache;
+
+    // Every result but the named one is removed to fit in no space
+    cache.name_result("first-upper", &garbage.first[0]).unwrap();
+    let mut policy = RetentionPolicy::new();
+    policy.max_bytes(0);
+    let report = cache.collect_garbage(&policy).unwrap();
+    let mut expected = vec![garbage.first[1]];
+    expected.extend(&garbage.second);
+    expected.extend(&garbage.third);
+    assert_eq!(report.results, sorted(&expected));
+    assert_eq!(report.workflows["pipeline"].results, 4);
+    assert_eq!(report.workflows["other"].results, 1);
+    assert!(cache.contains(&garbage.first[0]));
+
+    fs::write(&garbage.text, "a\nb\n").unwrap();
+    let (_, ran) = run(&garbage.workflow, cache, &garbage.text, scratch.path());
+    assert_eq!(ran, "count report");
+}
+
+#[test]
+fn test_garbage_collection_keeps_failed_run() {
+    let scratch = TempDir::new().unwrap();
+    let garbage = Garbage::new(scratch.path());
+    let cache = &garbage.cache;
+
+    // The last run of the pipeline keeps two results before its last step fails
+    fs::write(&garbage.text, "e\nf\ng\n").unwrap();
+    let failing = pipeline(&scratch.path().join("executions"), "%s'; exit 1; '");
+    let error = Runner::new(&LocalExecutor::new(), scratch.path().join("failed"))
+        .cache(cache)
+        .run(&failing, [("text", Value::File(garbage.text.clone()))])
+        .unwrap_err();
+    assert!(matches!(error, ExecutionError::StepFailed { step, .. } if step == "report"));
+
+    let mut policy = RetentionPolicy::new();
+    policy.keep_last_runs(1);
+    let report = cache.collect_garbage(&policy).unwrap();
+    let mut expected = garbage.first.clone();
+    expected.extend(&garbage.second);
+    assert_eq!(report.results, sorted(&expected));
+    assert_eq!(report.runs.len(), 2);
+    assert!(report.runs.contains(&garbage.first_run));
+
+    let (_, ran) = run(&garbage.workflow, cache, &garbage.text, scratch.path());
+    assert_eq!(ran, "report");
+}
+
+/// Sort and deduplicate keys, as garbage collection reports them.
+fn sorted(keys: &[ContentHash]) -> Vec<ContentHash> {
+    let mut keys = keys.to_vec();
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+// EOF
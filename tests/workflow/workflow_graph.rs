@@ -0,0 +1,240 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+use rivulet::prelude::*;
+use rivulet::workflow::Source;
+use std::sync::Arc;
+
+/// Build the fastqc → STAR → salmon pipeline from the crate documentation, with a final
+/// report step that depends on both the QC and the quantification.
+fn rnaseq() -> (Workflow, [StepId; 4]) {
+    let mut workflow = Workflow::new("rnaseq");
+    let reads = workflow.input("reads", PortType::File).unwrap();
+    let genome = workflow.input("genome", PortType::Directory).unwrap();
+    let transcripts = workflow.input("transcripts", PortType::File).unwrap();
+    let [qc, align, count, report] = rnaseq_steps();
+
+    // Add the report first so the order cannot simply follow insertion
+    let report = workflow.add_step(report).unwrap();
+    let qc = workflow.add_step(qc).unwrap();
+    let align = workflow.add_step(align).unwrap();
+    let count = workflow.add_step(count).unwrap();
+
+    workflow.connect(reads.clone(), qc.input("reads")).unwrap();
+    workflow.connect(reads, align.input("reads")).unwrap();
+    workflow.connect(genome, align.input("genome")).unwrap();
+    workflow
+        .connect(transcripts, count.input("transcripts"))
+        .unwrap();
+    workflow
+        .connect(align.output("alignments"), count.input("alignments"))
+        .unwrap();
+    workflow
+        .connect(qc.output("report"), report.input("qc"))
+        .unwrap();
+    workflow
+        .connect(count.output("quant"), report.input("quant"))
+        .unwrap();
+    workflow.output("html", report.output("html")).unwrap();
+
+    (workflow, [qc, align, count, report])
+}
+
+/// Build the unconnected qc, align, count and report steps of the pipeline.
+fn rnaseq_steps() -> [Step; 4] {
+    let mut qc = Step::new(
+        "qc",
+        &Container::from("biocontainers/fastqc:0.11.9"),
+        "fastqc {reads} -o .",
+    );
+    qc.input("reads", PortType::File)
+        .output("report", PortType::File);
+
+    let mut align = Step::new(
+        "align",
+        &Container::from("biocontainers/star:2.7.9a"),
+        "STAR --genomeDir {genome} --readFilesIn {reads} --quantMode TranscriptomeSAM",
+    );
+    align
+        .input("genome", PortType::Directory)
+        .input("reads", PortType::File)
+        .output("alignments", PortType::File);
+
+    let mut count = Step::new(
+        "count",
+        &Container::from("biocontainers/salmon:1.5.2"),
+        "salmon quant -t {transcripts} -l A -a {alignments} -o quant",
+    );
+    count
+        .input("transcripts", PortType::File)
+        .input("alignments", PortType::File)
+        .output("quant", PortType::Directory);
+
+    let mut report = Step::new(
+        "report",
+        &Container::from("multiqc/multiqc:v1.21"),
+        "multiqc {qc} {quant}",
+    );
+    report
+        .input("qc", PortType::File)
+        .input("quant", PortType::Directory)
+        .output("html", PortType::File);
+
+    [qc, align, count, report]
+}
+
+#[test]
+fn test_pipeline_validates() {
+    let (workflow, _) = rnaseq();
+    assert_eq!(workflow.validate(), Ok(()));
+    assert_eq!(workflow.steps().len(), 4);
+    assert_eq!(workflow.edges().len(), 7);
+    assert_eq!(workflow.inputs().len(), 3);
+}
+
+#[test]
+fn test_topological_order_respects_edges() {
+    let (workflow, [qc, align, count, report]) = rnaseq();
+    let order = workflow.topological_order().unwrap();
+    assert_eq!(order, [qc, align, count, report]);
+
+    let position = |id: StepId| order.iter().position(|&step| step == id).unwrap();
+    for edge in workflow.edges() {
+        if let Source::StepOutput { step, .. } = edge.from {
+            assert!(position(step) < position(edge.to.step));
+        }
+    }
+}
+
+#[test]
+fn test_steps_share_containers() {
+    let base = Container::from("python:3.12-slim");
+    let mut workflow = Workflow::new("shared");
+    let first = workflow
+        .add_step(Step::new("first", &base, "python -V"))
+        .unwrap();
+    let second = workflow
+        .add_step(Step::new("second", &base, "python -c 'print(1)'"))
+        .unwrap();
+    assert!(Arc::ptr_eq(
+        workflow.step(first).container(),
+        workflow.step(second).container()
+    ));
+    assert_eq!(workflow.step_id("second"), Some(second));
+    assert_eq!(workflow.step_id("third"), None);
+}
+
+#[test]
+fn test_cycle_is_rejected() {
+    let (mut workflow, _) = rnaseq();
+
+    // Two extra steps that each wait for the other
+    let mut polish = Step::new("polish", &Container::from("alpine"), "cat {draft}");
+    polish
+        .input("draft", PortType::File)
+        .output("final", PortType::File);
+    let polish = workflow.add_step(polish).unwrap();
+    let mut review = Step::new("review", &Container::from("alpine"), "cat {final}");
+    review
+        .input("final", PortType::File)
+        .output("draft", PortType::File);
+    let review = workflow.add_step(review).unwrap();
+    workflow
+        .connect(review.output("draft"), polish.input("draft"))
+        .unwrap();
+    workflow
+        .connect(polish.output("final"), review.input("final"))
+        .unwrap();
+
+    let error = workflow.validate().unwrap_err();
+    assert!(matches!(
+        error,
+        WorkflowError::Cycle(ref names) if names.len() == 2
+            && names.contains(&"polish".to_string())
+            && names.contains(&"review".to_string())
+    ));
+}
+
+#[test]
+fn test_error_messages_name_ports() {
+    let (mut workflow, _) = rnaseq();
+    let mut extra = Step::new("extra", &Container::from("alpine"), "cat {x}");
+    extra.input("x", PortType::Int);
+    workflow.add_step(extra).unwrap();
+    assert_eq!(
+        workflow.validate().unwrap_err().to_string(),
+        "Input 'x' of step 'extra' is not connected"
+    );
+}
+
+#[test]
+fn test_directory_to_file_is_rejected_at_build_time() {
+    let mut workflow = Workflow::new("index-then-quant");
+    let transcripts = workflow.input("transcripts", PortType::File).unwrap();
+
+    let salmon = Container::from("biocontainers/salmon:1.5.2");
+    let mut index = Step::new("index", &salmon, "salmon index -t {transcripts} -i index");
+    index
+        .input("transcripts", PortType::File)
+        .output("index", PortType::Directory);
+    let index = workflow.add_step(index).unwrap();
+    workflow
+        .connect(transcripts, index.input("transcripts"))
+        .unwrap();
+
+    let mut quant = Step::new("quant", &salmon, "salmon quant -r {reads} -o quant");
+    quant.input("reads", PortType::File);
+    let quant = workflow.add_step(quant).unwrap();
+
+    let error = workflow
+        .connect(index.output("index"), quant.input("reads"))
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        WorkflowError::IncompatibleTypes {
+            found: PortType::Directory,
+            expected: PortType::File,
+            ..
+        }
+    ));
+    assert_eq!(
+        error.to_string(),
+        "Cannot connect output 'index' of step 'index' of type Directory \
+         to input 'reads' of step 'quant' of type File"
+    );
+    assert!(workflow.edges().iter().all(|edge| edge.to.step != quant));
+}
+
+#[test]
+fn test_array_ports() {
+    let mut workflow = Workflow::new("merge");
+    let samples = workflow
+        .input("samples", PortType::array(PortType::File))
+        .unwrap();
+    let mut merge = Step::new("merge", &Container::from("alpine"), "cat {parts} > all");
+    merge
+        .input("parts", PortType::array(PortType::File))
+        .output("all", PortType::File);
+    let merge = workflow.add_step(merge).unwrap();
+    workflow.connect(samples, merge.input("parts")).unwrap();
+
+    let mut single = Step::new("single", &Container::from("alpine"), "cat {one}");
+    single.input("one", PortType::File);
+    let single = workflow.add_step(single).unwrap();
+    let result = workflow.connect(
+        Source::WorkflowInput("samples".to_string()),
+        single.input("one"),
+    );
+    assert!(matches!(
+        result,
+        Err(WorkflowError::IncompatibleTypes { ref found, .. })
+            if *found == PortType::array(PortType::File)
+    ));
+    assert_eq!(
+        workflow.source_type(&merge.output("all")),
+        Ok(PortType::File)
+    );
+}
+
+// EOF
@@ -0,0 +1,23 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License, v. 2.0.
+// If a copy of the MPL was not distributed with this file,
+// You can obtain one at <https://mozilla.org/MPL/2.0/>.
+
+// Import workflow tests
+mod workflow {
+    #[cfg(unix)]
+    mod batch_schedulers;
+    #[cfg(unix)]
+    mod common;
+    mod conditional_steps;
+    #[cfg(unix)]
+    mod container_executor;
+    mod local_executor;
+    mod provenance;
+    mod scatter_gather;
+    #[cfg(unix)]
+    mod slurm_executor;
+    mod step_cache;
+    mod workflow_graph;
+}
+
+// EOF